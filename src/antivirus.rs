@@ -0,0 +1,22 @@
+use std::time::Duration;
+
+/// Slower than this on average, a destination is copying slow enough to point at its writes being
+/// held up, even USB 2 sticks do better
+const SLOW_BYTES_PER_SEC: f64 = 1024. * 1024.;
+/// Copies over quicker than this don't say much with their speed
+const MIN_DURATION: Duration = Duration::from_secs(10);
+/// How often files have to be found locked on the destination, as a scanner opening every new
+/// file leaves them for a moment
+const MIN_LOCKS: u32 = 2;
+
+///
+/// Whether the writes to a destination look held up by a virus scanner such as Defender, which
+/// opens every file as it is written: `bytes` took `duration` to copy, pathologically slowly,
+/// and files on it were found locked `locks` times. Only a heuristic, and one that is worth
+/// checking on Windows, where scanners on removable drives are the norm.
+///
+pub fn suspected(bytes: usize, duration: Duration, locks: u32) -> bool {
+    locks >= MIN_LOCKS
+        && duration >= MIN_DURATION
+        && (bytes as f64 / duration.as_secs_f64()) < SLOW_BYTES_PER_SEC
+}
@@ -0,0 +1,240 @@
+use clap::Parser;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    copy::CopyQueue,
+    fixture::{self, FixtureSpec},
+    hash::sha256_file,
+    size::ByteSize,
+    walk::{Entry, Walk},
+    Args,
+};
+
+/// Copying to one destination with the default engine
+pub const SEQUENTIAL: &str = "sequential";
+/// Copying to two destinations with `--fan-out`
+pub const FAN_OUT: &str = "fan-out";
+/// Hashing the source as `--verify` and `--sha256sums` do
+pub const SHA256: &str = "sha256";
+
+///
+/// Options of `bench`
+///
+#[derive(clap::Args, Debug, Clone, PartialEq, Eq)]
+pub struct BenchArgs {
+    /// JSON file with the throughput to compare against, recorded by an earlier `--update`
+    #[arg(long, value_name = "FILE")]
+    pub baseline: Option<PathBuf>,
+
+    /// Record this run as the new baseline instead of comparing against it
+    #[arg(long, requires = "baseline")]
+    pub update: bool,
+
+    /// How far throughput may drop below the baseline before the run fails, in percent
+    #[arg(long, value_name = "PERCENT", default_value_t = 15, value_parser = clap::value_parser!(u8).range(1..100))]
+    pub threshold: u8,
+
+    /// How often each workload runs, the fastest run counts
+    #[arg(long, default_value_t = 3, value_parser = clap::value_parser!(u32).range(1..))]
+    pub runs: u32,
+
+    /// Where to build the workload, on the drive to be measured. Everything written there is
+    /// removed again afterwards. [default: the temporary directory]
+    #[arg(long, value_name = "DIR")]
+    pub dir: Option<PathBuf>,
+}
+
+///
+/// Throughput of every workload, as measured by `run` and stored with `--update`
+///
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Baseline {
+    /// decopy version that measured it
+    pub version: String,
+    /// Size of the source tree, baselines of a different tree can't be compared
+    pub workload_bytes: u64,
+    /// Bytes per second by workload
+    pub throughput: BTreeMap<String, f64>,
+}
+
+impl Baseline {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let json = ::std::fs::read(path).map_err(|e| e.to_string())?;
+        serde_json::from_slice(&json).map_err(|e| format!("not a bench baseline: {}", e))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let json = serde_json::to_string_pretty(self).expect("baselines always serialize");
+        ::std::fs::write(path, json + "\n").map_err(|e| e.to_string())
+    }
+}
+
+///
+/// One workload measured against its baseline
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct Comparison {
+    pub workload: String,
+    /// Bytes per second
+    pub current: f64,
+    /// `None` for workloads the baseline doesn't know yet
+    pub baseline: Option<f64>,
+}
+
+impl Comparison {
+    ///
+    /// Change against the baseline in percent, negative when slower
+    ///
+    pub fn change(&self) -> Option<f64> {
+        self.baseline
+            .map(|baseline| (self.current / baseline - 1.0) * 100.0)
+    }
+
+    pub fn regressed(&self, threshold: u8) -> bool {
+        self.change()
+            .is_some_and(|change| change < -f64::from(threshold))
+    }
+}
+
+///
+/// Lines up `current` with `baseline`, failing when they were measured on different trees
+///
+pub fn compare(baseline: &Baseline, current: &Baseline) -> Result<Vec<Comparison>, String> {
+    if baseline.workload_bytes != current.workload_bytes {
+        return Err(format!(
+            "the baseline was recorded by v{} on a different workload, record a new one with --update",
+            baseline.version
+        ));
+    }
+    Ok(current
+        .throughput
+        .iter()
+        .map(|(workload, current)| Comparison {
+            workload: workload.clone(),
+            current: *current,
+            baseline: baseline.throughput.get(workload).copied(),
+        })
+        .collect())
+}
+
+///
+/// The tree every workload copies: a few hundred files, mostly small, a few of several
+/// megabytes, so both per-file overhead and raw throughput count
+///
+fn workload(dir: PathBuf) -> FixtureSpec {
+    FixtureSpec {
+        dir,
+        seed: 1,
+        files: 300,
+        depth: 4,
+        max_size: ByteSize(8 * 1024 * 1024),
+        sparse: 0,
+        sparse_size: ByteSize(0),
+    }
+}
+
+///
+/// Builds the workload below `args.dir` and measures every workload on it, `onworkload` is called
+/// with the name of each before it runs. The tree is removed again however the run ends.
+///
+pub fn run(args: &BenchArgs, onworkload: impl FnMut(&str)) -> Result<Baseline, String> {
+    let root = args
+        .dir
+        .clone()
+        .unwrap_or_else(::std::env::temp_dir)
+        .join(format!("decopy-bench-{}", ::std::process::id()));
+    let measured = measure(&root, args.runs, onworkload);
+    let _ = ::std::fs::remove_dir_all(&root);
+    measured
+}
+
+fn measure(root: &Path, runs: u32, mut onworkload: impl FnMut(&str)) -> Result<Baseline, String> {
+    let source = root.join("source");
+    let summary = fixture::generate(&workload(source.clone())).map_err(|e| {
+        format!(
+            "could not build the workload in `{}`: {}",
+            root.display(),
+            e
+        )
+    })?;
+    let bytes = summary.bytes as f64;
+
+    let mut throughput = BTreeMap::new();
+    for (name, destinations) in [(SEQUENTIAL, 1), (FAN_OUT, 2)] {
+        onworkload(name);
+        let fastest = fastest(runs, || copy(root, &source, destinations, name == FAN_OUT))?;
+        throughput.insert(
+            name.to_string(),
+            bytes * destinations as f64 / fastest.as_secs_f64(),
+        );
+    }
+    onworkload(SHA256);
+    let fastest = fastest(runs, || hash(&source))?;
+    throughput.insert(SHA256.to_string(), bytes / fastest.as_secs_f64());
+
+    Ok(Baseline {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        workload_bytes: summary.bytes,
+        throughput,
+    })
+}
+
+fn fastest(
+    runs: u32,
+    mut run: impl FnMut() -> Result<Duration, String>,
+) -> Result<Duration, String> {
+    let mut fastest = Duration::MAX;
+    for _ in 0..runs {
+        fastest = fastest.min(run()?);
+    }
+    // A run too quick for the clock would divide by zero
+    Ok(fastest.max(Duration::from_micros(1)))
+}
+
+///
+/// Copies `source` to fresh destinations below `root`, which are removed again afterwards
+///
+fn copy(
+    root: &Path,
+    source: &Path,
+    destinations: usize,
+    fan_out: bool,
+) -> Result<Duration, String> {
+    let dests = (0..destinations)
+        .map(|i| root.join(format!("dest{}", i)))
+        .collect::<Vec<_>>();
+    let mut argv = vec![
+        "decopy".into(),
+        source.as_os_str().to_owned(),
+        "--state-file".into(),
+        root.join("state.toml").into_os_string(),
+    ];
+    argv.extend(dests.iter().map(|dest| dest.as_os_str().to_owned()));
+    if fan_out {
+        argv.push("--fan-out".into());
+    }
+    let args = Args::try_parse_from(argv).map_err(|e| e.to_string())?;
+
+    let started = Instant::now();
+    let copied = CopyQueue::from(&args).start_copy(Box::new(|_, _, _| {}), Box::new(|| {}));
+    let elapsed = started.elapsed();
+    for dest in &dests {
+        let _ = ::std::fs::remove_dir_all(dest);
+    }
+    copied.map(|_| elapsed).map_err(|e| e.to_string())
+}
+
+fn hash(source: &Path) -> Result<Duration, String> {
+    let started = Instant::now();
+    for entry in Walk::new(source) {
+        if let Entry::File(file, _) = entry.map_err(|e| e.to_string())? {
+            sha256_file(&source.join(&file)).map_err(|e| format!("`{}`: {}", file.display(), e))?;
+        }
+    }
+    Ok(started.elapsed())
+}
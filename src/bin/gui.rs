@@ -0,0 +1,319 @@
+use clap::{error::ErrorKind, CommandFactory};
+use eframe::egui;
+use std::{
+    path::PathBuf,
+    sync::mpsc::{channel, Receiver},
+    thread::JoinHandle,
+    time::Duration,
+};
+
+use deployment_copy::{
+    budget,
+    config::{self, Config},
+    copy::{CopyQueue, DestinationSummary},
+    error::CopyError,
+    i18n::Message,
+    locale::Locale,
+    ui::{completed_totals, copy_in_background, failed_file_lines, get_bytes_string, CopyingState},
+    update,
+    verify::Verification,
+    version, Args,
+};
+
+///
+/// A window around the same engine as `decopy`, for stations where operators would rather not
+/// touch a terminal. Takes the same options, pick the drives with the checkboxes.
+///
+fn main() -> eframe::Result {
+    let mut args = Args::parse_with_sources();
+    if args.version {
+        match args.json {
+            true => println!(
+                "{}",
+                serde_json::to_string_pretty(&version::info())
+                    .expect("version info always serializes")
+            ),
+            false => print!("{}", Args::command().render_version()),
+        }
+        return Ok(());
+    }
+    if args.command.is_some() {
+        Args::command()
+            .error(
+                ErrorKind::InvalidSubcommand,
+                "self-update is only available in `decopy`",
+            )
+            .exit();
+    }
+    if args.config.is_none() {
+        args.config = config::default_path().filter(|path| path.exists());
+    }
+    if let Some(path) = args.config.clone() {
+        match Config::load_profile(&path, args.profile.as_deref()) {
+            Ok(config) => args.merge_config(config),
+            Err(e) => {
+                eprintln!("error: {}", e);
+                ::std::process::exit(2);
+            }
+        }
+    }
+    if args.copy_from.is_none() {
+        Args::command()
+            .error(
+                ErrorKind::MissingRequiredArgument,
+                "no source given on the command line or in the config file",
+            )
+            .exit();
+    }
+    if args.stdin_format.is_some() {
+        Args::command()
+            .error(
+                ErrorKind::ArgumentConflict,
+                "--stdin-format is only available in `decopy`",
+            )
+            .exit();
+    }
+    if args.image {
+        Args::command()
+            .error(
+                ErrorKind::ArgumentConflict,
+                "--image is only available in `decopy`",
+            )
+            .exit();
+    }
+    args.locale = args.locale.resolve();
+    args.verify |= args.repair;
+    args.add_group_destinations();
+    budget::raise_open_file_limit();
+    if let Err(e) = args.check_memory().and_then(|()| args.check_open_files()) {
+        Args::command().error(ErrorKind::ValueValidation, e).exit();
+    }
+
+    eframe::run_native(
+        "decopy",
+        eframe::NativeOptions::default(),
+        Box::new(|_| {
+            let mut app = App::new(args);
+            if app.args.check_updates {
+                app.update = update::check().ok().flatten().map(|release| release.tag);
+            }
+            Ok(Box::new(app))
+        }),
+    )
+}
+
+enum State {
+    Selecting,
+    Copying {
+        updates: Receiver<CopyingState>,
+        worker: JoinHandle<CopyQueue>,
+        /// Percent and bytes copied for each selected destination
+        progress: Vec<(usize, usize)>,
+    },
+    Completed {
+        summaries: Vec<DestinationSummary>,
+        verifications: Option<Vec<Verification>>,
+    },
+    Failed(CopyError),
+}
+
+struct App {
+    args: Args,
+    selected: Vec<bool>,
+    /// The destinations the running copy was started with
+    copying_to: Vec<PathBuf>,
+    state: State,
+    /// A newer release found by `--check-updates`
+    update: Option<String>,
+}
+
+impl App {
+    fn new(args: Args) -> Self {
+        Self {
+            selected: vec![true; args.drives.len()],
+            args,
+            copying_to: Vec::new(),
+            state: State::Selecting,
+            update: None,
+        }
+    }
+
+    fn start(&mut self) {
+        let mut args = self.args.clone();
+        args.drives = self
+            .args
+            .drives
+            .iter()
+            .zip(&self.selected)
+            .filter(|(_, selected)| **selected)
+            .map(|(dest, _)| dest.clone())
+            .collect();
+        self.copying_to = args.drives.clone();
+
+        let mut queue = CopyQueue::from(&args);
+        let (sender, updates) = channel();
+        let worker = ::std::thread::spawn(move || {
+            copy_in_background(&mut queue, &args, &sender);
+            queue
+        });
+
+        self.state = State::Copying {
+            updates,
+            worker,
+            progress: vec![(0, 0); self.copying_to.len()],
+        };
+    }
+
+    ///
+    /// Applies everything the worker has sent since the last frame
+    ///
+    fn drain(&mut self) {
+        let State::Copying {
+            updates, progress, ..
+        } = &mut self.state
+        else {
+            return;
+        };
+
+        let mut next = None;
+        for update in updates.try_iter() {
+            match update {
+                CopyingState::Progress {
+                    dest,
+                    percent,
+                    bytes_copied,
+                } => {
+                    if let Some(progress) = progress.get_mut(dest) {
+                        *progress = (percent, bytes_copied);
+                    }
+                }
+                CopyingState::FileProgress { .. }
+                | CopyingState::Verifying { .. }
+                | CopyingState::Repairing { .. }
+                | CopyingState::Mirrored { .. }
+                | CopyingState::Cleaned { .. }
+                | CopyingState::Committed { .. }
+                | CopyingState::Syncing { .. }
+                | CopyingState::Ejecting { .. }
+                | CopyingState::SafeToRemove { .. } => {}
+                CopyingState::Finished {
+                    summaries,
+                    verifications,
+                    ..
+                } => {
+                    next = Some(State::Completed {
+                        summaries,
+                        verifications,
+                    });
+                    break;
+                }
+                CopyingState::Failed(e) => {
+                    next = Some(State::Failed(e));
+                    break;
+                }
+            }
+        }
+        if let Some(next) = next {
+            if let State::Copying { worker, .. } =
+                ::std::mem::replace(&mut self.state, State::Selecting)
+            {
+                let _ = worker.join();
+            }
+            self.state = next;
+        }
+    }
+}
+
+impl eframe::App for App {
+    fn ui(&mut self, ui: &mut egui::Ui, _frame: &mut eframe::Frame) {
+        self.drain();
+        let locale: Locale = self.args.locale;
+        let mut start = false;
+
+        egui::CentralPanel::default().show(ui, |ui| {
+            ui.heading(format!(
+                "Deploying `{}`",
+                self.args.copy_from.clone().unwrap_or_default().display()
+            ));
+            if let Some(tag) = &self.update {
+                ui.colored_label(
+                    egui::Color32::YELLOW,
+                    format!("{} is available, install it with `decopy self-update`", tag),
+                );
+            }
+            ui.separator();
+
+            match &self.state {
+                State::Selecting => {
+                    for (dest, selected) in self.args.drives.iter().zip(&mut self.selected) {
+                        ui.checkbox(selected, dest.display().to_string());
+                    }
+                    ui.separator();
+                    let any = self.selected.iter().any(|selected| *selected);
+                    start = ui.add_enabled(any, egui::Button::new("Start")).clicked();
+                }
+                State::Copying { progress, .. } => {
+                    for (dest, (percent, bytes)) in self.copying_to.iter().zip(progress) {
+                        ui.label(dest.display().to_string());
+                        ui.add(egui::ProgressBar::new(*percent as f32 / 100.).text(format!(
+                            "{} % ({})",
+                            percent,
+                            get_bytes_string(*bytes, locale)
+                        )));
+                    }
+                    ui.ctx().request_repaint_after(Duration::from_millis(100));
+                }
+                State::Completed {
+                    summaries,
+                    verifications,
+                } => {
+                    ui.label(format!(
+                        "Files finished copying, {}",
+                        completed_totals(summaries, locale)
+                    ));
+                    for (i, summary) in summaries.iter().enumerate() {
+                        let mut line = format!(
+                            "{}: {} in {:.1}s",
+                            summary.destination.display(),
+                            get_bytes_string(summary.bytes_copied, locale),
+                            summary.duration.as_secs_f64()
+                        );
+                        match verifications.as_ref().and_then(|v| v.get(i)) {
+                            Some(v) if v.passed() => {
+                                line.push_str(&format!(", {}", Message::Verified.text(locale)))
+                            }
+                            Some(v) => line.push_str(&format!(
+                                ", {} file(s) do not match the source",
+                                v.mismatches()
+                            )),
+                            None => {}
+                        }
+                        ui.label(line);
+                        for failed in failed_file_lines(summary) {
+                            ui.colored_label(egui::Color32::RED, format!("  {}", failed));
+                        }
+                        if summary.scanned {
+                            ui.colored_label(
+                                egui::Color32::YELLOW,
+                                format!("  {}", Message::ScanSuspected.text(locale)),
+                            );
+                        }
+                    }
+                }
+                State::Failed(e) => {
+                    ui.colored_label(
+                        egui::Color32::RED,
+                        format!("{} {}", Message::CopyFailed.text(locale), e),
+                    );
+                    if let Some(suggestion) = e.class().suggestion(locale) {
+                        ui.colored_label(egui::Color32::YELLOW, suggestion);
+                    }
+                }
+            }
+        });
+
+        if start {
+            self.start();
+        }
+    }
+}
@@ -0,0 +1,82 @@
+///
+/// Files the rest of the program may hold open besides what copies, hashes and verifies: the
+/// terminal, the log, the state file, the report and so on
+///
+pub const RESERVED_FILES: usize = 32;
+
+///
+/// How many destinations are copied and verified at the same time, `--jobs` and `--threads`
+/// lowered where needed so the run stays within `--max-open-files` and `--max-memory`, see
+/// `Args::schedule`
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Schedule {
+    /// Destinations copied at the same time, each walking and reading the source on its own
+    pub jobs: usize,
+    /// Threads hashing the source and verifying destinations
+    pub threads: usize,
+}
+
+///
+/// How many files the system lets this process hold open at once, `None` where it doesn't
+/// limit it in a way that matters (Windows)
+///
+pub fn open_file_limit() -> Option<usize> {
+    imp::open_file_limit()
+}
+
+///
+/// Raises how many files this process may hold open to the most the system allows it without
+/// privileges, so the default `--max-open-files` isn't held down by a low soft limit (`ulimit -n`)
+///
+pub fn raise_open_file_limit() {
+    imp::raise_open_file_limit()
+}
+
+#[cfg(unix)]
+mod imp {
+    fn limits() -> Option<libc::rlimit> {
+        let mut limits = libc::rlimit {
+            rlim_cur: 0,
+            rlim_max: 0,
+        };
+        // SAFETY: `limits` outlives the call
+        match unsafe { libc::getrlimit(libc::RLIMIT_NOFILE, &mut limits) } {
+            0 => Some(limits),
+            _ => None,
+        }
+    }
+
+    pub fn open_file_limit() -> Option<usize> {
+        limits()
+            .filter(|limits| limits.rlim_cur != libc::RLIM_INFINITY)
+            .map(|limits| usize::try_from(limits.rlim_cur).unwrap_or(usize::MAX))
+    }
+
+    pub fn raise_open_file_limit() {
+        let Some(mut limits) = limits() else {
+            return;
+        };
+        // macOS refuses more than `OPEN_MAX` from `<sys/syslimits.h>` for the soft limit,
+        // whatever the hard one says
+        #[cfg(target_os = "macos")]
+        let hard = limits.rlim_max.min(10240);
+        #[cfg(not(target_os = "macos"))]
+        let hard = limits.rlim_max;
+        if limits.rlim_cur >= hard {
+            return;
+        }
+        limits.rlim_cur = hard;
+        // SAFETY: `limits` outlives the call. Failing leaves the limit as it was.
+        unsafe { libc::setrlimit(libc::RLIMIT_NOFILE, &limits) };
+    }
+}
+
+#[cfg(not(unix))]
+mod imp {
+    pub fn open_file_limit() -> Option<usize> {
+        None
+    }
+
+    pub fn raise_open_file_limit() {}
+}
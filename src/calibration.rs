@@ -0,0 +1,102 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use crate::drive;
+
+/// Copies smaller than this are mostly overhead and say little about a drive's speed
+const MIN_BYTES: usize = 16 * 1024 * 1024;
+/// Nor do copies over quicker than this
+const MIN_DURATION: Duration = Duration::from_secs(2);
+
+///
+/// Where the speeds of earlier runs are kept: `decopy/throughput.toml` in `%LOCALAPPDATA%` on
+/// Windows, and in `$XDG_CACHE_HOME` (falling back to `~/.cache`) elsewhere
+///
+pub fn default_path() -> Option<PathBuf> {
+    let var = |name: &str| ::std::env::var_os(name).filter(|value| !value.is_empty());
+    let dir = if cfg!(windows) {
+        var("LOCALAPPDATA").map(PathBuf::from)
+    } else {
+        var("XDG_CACHE_HOME")
+            .map(PathBuf::from)
+            .or_else(|| var("HOME").map(|home| Path::new(&home).join(".cache")))
+    }?;
+    Some(dir.join("decopy").join("throughput.toml"))
+}
+
+///
+/// The speeds drives were copied to at on earlier runs, by `drive::volume_id`, so the ETAs of
+/// the next run are about right from the start instead of after a few noisy seconds
+///
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Calibration {
+    /// Bytes per second
+    #[serde(default)]
+    drives: BTreeMap<String, u64>,
+}
+
+impl Calibration {
+    ///
+    /// The speeds kept at `path`. A cache that is missing or can't be read starts over empty.
+    ///
+    pub fn load(path: &Path) -> Self {
+        ::std::fs::read_to_string(path)
+            .ok()
+            .and_then(|contents| toml::from_str(&contents).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self, path: &Path) -> ::std::io::Result<()> {
+        let contents = toml::to_string(self)
+            .map_err(|e| ::std::io::Error::new(::std::io::ErrorKind::InvalidData, e))?;
+        if let Some(dir) = path.parent() {
+            ::std::fs::create_dir_all(dir)?;
+        }
+        ::std::fs::write(path, contents)
+    }
+
+    ///
+    /// Bytes per second the drive `dest` is on was copied to at before, if it was
+    ///
+    pub fn speed(&self, dest: &Path) -> Option<u64> {
+        self.drives.get(&drive::volume_id(dest)?).copied()
+    }
+
+    ///
+    /// About how long copying `bytes` to `dest` takes at the speed it had before, for the
+    /// confirmation. `None` for a drive not copied to before.
+    ///
+    pub fn estimate(&self, dest: &Path, bytes: u64) -> Option<Duration> {
+        self.speed(dest).map(|speed| estimate(bytes, speed))
+    }
+
+    ///
+    /// Takes note of `bytes` having been copied to `dest` in `duration`. A drive seen before
+    /// meets the new speed halfway, so one odd run doesn't throw it off.
+    ///
+    pub fn record(&mut self, dest: &Path, bytes: usize, duration: Duration) {
+        if bytes < MIN_BYTES || duration < MIN_DURATION {
+            return;
+        }
+        let Some(id) = drive::volume_id(dest) else {
+            return;
+        };
+        let speed = (bytes as f64 / duration.as_secs_f64()) as u64;
+        self.drives
+            .entry(id)
+            .and_modify(|known| *known = (*known + speed) / 2)
+            .or_insert(speed);
+    }
+}
+
+///
+/// How long `bytes` take at `speed` bytes per second, at least a second so it never reads as
+/// nothing
+///
+pub fn estimate(bytes: u64, speed: u64) -> Duration {
+    Duration::from_secs((bytes / speed.max(1)).max(1))
+}
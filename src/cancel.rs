@@ -0,0 +1,55 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+///
+/// Asks a copy to stop, shared between whoever cancels (the UI, Ctrl+C, an embedder) and the
+/// copy itself. Clones share the same switch. A `child` is cancelled along with its parent but
+/// can also be cancelled on its own, so the run has one token and every destination one of its
+/// own under it: cancelling a destination's token stops just that destination, cancelling the
+/// run's stops all of them.
+///
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    switch: Arc<AtomicBool>,
+    parent: Option<Arc<CancellationToken>>,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    ///
+    /// A token that is cancelled when this one is, or on its own
+    ///
+    pub fn child(&self) -> Self {
+        Self {
+            switch: Arc::default(),
+            parent: Some(Arc::new(self.clone())),
+        }
+    }
+
+    pub fn cancel(&self) {
+        self.switch.store(true, Ordering::Relaxed);
+    }
+
+    ///
+    /// Whether this token or one of its parents was cancelled
+    ///
+    pub fn is_cancelled(&self) -> bool {
+        self.switch.load(Ordering::Relaxed)
+            || self
+                .parent
+                .as_ref()
+                .is_some_and(|parent| parent.is_cancelled())
+    }
+
+    ///
+    /// The switch of this token alone, for `interrupt::on_ctrl_c` to set from a signal handler
+    ///
+    pub(crate) fn switch(&self) -> Arc<AtomicBool> {
+        self.switch.clone()
+    }
+}
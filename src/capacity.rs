@@ -0,0 +1,61 @@
+use crate::{locale::Locale, ui::get_bytes_string};
+
+///
+/// How a destination's free space compares with the payload, for the fit margins shown before
+/// copying and `--skip-too-small`. Files already on the destination aren't counted, so a drive
+/// that gets overwritten or resumed may fit better than it looks.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fit {
+    /// The payload fits with this many bytes to spare
+    Spare(u64),
+    /// This many bytes are missing for the payload to fit
+    Short(u64),
+    /// The free space of the destination couldn't be looked up
+    Unknown,
+}
+
+impl Fit {
+    pub fn new(free: Option<u64>, payload: u64) -> Self {
+        match free {
+            Some(free) if free >= payload => Fit::Spare(free - payload),
+            Some(free) => Fit::Short(payload - free),
+            None => Fit::Unknown,
+        }
+    }
+
+    pub fn too_small(&self) -> bool {
+        matches!(self, Fit::Short(_))
+    }
+
+    ///
+    /// The margin as shown next to a destination, e.g. `1.2gb to spare`
+    ///
+    pub fn describe(&self, locale: Locale) -> String {
+        match self {
+            Fit::Spare(spare) => format!("{} to spare", get_bytes_string(*spare as usize, locale)),
+            Fit::Short(short) => format!(
+                "too small, {} short",
+                get_bytes_string(*short as usize, locale)
+            ),
+            Fit::Unknown => "free space unknown".to_string(),
+        }
+    }
+}
+
+///
+/// The payload next to a destination's free space and the margin between them, e.g.
+/// `3.1gb needed / 4.3gb free, 1.2gb to spare`
+///
+pub fn needed_vs_available(payload: u64, free: Option<u64>, locale: Locale) -> String {
+    let needed = get_bytes_string(payload as usize, locale);
+    match free {
+        Some(free) => format!(
+            "{} needed / {} free, {}",
+            needed,
+            get_bytes_string(free as usize, locale),
+            Fit::new(Some(free), payload).describe(locale)
+        ),
+        None => format!("{} needed, {}", needed, Fit::Unknown.describe(locale)),
+    }
+}
@@ -0,0 +1,96 @@
+use std::{
+    io::{Error as IoError, ErrorKind as IoErrorKind},
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+///
+/// Randomly injects I/O errors, stalls and full disks into the copy engine (`--chaos`), so the
+/// failure paths of the UI can be exercised and demoed without sacrificing real hardware.
+///
+/// Runs with the same seed inject the same faults into the same files, as long as the files are
+/// copied in the same order.
+///
+#[derive(Debug)]
+pub struct Chaos {
+    rate: f64,
+    seed: u64,
+    state: AtomicU64,
+}
+
+impl Chaos {
+    ///
+    /// Misbehaves on roughly `rate` (0 to 1) of all file writes
+    ///
+    pub fn new(rate: f64, seed: Option<u64>) -> Self {
+        let seed = seed.unwrap_or_else(|| {
+            ::std::time::SystemTime::now()
+                .duration_since(::std::time::UNIX_EPOCH)
+                .map_or(0, |d| d.as_nanos() as u64)
+                ^ ::std::process::id() as u64
+        });
+        Self {
+            rate: rate.clamp(0., 1.),
+            seed,
+            state: AtomicU64::new(seed),
+        }
+    }
+
+    pub fn rate(&self) -> f64 {
+        self.rate
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    ///
+    /// Called before a file is written. Either does nothing, sleeps for a while, or fails with
+    /// one of the errors a real drive produces.
+    ///
+    pub fn before_write(&self) -> ::std::io::Result<()> {
+        if self.next_f64() >= self.rate {
+            return Ok(());
+        }
+
+        match self.next() % 4 {
+            0 => {
+                ::std::thread::sleep(Duration::from_millis(500 + self.next() % 1500));
+                Ok(())
+            }
+            1 => Err(IoError::new(
+                IoErrorKind::StorageFull,
+                "no space left on device (chaos)",
+            )),
+            2 => Err(device_not_ready()),
+            _ => Err(IoError::new(
+                IoErrorKind::PermissionDenied,
+                "access denied (chaos)",
+            )),
+        }
+    }
+
+    /// splitmix64, good enough for picking faults and shared safely between writer threads
+    fn next(&self) -> u64 {
+        let mut z = self
+            .state
+            .fetch_add(0x9e37_79b9_7f4a_7c15, Ordering::Relaxed)
+            .wrapping_add(0x9e37_79b9_7f4a_7c15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    fn next_f64(&self) -> f64 {
+        (self.next() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+fn device_not_ready() -> IoError {
+    #[cfg(windows)]
+    return IoError::from_raw_os_error(21); // ERROR_NOT_READY
+    #[cfg(unix)]
+    return IoError::from_raw_os_error(libc::EIO);
+    #[cfg(not(any(windows, unix)))]
+    return IoError::other("device not ready (chaos)");
+}
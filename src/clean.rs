@@ -0,0 +1,89 @@
+use glob::Pattern;
+use serde::{Deserialize, Serialize, Serializer};
+use std::{
+    fmt,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use crate::{
+    error::CopyError,
+    walk::{walk_ahead, Entry, Selection, Source},
+};
+
+///
+/// A cleanup rule (`--clean-dest-glob`), e.g. `*.tmp` or `*/.lock`. Matched against each copied
+/// file's path relative to the destination, where `*` also matches across directories.
+///
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct CleanGlob(Pattern);
+
+impl CleanGlob {
+    ///
+    /// Names that aren't valid Unicode are matched in their lossy form rather than never matching
+    ///
+    pub fn matches(&self, path: &Path) -> bool {
+        self.0.matches(&path.to_string_lossy())
+    }
+}
+
+impl FromStr for CleanGlob {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Pattern::new(s)
+            .map(CleanGlob)
+            .map_err(|e| format!("invalid glob `{}`: {}", s, e.msg))
+    }
+}
+
+impl fmt::Display for CleanGlob {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0.as_str())
+    }
+}
+
+impl Serialize for CleanGlob {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl TryFrom<String> for CleanGlob {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+///
+/// Deletes every file of `source` matching one of `globs` from `dest`, returning what was
+/// removed. Only files that came from the source, as far as `selection` goes, are touched,
+/// anything else on the drive is left alone.
+///
+pub fn clean_destination(
+    source: &Source,
+    selection: &Selection,
+    dest: &Path,
+    globs: &[CleanGlob],
+) -> Result<Vec<PathBuf>, CopyError> {
+    let mut removed = Vec::new();
+    for entry in walk_ahead(selection.walk(source)) {
+        let Entry::File(file, _) = entry.map_err(|e| CopyError::new(&source.name(), None, e))?
+        else {
+            continue;
+        };
+        if !globs.iter().any(|glob| glob.matches(&file)) {
+            continue;
+        }
+        match ::std::fs::remove_file(dest.join(&file)) {
+            Ok(()) => removed.push(file),
+            Err(e) if e.kind() == ErrorKind::NotFound => {}
+            Err(e) => return Err(CopyError::new(&file, Some(dest), e)),
+        }
+    }
+    Ok(removed)
+}
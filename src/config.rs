@@ -0,0 +1,429 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    clean::CleanGlob,
+    copy::{ChangingFiles, Incremental},
+    download::Checksum,
+    keys::KeyBindings,
+    offload::Engine,
+    overwrite::Overwrite,
+    preserve::Preserve,
+    sound::Sounds,
+    start::Delay,
+    symlink::SymlinkPolicy,
+    throttle::{LimitRate, LimitSchedule},
+    ui::Theme,
+    verify::MetadataCheck,
+    walk::Collision,
+    Args,
+};
+
+/// Where profiles are saved when no `--config` is given and there is no per-user config directory
+pub const DEFAULT_CONFIG: &str = "decopy.toml";
+
+///
+/// The per-user config file, read when no `--config` is given: `decopy/config.toml` in
+/// `%APPDATA%` on Windows, and in `$XDG_CONFIG_HOME` (falling back to `~/.config`) elsewhere
+///
+pub fn default_path() -> Option<PathBuf> {
+    let var = |name: &str| ::std::env::var_os(name).filter(|value| !value.is_empty());
+    let dir = if cfg!(windows) {
+        var("APPDATA").map(PathBuf::from)
+    } else {
+        var("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| var("HOME").map(|home| Path::new(&home).join(".config")))
+    }?;
+    Some(dir.join("decopy").join("config.toml"))
+}
+
+///
+/// Options that can be kept in a TOML config file instead of being retyped on every run. Every
+/// key is optional and anything given on the command line takes precedence.
+///
+/// ```toml
+/// copy_from = "build/release"
+/// from = ["config/app.conf"]
+/// drives = ["E:\\", "F:\\"]
+/// yes = true
+///
+/// [groups]
+/// lineA = ["E:\\", "F:\\"]
+///
+/// [keys]
+/// confirm = "j"
+///
+/// [sounds]
+/// verify_failed = "bell*3"
+///
+/// [profiles.nightly]
+/// verify = true
+/// exclude = ["docs"]
+/// ```
+///
+/// A profile, picked with `--profile`, takes precedence over the keys at the top level.
+///
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    pub copy_from: Option<PathBuf>,
+    pub from: Option<Vec<PathBuf>>,
+    pub collision: Option<Collision>,
+    pub source_url: Option<String>,
+    pub source_sha256: Option<Checksum>,
+    pub allow_unchecked_source: Option<bool>,
+    pub drives: Option<Vec<PathBuf>>,
+    pub yes: Option<bool>,
+    pub nice_io: Option<bool>,
+    pub verify: Option<bool>,
+    pub repair: Option<bool>,
+    pub verify_metadata: Option<Vec<MetadataCheck>>,
+    pub sha256sums: Option<bool>,
+    pub skip_too_small: Option<bool>,
+    pub refuse_too_small: Option<bool>,
+    pub incremental: Option<Incremental>,
+    pub skip_identical: Option<bool>,
+    pub overwrite: Option<Overwrite>,
+    pub preserve: Option<Vec<Preserve>>,
+    pub mirror: Option<bool>,
+    pub transactional: Option<bool>,
+    pub drive_log: Option<bool>,
+    pub jobs: Option<u16>,
+    pub threads: Option<u16>,
+    pub max_open_files: Option<u32>,
+    pub fan_out: Option<bool>,
+    pub eject: Option<bool>,
+    pub sync: Option<bool>,
+    pub beep: Option<bool>,
+    pub check_updates: Option<bool>,
+    pub theme: Option<Theme>,
+    pub clean_dest_globs: Option<Vec<CleanGlob>>,
+    pub summary_csv: Option<PathBuf>,
+    pub report: Option<PathBuf>,
+    pub limit_rate: Option<LimitRate>,
+    pub limit_schedule: Option<LimitSchedule>,
+    pub max_retries: Option<u32>,
+    pub retry_delay: Option<Delay>,
+    pub keep_going: Option<bool>,
+    pub changing_files: Option<ChangingFiles>,
+    pub engine: Option<Engine>,
+    pub remove_partial: Option<bool>,
+    pub exclude: Option<Vec<PathBuf>>,
+    pub include: Option<Vec<PathBuf>>,
+    pub respect_gitignore: Option<bool>,
+    pub force_include: Option<Vec<PathBuf>>,
+    pub symlinks: Option<SymlinkPolicy>,
+    pub keys: Option<KeyBindings>,
+    pub sounds: Option<Sounds>,
+    pub groups: Option<BTreeMap<String, Vec<PathBuf>>>,
+    pub profiles: Option<BTreeMap<String, Config>>,
+}
+
+#[derive(Debug)]
+pub struct ConfigError {
+    pub path: PathBuf,
+    pub message: String,
+    pub suggestion: Option<String>,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "invalid config file `{}`", self.path.display())?;
+        write!(f, "{}", self.message.trim_end())?;
+        if let Some(suggestion) = &self.suggestion {
+            write!(f, "\n\nhelp: {}", suggestion)?;
+        }
+        Ok(())
+    }
+}
+
+impl ::std::error::Error for ConfigError {}
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Self, ConfigError> {
+        let contents = ::std::fs::read_to_string(path).map_err(|e| ConfigError {
+            path: path.to_path_buf(),
+            message: e.to_string(),
+            suggestion: None,
+        })?;
+
+        Self::parse(&contents).map_err(|mut e| {
+            e.path = path.to_path_buf();
+            e
+        })
+    }
+
+    ///
+    /// `load`, with the keys of `profile` on top when one is given
+    ///
+    pub fn load_profile(path: &Path, profile: Option<&str>) -> Result<Self, ConfigError> {
+        let config = Self::load(path)?;
+        let Some(name) = profile else {
+            return Ok(config);
+        };
+        config.with_profile(name).map_err(|mut e| {
+            e.path = path.to_path_buf();
+            e
+        })
+    }
+
+    ///
+    /// The options of `args` a config file can hold, as a profile to save with `save_profile`.
+    /// `exclude` replaces `args.exclude`, for exclusions picked on the PreCopy screen.
+    ///
+    pub fn from_args(args: &Args, exclude: Vec<PathBuf>) -> Self {
+        let groups = args
+            .groups
+            .iter()
+            .map(|group| (group.name.clone(), group.destinations.clone()))
+            .collect::<BTreeMap<_, _>>();
+        Self {
+            copy_from: args.copy_from.clone(),
+            from: Some(args.from.clone()),
+            collision: Some(args.collision),
+            source_url: args.source_url.clone(),
+            source_sha256: args.source_sha256.clone(),
+            allow_unchecked_source: Some(args.allow_unchecked_source),
+            drives: Some(args.drives.clone()),
+            yes: Some(args.yes),
+            nice_io: Some(args.nice_io),
+            verify: Some(args.verify),
+            repair: Some(args.repair),
+            verify_metadata: Some(args.verify_metadata.clone()),
+            sha256sums: Some(args.sha256sums),
+            skip_too_small: Some(args.skip_too_small),
+            refuse_too_small: Some(args.refuse_too_small),
+            incremental: args.incremental,
+            skip_identical: Some(args.skip_identical),
+            overwrite: Some(args.overwrite),
+            preserve: Some(args.preserve.clone()),
+            mirror: Some(args.mirror),
+            transactional: Some(args.transactional),
+            drive_log: Some(args.drive_log),
+            jobs: Some(args.jobs),
+            threads: args.threads,
+            max_open_files: args.max_open_files,
+            fan_out: Some(args.fan_out),
+            eject: Some(args.eject),
+            sync: Some(args.sync),
+            beep: Some(args.beep),
+            check_updates: Some(args.check_updates),
+            theme: Some(args.theme),
+            clean_dest_globs: Some(args.clean_dest_globs.clone()),
+            summary_csv: args.summary_csv.clone(),
+            report: args.report.clone(),
+            limit_rate: args.limit_rate,
+            limit_schedule: args.limit_schedule.clone(),
+            max_retries: Some(args.max_retries),
+            retry_delay: Some(args.retry_delay),
+            keep_going: Some(args.keep_going),
+            changing_files: Some(args.changing_files),
+            engine: Some(args.engine),
+            remove_partial: Some(args.remove_partial),
+            exclude: Some(exclude),
+            include: Some(args.include.clone()),
+            respect_gitignore: Some(args.respect_gitignore),
+            force_include: Some(args.force_include.clone()),
+            symlinks: Some(args.symlinks),
+            keys: (args.keys != KeyBindings::default()).then_some(args.keys),
+            sounds: (args.sounds != Sounds::default()).then(|| args.sounds.clone()),
+            groups: (!groups.is_empty()).then_some(groups),
+            profiles: None,
+        }
+    }
+
+    ///
+    /// The config with the keys of profile `name` taking precedence over the top level ones
+    ///
+    pub fn with_profile(mut self, name: &str) -> Result<Self, ConfigError> {
+        let mut profiles = self.profiles.take().unwrap_or_default();
+        let Some(profile) = profiles.remove(name) else {
+            let suggestion = profiles
+                .keys()
+                .map(|key| (key, strsim::jaro_winkler(name, key)))
+                .filter(|(_, score)| *score > 0.8)
+                .max_by(|(_, a), (_, b)| a.total_cmp(b))
+                .map(|(key, _)| format!("did you mean `{}`?", key));
+            return Err(ConfigError {
+                path: PathBuf::new(),
+                message: format!("no profile named `{}`", name),
+                suggestion,
+            });
+        };
+
+        Ok(Self {
+            copy_from: profile.copy_from.or(self.copy_from),
+            from: profile.from.or(self.from),
+            collision: profile.collision.or(self.collision),
+            source_url: profile.source_url.or(self.source_url),
+            source_sha256: profile.source_sha256.or(self.source_sha256),
+            allow_unchecked_source: profile
+                .allow_unchecked_source
+                .or(self.allow_unchecked_source),
+            drives: profile.drives.or(self.drives),
+            yes: profile.yes.or(self.yes),
+            nice_io: profile.nice_io.or(self.nice_io),
+            verify: profile.verify.or(self.verify),
+            repair: profile.repair.or(self.repair),
+            verify_metadata: profile.verify_metadata.or(self.verify_metadata),
+            sha256sums: profile.sha256sums.or(self.sha256sums),
+            skip_too_small: profile.skip_too_small.or(self.skip_too_small),
+            refuse_too_small: profile.refuse_too_small.or(self.refuse_too_small),
+            incremental: profile.incremental.or(self.incremental),
+            skip_identical: profile.skip_identical.or(self.skip_identical),
+            overwrite: profile.overwrite.or(self.overwrite),
+            preserve: profile.preserve.or(self.preserve),
+            mirror: profile.mirror.or(self.mirror),
+            transactional: profile.transactional.or(self.transactional),
+            drive_log: profile.drive_log.or(self.drive_log),
+            jobs: profile.jobs.or(self.jobs),
+            threads: profile.threads.or(self.threads),
+            max_open_files: profile.max_open_files.or(self.max_open_files),
+            fan_out: profile.fan_out.or(self.fan_out),
+            eject: profile.eject.or(self.eject),
+            sync: profile.sync.or(self.sync),
+            beep: profile.beep.or(self.beep),
+            check_updates: profile.check_updates.or(self.check_updates),
+            theme: profile.theme.or(self.theme),
+            clean_dest_globs: profile.clean_dest_globs.or(self.clean_dest_globs),
+            summary_csv: profile.summary_csv.or(self.summary_csv),
+            report: profile.report.or(self.report),
+            limit_rate: profile.limit_rate.or(self.limit_rate),
+            limit_schedule: profile.limit_schedule.or(self.limit_schedule),
+            max_retries: profile.max_retries.or(self.max_retries),
+            retry_delay: profile.retry_delay.or(self.retry_delay),
+            keep_going: profile.keep_going.or(self.keep_going),
+            changing_files: profile.changing_files.or(self.changing_files),
+            engine: profile.engine.or(self.engine),
+            remove_partial: profile.remove_partial.or(self.remove_partial),
+            exclude: profile.exclude.or(self.exclude),
+            include: profile.include.or(self.include),
+            respect_gitignore: profile.respect_gitignore.or(self.respect_gitignore),
+            force_include: profile.force_include.or(self.force_include),
+            symlinks: profile.symlinks.or(self.symlinks),
+            keys: profile.keys.or(self.keys),
+            sounds: profile.sounds.or(self.sounds),
+            groups: profile.groups.or(self.groups),
+            profiles: None,
+        })
+    }
+
+    ///
+    /// Writes `config` as a new config file at `path`, creating its directory
+    ///
+    pub fn create(path: &Path, config: &Config) -> Result<(), ConfigError> {
+        let error = |message: String| ConfigError {
+            path: path.to_path_buf(),
+            message,
+            suggestion: None,
+        };
+        let contents = toml::to_string(config).map_err(|e| error(e.to_string()))?;
+        if let Some(dir) = path.parent() {
+            ::std::fs::create_dir_all(dir).map_err(|e| error(e.to_string()))?;
+        }
+        ::std::fs::write(
+            path,
+            format!(
+                "# decopy settings, named as in `decopy --help` with `_` for `-`\n# Options for one \
+                 run or one machine, such as `dry_run`, `delay`, `state_file`, `locale` or \
+                 `copy_buffer`, can't be set here\n{}",
+                contents
+            ),
+        )
+        .map_err(|e| error(e.to_string()))
+    }
+
+    ///
+    /// Writes `profile` to the config file at `path` as `[profiles.<name>]`, replacing a profile of
+    /// the same name. Everything else in the file, comments included, is kept as it is; a missing
+    /// file is created.
+    ///
+    pub fn save_profile(path: &Path, name: &str, profile: &Config) -> Result<(), ConfigError> {
+        let error = |message: String| ConfigError {
+            path: path.to_path_buf(),
+            message,
+            suggestion: None,
+        };
+        let contents = match ::std::fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == ::std::io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(error(e.to_string())),
+        };
+        let mut document = contents
+            .parse::<toml_edit::DocumentMut>()
+            .map_err(|e| error(e.to_string()))?;
+        let profile = toml::to_string(profile)
+            .map_err(|e| error(e.to_string()))?
+            .parse::<toml_edit::DocumentMut>()
+            .map_err(|e| error(e.to_string()))?;
+
+        let profiles = document
+            .entry("profiles")
+            .or_insert(toml_edit::table())
+            .as_table_mut()
+            .ok_or_else(|| error("`profiles` is not a table".to_string()))?;
+        profiles.set_implicit(true);
+        profiles.insert(name, toml_edit::Item::Table(profile.as_table().clone()));
+
+        if let Some(dir) = path.parent() {
+            ::std::fs::create_dir_all(dir).map_err(|e| error(e.to_string()))?;
+        }
+        let mut tmp = path.as_os_str().to_owned();
+        tmp.push(".tmp");
+        ::std::fs::write(&tmp, document.to_string())
+            .and_then(|()| ::std::fs::rename(&tmp, path))
+            .map_err(|e| error(e.to_string()))
+    }
+
+    pub fn parse(contents: &str) -> Result<Self, ConfigError> {
+        let config: Self = toml::from_str(contents).map_err(|e: toml::de::Error| {
+            let message = e.to_string();
+            ConfigError {
+                suggestion: suggest_key(e.message()),
+                path: PathBuf::new(),
+                message,
+            }
+        })?;
+
+        // Clashing keys parse fine but would leave an action out of reach in the UI
+        let profiles = config
+            .profiles
+            .iter()
+            .flat_map(|profiles| profiles.values());
+        for keys in Some(&config)
+            .into_iter()
+            .chain(profiles)
+            .flat_map(|c| c.keys)
+        {
+            keys.check().map_err(|message| ConfigError {
+                path: PathBuf::new(),
+                message: format!("in [keys]: {}", message),
+                suggestion: Some("give every action a key of its own".to_string()),
+            })?;
+        }
+        Ok(config)
+    }
+}
+
+///
+/// Turns serde's "unknown field `drivs`, expected one of `copy_from`, `drives`" into a
+/// "did you mean `drives`?" hint when one of the expected keys is a close match
+///
+fn suggest_key(message: &str) -> Option<String> {
+    let unknown = message.strip_prefix("unknown field `")?.split('`').next()?;
+    let (_, expected) = message.split_once("expected ")?;
+
+    expected
+        .split('`')
+        .skip(1)
+        .step_by(2)
+        .map(|key| (key, strsim::jaro_winkler(unknown, key)))
+        .filter(|(_, score)| *score > 0.8)
+        .max_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map(|(key, _)| format!("did you mean `{}`?", key))
+}
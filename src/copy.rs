@@ -1,56 +1,2230 @@
-use std::path::PathBuf;
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashSet},
+    fmt,
+    fs::{File, OpenOptions},
+    io::{Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        mpsc::channel,
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
 
-use fs_extra::dir::{copy_with_progress, get_size, CopyOptions};
+use crate::fanout::{copy_fan_out, read_tree, FanOut, CHUNK_SIZE};
 
-use crate::Args;
+use crate::{
+    antivirus,
+    cancel::CancellationToken,
+    chaos::Chaos,
+    clean::{clean_destination, CleanGlob},
+    counters::{Counters, Counts},
+    drive,
+    drive_log::{DriveLog, DRIVE_LOG},
+    dry_run::DryRun,
+    error::{cancelled, is_transient, CopyError, ErrorClass},
+    hash::{sha256_file, HashPool},
+    hook::{DeploymentHook, FileProgress},
+    longpath,
+    manifest::{Manifest, SHA256SUMS},
+    mirror::{self, remove_stale},
+    offload::{self, Engine},
+    overwrite::{Conflict, Conflicts, FileVersion, Overwrite},
+    preserve::{make_writable, Kept, Preserve},
+    simulate::{SimulatedDrive, Simulation},
+    state::{Checkpoint, PartialFile},
+    symlink::{Symlink, SymlinkPolicy},
+    tar::{read_tar, StdinFormat},
+    throttle::Throttle,
+    transaction,
+    verify::{compare_files, MetadataCheck, Verification, VerifyPool},
+    walk::{
+        prescan, total_bytes, walk_ahead, Entry, NameCollision, Prescan, Selection, Source, Totals,
+        Walk,
+    },
+    Args,
+};
+
+/// How far modification times may drift and still count as the same, FAT keeps them to 2 seconds
+pub(crate) const MTIME_TOLERANCE: Duration = Duration::from_secs(2);
+/// The longest a retry waits, however many came before it
+const MAX_RETRY_WAIT: Duration = Duration::from_secs(60);
+/// The smallest `--copy-buffer`, smaller ones would spend the copy on progress events
+pub const MIN_COPY_BUFFER: usize = 4 * 1024;
+
+///
+/// How `--incremental` tells that a file on a destination is still the same as in the source
+///
+#[derive(ValueEnum, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Incremental {
+    /// Same size and modification time. Copies get the source's modification time for this.
+    #[default]
+    Mtime,
+    /// Same size and SHA-256, reading both files. Slower, but doesn't trust timestamps.
+    Hash,
+}
+
+impl Incremental {
+    ///
+    /// Whether `dest` is still the same as `source` and can be left as it is
+    ///
+    pub fn unchanged(self, source: &Path, dest: &Path) -> bool {
+        let (Ok(source_meta), Ok(dest_meta)) =
+            (::std::fs::metadata(source), ::std::fs::metadata(dest))
+        else {
+            return false;
+        };
+        if source_meta.len() != dest_meta.len() {
+            return false;
+        }
+        match self {
+            Incremental::Mtime => match (source_meta.modified(), dest_meta.modified()) {
+                (Ok(source), Ok(dest)) => {
+                    let drift = match source > dest {
+                        true => source.duration_since(dest),
+                        false => dest.duration_since(source),
+                    };
+                    drift.is_ok_and(|drift| drift <= MTIME_TOLERANCE)
+                }
+                _ => false,
+            },
+            Incremental::Hash => match (sha256_file(source), sha256_file(dest)) {
+                (Ok(source), Ok(dest)) => source == dest,
+                _ => false,
+            },
+        }
+    }
+}
+
+///
+/// What happens to a source file that changes size while it is read, e.g. a log file still being
+/// written to (`--changing-files`)
+///
+#[derive(ValueEnum, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangingFiles {
+    /// Read it once more, and leave it out when it changes again
+    #[default]
+    Retry,
+    /// Leave it out right away
+    Skip,
+}
+
+///
+/// How often `CopyQueue::start_copy` reports progress to `onpercentage` and
+/// `DeploymentHook::on_file_progress`, see `CopyQueue::set_progress_granularity`. A UI that is
+/// slow to redraw can do with fewer reports than one that shows every file as it goes. The
+/// checkpoint for `--resume` follows every chunk either way.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ProgressGranularity {
+    /// When a file is started and after every buffer of it is written (`--copy-buffer`, chunks
+    /// with `--fan-out`)
+    #[default]
+    PerChunk,
+    /// Once for every file, when it is done
+    PerFile,
+    /// Whenever a destination got at least this many more bytes since it was last reported,
+    /// and once more with the rest when it is done
+    EveryNBytes(u64),
+}
+
+impl ProgressGranularity {
+    /// Whether a destination that got `unreported` bytes since it was last reported is
+    /// reported again, after a chunk or once a file is `done`
+    fn reports(self, unreported: usize, done: bool) -> bool {
+        match self {
+            ProgressGranularity::PerChunk => !done,
+            ProgressGranularity::PerFile => done,
+            ProgressGranularity::EveryNBytes(bytes) => unreported as u64 >= bytes.max(1),
+        }
+    }
+}
+
+///
+/// What a run wrote to a single destination
+///
+#[derive(Debug, Clone)]
+pub struct DestinationSummary {
+    pub destination: PathBuf,
+    /// Bytes written during this run, excluding anything skipped by `--resume` or `--incremental`
+    pub bytes_copied: usize,
+    pub duration: Duration,
+    /// The files that could not be copied, which `--keep-going` went on without
+    pub failed: Vec<FailedFile>,
+    /// Source files left out because they changed size while they were read, see
+    /// `--changing-files`
+    pub changed: Vec<PathBuf>,
+    /// The writes looked held up by a virus scanner, see `antivirus::suspected`
+    pub scanned: bool,
+    /// The operator cancelled the run before the destination was done, `bytes_copied` are the
+    /// files that made it in full
+    pub cancelled: bool,
+}
+
+///
+/// A file that could not be copied to a destination, see `--keep-going`
+///
+#[derive(Debug, Clone)]
+pub struct FailedFile {
+    /// Relative to the source, or the destination itself when it couldn't be written at all
+    pub file: PathBuf,
+    pub error: String,
+    pub class: ErrorClass,
+}
+
+impl From<&CopyError> for FailedFile {
+    fn from(e: &CopyError) -> Self {
+        Self {
+            file: e.path.clone(),
+            error: e.error.to_string(),
+            class: e.class(),
+        }
+    }
+}
+
+impl fmt::Display for FailedFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} ({} [{}])",
+            self.file.display(),
+            self.error,
+            self.class.code()
+        )
+    }
+}
 
-#[derive(Clone)]
 pub struct CopyQueue {
-    source: PathBuf,
+    source: Source,
     destinations: Vec<PathBuf>,
+    hooks: Vec<Box<dyn DeploymentHook>>,
+    state_file: PathBuf,
+    resume: bool,
+    hash_threads: Option<usize>,
+    /// Write the manifest into every destination after copying (`--sha256sums`)
+    sha256sums: bool,
+    source_hashes: BTreeMap<PathBuf, String>,
+    /// How many destinations are copied at once (`--jobs`, see `Args::schedule`)
+    jobs: usize,
+    fan_out: bool,
+    fan_out_queue_chunks: usize,
+    chaos: Option<Chaos>,
+    simulation: Option<Simulation>,
+    counters: Counters,
+    /// Shared with the UI, which shows the limit in effect
+    throttle: Option<Arc<Throttle>>,
+    clean_globs: Vec<CleanGlob>,
+    /// What of the source this run covers
+    selection: Selection,
+    /// Leave files the destination already has alone (`--incremental`, `--skip-identical`)
+    incremental: Option<Incremental>,
+    /// What to do with the other files the destination already has (`--overwrite`)
+    overwrite: Overwrite,
+    /// The `--overwrite prompt` questions, see `conflicts`
+    conflicts: Arc<Conflicts>,
+    /// What of the source files' metadata their copies get (`--preserve`)
+    preserve: Vec<Preserve>,
+    /// What `--verify` compares besides the contents, see `Args::metadata_checks`
+    metadata_checks: Vec<MetadataCheck>,
+    /// Delete what the source doesn't have from every destination before copying (`--mirror`)
+    mirror: bool,
+    /// Keep a `deployment.log` on every destination (`--drive-log`)
+    drive_log: bool,
+    /// Read the source from stdin in this format instead of from the `source` directory
+    stdin: Option<StdinFormat>,
+    /// The files the last `start_copy` read from stdin, for `start_verify`
+    streamed: Vec<Entry>,
+    /// The links the last `start_copy` recreated that point outside the source, with where
+    outside_links: BTreeMap<PathBuf, PathBuf>,
+    /// Holds copying between reads while set, see `pause_switch`
+    paused: Arc<AtomicBool>,
+    /// How often and after how long a file is tried again after a transient error
+    retries: Retries,
+    /// Go on with the other files and destinations when a file can't be copied (`--keep-going`)
+    keep_going: bool,
+    /// Stops copying at the next chunk once cancelled, see `cancel_token`
+    cancelled: CancellationToken,
+    /// One child of `cancelled` for every destination, see `destination_cancel_token`
+    destination_cancels: Vec<CancellationToken>,
+    /// Delete the file a cancelled copy was writing instead of keeping it for `--resume`
+    /// (`--remove-partial`)
+    remove_partial: bool,
+    /// Write into a staging directory on every destination and only swap it in once the run
+    /// went through (`--transactional`)
+    transactional: bool,
+    /// What happens to source files that change size while they are read (`--changing-files`)
+    changing: ChangingFiles,
+    /// See `IoHooks::buffer`
+    copy_buffer: usize,
+    /// How files are copied (`--engine`)
+    engine: Engine,
+    /// How often `start_copy` reports progress
+    progress_granularity: ProgressGranularity,
+    /// The files the last `start_copy` left out of each destination for changing, which
+    /// `start_verify` doesn't hold against it
+    changed: Vec<Vec<PathBuf>>,
+    /// How many destinations are verified at once (`--threads`, see `Args::schedule`)
+    threads: usize,
+    /// Start verifying the destinations `start_copy` is done with while others are still being
+    /// copied, for `start_verify` to pick up (`--verify`)
+    verify_early: bool,
+    /// The verifications `start_copy` started, see `verify_early`
+    verifying: Mutex<Option<VerifyPool>>,
 }
 
 impl From<&Args> for CopyQueue {
     fn from(a: &Args) -> Self {
+        let schedule = a.schedule();
+        let cancelled = CancellationToken::new();
+        let destination_cancels = a.drives.iter().map(|_| cancelled.child()).collect();
         Self {
-            source: a.copy_from.clone(),
+            source: a
+                .source()
+                .expect("source is checked before the queue is built")
+                .extended(),
             destinations: a.drives.clone(),
+            hooks: match a.drive_log {
+                true => vec![Box::new(DriveLog::new(
+                    a.copy_from.as_deref().unwrap_or(Path::new("")),
+                ))],
+                false => Vec::new(),
+            },
+            state_file: a.state_file.clone(),
+            resume: a.resume,
+            hash_threads: (a.verify || a.qr || a.sha256sums).then_some(schedule.threads),
+            sha256sums: a.sha256sums,
+            source_hashes: BTreeMap::new(),
+            jobs: schedule.jobs,
+            fan_out: a.fan_out,
+            fan_out_queue_chunks: (a.pipeline_buffer.0 as usize / CHUNK_SIZE).max(1),
+            chaos: a.chaos.map(|rate| Chaos::new(rate, a.chaos_seed)),
+            simulation: a.simulate_speed.map(Simulation::new),
+            counters: Counters::default(),
+            throttle: (a.limit_rate.is_some() || a.limit_schedule.is_some())
+                .then(|| Arc::new(Throttle::new(a.limit_rate, a.limit_schedule.clone()))),
+            clean_globs: a.clean_dest_globs.clone(),
+            selection: a.selection(),
+            incremental: match a.skip_identical {
+                true => Some(Incremental::Hash),
+                false => a.incremental,
+            },
+            overwrite: a.overwrite,
+            conflicts: Arc::new(Conflicts::default()),
+            preserve: a.preserve.clone(),
+            metadata_checks: a.metadata_checks(),
+            mirror: a.mirror,
+            drive_log: a.drive_log,
+            stdin: a.stdin_format,
+            streamed: Vec::new(),
+            outside_links: BTreeMap::new(),
+            paused: Arc::new(AtomicBool::new(false)),
+            retries: Retries {
+                max: a.max_retries,
+                delay: a.retry_delay.0,
+            },
+            keep_going: a.keep_going,
+            cancelled,
+            destination_cancels,
+            remove_partial: a.remove_partial,
+            transactional: a.transactional,
+            changing: a.changing_files,
+            copy_buffer: a.copy_buffer(),
+            engine: a.engine,
+            progress_granularity: ProgressGranularity::default(),
+            changed: Vec::new(),
+            threads: schedule.threads,
+            verify_early: a.verify,
+            verifying: Mutex::new(None),
         }
     }
 }
 
 impl CopyQueue {
+    ///
+    /// Registers a hook that will be notified of every step of the deployment
+    ///
+    pub fn register_hook(&mut self, hook: Box<dyn DeploymentHook>) {
+        self.hooks.push(hook);
+    }
+
+    pub fn destinations(&self) -> &[PathBuf] {
+        &self.destinations
+    }
+
+    ///
+    /// Whether the destinations are written through a staging directory (`--transactional`)
+    ///
+    pub fn transactional(&self) -> bool {
+        self.transactional
+    }
+
+    ///
+    /// Where the files for `dest` are written: its staging directory under `--transactional`,
+    /// otherwise `dest` itself
+    ///
+    fn target(&self, dest: &Path) -> PathBuf {
+        longpath::extended(&match self.transactional {
+            true => transaction::staging(dest),
+            false => dest.to_path_buf(),
+        })
+    }
+
+    pub fn chaos(&self) -> Option<&Chaos> {
+        self.chaos.as_ref()
+    }
+
+    pub fn simulation(&self) -> Option<&Simulation> {
+        self.simulation.as_ref()
+    }
+
+    ///
+    /// What the copy engine did so far, for `-vv` and the `--report`
+    ///
+    pub fn counters(&self) -> Counts {
+        self.counters.counts()
+    }
+
+    pub fn throttle(&self) -> Option<&Arc<Throttle>> {
+        self.throttle.as_ref()
+    }
+
+    ///
+    /// Whether the source is read from stdin (`--stdin-format`), so its size isn't known
+    ///
+    pub fn streaming(&self) -> bool {
+        self.stdin.is_some()
+    }
+
+    ///
+    /// Whether several destinations are written at the same time, with `--fan-out` or `--jobs`
+    ///
+    pub fn concurrent(&self) -> bool {
+        self.destinations.len() > 1 && (self.fan_out || self.jobs > 1)
+    }
+
+    ///
+    /// Leaves `excluded` (relative to the source, with everything below them) out of the copy,
+    /// the verification and the cleanup
+    ///
+    pub fn exclude(&mut self, excluded: Vec<PathBuf>) {
+        self.selection.excluded = excluded;
+    }
+
+    ///
+    /// Leaves `skipped` out of the destinations, for `--skip-too-small`
+    ///
+    pub fn skip_destinations(&mut self, skipped: &[PathBuf]) {
+        let kept = ::std::mem::take(&mut self.destinations)
+            .into_iter()
+            .zip(::std::mem::take(&mut self.destination_cancels))
+            .filter(|(dest, _)| !skipped.contains(dest));
+        (self.destinations, self.destination_cancels) = kept.unzip();
+    }
+
+    ///
+    /// What the run goes through on both sides: the selection, and with `--drive-log` without
+    /// the `deployment.log` every destination keeps of its own
+    ///
+    fn selected(&self) -> Selection {
+        self.selection.with_excluded(mirror::kept(
+            &self.selection.excluded,
+            false,
+            self.drive_log,
+        ))
+    }
+
+    ///
+    /// Walks what was selected of the source
+    ///
+    fn walk(&self) -> Walk {
+        self.selected().walk(&self.source)
+    }
+
+    ///
+    /// Hashes every source file on a pool of `threads` workers while copying, reporting the
+    /// results through `DeploymentHook::on_file_hashed`
+    ///
+    pub fn enable_hashing(&mut self, threads: usize) {
+        self.hash_threads = Some(threads);
+    }
+
+    ///
+    /// Sets how often `start_copy` reports progress, after every chunk unless set
+    ///
+    pub fn set_progress_granularity(&mut self, granularity: ProgressGranularity) {
+        self.progress_granularity = granularity;
+    }
+
+    ///
+    /// The hashes collected during the last `start_copy`, empty unless hashing was enabled
+    ///
+    pub fn manifest(&self) -> Manifest {
+        Manifest::new(self.source_hashes.clone())
+    }
+
+    ///
+    /// Copying and repairing hold between reads for as long as the returned switch is set, e.g.
+    /// from the UI on another thread
+    ///
+    pub fn pause_switch(&self) -> Arc<AtomicBool> {
+        self.paused.clone()
+    }
+
+    ///
+    /// Copying stops at the next chunk once the returned token is cancelled, e.g. on Ctrl+C. The
+    /// destinations that weren't done by then come back from `start_copy` marked as cancelled.
+    ///
+    pub fn cancel_token(&self) -> CancellationToken {
+        self.cancelled.clone()
+    }
+
+    ///
+    /// Copying to destination number `dest` alone stops at the next chunk once the returned
+    /// token is cancelled, the others carry on. It comes back from `start_copy` marked as
+    /// cancelled, which makes the run a cancelled one for `--resume` to pick up.
+    ///
+    pub fn destination_cancel_token(&self, dest: usize) -> Option<CancellationToken> {
+        self.destination_cancels.get(dest).cloned()
+    }
+
+    ///
+    /// Under `--overwrite prompt` copying waits on the returned questions for every file a
+    /// destination already has, until someone answers them, e.g. the UI
+    ///
+    pub fn conflicts(&self) -> Arc<Conflicts> {
+        self.conflicts.clone()
+    }
+
+    ///
+    /// The top level names more than one source has, see `--collision`
+    ///
+    pub fn collisions(&self) -> &[NameCollision] {
+        self.source.collisions()
+    }
+
+    ///
+    /// The links the last `start_copy` recreated with `--symlinks preserve` that point outside
+    /// the source, with where they point. They may not resolve on the destination.
+    ///
+    pub fn outside_links(&self) -> &BTreeMap<PathBuf, PathBuf> {
+        &self.outside_links
+    }
+
     ///
     /// Starts the copy process using CopyQueue's source and destination variables
     ///
+    /// The source is streamed rather than listed up front, so memory use doesn't grow with the
+    /// number of files. Its total size is counted on the side while copying already runs.
+    ///
     /// Callbacks:
     /// * `onpercentage` - `|percentage: usize, source_dir: PathBuf, bytes_copied: usize| -> ()`
     /// * `oncomplete`   - `|| -> ()`
     ///
+    /// Returns what was written to each destination, in order, or the first error that stopped
+    /// the copy
+    ///
     pub fn start_copy(
-        &self,
+        &mut self,
         onpercentage: Box<impl Fn(usize, PathBuf, usize)>,
         oncomplete: Box<impl FnOnce()>,
+    ) -> Result<Vec<DestinationSummary>, CopyError> {
+        if self.sha256sums
+            && self.stdin.is_none()
+            && !self
+                .selection
+                .excluded
+                .iter()
+                .any(|path| path == Path::new(SHA256SUMS))
+            && self.source.path(SHA256SUMS).exists()
+        {
+            return Err(self.sha256sums_clash());
+        }
+        // Whatever an earlier copy started verifying is out of date
+        self.verifying.get_mut().unwrap().take();
+        // What a run cut off by a crash staged is no use, the copy starts over
+        if self.transactional {
+            for dest in &self.destinations {
+                transaction::roll_back(dest)
+                    .map_err(|e| self.failed(CopyError::new(dest, Some(dest), e)))?;
+            }
+        }
+        let targets = self
+            .destinations
+            .iter()
+            .map(|dest| self.target(dest))
+            .collect::<Vec<_>>();
+        // A stream's size is only known once it has been read
+        let prescan = self.stdin.is_none().then(|| prescan(self.walk()));
+        let totals = prescan.as_ref().map(Prescan::totals);
+        let mut planned = false;
+        let mut streamed = Vec::new();
+        let mut outside_links = BTreeMap::new();
+
+        let hash_pool = self.hash_threads.map(HashPool::new);
+        // Only worth it while there are other destinations left to copy, and a stream can only
+        // be verified once all of it came through
+        let mut verify_pool = (self.verify_early
+            && self.stdin.is_none()
+            && self.simulation.is_none()
+            && self.destinations.len() > 1)
+            .then(|| {
+                VerifyPool::new(
+                    self.threads.min(self.destinations.len() - 1),
+                    self.source.clone(),
+                    self.selected(),
+                    self.metadata_checks.clone(),
+                    None,
+                )
+            });
+        // A simulation has nothing to resume, and would mislead a real run resuming after it. Nor
+        // has a transactional run, which throws away what it staged unless it gets through.
+        let state_file =
+            (self.simulation.is_none() && !self.transactional).then(|| self.state_file.clone());
+        let mut checkpoint = Checkpoint::new(state_file, &self.source.name(), self.resume);
+
+        let starts = self
+            .destinations
+            .iter()
+            .map(|dest| {
+                let resumed = checkpoint
+                    .state
+                    .destination(dest)
+                    .cloned()
+                    .unwrap_or_default();
+                match (resumed.last_completed, resumed.partial) {
+                    _ if resumed.done => ResumePoint::Complete,
+                    (last, Some(partial)) => ResumePoint::Partway { last, partial },
+                    (Some(last), None) => ResumePoint::After(last),
+                    (None, None) => ResumePoint::Beginning,
+                }
+            })
+            .collect::<Vec<_>>();
+        // `--fan-out` and streams hash the chunks they read anyway and reports every file itself
+        let hash_in_fan_out = (self.fan_out || self.stdin.is_some()) && hash_pool.is_some();
+        // Otherwise every file shows up exactly once in the events of any destination that isn't
+        // complete yet, so following one of them hashes each file once without remembering which
+        // were
+        let hashed_with = starts
+            .iter()
+            .position(|start| *start != ResumePoint::Complete)
+            .filter(|_| !hash_in_fan_out);
+
+        let mut unfinished = starts
+            .iter()
+            .filter(|start| **start != ResumePoint::Complete)
+            .count();
+        let mut summaries = Vec::new();
+        let mut copied_bytes = vec![0; self.destinations.len()];
+        let mut resumed_bytes = vec![0; self.destinations.len()];
+        let mut started = vec![None; self.destinations.len()];
+        let mut failed = vec![Vec::new(); self.destinations.len()];
+        let mut changed = vec![Vec::new(); self.destinations.len()];
+        // The file being written to each destination and its size, for `on_file_progress`
+        let mut current: Vec<Option<(PathBuf, usize)>> = vec![None; self.destinations.len()];
+        // The bytes each destination had when its progress was last reported
+        let mut reported = vec![0; self.destinations.len()];
+        let granularity = self.progress_granularity;
+        // How often files were found locked on each destination, for `antivirus::suspected`
+        let mut locks = vec![0; self.destinations.len()];
+        for (dest, start) in self.destinations.iter().zip(&starts) {
+            if *start == ResumePoint::Complete {
+                onpercentage(100, dest.clone(), totals.map_or(0, Totals::bytes));
+            }
+        }
+
+        let mut handle = |event: CopyEvent| {
+            if let Some(totals) = totals.filter(|totals| !planned && totals.done()) {
+                planned = true;
+                for hook in &self.hooks {
+                    hook.on_plan(&self.source.name(), &self.destinations, totals.bytes());
+                }
+            }
+
+            let started = event.destination().map(|dest| {
+                *started[dest].get_or_insert_with(|| {
+                    for hook in &self.hooks {
+                        hook.on_destination_started(&self.destinations[dest]);
+                    }
+                    Instant::now()
+                })
+            });
+            match event {
+                CopyEvent::Progress { dest, file_bytes } => {
+                    checkpoint.file_progress(&self.destinations[dest], file_bytes);
+                    let copied = copied_bytes[dest] + file_bytes;
+                    if granularity.reports(copied.saturating_sub(reported[dest]), false) {
+                        reported[dest] = copied;
+                        if let Some((file, size)) = &current[dest] {
+                            self.file_progress(dest, file, file_bytes, *size, copied, totals);
+                        }
+                        onpercentage(
+                            percentage(copied, totals),
+                            self.destinations[dest].clone(),
+                            copied,
+                        );
+                    }
+                }
+                CopyEvent::FileStarted {
+                    dest,
+                    file,
+                    size,
+                    offset,
+                } => {
+                    checkpoint.file_started(&self.destinations[dest], &file, size, offset);
+                    resumed_bytes[dest] += offset;
+                    let copied = copied_bytes[dest] + offset;
+                    if granularity == ProgressGranularity::PerChunk {
+                        self.file_progress(dest, &file, offset, size, copied, totals);
+                    }
+                    current[dest] = Some((file, size));
+                }
+                CopyEvent::FileSkipped { dest, file, size } => {
+                    copied_bytes[dest] += size;
+                    resumed_bytes[dest] += size;
+                    if let (Some(pool), Some(hashed_with)) = (&hash_pool, hashed_with) {
+                        if dest == hashed_with {
+                            pool.submit(file.clone(), self.source.path(&file));
+                        }
+                    }
+                }
+                CopyEvent::FileChanged { dest, file, size } => {
+                    copied_bytes[dest] += size;
+                    resumed_bytes[dest] += size;
+                    changed[dest].push(file);
+                }
+                CopyEvent::FileDone { dest, file, size } => {
+                    let dest_path = &self.destinations[dest];
+                    copied_bytes[dest] += size;
+                    checkpoint.file_completed(dest_path, &file);
+                    let copied = copied_bytes[dest];
+                    if granularity.reports(copied.saturating_sub(reported[dest]), true) {
+                        reported[dest] = copied;
+                        self.file_progress(dest, &file, size, size, copied, totals);
+                        onpercentage(percentage(copied, totals), dest_path.clone(), copied);
+                    }
+
+                    // The file was just read, so hashing it now mostly hits the page cache
+                    if let (Some(pool), Some(hashed_with)) = (&hash_pool, hashed_with) {
+                        if dest == hashed_with {
+                            pool.submit(file.clone(), self.source.path(&file));
+                        }
+                    }
+
+                    for hook in &self.hooks {
+                        hook.on_file_copied(dest_path, &file, size);
+                    }
+                }
+                CopyEvent::DestinationCancelled { dest } => {
+                    let dest_path = &self.destinations[dest];
+                    unfinished = unfinished.saturating_sub(1);
+                    self.remove_partial_file(&mut checkpoint, dest_path);
+                    checkpoint.save();
+                    summaries.push(DestinationSummary {
+                        destination: dest_path.clone(),
+                        bytes_copied: copied_bytes[dest] - resumed_bytes[dest],
+                        duration: started.map_or(Duration::ZERO, |started| started.elapsed()),
+                        failed: ::std::mem::take(&mut failed[dest]),
+                        changed: ::std::mem::take(&mut changed[dest]),
+                        scanned: false,
+                        cancelled: true,
+                    });
+                }
+                CopyEvent::DestinationDone { dest } => {
+                    let dest_path = &self.destinations[dest];
+                    for hook in &self.hooks {
+                        hook.on_destination_done(dest_path);
+                    }
+                    checkpoint.destination_done(dest_path);
+                    unfinished = unfinished.saturating_sub(1);
+                    // What the granularity held back, so the destination is seen reaching its
+                    // total
+                    let copied = copied_bytes[dest];
+                    if copied > reported[dest] {
+                        reported[dest] = copied;
+                        onpercentage(percentage(copied, totals), dest_path.clone(), copied);
+                    }
+                    // The source isn't hashed to the end yet, so the worker hashes it again
+                    if let Some(pool) = verify_pool.as_mut().filter(|_| unfinished > 0) {
+                        pool.submit(dest, targets[dest].clone(), Arc::default());
+                    }
+                    let bytes_copied = copied_bytes[dest] - resumed_bytes[dest];
+                    let duration = started.map_or(Duration::ZERO, |started| started.elapsed());
+                    summaries.push(DestinationSummary {
+                        destination: dest_path.clone(),
+                        bytes_copied,
+                        duration,
+                        failed: ::std::mem::take(&mut failed[dest]),
+                        changed: ::std::mem::take(&mut changed[dest]),
+                        // A speed limit is slow on purpose
+                        scanned: cfg!(windows)
+                            && self.throttle.is_none()
+                            && self.simulation.is_none()
+                            && antivirus::suspected(bytes_copied, duration, locks[dest]),
+                        cancelled: false,
+                    });
+                }
+                CopyEvent::LinkCreated { file, link, .. } => {
+                    if link.outside {
+                        outside_links.insert(file, link.target);
+                    }
+                }
+                CopyEvent::FileFailed { dest, error } => {
+                    if error.class() == ErrorClass::InUse {
+                        locks[dest] += 1;
+                    }
+                    for hook in &self.hooks {
+                        hook.on_error(&error);
+                    }
+                    failed[dest].push(FailedFile::from(&error));
+                }
+                CopyEvent::Retried { error, attempt } => {
+                    let dest = error.destination.as_ref().and_then(|dest| {
+                        self.destinations
+                            .iter()
+                            .position(|d| self.target(d) == *dest)
+                    });
+                    if let (Some(dest), ErrorClass::InUse) = (dest, error.class()) {
+                        locks[dest] += 1;
+                    }
+                    for hook in &self.hooks {
+                        hook.on_retry(&error, attempt);
+                    }
+                }
+                CopyEvent::FileHashed { file, size, hash } => {
+                    // Verifying a stream can only go by what came through it
+                    if self.stdin.is_some() {
+                        streamed.push(Entry::File(file.clone(), size));
+                    }
+                    if let Some(pool) = &hash_pool {
+                        pool.record(file, hash);
+                    }
+                }
+            }
+        };
+
+        let io = IoHooks {
+            chaos: self.chaos.as_ref(),
+            simulation: self.simulation.as_ref(),
+            counters: &self.counters,
+            throttle: self.throttle.as_deref(),
+            incremental: self.incremental,
+            overwrite: self.overwrite,
+            conflicts: Some(&self.conflicts),
+            preserve: &self.preserve,
+            paused: Some(&self.paused),
+            cancelled: Some(&self.cancelled),
+            destination_cancels: &self.destination_cancels,
+            retries: self.retries,
+            keep_going: self.keep_going,
+            changing: self.changing,
+            buffer: self.copy_buffer,
+            engine: self.engine,
+        };
+        let fan_out = FanOut {
+            queue_chunks: self.fan_out_queue_chunks,
+            hash: hash_in_fan_out,
+        };
+        let result = if let Some(StdinFormat::Tar) = self.stdin {
+            copy_fan_out(
+                |broadcast| {
+                    read_tar(
+                        ::std::io::stdin().lock(),
+                        &self.selected().excluded,
+                        broadcast,
+                    )
+                },
+                &targets,
+                &starts,
+                fan_out,
+                io,
+                &mut handle,
+            )
+        } else if self.fan_out {
+            copy_fan_out(
+                |broadcast| read_tree(&self.source, walk_ahead(self.walk()), broadcast),
+                &targets,
+                &starts,
+                fan_out,
+                io,
+                &mut handle,
+            )
+        } else if self.jobs > 1 {
+            copy_parallel(
+                &self.source,
+                || walk_ahead(self.walk()),
+                &targets,
+                &starts,
+                self.jobs,
+                io,
+                &mut handle,
+            )
+        } else {
+            copy_sequential(
+                &self.source,
+                || walk_ahead(self.walk()),
+                &targets,
+                &starts,
+                io,
+                &mut handle,
+            )
+        };
+        // On failure the checkpoint is left behind so the run can be picked up with `--resume`
+        match result {
+            Err(e) if e.cancelled() => {
+                self.failed(e);
+                let cut_off = self
+                    .destinations
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, dest_path)| {
+                        !summaries
+                            .iter()
+                            .any(|summary| summary.destination == **dest_path)
+                    })
+                    .map(|(dest, dest_path)| DestinationSummary {
+                        destination: dest_path.clone(),
+                        bytes_copied: copied_bytes[dest] - resumed_bytes[dest],
+                        duration: started[dest].map_or(Duration::ZERO, |started| started.elapsed()),
+                        failed: ::std::mem::take(&mut failed[dest]),
+                        changed: ::std::mem::take(&mut changed[dest]),
+                        scanned: false,
+                        // Done by an earlier run
+                        cancelled: starts[dest] != ResumePoint::Complete,
+                    })
+                    .collect::<Vec<_>>();
+                for summary in cut_off.iter().filter(|summary| summary.cancelled) {
+                    self.remove_partial_file(&mut checkpoint, &summary.destination);
+                }
+                checkpoint.save();
+                summaries.extend(cut_off);
+                summaries.sort_by_key(|summary| {
+                    self.destinations
+                        .iter()
+                        .position(|dest| *dest == summary.destination)
+                });
+                return Ok(summaries);
+            }
+            result => result.map_err(|e| self.failed(e))?,
+        }
+        // A destination cancelled on its own is left for `--resume`, like a cancelled run
+        match summaries.iter().any(|summary| summary.cancelled) {
+            true => checkpoint.save(),
+            false => checkpoint.finish(),
+        }
+
+        let total_bytes = match prescan {
+            Some(prescan) => prescan.finish().bytes(),
+            None => copied_bytes.iter().copied().max().unwrap_or(0),
+        };
+        if !planned {
+            for hook in &self.hooks {
+                hook.on_plan(&self.source.name(), &self.destinations, total_bytes);
+            }
+        }
+        self.streamed = streamed;
+        self.outside_links = outside_links;
+        *self.verifying.lock().unwrap() = verify_pool;
+
+        // Destinations that were already complete still get a summary, in the original order
+        for (dest, start) in self.destinations.iter().zip(&starts) {
+            if *start == ResumePoint::Complete {
+                onpercentage(100, dest.clone(), total_bytes);
+                summaries.push(DestinationSummary {
+                    destination: dest.clone(),
+                    bytes_copied: 0,
+                    duration: Duration::ZERO,
+                    failed: Vec::new(),
+                    changed: Vec::new(),
+                    scanned: false,
+                    cancelled: false,
+                });
+            }
+        }
+        summaries.sort_by_key(|summary| {
+            self.destinations
+                .iter()
+                .position(|dest| *dest == summary.destination)
+        });
+        self.changed = summaries
+            .iter()
+            .map(|summary| summary.changed.clone())
+            .collect();
+
+        if let Some(pool) = hash_pool {
+            // Nothing was copied, so nothing was hashed along the way either
+            if hashed_with.is_none() && !hash_in_fan_out {
+                for entry in self.walk() {
+                    if let Entry::File(file, _) =
+                        entry.map_err(|e| CopyError::new(&self.source.name(), None, e))?
+                    {
+                        pool.submit(file.clone(), self.source.path(&file));
+                    }
+                }
+            }
+            for (file, hash) in pool.finish() {
+                let hash = hash.map_err(|e| CopyError::new(&file, None, e))?;
+                for hook in &self.hooks {
+                    hook.on_file_hashed(&file, &hash);
+                }
+                self.source_hashes.insert(file, hash);
+            }
+        }
+        if self.sha256sums {
+            self.write_sha256sums().map_err(|e| self.failed(e))?;
+        }
+
+        for hook in &self.hooks {
+            hook.on_finish();
+        }
+        oncomplete();
+        Ok(summaries)
+    }
+
+    ///
+    /// Writes the manifest of the hashes collected while copying into the root of every
+    /// destination, next to what it lists
+    ///
+    fn write_sha256sums(&self) -> Result<(), CopyError> {
+        // Only a stream can still bring its own, a source directory was checked up front
+        if self.source_hashes.contains_key(Path::new(SHA256SUMS)) {
+            return Err(self.sha256sums_clash());
+        }
+        let sums = self.manifest().to_sha256sums();
+        for dest in &self.destinations {
+            ::std::fs::write(self.target(dest).join(SHA256SUMS), &sums)
+                .map_err(|e| CopyError::new(Path::new(SHA256SUMS), Some(dest), e))?;
+        }
+        Ok(())
+    }
+
+    fn sha256sums_clash(&self) -> CopyError {
+        CopyError::new(
+            Path::new(SHA256SUMS),
+            None,
+            ::std::io::Error::new(
+                ::std::io::ErrorKind::AlreadyExists,
+                "the source has a SHA256SUMS of its own, --sha256sums would overwrite it on \
+                 the destinations",
+            ),
+        )
+    }
+
+    ///
+    /// Re-reads every destination after `start_copy` and compares each file against the source,
+    /// reusing the hashes computed during the copy when hashing was enabled. Up to `--threads`
+    /// destinations are checked at once, those `start_copy` started on already are waited for.
+    ///
+    /// Callbacks:
+    /// * `onprogress` - `|destination_index: usize, percentage: usize| -> ()`
+    ///
+    pub fn start_verify(
+        &self,
+        onprogress: Box<impl Fn(usize, usize)>,
+    ) -> Result<Vec<Verification>, CopyError> {
+        let total_bytes = match self.stdin {
+            None => total_bytes(self.walk())
+                .map_err(|e| CopyError::new(&self.source.name(), None, e))?,
+            Some(_) => self
+                .streamed
+                .iter()
+                .map(|entry| match entry {
+                    Entry::File(_, size) => *size,
+                    Entry::Dir(_) | Entry::Link(..) => 0,
+                })
+                .sum(),
+        }
+        .max(1);
+
+        let streamed = self.stdin.map(|_| self.streamed.clone());
+        let mut pool = self.verifying.lock().unwrap().take().unwrap_or_else(|| {
+            VerifyPool::new(
+                self.threads.min(self.destinations.len()),
+                self.source.clone(),
+                self.selected(),
+                self.metadata_checks.clone(),
+                streamed,
+            )
+        });
+        let source_hashes = Arc::new(self.source_hashes.clone());
+        for (i, dest) in self.destinations.iter().enumerate() {
+            if !pool.submitted(i) {
+                pool.submit(i, self.target(dest), source_hashes.clone());
+            }
+        }
+
+        let mut last_percentages = vec![None; self.destinations.len()];
+        let mut results = pool.finish(|i, verified| {
+            let percentage = verified * 100 / total_bytes;
+            if last_percentages[i] != Some(percentage) {
+                last_percentages[i] = Some(percentage);
+                onprogress(i, percentage);
+            }
+        });
+        let verifications = self
+            .destinations
+            .iter()
+            .enumerate()
+            .map(|(i, dest)| {
+                let mut verification = results
+                    .remove(&i)
+                    .expect("every destination was submitted")?;
+                verification.destination = dest.clone();
+                // Left out on purpose, the summary lists them
+                if let Some(changed) = self.changed.get(i).filter(|changed| !changed.is_empty()) {
+                    verification.missing.retain(|file| !changed.contains(file));
+                    verification
+                        .size_mismatch
+                        .retain(|file| !changed.contains(file));
+                    verification
+                        .corrupted
+                        .retain(|file| !changed.contains(file));
+                }
+                // Written by this run rather than left over
+                if self.sha256sums {
+                    verification
+                        .extra
+                        .retain(|file| file != Path::new(SHA256SUMS));
+                }
+                if self.drive_log {
+                    verification
+                        .extra
+                        .retain(|file| file != Path::new(DRIVE_LOG));
+                }
+                Ok(verification)
+            })
+            .collect();
+        self.verified(verifications)
+    }
+
+    ///
+    /// Copies the files `verifications` flagged on each destination again and re-checks just
+    /// those (`--repair`), returning the updated verifications in the same order. Destinations
+    /// that passed are left alone.
+    ///
+    /// Callbacks:
+    /// * `onprogress` - `|destination_index: usize, percentage: usize| -> ()`
+    ///
+    pub fn start_repair(
+        &self,
+        verifications: &[Verification],
+        onprogress: Box<impl Fn(usize, usize)>,
+    ) -> Result<Vec<Verification>, CopyError> {
+        let repaired = verifications
+            .iter()
+            .enumerate()
+            .map(|(i, verification)| {
+                let failed = verification.failed_files().cloned().collect();
+                self.repair(verification, failed, |percent| onprogress(i, percent))
+            })
+            .collect();
+        self.verified(repaired)
+    }
+
+    ///
+    /// Copies just `file` again, which failed `verification`, and re-checks it. Returns
+    /// `verification` with the outcome for `file` updated and the rest as it was.
+    ///
+    /// Callbacks:
+    /// * `onprogress` - `|percentage: usize| -> ()`
+    ///
+    pub fn repair_file(
+        &self,
+        verification: &Verification,
+        file: &Path,
+        onprogress: impl FnMut(usize),
+    ) -> Result<Verification, CopyError> {
+        let repaired = self.repair(verification, vec![file.to_path_buf()], onprogress);
+        self.verified(repaired.map(|repaired| vec![repaired]))
+            .map(|mut repaired| repaired.remove(0))
+    }
+
+    ///
+    /// Copies `failed` of the files `verification` flagged again and re-checks just those. The
+    /// files it flagged that aren't in `failed` keep their outcome.
+    ///
+    fn repair(
+        &self,
+        verification: &Verification,
+        mut failed: Vec<PathBuf>,
+        mut onprogress: impl FnMut(usize),
+    ) -> Result<Verification, CopyError> {
+        if failed.is_empty() {
+            return Ok(verification.clone());
+        }
+        failed.sort();
+
+        // Files that failed verification are rewritten even when their size and time look right
+        let io = IoHooks {
+            chaos: self.chaos.as_ref(),
+            simulation: None,
+            counters: &self.counters,
+            throttle: self.throttle.as_deref(),
+            incremental: None,
+            overwrite: Overwrite::Always,
+            conflicts: None,
+            preserve: &self.preserve,
+            paused: Some(&self.paused),
+            cancelled: None,
+            destination_cancels: &[],
+            retries: self.retries,
+            keep_going: false,
+            changing: self.changing,
+            buffer: self.copy_buffer,
+            engine: self.engine,
+        };
+
+        // Only the broken files, with the directories they need
+        let mut repair = Vec::new();
+        for file in &failed {
+            let source = self.source.path(file);
+            let metadata =
+                ::std::fs::symlink_metadata(&source).map_err(|e| CopyError::new(file, None, e))?;
+            if let Some(dir) = file.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+                repair.push(Entry::Dir(dir.to_path_buf()));
+            }
+            if metadata.is_symlink() && self.selection.symlinks == SymlinkPolicy::Preserve {
+                let link = Symlink::read(self.source.root_of(file), &self.source.real(file))
+                    .map_err(|e| CopyError::new(file, None, e))?;
+                repair.push(Entry::Link(file.clone(), link));
+                continue;
+            }
+            let size = ::std::fs::metadata(&source)
+                .map_err(|e| CopyError::new(file, None, e))?
+                .len() as usize;
+            repair.push(Entry::File(file.clone(), size));
+        }
+        let total_bytes = repair
+            .iter()
+            .map(|entry| match entry {
+                Entry::File(_, size) => *size,
+                Entry::Dir(_) | Entry::Link(..) => 0,
+            })
+            .sum::<usize>()
+            .max(1);
+        let dest = &self.target(&verification.destination);
+
+        let mut copied_bytes = 0;
+        copy_sequential(
+            &self.source,
+            || repair.iter().cloned().map(Ok),
+            ::std::slice::from_ref(dest),
+            &[ResumePoint::Beginning],
+            io,
+            &mut |event| match event {
+                CopyEvent::Progress { file_bytes, .. } => {
+                    onprogress((copied_bytes + file_bytes) * 100 / total_bytes)
+                }
+                CopyEvent::FileDone { size, .. } => copied_bytes += size,
+                CopyEvent::FileStarted { .. }
+                | CopyEvent::FileSkipped { .. }
+                | CopyEvent::FileChanged { .. }
+                | CopyEvent::LinkCreated { .. }
+                | CopyEvent::FileFailed { .. }
+                | CopyEvent::Retried { .. }
+                | CopyEvent::FileHashed { .. } => {}
+                CopyEvent::DestinationDone { .. } => onprogress(100),
+                CopyEvent::DestinationCancelled { .. } => {}
+            },
+        )?;
+
+        let recheck = compare_files(
+            &self.source,
+            dest,
+            repair.iter().cloned().map(Ok),
+            &self.source_hashes,
+            &self.metadata_checks,
+            |_| {},
+        )
+        .map_err(|e| CopyError::new(&self.source.name(), None, e))?;
+        let still_failed = recheck.failed_files().collect::<HashSet<_>>();
+        let retried = failed.iter().collect::<HashSet<_>>();
+        let kept = |files: &[PathBuf], rechecked: &[PathBuf]| {
+            files
+                .iter()
+                .filter(|file| !retried.contains(file))
+                .chain(rechecked)
+                .cloned()
+                .collect()
+        };
+        Ok(Verification {
+            repaired: verification
+                .repaired
+                .iter()
+                .chain(failed.iter().filter(|file| !still_failed.contains(file)))
+                .cloned()
+                .collect(),
+            missing: kept(&verification.missing, &recheck.missing),
+            size_mismatch: kept(&verification.size_mismatch, &recheck.size_mismatch),
+            corrupted: kept(&verification.corrupted, &recheck.corrupted),
+            time_mismatch: kept(&verification.time_mismatch, &recheck.time_mismatch),
+            perms_mismatch: kept(&verification.perms_mismatch, &recheck.perms_mismatch),
+            target_mismatch: kept(&verification.target_mismatch, &recheck.target_mismatch),
+            ..verification.clone()
+        })
+    }
+
+    ///
+    /// Passes the outcome of `start_verify` or `start_repair` on to the hooks
+    ///
+    fn verified(
+        &self,
+        verifications: Result<Vec<Verification>, CopyError>,
+    ) -> Result<Vec<Verification>, CopyError> {
+        let verifications = verifications.map_err(|e| self.failed(e))?;
+        for verification in &verifications {
+            for hook in &self.hooks {
+                hook.on_verified(verification);
+            }
+        }
+        Ok(verifications)
+    }
+
+    ///
+    /// Tells the hooks where the copy of `file` to destination number `dest` is at
+    ///
+    fn file_progress(
+        &self,
+        dest: usize,
+        file: &Path,
+        file_bytes: usize,
+        file_size: usize,
+        bytes_copied: usize,
+        totals: Option<&Totals>,
     ) {
-        let total_bytes = get_size(self.source.clone()).unwrap();
-        for dest in self.destinations.clone() {
-            let opt = CopyOptions {
-                overwrite: true,
-                content_only: true,
-                ..CopyOptions::new()
+        let progress = FileProgress {
+            destination: &self.destinations[dest],
+            file,
+            file_bytes,
+            file_size,
+            bytes_copied,
+            total_bytes: totals.filter(|totals| totals.done()).map(Totals::bytes),
+        };
+        for hook in &self.hooks {
+            hook.on_file_progress(&progress);
+        }
+    }
+
+    ///
+    /// Under `--remove-partial`, deletes the file a cancelled copy was writing to `dest` and
+    /// takes it out of the checkpoint
+    ///
+    fn remove_partial_file(&self, checkpoint: &mut Checkpoint, dest: &Path) {
+        let state = checkpoint.state.destination_mut(dest);
+        // A simulated destination has nothing of its own to remove
+        let remove = self.remove_partial && self.simulation.is_none();
+        if let Some(partial) = state.partial.take_if(|_| remove) {
+            let dest = longpath::extended(dest);
+            let _ = ::std::fs::remove_file(dest.join(&partial.file));
+        }
+    }
+
+    ///
+    /// Tells the hooks about `error`, which ends the run, naming its destination the way it was
+    /// given. Under `--transactional` what was staged is thrown away, the destinations stay as
+    /// they were.
+    ///
+    fn failed(&self, mut error: CopyError) -> CopyError {
+        for dest in &self.destinations {
+            let written = [self.target(dest), longpath::extended(dest)];
+            if error
+                .destination
+                .as_ref()
+                .is_some_and(|destination| written.contains(destination))
+            {
+                error.destination = Some(dest.clone());
+            }
+            if self.transactional {
+                // The run failed already, a staging directory left behind is cleared by the next
+                let _ = transaction::roll_back(dest);
+            }
+        }
+        for hook in &self.hooks {
+            hook.on_error(&error);
+        }
+        error
+    }
+
+    ///
+    /// Deletes what the source doesn't have from every destination (`--mirror`), returning the
+    /// files removed from each of them, in order. Runs before `start_copy`, so the space is free
+    /// by the time it is needed.
+    ///
+    pub fn start_mirror(&self) -> Result<Vec<Vec<PathBuf>>, CopyError> {
+        if !self.mirror || self.stdin.is_some() {
+            return Ok(vec![Vec::new(); self.destinations.len()]);
+        }
+
+        let kept = self.kept();
+        self.destinations
+            .iter()
+            .map(|dest| {
+                let dest = &longpath::extended(dest);
+                remove_stale(dest, &mirror::stale(&self.source, &kept, dest)?)
+            })
+            .collect::<Result<_, _>>()
+            .map_err(|e| self.failed(e))
+    }
+
+    ///
+    /// What `--mirror` leaves alone on the destinations
+    ///
+    fn kept(&self) -> Selection {
+        self.selection.with_excluded(mirror::kept(
+            &self.selection.excluded,
+            self.sha256sums,
+            self.drive_log,
+        ))
+    }
+
+    ///
+    /// Works out what `start_mirror` and `start_copy` would do to every destination, in order,
+    /// without writing anything (`--dry-run`)
+    ///
+    pub fn dry_run(&self) -> Result<Vec<DryRun>, CopyError> {
+        let io = IoHooks {
+            chaos: None,
+            simulation: None,
+            counters: &self.counters,
+            throttle: None,
+            incremental: self.incremental,
+            overwrite: self.overwrite,
+            conflicts: None,
+            preserve: &[],
+            paused: None,
+            cancelled: None,
+            destination_cancels: &[],
+            retries: Retries::default(),
+            keep_going: false,
+            changing: self.changing,
+            buffer: self.copy_buffer,
+            engine: self.engine,
+        };
+        let kept = self.kept();
+        self.destinations
+            .iter()
+            .enumerate()
+            .map(|(dest_index, dest)| {
+                let mut plan = DryRun {
+                    destination: dest.clone(),
+                    ..DryRun::default()
+                };
+                if self.mirror {
+                    plan.deleted = mirror::stale(&self.source, &kept, dest)?;
+                }
+                for entry in walk_ahead(self.walk()) {
+                    let Entry::File(file, size) =
+                        entry.map_err(|e| CopyError::new(&self.source.name(), None, e))?
+                    else {
+                        continue;
+                    };
+                    let target = dest.join(&file);
+                    let listed = match target.exists() {
+                        false => &mut plan.copied,
+                        true if io.unchanged(&self.source.path(&file), &target)
+                            || !io.overwrites(
+                                dest_index,
+                                &file,
+                                Some(&self.source.path(&file)),
+                                &target,
+                            ) =>
+                        {
+                            &mut plan.unchanged
+                        }
+                        true => &mut plan.overwritten,
+                    };
+                    listed.push((file, size));
+                }
+                Ok(plan)
+            })
+            .collect()
+    }
+
+    ///
+    /// Applies the `--clean-dest-glob` rules to every destination, returning the files removed
+    /// from each of them, in order
+    ///
+    pub fn start_clean(&self) -> Result<Vec<Vec<PathBuf>>, CopyError> {
+        if self.clean_globs.is_empty() {
+            return Ok(vec![Vec::new(); self.destinations.len()]);
+        }
+
+        self.destinations
+            .iter()
+            .map(|dest| {
+                clean_destination(
+                    &self.source,
+                    &self.selected(),
+                    &self.target(dest),
+                    &self.clean_globs,
+                )
+            })
+            .collect::<Result<_, _>>()
+            .map_err(|e| self.failed(e))
+    }
+
+    ///
+    /// Swaps the staged copy into every destination that the run got through for, with all of its
+    /// files copied and, when `verifications` are given, verified. The others are rolled back.
+    /// Returns which destinations were swapped in, in order. Does nothing but that without
+    /// `--transactional`, where the files went straight to the destinations.
+    ///
+    pub fn start_commit(
+        &self,
+        summaries: &[DestinationSummary],
+        verifications: Option<&[Verification]>,
+    ) -> Result<Vec<bool>, CopyError> {
+        if !self.transactional {
+            return Ok(vec![true; self.destinations.len()]);
+        }
+
+        self.destinations
+            .iter()
+            .enumerate()
+            .map(|(i, dest)| {
+                let through = summaries
+                    .get(i)
+                    .is_some_and(|summary| summary.failed.is_empty() && !summary.cancelled)
+                    && verifications.is_none_or(|v| v.get(i).is_some_and(Verification::passed));
+                match through {
+                    true => transaction::commit(dest),
+                    false => transaction::roll_back(dest),
+                }
+                .map(|()| through)
+                .map_err(|e| self.failed(CopyError::new(dest, Some(dest), e)))
+            })
+            .collect()
+    }
+}
+
+fn percentage(copied: usize, totals: Option<&Totals>) -> usize {
+    // Streams don't tell their size up front
+    let Some(totals) = totals else {
+        return 0;
+    };
+    // Until the pre-scan is through, the total is only a lower bound
+    let total = match totals.done() {
+        true => totals.bytes(),
+        false => totals.bytes().max(copied + 1),
+    };
+    (copied as f64 / total.max(1) as f64 * 100.) as usize
+}
+
+///
+/// Where a destination picks up, from the checkpoint of an earlier run
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum ResumePoint {
+    Beginning,
+    /// Everything up to and including this file is already on the drive
+    After(PathBuf),
+    /// Everything up to and including `last` is on the drive, and `partial` was cut off after it
+    Partway {
+        last: Option<PathBuf>,
+        partial: PartialFile,
+    },
+    Complete,
+}
+
+impl ResumePoint {
+    ///
+    /// Whether `file` still has to be copied. Files come in `Walk` order, which is the order of
+    /// their paths, so everything up to the checkpointed file is done.
+    ///
+    pub fn wants(&self, file: &Path) -> bool {
+        match self {
+            ResumePoint::Beginning => true,
+            ResumePoint::After(last) => file > last.as_path(),
+            ResumePoint::Partway { last, .. } => last.as_ref().is_none_or(|last| file > last),
+            ResumePoint::Complete => false,
+        }
+    }
+
+    ///
+    /// Whether `file` is the one the earlier run stopped partway through, which is finished
+    /// whatever `--overwrite` says about files already there
+    ///
+    pub fn resumes(&self, file: &Path) -> bool {
+        matches!(self, ResumePoint::Partway { partial, .. } if partial.file == file)
+    }
+
+    ///
+    /// How many bytes of `file` can be kept on `dest_path` from a run that stopped partway
+    /// through it. That is only trusted while the source file still has the same size, and never
+    /// goes beyond what actually made it onto the drive.
+    ///
+    pub fn offset(&self, file: &Path, size: usize, dest_path: &Path) -> usize {
+        match self {
+            ResumePoint::Partway { partial, .. }
+                if partial.file == file && partial.size == size =>
+            {
+                let written = ::std::fs::metadata(dest_path.join(file))
+                    .map_or(0, |metadata| metadata.len() as usize);
+                partial.bytes.min(written)
+            }
+            _ => 0,
+        }
+    }
+}
+
+///
+/// What the copy backends report back to `start_copy`. Destinations are indices into the
+/// queue's destinations, files are relative to the source.
+///
+pub(crate) enum CopyEvent {
+    /// `file_bytes` of the file currently being written to `dest` are on disk
+    Progress {
+        dest: usize,
+        file_bytes: usize,
+    },
+    /// Writing `file` to `dest` begins, with its first `offset` bytes kept from an earlier run
+    FileStarted {
+        dest: usize,
+        file: PathBuf,
+        size: usize,
+        offset: usize,
+    },
+    /// `file` is already on `dest`, from an earlier run, unchanged under `--incremental` or kept
+    /// by `--overwrite`
+    FileSkipped {
+        dest: usize,
+        file: PathBuf,
+        size: usize,
+    },
+    /// `file` changed size while it was read for `dest` and was left out, see `--changing-files`
+    FileChanged {
+        dest: usize,
+        file: PathBuf,
+        size: usize,
+    },
+    FileDone {
+        dest: usize,
+        file: PathBuf,
+        size: usize,
+    },
+    DestinationDone {
+        dest: usize,
+    },
+    /// Destination number `dest` was cancelled on its own and stopped, the others carry on
+    DestinationCancelled {
+        dest: usize,
+    },
+    /// The symlink `file` was recreated on `dest`, with `--symlinks preserve`
+    LinkCreated {
+        dest: usize,
+        file: PathBuf,
+        link: Symlink,
+    },
+    /// What `error` names could not be copied to `dest`, and `--keep-going` went on without it
+    FileFailed {
+        dest: usize,
+        error: CopyError,
+    },
+    /// A transient error that what failed is tried again after, for the `attempt`th time
+    Retried {
+        error: CopyError,
+        attempt: u32,
+    },
+    /// The hex SHA-256 of `file`, from the chunks `--fan-out` read while copying
+    FileHashed {
+        file: PathBuf,
+        size: usize,
+        hash: String,
+    },
+}
+
+impl CopyEvent {
+    fn destination(&self) -> Option<usize> {
+        match self {
+            CopyEvent::Progress { dest, .. }
+            | CopyEvent::FileStarted { dest, .. }
+            | CopyEvent::FileSkipped { dest, .. }
+            | CopyEvent::FileChanged { dest, .. }
+            | CopyEvent::FileDone { dest, .. }
+            | CopyEvent::DestinationDone { dest }
+            | CopyEvent::DestinationCancelled { dest }
+            | CopyEvent::LinkCreated { dest, .. }
+            | CopyEvent::FileFailed { dest, .. } => Some(*dest),
+            CopyEvent::Retried { .. } | CopyEvent::FileHashed { .. } => None,
+        }
+    }
+}
+
+///
+/// What the copy backends check in with around their raw reads and writes
+///
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct IoHooks<'a> {
+    pub chaos: Option<&'a Chaos>,
+    /// Stands in for the destinations under `--simulate-speed`
+    pub simulation: Option<&'a Simulation>,
+    pub counters: &'a Counters,
+    pub throttle: Option<&'a Throttle>,
+    pub incremental: Option<Incremental>,
+    pub overwrite: Overwrite,
+    /// Where `--overwrite prompt` asks, files are overwritten without it
+    pub conflicts: Option<&'a Conflicts>,
+    pub preserve: &'a [Preserve],
+    pub paused: Option<&'a AtomicBool>,
+    pub cancelled: Option<&'a CancellationToken>,
+    /// What `for_destination` cancels by, one for every destination
+    pub destination_cancels: &'a [CancellationToken],
+    pub retries: Retries,
+    pub keep_going: bool,
+    pub changing: ChangingFiles,
+    /// How much of a file is read and written at a time, and so how often its progress is
+    /// reported (`--copy-buffer`)
+    pub buffer: usize,
+    pub engine: Engine,
+}
+
+///
+/// How often a write or read that failed with a transient error is tried again
+/// (`--max-retries`), and how long the first retry waits (`--retry-delay`). Every further retry
+/// waits twice as long, up to `MAX_RETRY_WAIT`.
+///
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct Retries {
+    pub max: u32,
+    pub delay: Duration,
+}
+
+impl IoHooks<'_> {
+    /// Before `dest` is written on a destination, may fail under `--chaos`. A copy `--preserve
+    /// perms` left read-only is made writable again first.
+    pub fn before_write(&self, dest: &Path) -> ::std::io::Result<()> {
+        self.chaos.map_or(Ok(()), Chaos::before_write)?;
+        match self.preserve.contains(&Preserve::Perms) {
+            true => make_writable(dest),
+            false => Ok(()),
+        }
+    }
+
+    /// After `bytes` were read from the source, may sleep under `--limit-rate`, `--limit-schedule`
+    /// or while paused. Fails once the run was cancelled, which stops the copy.
+    pub fn after_read(&self, bytes: usize) -> ::std::io::Result<()> {
+        if let Some(throttle) = self.throttle {
+            throttle.consume(bytes);
+        }
+        while !self.cancelled()
+            && self
+                .paused
+                .is_some_and(|paused| paused.load(Ordering::Relaxed))
+        {
+            ::std::thread::sleep(Duration::from_millis(50));
+        }
+        self.check_cancelled()
+    }
+
+    /// Whether the operator cancelled the run, or the destination after `for_destination`, see
+    /// `CopyQueue::cancel_token`
+    pub fn cancelled(&self) -> bool {
+        self.cancelled
+            .is_some_and(|cancelled| cancelled.is_cancelled())
+    }
+
+    /// For copying to destination number `dest`, which also stops once it is cancelled on its
+    /// own, see `CopyQueue::destination_cancel_token`
+    pub fn for_destination(self, dest: usize) -> Self {
+        Self {
+            cancelled: self.destination_cancels.get(dest).or(self.cancelled),
+            ..self
+        }
+    }
+
+    /// Fails once the run was cancelled, for the copy loops to stop with
+    pub fn check_cancelled(&self) -> ::std::io::Result<()> {
+        match self.cancelled() {
+            true => Err(cancelled()),
+            false => Ok(()),
+        }
+    }
+
+    /// Whether what failed with `error` should be tried again, after `retries` earlier attempts.
+    /// If so, counts the attempt and waits before returning.
+    pub fn retry_after(&self, error: &::std::io::Error, retries: &mut u32) -> bool {
+        if *retries >= self.retries.max || !is_transient(error) || self.cancelled() {
+            return false;
+        }
+        let wait = self
+            .retries
+            .delay
+            .saturating_mul(1 << (*retries).min(16))
+            .min(MAX_RETRY_WAIT);
+        *retries += 1;
+        self.counters.retry();
+        ::std::thread::sleep(wait);
+        true
+    }
+
+    /// Whether writing `source` to `dest` can be left out under `--incremental`
+    pub fn unchanged(&self, source: &Path, dest: &Path) -> bool {
+        let unchanged = self
+            .incremental
+            .is_some_and(|incremental| incremental.unchanged(source, dest));
+        if unchanged {
+            self.counters.cache_hit();
+        }
+        unchanged
+    }
+
+    /// Whether the copy of `file` that destination number `dest` already has at `existing` is
+    /// written over with `source`, by `--overwrite`. Files it doesn't have yet always are.
+    pub fn overwrites(
+        &self,
+        dest: usize,
+        file: &Path,
+        source: Option<&Path>,
+        existing: &Path,
+    ) -> bool {
+        if self.overwrite == Overwrite::Always || !existing.exists() {
+            return true;
+        }
+        self.overwrite.allows(source, existing, || {
+            let conflict = Conflict {
+                dest,
+                file: file.to_path_buf(),
+                source: source.and_then(FileVersion::of),
+                existing: FileVersion::of(existing),
             };
-            copy_with_progress(self.source.clone(), dest.clone(), &opt, |proc_info| {
-                let percentage = (proc_info.copied_bytes as f64 / total_bytes as f64) * 100.;
-                onpercentage(
-                    percentage as usize,
-                    dest.clone(),
-                    proc_info.copied_bytes as usize,
-                );
-                fs_extra::dir::TransitProcessResult::ContinueOrAbort
+            self.conflicts
+                .is_none_or(|conflicts| conflicts.ask(conflict, || self.cancelled()))
+        })
+    }
+
+    /// What of `source`'s metadata a copy of it gets: the modification time under `--preserve
+    /// times`, and so `--incremental` recognizes it next time, the permissions under `--preserve
+    /// perms`
+    pub fn kept(&self, source: &Path) -> Option<Kept> {
+        let times = self.incremental == Some(Incremental::Mtime)
+            || self.preserve.contains(&Preserve::Times);
+        let perms = self.preserve.contains(&Preserve::Perms);
+        if !times && !perms {
+            return None;
+        }
+        let metadata = ::std::fs::metadata(source).ok()?;
+        Some(Kept {
+            modified: metadata.modified().ok().filter(|_| times),
+            permissions: perms.then(|| metadata.permissions()),
+        })
+    }
+}
+
+///
+/// Copies to one destination after the other, walking the source again with `entries` for each
+/// of them
+///
+fn copy_sequential<I: IntoIterator<Item = ::std::io::Result<Entry>>>(
+    source: &Source,
+    entries: impl Fn() -> I,
+    destinations: &[PathBuf],
+    starts: &[ResumePoint],
+    io: IoHooks,
+    handle: &mut impl FnMut(CopyEvent),
+) -> Result<(), CopyError> {
+    for (dest, (dest_path, start)) in destinations.iter().zip(starts).enumerate() {
+        copy_destination(source, entries(), dest, dest_path, start, io, handle)?;
+    }
+    Ok(())
+}
+
+///
+/// Copies to up to `jobs` destinations at the same time (`--jobs`), each on its own thread with
+/// its own walk of the source from `entries`. Once a destination fails no further ones are
+/// started, those already underway still run to the end. The first error in destination order
+/// is returned.
+///
+fn copy_parallel<I: IntoIterator<Item = ::std::io::Result<Entry>>>(
+    source: &Source,
+    entries: impl Fn() -> I + Sync,
+    destinations: &[PathBuf],
+    starts: &[ResumePoint],
+    jobs: usize,
+    io: IoHooks,
+    handle: &mut impl FnMut(CopyEvent),
+) -> Result<(), CopyError> {
+    let next = AtomicUsize::new(0);
+    let failed = AtomicBool::new(false);
+    let (events, event_rx) = channel();
+
+    ::std::thread::scope(|scope| {
+        let (next, failed, entries) = (&next, &failed, &entries);
+        let workers = (0..jobs.min(destinations.len()))
+            .map(|_| {
+                let events = events.clone();
+                scope.spawn(move || {
+                    let mut errors = Vec::new();
+                    while !failed.load(Ordering::Relaxed) {
+                        let dest = next.fetch_add(1, Ordering::Relaxed);
+                        let Some(dest_path) = destinations.get(dest) else {
+                            break;
+                        };
+                        let mut send = |event| {
+                            let _ = events.send(event);
+                        };
+                        let copied = copy_destination(
+                            source,
+                            entries(),
+                            dest,
+                            dest_path,
+                            &starts[dest],
+                            io,
+                            &mut send,
+                        );
+                        if let Err(e) = copied {
+                            failed.store(true, Ordering::Relaxed);
+                            errors.push((dest, e));
+                        }
+                    }
+                    errors
+                })
             })
-            .unwrap();
+            .collect::<Vec<_>>();
+        drop(events);
+
+        for event in event_rx {
+            handle(event);
         }
 
-        oncomplete();
+        workers
+            .into_iter()
+            .flat_map(|worker| worker.join().expect("destination copier panicked"))
+            .min_by_key(|(dest, _)| *dest)
+            .map_or(Ok(()), |(_, e)| Err(e))
+    })
+}
+
+///
+/// Copies the files of `entries` to destination number `dest`, skipping what `start` says is
+/// already there, what `--incremental` finds unchanged and what `--overwrite` keeps
+///
+fn copy_destination(
+    source: &Source,
+    entries: impl IntoIterator<Item = ::std::io::Result<Entry>>,
+    dest: usize,
+    dest_path: &Path,
+    start: &ResumePoint,
+    io: IoHooks,
+    handle: &mut impl FnMut(CopyEvent),
+) -> Result<(), CopyError> {
+    if *start == ResumePoint::Complete {
+        return Ok(());
+    }
+    let (run, io) = (io, io.for_destination(dest));
+
+    // A simulated destination is only in memory, there is nothing to create on it
+    let drive = io.simulation.map(Simulation::drive);
+    let mut copied = match drive {
+        Some(_) => Ok(()),
+        None => create_dir(dest_path, Path::new("")),
+    };
+    let target = Target {
+        dest,
+        path: dest_path,
+        start,
+        fat: drive::is_fat(dest_path),
+        drive: drive.as_ref(),
+    };
+    let mut entries = entries.into_iter();
+    loop {
+        if let Err(error) = copied {
+            // Cancelled on its own, the other destinations carry on
+            if error.cancelled() && !run.cancelled() {
+                handle(CopyEvent::DestinationCancelled { dest });
+                return Ok(());
+            }
+            match io.keep_going && !error.cancelled() {
+                true => handle(CopyEvent::FileFailed { dest, error }),
+                false => return Err(error),
+            }
+            // Everything else would fail the same way
+            if drive.is_none() && !dest_path.exists() {
+                break;
+            }
+        }
+        let Some(entry) = entries.next() else {
+            break;
+        };
+        let entry = entry.map_err(|e| CopyError::new(&source.name(), None, e))?;
+        copied = io
+            .check_cancelled()
+            .map_err(|e| CopyError::new(dest_path, Some(dest_path), e))
+            .and_then(|()| copy_entry(source, entry, target, io, handle));
+    }
+    handle(CopyEvent::DestinationDone { dest });
+    Ok(())
+}
+
+///
+/// Destination number `dest` at `path`, as `copy_destination` writes to it
+///
+#[derive(Clone, Copy)]
+struct Target<'a> {
+    dest: usize,
+    path: &'a Path,
+    start: &'a ResumePoint,
+    /// Whether it is FAT, which holds less of the metadata `--preserve` keeps
+    fat: bool,
+    /// What is written to instead under `--simulate-speed`
+    drive: Option<&'a SimulatedDrive>,
+}
+
+///
+/// Copies one entry of the source to destination number `dest`, see `copy_destination`
+///
+fn copy_entry(
+    source: &Source,
+    entry: Entry,
+    target: Target,
+    io: IoHooks,
+    handle: &mut impl FnMut(CopyEvent),
+) -> Result<(), CopyError> {
+    let Target {
+        dest,
+        path: dest_path,
+        start,
+        fat,
+        drive,
+    } = target;
+    let (path, mut size) = match entry {
+        // Only the files take time to write
+        Entry::Dir(_) | Entry::Link(..) if drive.is_some() => return Ok(()),
+        Entry::Dir(dir) => return create_dir(dest_path, &dir),
+        Entry::Link(path, link) => return create_link(dest, dest_path, path, link, handle),
+        Entry::File(path, size) => (path, size),
+    };
+    let (source_file, dest_file) = (source.path(&path), dest_path.join(&path));
+    // A simulated destination starts out empty, whatever is at its path
+    if drive.is_none()
+        && (!start.wants(&path)
+            || io.unchanged(&source_file, &dest_file)
+            || !(start.resumes(&path)
+                || io.overwrites(dest, &path, Some(&source_file), &dest_file)))
+    {
+        handle(CopyEvent::FileSkipped {
+            dest,
+            file: path,
+            size,
+        });
+        return Ok(());
+    }
+    let mut offset = start.offset(&path, size, dest_path);
+    handle(CopyEvent::FileStarted {
+        dest,
+        file: path.clone(),
+        size,
+        offset,
+    });
+    // A retry starts the file over, from the same offset
+    let mut retries = 0;
+    // Whether the file changed while it was read once already, see `--changing-files`
+    let mut reread = false;
+    loop {
+        let mut throttled = offset;
+        let progress = |file_bytes: usize| {
+            io.after_read(file_bytes.saturating_sub(throttled))?;
+            throttled = file_bytes;
+            handle(CopyEvent::Progress { dest, file_bytes });
+            Ok(())
+        };
+        let copied = match drive {
+            Some(mut drive) => io
+                .chaos
+                .map_or(Ok(()), Chaos::before_write)
+                .and_then(|()| write_from_offset(&source_file, &mut drive, offset, io, progress)),
+            None => io.before_write(&dest_file).and_then(|()| {
+                let read = copy_from_offset(&source_file, &dest_file, offset, io, progress)?;
+                match io.kept(&source_file) {
+                    Some(kept) => OpenOptions::new()
+                        .write(true)
+                        .open(&dest_file)
+                        .and_then(|file| kept.apply(&file, fat))
+                        .map(|()| read),
+                    None => Ok(read),
+                }
+            }),
+        };
+        match copied.map(|read| changed_while_read(&source_file, size, read)) {
+            Ok(None) => break,
+            Ok(Some(change)) => {
+                // Part of one version and part of another, of no use to anyone
+                if drive.is_none() {
+                    let _ = ::std::fs::remove_file(&dest_file);
+                }
+                if io.changing == ChangingFiles::Skip || ::std::mem::replace(&mut reread, true) {
+                    handle(CopyEvent::FileChanged {
+                        dest,
+                        file: path,
+                        size,
+                    });
+                    return Ok(());
+                }
+                handle(CopyEvent::Retried {
+                    error: CopyError::new(&path, Some(dest_path), change),
+                    attempt: 1,
+                });
+                size = ::std::fs::metadata(&source_file)
+                    .map_or(size, |metadata| metadata.len() as usize);
+                offset = 0;
+            }
+            Err(e) if io.retry_after(&e, &mut retries) => handle(CopyEvent::Retried {
+                error: CopyError::new(&path, Some(dest_path), e),
+                attempt: retries,
+            }),
+            Err(e) => return Err(CopyError::new(&path, Some(dest_path), e)),
+        }
+    }
+    handle(CopyEvent::FileDone {
+        dest,
+        file: path,
+        size,
+    });
+    Ok(())
+}
+
+///
+/// Writes `source` to `dest`, keeping the first `offset` bytes an earlier run already wrote.
+/// `progress` gets the bytes of the file that are on `dest` so far, and stops the copy by failing.
+/// Returns how many bytes the file has on `dest`, which is what was read of `source`.
+///
+/// With `Engine::Auto` the file is cloned or copied by the system where it can (see `offload`),
+/// and only what it leaves is read through a buffer of ours.
+///
+fn copy_from_offset(
+    source: &Path,
+    dest: &Path,
+    offset: usize,
+    io: IoHooks,
+    mut progress: impl FnMut(usize) -> ::std::io::Result<()>,
+) -> ::std::io::Result<usize> {
+    let offloaded = io.engine == Engine::Auto;
+    if offloaded && offset == 0 {
+        if let Some(cloned) = offload::clone_file(source, dest) {
+            io.counters.cloned();
+            progress(cloned)?;
+            return Ok(cloned);
+        }
+        if let Some(copied) = offload::copy_file(source, dest, &mut progress) {
+            io.counters.offloaded();
+            return copied;
+        }
+    }
+    let mut writer = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(offset == 0)
+        .open(dest)?;
+    writer.set_len(offset as u64)?;
+
+    let reader = File::open(source)?;
+    let size = reader.metadata()?.len() as usize;
+    let mut file_bytes = offset;
+    while offloaded && file_bytes < size {
+        let chunk = (size - file_bytes).min(io.buffer);
+        // Stops at the end of what the file systems can copy between them, or of the file
+        // when it's shorter than it was, and the rest is copied below
+        let Some(copied) =
+            offload::copy_range(&reader, &writer, file_bytes, chunk).filter(|copied| *copied > 0)
+        else {
+            break;
+        };
+        io.counters.read();
+        io.counters.refill();
+        io.counters.write();
+        file_bytes += copied;
+        progress(file_bytes)?;
     }
+    writer.seek(SeekFrom::End(0))?;
+    write_from_offset(source, &mut writer, file_bytes, io, progress)
+}
+
+///
+/// Writes `source` from `offset` on to `writer`, `io.buffer` bytes at a time, see
+/// `copy_from_offset`. Returns the bytes of the file `writer` has, the first `offset` of them
+/// included.
+///
+fn write_from_offset(
+    source: &Path,
+    writer: &mut impl Write,
+    offset: usize,
+    io: IoHooks,
+    mut progress: impl FnMut(usize) -> ::std::io::Result<()>,
+) -> ::std::io::Result<usize> {
+    let counters = io.counters;
+    let mut file = File::open(source)?;
+    file.seek(SeekFrom::Start(offset as u64))?;
+    let mut reader = counters.reading(file);
+
+    let mut buffer = vec![0; io.buffer];
+    let mut file_bytes = offset;
+    loop {
+        let read = match reader.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(e) if e.kind() == ::std::io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        counters.refill();
+        counters.write();
+        writer.write_all(&buffer[..read])?;
+        file_bytes += read;
+        progress(file_bytes)?;
+    }
+    writer.flush()?;
+    Ok(file_bytes)
+}
+
+///
+/// Why the source file at `path` can't be taken as read whole: it was listed with `size` bytes,
+/// but `read` bytes of it were read or it has another size by now, like a log file still being
+/// written to
+///
+pub(crate) fn changed_while_read(
+    path: &Path,
+    size: usize,
+    read: usize,
+) -> Option<::std::io::Error> {
+    let now = ::std::fs::metadata(path).map_or(read, |metadata| metadata.len() as usize);
+    (read != size || now != size).then(|| {
+        ::std::io::Error::other(format!(
+            "changed size while it was read: {} bytes when listed, {} read, {} now",
+            size, read, now
+        ))
+    })
+}
+
+///
+/// Recreates the symlink `path` (relative to the source) on destination number `dest`, unless it
+/// is there already
+///
+pub(crate) fn create_link(
+    dest: usize,
+    dest_path: &Path,
+    path: PathBuf,
+    link: Symlink,
+    handle: &mut impl FnMut(CopyEvent),
+) -> Result<(), CopyError> {
+    link.create(&dest_path.join(&path))
+        .map_err(|e| CopyError::new(&path, Some(dest_path), e))?;
+    handle(CopyEvent::LinkCreated {
+        dest,
+        file: path,
+        link,
+    });
+    Ok(())
+}
+
+///
+/// Creates `dir` (relative to the source) on `dest`, or `dest` itself for an empty `dir`
+///
+pub(crate) fn create_dir(dest: &Path, dir: &Path) -> Result<(), CopyError> {
+    let path = match dir.as_os_str().is_empty() {
+        true => dest,
+        false => dir,
+    };
+    ::std::fs::create_dir_all(dest.join(dir)).map_err(|e| CopyError::new(path, Some(dest), e))
 }
@@ -0,0 +1,134 @@
+use serde::Serialize;
+use std::{
+    fmt,
+    io::Read,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+///
+/// What the copy engine did at the lowest level, counted as it goes so a slow run can be looked
+/// into from `-vv` and the `--report` rather than with strace. Shared by every copy thread.
+///
+#[derive(Debug, Default)]
+pub struct Counters {
+    reads: AtomicU64,
+    writes: AtomicU64,
+    refills: AtomicU64,
+    retries: AtomicU64,
+    cache_hits: AtomicU64,
+    clones: AtomicU64,
+    offloads: AtomicU64,
+}
+
+impl Counters {
+    ///
+    /// `reader` with every read call on it counted
+    ///
+    pub fn reading<R: Read>(&self, reader: R) -> CountedReader<'_, R> {
+        CountedReader {
+            reader,
+            counters: self,
+        }
+    }
+
+    ///
+    /// A read that didn't go through `reading`, such as a `copy_file_range` call
+    ///
+    pub fn read(&self) {
+        self.reads.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn write(&self) {
+        self.writes.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn refill(&self) {
+        self.refills.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn retry(&self) {
+        self.retries.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn cache_hit(&self) {
+        self.cache_hits.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn cloned(&self) {
+        self.clones.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn offloaded(&self) {
+        self.offloads.fetch_add(1, Ordering::Relaxed);
+    }
+
+    ///
+    /// The counts so far
+    ///
+    pub fn counts(&self) -> Counts {
+        Counts {
+            reads: self.reads.load(Ordering::Relaxed),
+            writes: self.writes.load(Ordering::Relaxed),
+            refills: self.refills.load(Ordering::Relaxed),
+            retries: self.retries.load(Ordering::Relaxed),
+            cache_hits: self.cache_hits.load(Ordering::Relaxed),
+            clones: self.clones.load(Ordering::Relaxed),
+            offloads: self.offloads.load(Ordering::Relaxed),
+        }
+    }
+}
+
+///
+/// The `Counters` of a run at one point
+///
+#[derive(Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Counts {
+    /// Read calls on source files and streams
+    pub reads: u64,
+    /// Write calls on destination files, one per chunk written
+    pub writes: u64,
+    /// Times the copy buffer was filled up from the source
+    pub refills: u64,
+    /// Reads and writes tried again after a transient error
+    pub retries: u64,
+    /// Files `--incremental` found on a destination already, which weren't read at all
+    pub cache_hits: u64,
+    /// Files the file system cloned (reflinks), which share their blocks with the source rather
+    /// than take reads or writes of ours
+    pub clones: u64,
+    /// Files the system copied in full itself (`CopyFileExW`), which take no reads or writes of
+    /// ours either but do move every byte
+    pub offloads: u64,
+}
+
+impl fmt::Display for Counts {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} reads, {} writes, {} buffer refills, {} retries, {} cache hits, {} clones, {} \
+             offloaded copies",
+            self.reads,
+            self.writes,
+            self.refills,
+            self.retries,
+            self.cache_hits,
+            self.clones,
+            self.offloads
+        )
+    }
+}
+
+///
+/// A reader that counts its read calls, see `Counters::reading`
+///
+pub struct CountedReader<'a, R> {
+    reader: R,
+    counters: &'a Counters,
+}
+
+impl<R: Read> Read for CountedReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> ::std::io::Result<usize> {
+        self.counters.reads.fetch_add(1, Ordering::Relaxed);
+        self.reader.read(buf)
+    }
+}
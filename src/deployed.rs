@@ -0,0 +1,131 @@
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use crate::{
+    hash::HashPool,
+    manifest::{Manifest, SHA256SUMS},
+    start::Delay,
+    walk::{Entry, Selection, Source},
+};
+
+///
+/// A destination that already holds the payload of the run, going by the `SHA256SUMS` an
+/// earlier `--sha256sums` run left in its root
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Deployed {
+    /// When that `SHA256SUMS` was written
+    pub at: SystemTime,
+}
+
+impl Deployed {
+    ///
+    /// How long ago the payload was deployed, to the largest whole unit, e.g. `2h` rather than
+    /// `2h13m5s`
+    ///
+    pub fn ago(&self) -> Delay {
+        let secs = SystemTime::now()
+            .duration_since(self.at)
+            .unwrap_or_default()
+            .as_secs();
+        let unit = [24 * 60 * 60, 60 * 60, 60]
+            .into_iter()
+            .find(|unit| secs >= *unit)
+            .unwrap_or(1);
+        Delay(Duration::from_secs((secs / unit * unit).max(1)))
+    }
+}
+
+///
+/// Which of `destinations` already hold exactly what `selection` takes from `source`: the
+/// `SHA256SUMS` in their root is what `--sha256sums` would write for the payload now, and the
+/// files it lists are there with the sizes they have in the source. The source is only hashed,
+/// once and on `threads` threads, when some destination passes the cheaper checks. Anything
+/// that can't be read counts as not deployed, the copy goes ahead and finds out.
+///
+pub fn deployed(
+    source: &Source,
+    selection: &Selection,
+    destinations: &[PathBuf],
+    threads: usize,
+) -> Vec<Option<Deployed>> {
+    let manifests = destinations
+        .iter()
+        .map(|dest| {
+            let path = dest.join(SHA256SUMS);
+            let sums = ::std::fs::read(&path).ok()?;
+            let at = ::std::fs::metadata(&path)
+                .and_then(|metadata| metadata.modified())
+                .unwrap_or_else(|_| SystemTime::now());
+            Some((sums, at))
+        })
+        .collect::<Vec<_>>();
+    if manifests.iter().all(Option::is_none) {
+        return vec![None; destinations.len()];
+    }
+
+    let Some(files) = payload(source, selection) else {
+        return vec![None; destinations.len()];
+    };
+    let candidates = destinations
+        .iter()
+        .zip(manifests)
+        .map(|(dest, manifest)| {
+            manifest.filter(|(sums, _)| {
+                sums.split(|byte| *byte == b'\n')
+                    .filter(|line| !line.is_empty())
+                    .count()
+                    == files.len()
+                    && files
+                        .iter()
+                        .all(|(file, size)| same_size(dest, file, *size))
+            })
+        })
+        .collect::<Vec<_>>();
+    if candidates.iter().all(Option::is_none) {
+        return vec![None; destinations.len()];
+    }
+
+    let pool = HashPool::new(threads);
+    for (file, _) in &files {
+        pool.submit(file.clone(), source.path(file));
+    }
+    let hashes = pool
+        .finish()
+        .into_iter()
+        .map(|(file, hash)| Some((file, hash.ok()?)))
+        .collect::<Option<_>>();
+    let Some(hashes) = hashes else {
+        return vec![None; destinations.len()];
+    };
+    let expected = Manifest::new(hashes).to_sha256sums();
+    candidates
+        .into_iter()
+        .map(|candidate| {
+            let (sums, at) = candidate?;
+            (sums == expected.as_bytes()).then_some(Deployed { at })
+        })
+        .collect()
+}
+
+///
+/// The files of the payload with their sizes, `None` if the source can't be walked or has links
+/// kept as links, which `SHA256SUMS` doesn't list
+///
+fn payload(source: &Source, selection: &Selection) -> Option<Vec<(PathBuf, usize)>> {
+    let mut files = Vec::new();
+    for entry in selection.walk(source) {
+        match entry.ok()? {
+            Entry::Dir(_) => {}
+            Entry::File(file, size) => files.push((file, size)),
+            Entry::Link(..) => return None,
+        }
+    }
+    Some(files)
+}
+
+fn same_size(dest: &Path, file: &Path, size: usize) -> bool {
+    ::std::fs::metadata(dest.join(file)).is_ok_and(|metadata| metadata.len() as usize == size)
+}
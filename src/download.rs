@@ -0,0 +1,265 @@
+use serde::{Deserialize, Serialize, Serializer};
+use sha2::{Digest, Sha256};
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    str::FromStr,
+    time::Duration,
+};
+
+use crate::{hash::sha256_file, update};
+
+/// How long looking up the size of a download or its `.sha256` may take, in seconds
+const LOOKUP_TIMEOUT: &str = "10";
+/// How often the size of a running download is looked at
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(200);
+/// Names of archives that are unpacked rather than deployed as a single file
+pub const ARCHIVES: [&str; 6] = [".tar", ".tar.gz", ".tgz", ".tar.xz", ".tar.bz2", ".zip"];
+
+///
+/// The hex SHA-256 a download has to match (`--source-sha256`)
+///
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Checksum(String);
+
+impl FromStr for Checksum {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let hex = s.trim().to_lowercase();
+        match hex.len() == 64 && hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            true => Ok(Checksum(hex)),
+            false => Err(format!("`{}` is not a SHA-256, 64 hex digits", s)),
+        }
+    }
+}
+
+impl fmt::Display for Checksum {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl Serialize for Checksum {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl TryFrom<String> for Checksum {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+///
+/// A payload fetched with `--source-url`, unpacked when it is an archive
+///
+#[derive(Debug, Clone)]
+pub struct Download {
+    /// What the run deploys: the unpacked archive, or the file as it was downloaded
+    pub source: PathBuf,
+    pub bytes: u64,
+    /// Hex SHA-256 of what was downloaded
+    pub sha256: String,
+    /// What it was checked against: `--source-sha256`, a `.sha256` published next to it, or
+    /// nothing when there was neither
+    pub checked: Option<Checksum>,
+}
+
+///
+/// Where the payload of `url` is downloaded and unpacked to, in the temp directory. The same URL
+/// always goes to the same place, so `--resume` finds the source it checkpointed.
+///
+pub fn download_dir(url: &str) -> PathBuf {
+    let digest = format!("{:x}", Sha256::digest(url.as_bytes()));
+    ::std::env::temp_dir()
+        .join("decopy-source")
+        .join(&digest[..16])
+}
+
+///
+/// Downloads `url` into `download_dir`, checks it against `expected` or the `.sha256` next to it
+/// and unpacks it when it is an archive (see `ARCHIVES`), with the `tar` command that ships with
+/// Windows 10 and later and every Linux distribution, or `unzip` for zips outside Windows.
+/// Downloads go through `curl`, as updates do.
+///
+/// Callbacks:
+/// * `onprogress` - `|downloaded: u64, total: Option<u64>| -> ()`, the total when the server
+///   tells it
+///
+pub fn download(
+    url: &str,
+    expected: Option<&Checksum>,
+    mut onprogress: impl FnMut(u64, Option<u64>),
+) -> Result<Download, String> {
+    let dir = download_dir(url);
+    remove(&dir)?;
+    ::std::fs::create_dir_all(&dir)
+        .map_err(|e| format!("could not create `{}`: {}", dir.display(), e))?;
+
+    let name = file_name(url);
+    let file = dir.join(&name);
+    let total = content_length(url);
+    let mut child = curl()
+        .arg("--output")
+        .arg(&file)
+        .arg(url)
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("could not run curl: {}", e))?;
+    let status = loop {
+        let downloaded = ::std::fs::metadata(&file).map_or(0, |metadata| metadata.len());
+        onprogress(downloaded, total);
+        match child.try_wait() {
+            Ok(Some(status)) => break status,
+            Ok(None) => ::std::thread::sleep(PROGRESS_INTERVAL),
+            Err(e) => return Err(format!("could not wait for curl: {}", e)),
+        }
+    };
+    if !status.success() {
+        let output = child.wait_with_output().map_err(|e| e.to_string())?;
+        return Err(format!(
+            "could not download `{}`: {}",
+            url,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    let bytes = ::std::fs::metadata(&file).map_or(0, |metadata| metadata.len());
+    onprogress(bytes, Some(bytes));
+
+    let sha256 =
+        sha256_file(&file).map_err(|e| format!("could not read `{}`: {}", file.display(), e))?;
+    let checked = match expected {
+        Some(expected) => Some(expected.clone()),
+        None => published_checksum(url),
+    };
+    if let Some(checked) = checked.as_ref().filter(|checked| checked.0 != sha256) {
+        return Err(format!(
+            "the download of `{}` doesn't match its checksum (sha256 {}, {} expected), nothing \
+             was deployed",
+            url, sha256, checked
+        ));
+    }
+
+    let lowercase = name.to_lowercase();
+    let source = match ARCHIVES.iter().any(|suffix| lowercase.ends_with(suffix)) {
+        true => {
+            let unpacked = dir.join("source");
+            unpack(&file, &unpacked)?;
+            unpacked
+        }
+        false => file,
+    };
+    Ok(Download {
+        source,
+        bytes,
+        sha256,
+        checked,
+    })
+}
+
+///
+/// Removes what `download` left in `download_dir`, once the run is through with it
+///
+pub fn forget(url: &str) -> Result<(), String> {
+    remove(&download_dir(url))
+}
+
+fn remove(dir: &Path) -> Result<(), String> {
+    match ::std::fs::remove_dir_all(dir) {
+        Err(e) if e.kind() != ::std::io::ErrorKind::NotFound => {
+            Err(format!("could not remove `{}`: {}", dir.display(), e))
+        }
+        _ => Ok(()),
+    }
+}
+
+///
+/// The last part of the path of `url`, without its query, which names the download
+///
+fn file_name(url: &str) -> String {
+    let path = url.split(['?', '#']).next().unwrap_or_default();
+    path.rsplit('/')
+        .find(|part| !part.is_empty() && !part.contains(':'))
+        .map_or("download".to_string(), str::to_string)
+}
+
+///
+/// The size the server gives for `url`, if it answers a HEAD request with one
+///
+fn content_length(url: &str) -> Option<u64> {
+    let output = curl()
+        .args(["--head", "--max-time", LOOKUP_TIMEOUT])
+        .arg(url)
+        .output()
+        .ok()
+        .filter(|output| output.status.success())?;
+    // Every redirect has headers of its own, the last are the download's
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| line.split_once(':'))
+        .filter(|(name, _)| name.trim().eq_ignore_ascii_case("content-length"))
+        .filter_map(|(_, value)| value.trim().parse().ok())
+        .next_back()
+}
+
+///
+/// The SHA-256 in the `.sha256` file published next to `url`, as `sha256sum` writes it
+///
+fn published_checksum(url: &str) -> Option<Checksum> {
+    let sums = update::fetch(&format!("{}.sha256", url), LOOKUP_TIMEOUT).ok()?;
+    String::from_utf8_lossy(&sums)
+        .split_whitespace()
+        .next()?
+        .parse()
+        .ok()
+}
+
+fn unpack(archive: &Path, dir: &Path) -> Result<(), String> {
+    ::std::fs::create_dir_all(dir)
+        .map_err(|e| format!("could not create `{}`: {}", dir.display(), e))?;
+    // The tar of Windows reads zips too, GNU tar doesn't
+    let zip = archive.to_string_lossy().to_lowercase().ends_with(".zip") && !cfg!(windows);
+    let mut command = match zip {
+        true => {
+            let mut unzip = Command::new("unzip");
+            unzip.arg("-q").arg(archive).arg("-d").arg(dir);
+            unzip
+        }
+        false => {
+            let mut tar = Command::new("tar");
+            tar.arg("-xf").arg(archive).arg("-C").arg(dir);
+            tar
+        }
+    };
+    let output = command.stdin(Stdio::null()).output().map_err(|e| {
+        format!(
+            "could not run {}: {}",
+            command.get_program().to_string_lossy(),
+            e
+        )
+    })?;
+    match output.status.success() {
+        true => Ok(()),
+        false => Err(format!(
+            "could not unpack `{}`: {}",
+            archive.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )),
+    }
+}
+
+fn curl() -> Command {
+    let mut curl = Command::new("curl");
+    curl.args(["--fail", "--silent", "--show-error", "--location"])
+        .args([
+            "--user-agent",
+            concat!("decopy/", env!("CARGO_PKG_VERSION")),
+        ]);
+    curl
+}
@@ -0,0 +1,1063 @@
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+///
+/// The filesystem label of the volume `path` lives on (e.g. `FIRMWARE` for a USB stick), where
+/// the platform exposes one
+///
+pub fn volume_label(path: &Path) -> Option<String> {
+    imp::volume_label(path).filter(|label| !label.is_empty())
+}
+
+///
+/// The `\\?\Volume{GUID}\` path of the volume `path` lives on. Unlike drive letters it stays the
+/// same across reboots and replugs. Windows only.
+///
+pub fn volume_guid(path: &Path) -> Option<String> {
+    #[cfg(windows)]
+    return imp::volume_guid(path);
+    #[cfg(not(windows))]
+    return {
+        let _ = path;
+        None
+    };
+}
+
+///
+/// The hardware serial number of the drive `path` lives on, which stays the same when the drive
+/// is relabeled or reformatted. From udev or sysfs on Linux and the storage device descriptor on
+/// Windows, where the drive reports one.
+///
+pub fn serial_number(path: &Path) -> Option<String> {
+    #[cfg(any(target_os = "linux", windows))]
+    return imp::serial_number(path)
+        .map(|serial| serial.trim().to_string())
+        .filter(|serial| !serial.is_empty());
+    #[cfg(not(any(target_os = "linux", windows)))]
+    return {
+        let _ = path;
+        None
+    };
+}
+
+///
+/// What tells the volume `path` lives on apart from others across runs: its volume GUID on
+/// Windows, its filesystem UUID on Linux and its label elsewhere, as `guid:`, `uuid:` or `label:`
+/// followed by it
+///
+pub fn volume_id(path: &Path) -> Option<String> {
+    #[cfg(windows)]
+    return imp::volume_guid(path).map(|guid| format!("guid:{}", guid));
+    #[cfg(target_os = "linux")]
+    return imp::volume_uuid(path).map(|uuid| format!("uuid:{}", uuid));
+    #[cfg(not(any(target_os = "linux", windows)))]
+    return volume_label(path).map(|label| format!("label:{}", label));
+}
+
+///
+/// Where a `\\?\Volume{GUID}\` path is currently mounted, e.g. `E:\`. Windows only.
+///
+pub fn current_mount(path: &Path) -> Option<PathBuf> {
+    #[cfg(windows)]
+    return imp::current_mount(path);
+    #[cfg(not(windows))]
+    return {
+        let _ = path;
+        None
+    };
+}
+
+///
+/// Bytes free for this user on the volume `path` lives on. A destination that doesn't exist yet
+/// is looked up through the closest directory above it that does.
+///
+pub fn free_space(path: &Path) -> Option<u64> {
+    // A relative path runs out of ancestors at the empty path, which is the working directory
+    let existing = path
+        .ancestors()
+        .map(|ancestor| match ancestor.as_os_str().is_empty() {
+            true => Path::new("."),
+            false => ancestor,
+        })
+        .find(|ancestor| ancestor.exists())?;
+    imp::free_space(existing)
+}
+
+///
+/// The name of the filesystem `path` lives on as the platform reports it, e.g. `vfat` or `ext4`
+/// on Linux, `FAT32` or `NTFS` on Windows and `msdos` or `apfs` on macOS. A path that doesn't
+/// exist yet is looked up like for `free_space`.
+///
+pub fn filesystem(path: &Path) -> Option<String> {
+    let existing = path
+        .ancestors()
+        .map(|ancestor| match ancestor.as_os_str().is_empty() {
+            true => Path::new("."),
+            false => ancestor,
+        })
+        .find(|ancestor| ancestor.exists())?;
+    imp::filesystem(existing)
+}
+
+///
+/// `path` with symlinks, `.` and `..` resolved as far as it exists, and the rest as given, so a
+/// destination that doesn't exist yet can be compared with others
+///
+pub fn canonical(path: &Path) -> PathBuf {
+    path.ancestors()
+        .find_map(|ancestor| {
+            let existing = match ancestor.as_os_str().is_empty() {
+                true => Path::new("."),
+                false => ancestor,
+            };
+            let rest = path.strip_prefix(ancestor).ok()?;
+            Some(existing.canonicalize().ok()?.join(rest))
+        })
+        .unwrap_or_else(|| path.to_path_buf())
+}
+
+///
+/// Which file or directory `path` is on its volume, the same whichever mount, bind mount or
+/// `subst` drive it is reached through: the device and inode on Unix, the volume serial number
+/// and file index on Windows. `None` for a path that doesn't exist.
+///
+pub fn file_id(path: &Path) -> Option<(u64, u64)> {
+    imp::file_id(path)
+}
+
+///
+/// Whether `path` is `dir` or inside it when reached another way, e.g. through a bind mount or
+/// `subst` drive of it, which comparing canonical paths misses
+///
+pub fn is_within(path: &Path, dir: &Path) -> bool {
+    let Some(dir) = file_id(dir) else {
+        return false;
+    };
+    canonical(path)
+        .ancestors()
+        .any(|ancestor| file_id(ancestor) == Some(dir))
+}
+
+///
+/// Whether `path` lives on FAT, which keeps modification times to 2 seconds from 1980 on and has
+/// no permissions beyond a read-only flag. exFAT shares the latter.
+///
+pub fn is_fat(path: &Path) -> bool {
+    filesystem(path).is_some_and(|name| {
+        matches!(
+            name.to_ascii_lowercase().as_str(),
+            "vfat" | "msdos" | "fat" | "fat12" | "fat16" | "fat32"
+        )
+    })
+}
+
+pub fn is_volume_guid_path(path: &Path) -> bool {
+    path.to_string_lossy()
+        .to_ascii_lowercase()
+        .starts_with(r"\\?\volume{")
+}
+
+///
+/// How a destination is shown to the operator: the path as given plus, where they differ, the
+/// drive letter it is currently mounted on or the volume GUID backing it
+///
+pub fn describe(dest: &Path) -> String {
+    if is_volume_guid_path(dest) {
+        return match current_mount(dest) {
+            Some(mount) => format!("{} ({})", mount.display(), dest.display()),
+            None => format!("{} (not mounted)", dest.display()),
+        };
+    }
+    match volume_guid(dest) {
+        Some(guid) => format!("{} ({})", dest.display(), guid),
+        None => dest.display().to_string(),
+    }
+}
+
+///
+/// Where filesystems on the block device `device`, or on its partitions, are mounted. Writing a
+/// raw image underneath them would corrupt whatever is using them. Linux only.
+///
+pub fn mounts_on_device(device: &Path) -> Vec<PathBuf> {
+    #[cfg(target_os = "linux")]
+    return imp::mounts_on_device(device);
+    #[cfg(not(target_os = "linux"))]
+    return {
+        let _ = device;
+        Vec::new()
+    };
+}
+
+/// How long to wait for the partitions of a freshly written image to show up
+pub const PARTITION_TIMEOUT: Duration = Duration::from_secs(15);
+
+///
+/// Mounts partition `number` (counting from 1) of the block device `device`, once the OS has
+/// picked up the partition table an image just written onto it brought along. Returns where the
+/// partition is mounted, which may be where an automounter put it first. Linux only.
+///
+pub fn mount_partition(device: &Path, number: u32) -> ::std::io::Result<PathBuf> {
+    #[cfg(target_os = "linux")]
+    return imp::mount_partition(device, number);
+    #[cfg(not(target_os = "linux"))]
+    return {
+        let _ = (device, number);
+        Err(::std::io::Error::new(
+            ::std::io::ErrorKind::Unsupported,
+            "mounting partitions is only supported on Linux",
+        ))
+    };
+}
+
+///
+/// Unmounts the volume mounted at `mount`, leaving the drive powered for what comes next
+///
+pub fn unmount(mount: &Path) -> ::std::io::Result<()> {
+    #[cfg(target_os = "linux")]
+    return imp::unmount(mount);
+    #[cfg(not(target_os = "linux"))]
+    return {
+        let _ = mount;
+        Err(::std::io::Error::new(
+            ::std::io::ErrorKind::Unsupported,
+            "unmounting partitions is only supported on Linux",
+        ))
+    };
+}
+
+/// How long to wait for the OS to let go of an ejected drive before giving up
+pub const EJECT_TIMEOUT: Duration = Duration::from_secs(30);
+
+///
+/// Ejects the volume `path` lives on and waits until it is really gone, see `eject` and
+/// `wait_until_removed`
+///
+pub fn eject_and_wait(path: &Path, onwait: impl FnMut(Duration)) -> ::std::io::Result<()> {
+    let mount = eject(path)?;
+    wait_until_removed(&mount, EJECT_TIMEOUT, onwait)
+}
+
+///
+/// Unmounts and ejects the volume `path` lives on, returning its mount point for
+/// `wait_until_removed`
+///
+pub fn eject(path: &Path) -> ::std::io::Result<PathBuf> {
+    let mount = imp::mount_point(path).ok_or_else(|| {
+        ::std::io::Error::new(
+            ::std::io::ErrorKind::NotFound,
+            format!("could not find the volume of `{}`", path.display()),
+        )
+    })?;
+    imp::eject(&mount)?;
+    Ok(mount)
+}
+
+///
+/// Writes everything the OS still holds in its write cache for the volume `path` lives on out
+/// to the drive, and waits until the drive took it. Removable drives report writes as done as
+/// soon as they are cached, long before they are on the drive.
+///
+pub fn sync(path: &Path) -> ::std::io::Result<()> {
+    let mount = imp::mount_point(path).unwrap_or_else(|| path.to_path_buf());
+    imp::sync(&mount)
+}
+
+///
+/// Polls until the OS no longer lists `mount` as mounted, so the drive can really be pulled.
+/// `onwait` gets the time left before giving up, about every quarter second.
+///
+pub fn wait_until_removed(
+    mount: &Path,
+    timeout: Duration,
+    mut onwait: impl FnMut(Duration),
+) -> ::std::io::Result<()> {
+    let started = Instant::now();
+    while imp::is_mounted(mount) {
+        let waited = started.elapsed();
+        if waited >= timeout {
+            return Err(::std::io::Error::new(
+                ::std::io::ErrorKind::TimedOut,
+                format!("`{}` is still mounted", mount.display()),
+            ));
+        }
+        onwait(timeout - waited);
+        ::std::thread::sleep(Duration::from_millis(250));
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn statvfs_free_space(path: &Path) -> Option<u64> {
+    use ::std::os::unix::ffi::OsStrExt;
+    let path = ::std::ffi::CString::new(path.as_os_str().as_bytes()).ok()?;
+    // SAFETY: `path` is nul terminated and `stats` is only read after statvfs filled it in
+    let mut stats = unsafe { ::std::mem::zeroed::<libc::statvfs>() };
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stats) } != 0 {
+        return None;
+    }
+    Some(stats.f_bavail as u64 * stats.f_frsize as u64)
+}
+
+#[cfg(unix)]
+fn stat_file_id(path: &Path) -> Option<(u64, u64)> {
+    use ::std::os::unix::fs::MetadataExt;
+    let metadata = ::std::fs::metadata(path).ok()?;
+    Some((metadata.dev(), metadata.ino()))
+}
+
+#[cfg(unix)]
+fn run(command: &mut ::std::process::Command) -> ::std::io::Result<()> {
+    let output = command.output()?;
+    if !output.status.success() {
+        return Err(::std::io::Error::other(
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+mod imp {
+    use std::{
+        path::{Path, PathBuf},
+        process::Command,
+        time::{Duration, Instant},
+    };
+
+    use super::run;
+
+    ///
+    /// The mount point, device and filesystem of the mount containing `path`, read from
+    /// `/proc/self/mountinfo`
+    ///
+    fn mount_of(path: &Path) -> Option<(PathBuf, String, String)> {
+        let path = path.canonicalize().ok()?;
+        let mountinfo = ::std::fs::read_to_string("/proc/self/mountinfo").ok()?;
+
+        mountinfo
+            .lines()
+            .filter_map(|line| {
+                let mut fields = line.split(' ');
+                let mount_point = PathBuf::from(unescape(fields.nth(4)?));
+                let (_, rest) = line.split_once(" - ")?;
+                let mut rest = rest.split(' ');
+                let filesystem = rest.next()?.to_string();
+                let device = rest.next()?.to_string();
+                Some((mount_point, device, filesystem))
+            })
+            .filter(|(mount_point, _, _)| path.starts_with(mount_point))
+            .max_by_key(|(mount_point, _, _)| mount_point.as_os_str().len())
+    }
+
+    pub fn mount_point(path: &Path) -> Option<PathBuf> {
+        mount_of(path).map(|(mount_point, _, _)| mount_point)
+    }
+
+    pub fn filesystem(path: &Path) -> Option<String> {
+        mount_of(path).map(|(_, _, filesystem)| filesystem)
+    }
+
+    pub fn free_space(path: &Path) -> Option<u64> {
+        super::statvfs_free_space(path)
+    }
+
+    pub fn file_id(path: &Path) -> Option<(u64, u64)> {
+        super::stat_file_id(path)
+    }
+
+    ///
+    /// The device of partition `number` of `device`, a digit at the end of the device name is
+    /// followed by a `p`: `/dev/sdb2`, but `/dev/mmcblk0p2` and `/dev/loop0p2`
+    ///
+    fn partition_path(device: &Path, number: u32) -> PathBuf {
+        let mut path = device.as_os_str().to_owned();
+        if device
+            .to_string_lossy()
+            .ends_with(|c: char| c.is_ascii_digit())
+        {
+            path.push("p");
+        }
+        path.push(number.to_string());
+        PathBuf::from(path)
+    }
+
+    ///
+    /// Where `partition` is mounted, if anywhere
+    ///
+    fn mount_of_device(partition: &Path) -> Option<PathBuf> {
+        let mountinfo = ::std::fs::read_to_string("/proc/self/mountinfo").ok()?;
+        mountinfo.lines().find_map(|line| {
+            let mount_point = PathBuf::from(unescape(line.split(' ').nth(4)?));
+            let (_, rest) = line.split_once(" - ")?;
+            let source = Path::new(&unescape(rest.split(' ').nth(1)?))
+                .canonicalize()
+                .ok()?;
+            (source == partition).then_some(mount_point)
+        })
+    }
+
+    pub fn mount_partition(device: &Path, number: u32) -> ::std::io::Result<PathBuf> {
+        let device = device.canonicalize()?;
+        let partition = partition_path(&device, number);
+
+        // udev usually re-reads the partition table once the image is written, this is for when
+        // it doesn't
+        let _ = run(Command::new("partx").arg("-u").arg(&device));
+        let started = Instant::now();
+        while !partition.exists() {
+            if started.elapsed() >= super::PARTITION_TIMEOUT {
+                return Err(::std::io::Error::new(
+                    ::std::io::ErrorKind::NotFound,
+                    format!(
+                        "`{}` didn't show up, does the image have that partition?",
+                        partition.display()
+                    ),
+                ));
+            }
+            ::std::thread::sleep(Duration::from_millis(250));
+        }
+
+        if let Some(mount) = mount_of_device(&partition) {
+            return Ok(mount);
+        }
+        let udisks = run(Command::new("udisksctl")
+            .args(["mount", "--no-user-interaction", "-b"])
+            .arg(&partition));
+        if udisks.is_err() {
+            let mount = ::std::env::temp_dir().join(format!(
+                "decopy-{}",
+                partition.file_name().unwrap_or_default().to_string_lossy()
+            ));
+            ::std::fs::create_dir_all(&mount)?;
+            run(Command::new("mount").arg(&partition).arg(&mount))?;
+        }
+        mount_of_device(&partition).ok_or_else(|| {
+            ::std::io::Error::new(
+                ::std::io::ErrorKind::NotFound,
+                format!("`{}` was mounted but can't be found", partition.display()),
+            )
+        })
+    }
+
+    pub fn unmount(mount: &Path) -> ::std::io::Result<()> {
+        if let Some((_, device, _)) = mount_of(mount) {
+            let udisks = run(Command::new("udisksctl").args([
+                "unmount",
+                "--no-user-interaction",
+                "-b",
+                &device,
+            ]));
+            if udisks.is_ok() {
+                return Ok(());
+            }
+        }
+        run(Command::new("umount").arg(mount))?;
+        // Left behind by the fallback of `mount_partition`
+        if mount.starts_with(::std::env::temp_dir()) {
+            let _ = ::std::fs::remove_dir(mount);
+        }
+        Ok(())
+    }
+
+    ///
+    /// Mounts whose source is `device` or one of its partitions, e.g. `/dev/sdb1` or
+    /// `/dev/nvme0n1p2` for `/dev/sdb` and `/dev/nvme0n1`
+    ///
+    pub fn mounts_on_device(device: &Path) -> Vec<PathBuf> {
+        let Ok(device) = device.canonicalize() else {
+            return Vec::new();
+        };
+        let device = device.to_string_lossy().into_owned();
+        let Ok(mountinfo) = ::std::fs::read_to_string("/proc/self/mountinfo") else {
+            return Vec::new();
+        };
+
+        mountinfo
+            .lines()
+            .filter_map(|line| {
+                let mount_point = PathBuf::from(unescape(line.split(' ').nth(4)?));
+                let (_, rest) = line.split_once(" - ")?;
+                let source = Path::new(&unescape(rest.split(' ').nth(1)?))
+                    .canonicalize()
+                    .ok()?;
+                let partition = source.to_str()?.strip_prefix(&device)?;
+                let partition = partition.strip_prefix('p').unwrap_or(partition);
+                partition
+                    .chars()
+                    .all(|c| c.is_ascii_digit())
+                    .then_some(mount_point)
+            })
+            .collect()
+    }
+
+    pub fn is_mounted(mount: &Path) -> bool {
+        ::std::fs::read_to_string("/proc/self/mountinfo")
+            .map(|mountinfo| {
+                mountinfo.lines().any(|line| {
+                    line.split(' ')
+                        .nth(4)
+                        .is_some_and(|mount_point| Path::new(&unescape(mount_point)) == mount)
+                })
+            })
+            .unwrap_or(false)
+    }
+
+    ///
+    /// Goes through udisks when it is around so unprivileged users can eject and the drive gets
+    /// powered off, falling back to a plain `umount`
+    ///
+    pub fn eject(mount: &Path) -> ::std::io::Result<()> {
+        let Some((_, device, _)) = mount_of(mount) else {
+            return Ok(());
+        };
+        let udisks = run(Command::new("udisksctl").args([
+            "unmount",
+            "--no-user-interaction",
+            "-b",
+            &device,
+        ]));
+        match udisks {
+            Ok(()) => {
+                // Best effort, not every device can be powered off
+                let _ = run(Command::new("udisksctl").args([
+                    "power-off",
+                    "--no-user-interaction",
+                    "-b",
+                    &device,
+                ]));
+                Ok(())
+            }
+            Err(_) => run(Command::new("umount").arg(mount)),
+        }
+    }
+
+    pub fn sync(mount: &Path) -> ::std::io::Result<()> {
+        use ::std::os::unix::io::AsRawFd;
+        let dir = ::std::fs::File::open(mount)?;
+        // SAFETY: the descriptor stays open for the duration of the call
+        match unsafe { libc::syncfs(dir.as_raw_fd()) } {
+            0 => Ok(()),
+            _ => Err(::std::io::Error::last_os_error()),
+        }
+    }
+
+    pub fn volume_label(path: &Path) -> Option<String> {
+        let (_, device, _) = mount_of(path)?;
+        let device = Path::new(&device).canonicalize().ok()?;
+
+        ::std::fs::read_dir("/dev/disk/by-label")
+            .ok()?
+            .filter_map(Result::ok)
+            .find(|entry| entry.path().canonicalize().ok().as_ref() == Some(&device))
+            .map(|entry| unescape(&entry.file_name().to_string_lossy()))
+    }
+
+    pub fn volume_uuid(path: &Path) -> Option<String> {
+        let (_, device, _) = mount_of(path)?;
+        let device = Path::new(&device).canonicalize().ok()?;
+
+        ::std::fs::read_dir("/dev/disk/by-uuid")
+            .ok()?
+            .filter_map(Result::ok)
+            .find(|entry| entry.path().canonicalize().ok().as_ref() == Some(&device))
+            .map(|entry| entry.file_name().to_string_lossy().into_owned())
+    }
+
+    pub fn serial_number(path: &Path) -> Option<String> {
+        let (_, device, _) = mount_of(path)?;
+        let device = Path::new(&device).canonicalize().ok()?;
+        let mut disk = Path::new("/sys/class/block")
+            .join(device.file_name()?)
+            .canonicalize()
+            .ok()?;
+        // A partition sits inside the disk it is on
+        if disk.join("partition").exists() {
+            disk.pop();
+        }
+
+        // udev has it for USB sticks and card readers, whose serial sysfs doesn't show
+        let numbers = ::std::fs::read_to_string(disk.join("dev")).ok()?;
+        ::std::fs::read_to_string(format!("/run/udev/data/b{}", numbers.trim()))
+            .ok()
+            .and_then(|data| {
+                data.lines()
+                    .find_map(|line| line.strip_prefix("E:ID_SERIAL_SHORT="))
+                    .map(str::to_string)
+            })
+            .or_else(|| ::std::fs::read_to_string(disk.join("device/serial")).ok())
+            .or_else(|| ::std::fs::read_to_string(disk.join("serial")).ok())
+    }
+
+    ///
+    /// Undoes the `\040`/`\x20` style escaping used by mountinfo and udev
+    ///
+    fn unescape(s: &str) -> String {
+        let mut out = Vec::with_capacity(s.len());
+        let bytes = s.as_bytes();
+        let mut i = 0;
+        while i < bytes.len() {
+            let escaped = match bytes.get(i..i + 4) {
+                Some([b'\\', b'x', a, b]) => {
+                    u8::from_str_radix(&format!("{}{}", *a as char, *b as char), 16).ok()
+                }
+                Some([b'\\', a, b, c]) => {
+                    u8::from_str_radix(&format!("{}{}{}", *a as char, *b as char, *c as char), 8)
+                        .ok()
+                }
+                _ => None,
+            };
+            match escaped {
+                Some(byte) => {
+                    out.push(byte);
+                    i += 4;
+                }
+                None => {
+                    out.push(bytes[i]);
+                    i += 1;
+                }
+            }
+        }
+        String::from_utf8_lossy(&out).into_owned()
+    }
+}
+
+#[cfg(windows)]
+mod imp {
+    use std::{
+        os::windows::ffi::OsStrExt,
+        path::{Path, PathBuf},
+    };
+    use windows_sys::Win32::{
+        Foundation::{CloseHandle, GENERIC_READ, GENERIC_WRITE, INVALID_HANDLE_VALUE},
+        Storage::FileSystem::{
+            CreateFileW, FlushFileBuffers, GetDiskFreeSpaceExW, GetFileInformationByHandle,
+            GetVolumeInformationW, GetVolumeNameForVolumeMountPointW, GetVolumePathNameW,
+            GetVolumePathNamesForVolumeNameW, BY_HANDLE_FILE_INFORMATION,
+            FILE_FLAG_BACKUP_SEMANTICS, FILE_SHARE_DELETE, FILE_SHARE_READ, FILE_SHARE_WRITE,
+            OPEN_EXISTING,
+        },
+        System::{
+            Ioctl::{
+                PropertyStandardQuery, StorageDeviceProperty, FSCTL_DISMOUNT_VOLUME,
+                FSCTL_LOCK_VOLUME, IOCTL_STORAGE_EJECT_MEDIA, IOCTL_STORAGE_MEDIA_REMOVAL,
+                IOCTL_STORAGE_QUERY_PROPERTY, PREVENT_MEDIA_REMOVAL, STORAGE_DEVICE_DESCRIPTOR,
+                STORAGE_PROPERTY_QUERY,
+            },
+            IO::DeviceIoControl,
+        },
+    };
+
+    fn wide(s: &::std::ffi::OsStr) -> Vec<u16> {
+        s.encode_wide().chain(Some(0)).collect()
+    }
+
+    ///
+    /// The root of the volume `path` lives on, e.g. `E:\` or a mounted folder
+    ///
+    fn volume_root(path: &Path) -> Option<Vec<u16>> {
+        let path = wide(path.as_os_str());
+        let mut root = vec![0u16; 1024];
+        // SAFETY: both buffers are valid for the lengths passed
+        if unsafe { GetVolumePathNameW(path.as_ptr(), root.as_mut_ptr(), root.len() as u32) } == 0 {
+            return None;
+        }
+        Some(root)
+    }
+
+    pub fn mount_point(path: &Path) -> Option<PathBuf> {
+        let root = volume_root(path)?;
+        let len = root.iter().position(|c| *c == 0).unwrap_or(root.len());
+        Some(PathBuf::from(String::from_utf16_lossy(&root[..len])))
+    }
+
+    ///
+    /// A volume counts as mounted as long as its filesystem can still be queried, which stops
+    /// once the media is ejected even though a card reader's drive letter stays around
+    ///
+    pub fn is_mounted(mount: &Path) -> bool {
+        let root = wide(mount.as_os_str());
+        // SAFETY: `root` is nul terminated and every out parameter is optional
+        unsafe {
+            GetVolumeInformationW(
+                root.as_ptr(),
+                ::std::ptr::null_mut(),
+                0,
+                ::std::ptr::null_mut(),
+                ::std::ptr::null_mut(),
+                ::std::ptr::null_mut(),
+                ::std::ptr::null_mut(),
+                0,
+            ) != 0
+        }
+    }
+
+    ///
+    /// What the volume mounted at `mount` is opened as for I/O controls: `E:\` as `\\.\E:`,
+    /// volume GUID paths without their trailing backslash
+    ///
+    fn device_path(mount: &Path) -> Vec<u16> {
+        let mount = mount.to_string_lossy();
+        let mount = mount.trim_end_matches('\\');
+        let device = if mount.len() == 2 && mount.ends_with(':') {
+            format!(r"\\.\{}", mount)
+        } else {
+            mount.to_string()
+        };
+        wide(::std::ffi::OsStr::new(&device))
+    }
+
+    ///
+    /// Locks and dismounts the volume, then asks the device to eject its media, the same steps
+    /// Explorer's "Eject" goes through
+    ///
+    pub fn eject(mount: &Path) -> ::std::io::Result<()> {
+        let device = device_path(mount);
+
+        // SAFETY: `device` is nul terminated, the handle is checked before use and closed below
+        let handle = unsafe {
+            CreateFileW(
+                device.as_ptr(),
+                GENERIC_READ | GENERIC_WRITE,
+                FILE_SHARE_READ | FILE_SHARE_WRITE,
+                ::std::ptr::null(),
+                OPEN_EXISTING,
+                0,
+                0,
+            )
+        };
+        if handle == INVALID_HANDLE_VALUE {
+            return Err(::std::io::Error::last_os_error());
+        }
+
+        let allow_removal = PREVENT_MEDIA_REMOVAL {
+            PreventMediaRemoval: 0,
+        };
+        let result = [
+            (FSCTL_LOCK_VOLUME, None),
+            (FSCTL_DISMOUNT_VOLUME, None),
+            (IOCTL_STORAGE_MEDIA_REMOVAL, Some(&allow_removal)),
+            (IOCTL_STORAGE_EJECT_MEDIA, None),
+        ]
+        .into_iter()
+        .try_for_each(|(code, input)| {
+            let (input, input_len) = match input {
+                Some(input) => (
+                    input as *const PREVENT_MEDIA_REMOVAL as *const _,
+                    ::std::mem::size_of::<PREVENT_MEDIA_REMOVAL>() as u32,
+                ),
+                None => (::std::ptr::null(), 0),
+            };
+            let mut returned = 0;
+            // SAFETY: the handle is open and the input buffer is valid for `input_len` bytes
+            let ok = unsafe {
+                DeviceIoControl(
+                    handle,
+                    code,
+                    input,
+                    input_len,
+                    ::std::ptr::null_mut(),
+                    0,
+                    &mut returned,
+                    ::std::ptr::null_mut(),
+                )
+            };
+            if ok == 0 {
+                return Err(::std::io::Error::last_os_error());
+            }
+            Ok(())
+        });
+
+        // SAFETY: the handle was opened above and is not used afterwards
+        unsafe { CloseHandle(handle) };
+        result
+    }
+
+    ///
+    /// Flushes the volume as a whole, which takes the same access ejecting it does
+    ///
+    pub fn sync(mount: &Path) -> ::std::io::Result<()> {
+        let device = device_path(mount);
+
+        // SAFETY: `device` is nul terminated, the handle is checked before use and closed below
+        let handle = unsafe {
+            CreateFileW(
+                device.as_ptr(),
+                GENERIC_READ | GENERIC_WRITE,
+                FILE_SHARE_READ | FILE_SHARE_WRITE,
+                ::std::ptr::null(),
+                OPEN_EXISTING,
+                0,
+                0,
+            )
+        };
+        if handle == INVALID_HANDLE_VALUE {
+            return Err(::std::io::Error::last_os_error());
+        }
+        // SAFETY: the handle is open
+        let result = match unsafe { FlushFileBuffers(handle) } {
+            0 => Err(::std::io::Error::last_os_error()),
+            _ => Ok(()),
+        };
+        // SAFETY: the handle was opened above and is not used afterwards
+        unsafe { CloseHandle(handle) };
+        result
+    }
+
+    pub fn file_id(path: &Path) -> Option<(u64, u64)> {
+        let path = wide(path.as_os_str());
+        // SAFETY: `path` is nul terminated, the handle is checked before use and closed below.
+        // Backup semantics is what lets a directory be opened, no access is asked for.
+        let handle = unsafe {
+            CreateFileW(
+                path.as_ptr(),
+                0,
+                FILE_SHARE_READ | FILE_SHARE_WRITE | FILE_SHARE_DELETE,
+                ::std::ptr::null(),
+                OPEN_EXISTING,
+                FILE_FLAG_BACKUP_SEMANTICS,
+                0,
+            )
+        };
+        if handle == INVALID_HANDLE_VALUE {
+            return None;
+        }
+        // SAFETY: the handle is open and `info` is only read after the call filled it in
+        let mut info = unsafe { ::std::mem::zeroed::<BY_HANDLE_FILE_INFORMATION>() };
+        let ok = unsafe { GetFileInformationByHandle(handle, &mut info) };
+        // SAFETY: the handle was opened above and is not used afterwards
+        unsafe { CloseHandle(handle) };
+        if ok == 0 {
+            return None;
+        }
+        Some((
+            info.dwVolumeSerialNumber as u64,
+            (info.nFileIndexHigh as u64) << 32 | info.nFileIndexLow as u64,
+        ))
+    }
+
+    pub fn free_space(path: &Path) -> Option<u64> {
+        let path = wide(path.as_os_str());
+        let mut available = 0;
+        // SAFETY: `path` is nul terminated and the totals not asked for may be null
+        let ok = unsafe {
+            GetDiskFreeSpaceExW(
+                path.as_ptr(),
+                &mut available,
+                ::std::ptr::null_mut(),
+                ::std::ptr::null_mut(),
+            )
+        };
+        (ok != 0).then_some(available)
+    }
+
+    pub fn volume_label(path: &Path) -> Option<String> {
+        let root = volume_root(path)?;
+        let mut label = [0u16; 261];
+        // SAFETY: `root` is nul terminated and `label` is valid for the length passed, every other
+        // out parameter is optional
+        let ok = unsafe {
+            GetVolumeInformationW(
+                root.as_ptr(),
+                label.as_mut_ptr(),
+                label.len() as u32,
+                ::std::ptr::null_mut(),
+                ::std::ptr::null_mut(),
+                ::std::ptr::null_mut(),
+                ::std::ptr::null_mut(),
+                0,
+            )
+        };
+        if ok == 0 {
+            return None;
+        }
+
+        let len = label.iter().position(|c| *c == 0).unwrap_or(label.len());
+        Some(String::from_utf16_lossy(&label[..len]))
+    }
+
+    pub fn filesystem(path: &Path) -> Option<String> {
+        let root = volume_root(path)?;
+        let mut name = [0u16; 261];
+        // SAFETY: `root` is nul terminated and `name` is valid for the length passed, every other
+        // out parameter is optional
+        let ok = unsafe {
+            GetVolumeInformationW(
+                root.as_ptr(),
+                ::std::ptr::null_mut(),
+                0,
+                ::std::ptr::null_mut(),
+                ::std::ptr::null_mut(),
+                ::std::ptr::null_mut(),
+                name.as_mut_ptr(),
+                name.len() as u32,
+            )
+        };
+        if ok == 0 {
+            return None;
+        }
+
+        let len = name.iter().position(|c| *c == 0).unwrap_or(name.len());
+        Some(String::from_utf16_lossy(&name[..len]))
+    }
+
+    pub fn volume_guid(path: &Path) -> Option<String> {
+        let root = volume_root(path)?;
+        let mut guid = [0u16; 50];
+        // SAFETY: `root` is nul terminated and `guid` is valid for the length passed
+        let ok = unsafe {
+            GetVolumeNameForVolumeMountPointW(root.as_ptr(), guid.as_mut_ptr(), guid.len() as u32)
+        };
+        if ok == 0 {
+            return None;
+        }
+
+        let len = guid.iter().position(|c| *c == 0).unwrap_or(guid.len());
+        Some(String::from_utf16_lossy(&guid[..len]))
+    }
+
+    pub fn serial_number(path: &Path) -> Option<String> {
+        let device = device_path(Path::new(&volume_guid(path)?));
+        // SAFETY: `device` is nul terminated, the handle is checked before use and closed below.
+        // Querying properties needs no access rights, so this works without elevation.
+        let handle = unsafe {
+            CreateFileW(
+                device.as_ptr(),
+                0,
+                FILE_SHARE_READ | FILE_SHARE_WRITE,
+                ::std::ptr::null(),
+                OPEN_EXISTING,
+                0,
+                0,
+            )
+        };
+        if handle == INVALID_HANDLE_VALUE {
+            return None;
+        }
+
+        let query = STORAGE_PROPERTY_QUERY {
+            PropertyId: StorageDeviceProperty,
+            QueryType: PropertyStandardQuery,
+            AdditionalParameters: [0],
+        };
+        // The descriptor with the strings it points to after it
+        let mut descriptor = vec![0u8; 1024];
+        let mut returned = 0;
+        // SAFETY: the handle is open and both buffers are valid for the lengths passed
+        let ok = unsafe {
+            DeviceIoControl(
+                handle,
+                IOCTL_STORAGE_QUERY_PROPERTY,
+                &query as *const STORAGE_PROPERTY_QUERY as *const _,
+                ::std::mem::size_of::<STORAGE_PROPERTY_QUERY>() as u32,
+                descriptor.as_mut_ptr() as *mut _,
+                descriptor.len() as u32,
+                &mut returned,
+                ::std::ptr::null_mut(),
+            )
+        };
+        // SAFETY: the handle was opened above and is not used afterwards
+        unsafe { CloseHandle(handle) };
+        if ok == 0 {
+            return None;
+        }
+
+        descriptor.truncate(returned as usize);
+        let field = ::std::mem::offset_of!(STORAGE_DEVICE_DESCRIPTOR, SerialNumberOffset);
+        let offset = u32::from_ne_bytes(descriptor.get(field..field + 4)?.try_into().ok()?);
+        // No serial number is offset 0
+        let serial = descriptor.get(offset as usize..).filter(|_| offset > 0)?;
+        let len = serial.iter().position(|b| *b == 0).unwrap_or(serial.len());
+        Some(String::from_utf8_lossy(&serial[..len]).into_owned())
+    }
+
+    pub fn current_mount(path: &Path) -> Option<PathBuf> {
+        let root = volume_root(path)?;
+        let mut names = vec![0u16; 1024];
+        let mut len = 0;
+        // SAFETY: `root` is nul terminated and `names` is valid for the length passed
+        let ok = unsafe {
+            GetVolumePathNamesForVolumeNameW(
+                root.as_ptr(),
+                names.as_mut_ptr(),
+                names.len() as u32,
+                &mut len,
+            )
+        };
+        if ok == 0 {
+            return None;
+        }
+
+        // A list of nul terminated mount points, the first one is enough to recognize the drive
+        let first = names.iter().position(|c| *c == 0).unwrap_or(0);
+        (first > 0).then(|| PathBuf::from(String::from_utf16_lossy(&names[..first])))
+    }
+}
+
+#[cfg(not(any(target_os = "linux", windows)))]
+mod imp {
+    use std::{
+        path::{Path, PathBuf},
+        process::Command,
+    };
+
+    use super::run;
+
+    pub fn mount_point(path: &Path) -> Option<PathBuf> {
+        let path = path.canonicalize().ok()?;
+        let name = path.strip_prefix("/Volumes").ok()?.components().next()?;
+        Some(Path::new("/Volumes").join(name))
+    }
+
+    pub fn is_mounted(mount: &Path) -> bool {
+        mount.exists()
+    }
+
+    pub fn free_space(path: &Path) -> Option<u64> {
+        super::statvfs_free_space(path)
+    }
+
+    pub fn file_id(path: &Path) -> Option<(u64, u64)> {
+        super::stat_file_id(path)
+    }
+
+    pub fn eject(mount: &Path) -> ::std::io::Result<()> {
+        run(Command::new("diskutil").arg("eject").arg(mount))
+    }
+
+    ///
+    /// There is no syncing a single filesystem here, so every one of them is
+    ///
+    pub fn sync(_mount: &Path) -> ::std::io::Result<()> {
+        // SAFETY: sync takes no arguments and can't fail
+        unsafe { libc::sync() };
+        Ok(())
+    }
+
+    pub fn filesystem(path: &Path) -> Option<String> {
+        use ::std::os::unix::ffi::OsStrExt;
+        let path = ::std::ffi::CString::new(path.as_os_str().as_bytes()).ok()?;
+        // SAFETY: `path` is nul terminated and `stats` is only read after statfs filled it in
+        let mut stats = unsafe { ::std::mem::zeroed::<libc::statfs>() };
+        if unsafe { libc::statfs(path.as_ptr(), &mut stats) } != 0 {
+            return None;
+        }
+        // SAFETY: statfs leaves a nul terminated name in `f_fstypename`
+        let name = unsafe { ::std::ffi::CStr::from_ptr(stats.f_fstypename.as_ptr()) };
+        Some(name.to_string_lossy().into_owned())
+    }
+
+    pub fn volume_label(path: &Path) -> Option<String> {
+        // Removable media is mounted as /Volumes/<label> on macOS
+        let path = path.canonicalize().ok()?;
+        let mut components = path.strip_prefix("/Volumes").ok()?.components();
+        Some(
+            components
+                .next()?
+                .as_os_str()
+                .to_string_lossy()
+                .into_owned(),
+        )
+    }
+}
@@ -0,0 +1,154 @@
+use chrono::Local;
+use std::{
+    collections::BTreeMap,
+    fmt::Display,
+    fs::{File, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use crate::{error::CopyError, hook::DeploymentHook, verify::Verification};
+
+/// Name of the log `--drive-log` keeps in the root of every destination
+pub const DRIVE_LOG: &str = "deployment.log";
+
+///
+/// Keeps a `deployment.log` in the root of every destination with that drive's own timeline:
+/// when the copy started, every file written, errors and the verification (`--drive-log`).
+/// Runs append to it, so a drive that is deployed again carries its whole history. The log is
+/// best effort, a drive that can't take it doesn't fail the copy over it.
+///
+pub struct DriveLog {
+    /// The first line of every run in the log
+    header: String,
+    logs: Mutex<BTreeMap<PathBuf, Timeline>>,
+}
+
+#[derive(Default)]
+struct Timeline {
+    /// `None` until the log could be opened, the destination may not exist yet
+    file: Option<File>,
+    files: usize,
+    bytes: usize,
+}
+
+impl DriveLog {
+    pub fn new(source: &Path) -> Self {
+        Self {
+            header: format!(
+                "decopy v{} deploying from `{}`",
+                env!("CARGO_PKG_VERSION"),
+                source.display()
+            ),
+            logs: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    ///
+    /// Appends `line` to the log of `dest`, opening it first if it isn't yet
+    ///
+    fn write(&self, dest: &Path, line: impl Display) {
+        let mut logs = self.logs.lock().expect("drive logs are never poisoned");
+        self.append(logs.entry(dest.to_path_buf()).or_default(), dest, line);
+    }
+
+    ///
+    /// Appends `line` to the log of the destination `error` happened on, or to every log started
+    /// so far when it happened on the source, which holds up all of them
+    ///
+    fn write_about(&self, error: &CopyError, line: impl Display) {
+        match &error.destination {
+            Some(dest) => self.write(dest, line),
+            None => {
+                let mut logs = self.logs.lock().expect("drive logs are never poisoned");
+                for (dest, timeline) in logs.iter_mut() {
+                    self.append(timeline, dest, &line);
+                }
+            }
+        }
+    }
+
+    fn append(&self, timeline: &mut Timeline, dest: &Path, line: impl Display) {
+        let now = Local::now().format("%Y-%m-%d %H:%M:%S");
+        if timeline.file.is_none() {
+            timeline.file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(dest.join(DRIVE_LOG))
+                .and_then(|mut file| writeln!(file, "{}  {}", now, self.header).map(|()| file))
+                .ok();
+        }
+        if let Some(file) = &mut timeline.file {
+            if writeln!(file, "{}  {}", now, line).is_err() {
+                timeline.file = None;
+            }
+        }
+    }
+}
+
+impl DeploymentHook for DriveLog {
+    fn on_destination_started(&self, destination: &Path) {
+        self.write(destination, "started copying");
+    }
+
+    fn on_file_copied(&self, destination: &Path, file: &Path, bytes: usize) {
+        let mut logs = self.logs.lock().expect("drive logs are never poisoned");
+        let timeline = logs.entry(destination.to_path_buf()).or_default();
+        timeline.files += 1;
+        timeline.bytes += bytes;
+        let line = format!("copied `{}` ({} bytes)", file.display(), bytes);
+        self.append(timeline, destination, line);
+    }
+
+    fn on_destination_done(&self, destination: &Path) {
+        let mut logs = self.logs.lock().expect("drive logs are never poisoned");
+        let timeline = logs.entry(destination.to_path_buf()).or_default();
+        let line = format!(
+            "finished copying, {} files with {} bytes written",
+            timeline.files, timeline.bytes
+        );
+        self.append(timeline, destination, line);
+    }
+
+    fn on_retry(&self, error: &CopyError, attempt: u32) {
+        let line = format!(
+            "retrying after [{}] {} (attempt {})",
+            error.class().code(),
+            error,
+            attempt
+        );
+        self.write_about(error, line);
+    }
+
+    fn on_error(&self, error: &CopyError) {
+        let line = format!("error [{}] {}", error.class().code(), error);
+        self.write_about(error, line);
+    }
+
+    fn on_verified(&self, verification: &Verification) {
+        let dest = &verification.destination;
+        for file in &verification.repaired {
+            self.write(dest, format!("repaired `{}`", file.display()));
+        }
+        for (file, reason) in verification.failures() {
+            self.write(dest, format!("mismatch `{}`: {}", file.display(), reason));
+        }
+        match verification.passed() {
+            true => self.write(
+                dest,
+                format!(
+                    "verification passed, {} extra files",
+                    verification.extra.len()
+                ),
+            ),
+            false => self.write(
+                dest,
+                format!(
+                    "verification failed, {} files don't match the source",
+                    verification.mismatches()
+                ),
+            ),
+        }
+    }
+}
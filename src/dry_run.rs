@@ -0,0 +1,34 @@
+use std::path::PathBuf;
+
+use crate::mirror::Stale;
+
+///
+/// What a run would do to one destination, worked out by `CopyQueue::dry_run` without writing
+/// anything (`--dry-run`). Paths are relative to the destination, in `Walk` order, with their
+/// sizes.
+///
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DryRun {
+    pub destination: PathBuf,
+    /// Files the destination doesn't have yet
+    pub copied: Vec<(PathBuf, usize)>,
+    /// Files the destination has that would be written again
+    pub overwritten: Vec<(PathBuf, usize)>,
+    /// Files `--incremental` or `--overwrite` would leave as they are
+    pub unchanged: Vec<(PathBuf, usize)>,
+    /// What `--mirror` would delete
+    pub deleted: Stale,
+}
+
+impl DryRun {
+    ///
+    /// Bytes that would be written
+    ///
+    pub fn bytes(&self) -> usize {
+        self.copied
+            .iter()
+            .chain(&self.overwritten)
+            .map(|(_, size)| size)
+            .sum()
+    }
+}
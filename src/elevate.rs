@@ -0,0 +1,98 @@
+use clap::Parser;
+use std::ffi::OsString;
+
+use crate::Args;
+
+///
+/// Whether `relaunch_elevated` can work on this platform
+///
+pub const SUPPORTED: bool = cfg!(windows);
+
+///
+/// Starts this program again as administrator (the UAC "runas" prompt) with the same arguments
+/// plus those of `elevated_args`, so the elevated copy picks up from the checkpoint the failed run
+/// left behind. Returns once the new process has been started; the caller should then exit.
+///
+pub fn relaunch_elevated() -> ::std::io::Result<()> {
+    let argv = elevated_args(&::std::env::args_os().collect::<Vec<_>>());
+    imp::relaunch_elevated(argv.get(1..).unwrap_or_default())
+}
+
+///
+/// `argv` with `--yes`, and `--resume` when the options allow it: it conflicts with a few, such
+/// as `--transactional` and `--stdin-format`, which then start over
+///
+pub fn elevated_args(argv: &[OsString]) -> Vec<OsString> {
+    let with = |flags: &[&str]| {
+        let mut args = argv.to_vec();
+        for flag in flags {
+            if !args.iter().any(|arg| arg == flag) {
+                args.push(OsString::from(flag));
+            }
+        }
+        args
+    };
+    let resumed = with(&["--resume", "--yes"]);
+    match Args::try_parse_from(&resumed) {
+        Ok(_) => resumed,
+        Err(_) => with(&["--yes"]),
+    }
+}
+
+#[cfg(windows)]
+mod imp {
+    use std::{
+        ffi::{OsStr, OsString},
+        os::windows::ffi::OsStrExt,
+    };
+    use windows_sys::Win32::UI::{Shell::ShellExecuteW, WindowsAndMessaging::SW_SHOWNORMAL};
+
+    use crate::rerun::quote_windows;
+
+    pub fn relaunch_elevated(args: &[OsString]) -> ::std::io::Result<()> {
+        let exe = wide(::std::env::current_exe()?.as_os_str());
+        let dir = wide(::std::env::current_dir()?.as_os_str());
+        let mut parameters = Vec::new();
+        for arg in args {
+            if !parameters.is_empty() {
+                parameters.push(b' ' as u16);
+            }
+            parameters.extend(quote_windows(&arg.encode_wide().collect::<Vec<_>>()));
+        }
+        parameters.push(0);
+        let runas = wide(OsStr::new("runas"));
+
+        // SAFETY: every string is NUL-terminated UTF-16 that outlives the call
+        let result = unsafe {
+            ShellExecuteW(
+                0,
+                runas.as_ptr(),
+                exe.as_ptr(),
+                parameters.as_ptr(),
+                dir.as_ptr(),
+                SW_SHOWNORMAL,
+            )
+        };
+        // Anything up to 32 is an error code, e.g. when the UAC prompt was declined
+        if result <= 32 {
+            return Err(::std::io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    fn wide(s: &OsStr) -> Vec<u16> {
+        s.encode_wide().chain(Some(0)).collect()
+    }
+}
+
+#[cfg(not(windows))]
+mod imp {
+    use std::ffi::OsString;
+
+    pub fn relaunch_elevated(_args: &[OsString]) -> ::std::io::Result<()> {
+        Err(::std::io::Error::new(
+            ::std::io::ErrorKind::Unsupported,
+            "relaunching as administrator is only supported on Windows",
+        ))
+    }
+}
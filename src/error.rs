@@ -0,0 +1,160 @@
+use std::{
+    fmt,
+    io::{Error as IoError, ErrorKind as IoErrorKind},
+    path::{Path, PathBuf},
+};
+
+use crate::{i18n::Message, locale::Locale};
+
+///
+/// A broad category of I/O failure that an operator can act on
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorClass {
+    AccessDenied,
+    WriteProtected,
+    DiskFull,
+    DeviceNotReady,
+    NotFound,
+    PathTooLong,
+    InUse,
+    Other,
+}
+
+impl ErrorClass {
+    pub fn of(error: &IoError) -> Self {
+        #[cfg(windows)]
+        match error.raw_os_error() {
+            // ERROR_WRITE_PROTECT
+            Some(19) => return ErrorClass::WriteProtected,
+            // ERROR_NOT_READY, ERROR_DEV_NOT_EXIST, ERROR_DEVICE_NOT_CONNECTED
+            Some(21 | 55 | 1167) => return ErrorClass::DeviceNotReady,
+            // ERROR_SHARING_VIOLATION, ERROR_LOCK_VIOLATION
+            Some(32 | 33) => return ErrorClass::InUse,
+            // ERROR_HANDLE_DISK_FULL, ERROR_DISK_FULL
+            Some(39 | 112) => return ErrorClass::DiskFull,
+            // ERROR_FILENAME_EXCED_RANGE
+            Some(206) => return ErrorClass::PathTooLong,
+            _ => {}
+        }
+        #[cfg(unix)]
+        match error.raw_os_error() {
+            Some(libc::ENODEV | libc::ENXIO | libc::EIO) => return ErrorClass::DeviceNotReady,
+            Some(libc::ENAMETOOLONG) => return ErrorClass::PathTooLong,
+            _ => {}
+        }
+
+        match error.kind() {
+            IoErrorKind::PermissionDenied => ErrorClass::AccessDenied,
+            IoErrorKind::ReadOnlyFilesystem => ErrorClass::WriteProtected,
+            IoErrorKind::StorageFull | IoErrorKind::QuotaExceeded => ErrorClass::DiskFull,
+            IoErrorKind::NotFound => ErrorClass::NotFound,
+            IoErrorKind::ResourceBusy => ErrorClass::InUse,
+            _ => ErrorClass::Other,
+        }
+    }
+
+    ///
+    /// Stable identifier for reports and scripts
+    ///
+    pub fn code(self) -> &'static str {
+        match self {
+            ErrorClass::AccessDenied => "access-denied",
+            ErrorClass::WriteProtected => "write-protected",
+            ErrorClass::DiskFull => "disk-full",
+            ErrorClass::DeviceNotReady => "device-not-ready",
+            ErrorClass::NotFound => "not-found",
+            ErrorClass::PathTooLong => "path-too-long",
+            ErrorClass::InUse => "in-use",
+            ErrorClass::Other => "io-error",
+        }
+    }
+
+    ///
+    /// What the operator can do about it, in `locale`'s language
+    ///
+    pub fn suggestion(self, locale: Locale) -> Option<&'static str> {
+        match self {
+            ErrorClass::Other => None,
+            class => Some(Message::Error(class).text(locale)),
+        }
+    }
+}
+
+///
+/// An I/O failure during a deployment, with the file and destination it happened on
+///
+#[derive(Debug)]
+pub struct CopyError {
+    pub path: PathBuf,
+    pub destination: Option<PathBuf>,
+    pub error: IoError,
+}
+
+impl CopyError {
+    pub fn new(path: &Path, destination: Option<&Path>, error: IoError) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            destination: destination.map(Path::to_path_buf),
+            error,
+        }
+    }
+
+    pub fn class(&self) -> ErrorClass {
+        ErrorClass::of(&self.error)
+    }
+
+    ///
+    /// Whether the copy stopped because the operator cancelled it rather than because of a fault
+    ///
+    pub fn cancelled(&self) -> bool {
+        self.error
+            .get_ref()
+            .is_some_and(|error| error.is::<Cancelled>())
+    }
+}
+
+///
+/// What the copy loops stop with once the operator cancelled the run, see
+/// `CopyQueue::cancel_switch`
+///
+#[derive(Debug)]
+struct Cancelled;
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "cancelled by the operator")
+    }
+}
+
+impl ::std::error::Error for Cancelled {}
+
+pub(crate) fn cancelled() -> IoError {
+    IoError::other(Cancelled)
+}
+
+impl fmt::Display for CopyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "`{}`: {}", self.path.display(), self.error)
+    }
+}
+
+impl ::std::error::Error for CopyError {
+    fn source(&self) -> Option<&(dyn ::std::error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+///
+/// Whether `error` may well go away when the same thing is tried again a moment later, as with
+/// a flaky card reader or a file another program has open for a moment
+///
+pub fn is_transient(error: &IoError) -> bool {
+    matches!(
+        ErrorClass::of(error),
+        ErrorClass::DeviceNotReady | ErrorClass::InUse
+    ) || matches!(
+        error.kind(),
+        IoErrorKind::TimedOut | IoErrorKind::Interrupted
+    )
+}
@@ -0,0 +1,539 @@
+use sha2::{Digest, Sha256};
+use std::{
+    fs::{File, OpenOptions},
+    io::{Error as IoError, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::{
+        mpsc::{channel, sync_channel, Sender, SyncSender},
+        Arc,
+    },
+};
+
+use crate::{
+    copy::{
+        changed_while_read, create_dir, create_link, ChangingFiles, CopyEvent, IoHooks, ResumePoint,
+    },
+    drive,
+    error::CopyError,
+    hash::finish_hex,
+    preserve::Kept,
+    symlink::Symlink,
+    walk::{Entry, Source},
+};
+
+/// Size of the chunks the source is read in and handed to the destination writers
+pub const CHUNK_SIZE: usize = 1024 * 1024;
+
+///
+/// How `copy_fan_out` runs
+///
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct FanOut {
+    /// Chunks each destination's queue holds, from `--pipeline-buffer`
+    pub queue_chunks: usize,
+    /// Hash the chunks as well, see `copy_fan_out`
+    pub hash: bool,
+}
+
+enum Chunk {
+    Dir(Arc<PathBuf>),
+    Link(Arc<PathBuf>, Symlink),
+    /// A file with its size, and the metadata its copies get under `--incremental` and
+    /// `--preserve`
+    Open(Arc<PathBuf>, usize, Option<Kept>),
+    Data(Arc<Vec<u8>>),
+    Close,
+    /// The file changed size while it was read, what was written of it has to go again
+    Changed,
+}
+
+///
+/// Reads every source file once and hands each chunk to one writer thread per destination.
+/// `read` feeds the source in through a `Broadcast` on a thread of its own, see `read_tree` for
+/// a source directory.
+///
+/// Each destination gets its own bounded queue of `FanOut::queue_chunks` chunks, so fast drives
+/// keep writing while a slow one works through its backlog instead of every drive moving in lock
+/// step. The reader only stalls once the slowest destination's queue is full.
+///
+/// With `FanOut::hash`, one more consumer hashes the same chunks and reports
+/// `CopyEvent::FileHashed` for every source file, so hashing for `--verify` doesn't read the
+/// source a second time. Files every destination already has are then read for the hash alone.
+///
+pub(crate) fn copy_fan_out(
+    read: impl FnOnce(&Broadcast) -> Result<(), CopyError> + Send,
+    destinations: &[PathBuf],
+    starts: &[ResumePoint],
+    options: FanOut,
+    io: IoHooks,
+    handle: &mut impl FnMut(CopyEvent),
+) -> Result<(), CopyError> {
+    let (events, event_rx) = channel();
+
+    ::std::thread::scope(|scope| {
+        let (queues, writers): (Vec<_>, Vec<_>) = destinations
+            .iter()
+            .zip(starts)
+            .enumerate()
+            .filter(|(_, (_, start))| **start != ResumePoint::Complete)
+            .map(|(dest, (dest_path, start))| {
+                let (queue, chunks) = sync_channel(options.queue_chunks.max(1));
+                let events = events.clone();
+                let writer = scope.spawn(move || {
+                    let event = match write_destination(
+                        dest,
+                        dest_path,
+                        start,
+                        chunks.iter(),
+                        io,
+                        &events,
+                    )? {
+                        true => CopyEvent::DestinationDone { dest },
+                        false => CopyEvent::DestinationCancelled { dest },
+                    };
+                    let _ = events.send(event);
+                    Ok(())
+                });
+                ((dest, dest_path.as_path(), start, queue), writer)
+            })
+            .unzip();
+
+        let hasher = options.hash.then(|| {
+            let (queue, chunks) = sync_channel(options.queue_chunks.max(1));
+            let events = events.clone();
+            scope.spawn(move || hash_chunks(chunks.iter(), &events));
+            queue
+        });
+
+        let reader_events = events.clone();
+        drop(events);
+        let reader = scope.spawn(move || {
+            read(&Broadcast {
+                queues,
+                hasher,
+                io,
+                events: reader_events,
+            })
+        });
+
+        for event in event_rx {
+            handle(event);
+        }
+
+        // A writer failing makes the reader stop early, so report the writer's error first
+        writers
+            .into_iter()
+            .map(|writer| writer.join().expect("destination writer panicked"))
+            .chain(Some(reader.join().expect("source reader panicked")))
+            .collect::<Result<(), CopyError>>()
+    })
+}
+
+///
+/// Where the reader of `copy_fan_out` sends the source to. Both methods return `false` once a
+/// writer has given up, the reader should then stop and leave the error to the writer.
+///
+pub(crate) struct Broadcast<'a> {
+    queues: Vec<(usize, &'a Path, &'a ResumePoint, SyncSender<Chunk>)>,
+    hasher: Option<SyncSender<Chunk>>,
+    io: IoHooks<'a>,
+    events: Sender<CopyEvent>,
+}
+
+impl Broadcast<'_> {
+    ///
+    /// Creates `dir` (relative to the source) on every destination
+    ///
+    pub fn dir(&self, dir: PathBuf) -> bool {
+        let dir = Arc::new(dir);
+        self.queues
+            .iter()
+            .all(|(_, _, _, queue)| queue.send(Chunk::Dir(dir.clone())).is_ok())
+    }
+
+    ///
+    /// Recreates the symlink `path` (relative to the source) on every destination
+    ///
+    pub fn link(&self, path: PathBuf, link: Symlink) -> bool {
+        let path = Arc::new(path);
+        self.queues
+            .iter()
+            .all(|(_, _, _, queue)| queue.send(Chunk::Link(path.clone(), link.clone())).is_ok())
+    }
+
+    ///
+    /// Writes `path` (relative to the source) to every destination that doesn't have it yet,
+    /// reading its `size` bytes from what `open` returns. `open` isn't called when no one needs
+    /// the file. `source` is the file itself when it is on disk, for `--incremental`,
+    /// `--overwrite if-newer` and `--changing-files`.
+    ///
+    pub fn file<R: Read>(
+        &self,
+        path: PathBuf,
+        mut size: usize,
+        source: Option<&Path>,
+        open: impl FnOnce() -> ::std::io::Result<R>,
+    ) -> Result<bool, CopyError> {
+        let path = Arc::new(path);
+        let unchanged =
+            |dest_path: &Path| source.is_some_and(|source| self.io.unchanged(source, dest_path));
+        let mut targets = Vec::new();
+        let mut writing = Vec::new();
+        for (dest, dest_path, start, queue) in &self.queues {
+            let existing = dest_path.join(&*path);
+            if start.wants(&path)
+                && !unchanged(&existing)
+                && (start.resumes(&path) || self.io.overwrites(*dest, &path, source, &existing))
+            {
+                targets.push(queue);
+                writing.push(*dest);
+            } else {
+                let _ = self.events.send(CopyEvent::FileSkipped {
+                    dest: *dest,
+                    file: path.to_path_buf(),
+                    size,
+                });
+            }
+        }
+        targets.extend(&self.hasher);
+        if targets.is_empty() {
+            return Ok(true);
+        }
+
+        let mut kept = source.and_then(|source| self.io.kept(source));
+        let mut reader: Box<dyn Read> = Box::new(
+            self.io
+                .counters
+                .reading(open().map_err(|e| CopyError::new(&path, None, e))?),
+        );
+        // Whether the file changed while it was read once already, see `--changing-files`
+        let mut reread = false;
+        loop {
+            if !broadcast(&targets, || Chunk::Open(path.clone(), size, kept.clone())) {
+                return Ok(false);
+            }
+            let mut file_bytes = 0;
+            let mut retries = 0;
+            loop {
+                let mut buffer = vec![0; CHUNK_SIZE];
+                let read = read_full(&mut reader, &mut buffer, |e| {
+                    match self.io.retry_after(&e, &mut retries) {
+                        true => {
+                            let _ = self.events.send(CopyEvent::Retried {
+                                error: CopyError::new(&path, None, e),
+                                attempt: retries,
+                            });
+                            Ok(())
+                        }
+                        false => Err(e),
+                    }
+                })
+                .map_err(|e| CopyError::new(&path, None, e))?;
+                if read == 0 {
+                    break;
+                }
+                buffer.truncate(read);
+                file_bytes += read;
+                self.io.counters.refill();
+                self.io
+                    .after_read(read)
+                    .map_err(|e| CopyError::new(&path, None, e))?;
+                let data = Arc::new(buffer);
+                if !broadcast(&targets, || Chunk::Data(data.clone())) {
+                    return Ok(false);
+                }
+            }
+
+            // A stream has nothing to look at again, what came through is the file
+            let Some((source, change)) = source.and_then(|source| {
+                changed_while_read(source, size, file_bytes).map(|change| (source, change))
+            }) else {
+                return Ok(broadcast(&targets, || Chunk::Close));
+            };
+            if !broadcast(&targets, || Chunk::Changed) {
+                return Ok(false);
+            }
+            if self.io.changing == ChangingFiles::Skip || ::std::mem::replace(&mut reread, true) {
+                for dest in &writing {
+                    let _ = self.events.send(CopyEvent::FileChanged {
+                        dest: *dest,
+                        file: path.to_path_buf(),
+                        size,
+                    });
+                }
+                return Ok(true);
+            }
+            let _ = self.events.send(CopyEvent::Retried {
+                error: CopyError::new(&path, None, change),
+                attempt: 1,
+            });
+            size = ::std::fs::metadata(source).map_or(size, |metadata| metadata.len() as usize);
+            kept = self.io.kept(source);
+            reader = Box::new(
+                self.io
+                    .counters
+                    .reading(File::open(source).map_err(|e| CopyError::new(&path, None, e))?),
+            );
+        }
+    }
+}
+
+///
+/// Reads a source directory for `copy_fan_out`, file by file as `entries` lists them
+///
+pub(crate) fn read_tree(
+    source: &Source,
+    entries: impl IntoIterator<Item = ::std::io::Result<Entry>>,
+    broadcast: &Broadcast,
+) -> Result<(), CopyError> {
+    for entry in entries {
+        broadcast
+            .io
+            .check_cancelled()
+            .map_err(|e| CopyError::new(&source.name(), None, e))?;
+        let carry_on = match entry.map_err(|e| CopyError::new(&source.name(), None, e))? {
+            Entry::Dir(dir) => broadcast.dir(dir),
+            Entry::Link(path, link) => broadcast.link(path, link),
+            Entry::File(path, size) => {
+                let full_path = source.path(&path);
+                broadcast.file(path, size, Some(&full_path), || File::open(&full_path))?
+            }
+        };
+        if !carry_on {
+            break;
+        }
+    }
+    Ok(())
+}
+
+///
+/// Fills `buffer` as far as `reader` goes, so pipes that hand out a little at a time still make
+/// full chunks. Returns how much was read, less than the buffer only at the end.
+///
+/// A read that fails is handed to `retry`, which either lets the reading go on where it left off
+/// or gives the error back to fail with.
+///
+pub(crate) fn read_full(
+    reader: &mut impl Read,
+    buffer: &mut [u8],
+    mut retry: impl FnMut(IoError) -> ::std::io::Result<()>,
+) -> ::std::io::Result<usize> {
+    let mut filled = 0;
+    while filled < buffer.len() {
+        match reader.read(&mut buffer[filled..]) {
+            Ok(0) => break,
+            Ok(read) => filled += read,
+            Err(e) if e.kind() == ::std::io::ErrorKind::Interrupted => {}
+            Err(e) => retry(e)?,
+        }
+    }
+    Ok(filled)
+}
+
+///
+/// Queues a chunk for every target, returning `false` if a writer has given up
+///
+fn broadcast(targets: &[&SyncSender<Chunk>], chunk: impl Fn() -> Chunk) -> bool {
+    targets.iter().all(|queue| queue.send(chunk()).is_ok())
+}
+
+///
+/// Hashes the files going by, for `copy_fan_out` with `FanOut::hash`
+///
+fn hash_chunks(chunks: impl Iterator<Item = Chunk>, events: &Sender<CopyEvent>) {
+    let mut current = None;
+    for chunk in chunks {
+        match chunk {
+            Chunk::Dir(_) | Chunk::Link(..) => {}
+            Chunk::Open(path, size, _) => current = Some((path, size, Sha256::new())),
+            Chunk::Data(data) => {
+                if let Some((_, _, hasher)) = current.as_mut() {
+                    hasher.update(&*data);
+                }
+            }
+            Chunk::Changed => current = None,
+            Chunk::Close => {
+                if let Some((path, size, hasher)) = current.take() {
+                    let _ = events.send(CopyEvent::FileHashed {
+                        file: path.to_path_buf(),
+                        size,
+                        hash: finish_hex(hasher),
+                    });
+                }
+            }
+        }
+    }
+}
+
+///
+/// Writes the chunks for destination number `dest`. A file an earlier run got partway through
+/// (see `ResumePoint::offset`) is continued, the chunks it already has are passed over.
+///
+/// With `--keep-going` a file that fails is reported and the rest of its chunks passed over,
+/// and once the destination is gone altogether everything after it. The chunks are still taken
+/// off the queue, so the other destinations carry on.
+///
+/// Once the run is cancelled the chunks queued up to then are still written. A destination
+/// cancelled on its own stops at the next chunk and passes over the rest, then returns `false`
+/// rather than `true` for having got everything.
+///
+fn write_destination(
+    dest: usize,
+    dest_path: &Path,
+    start: &ResumePoint,
+    chunks: impl Iterator<Item = Chunk>,
+    io: IoHooks,
+    events: &Sender<CopyEvent>,
+) -> Result<bool, CopyError> {
+    let (run, io) = (io, io.for_destination(dest));
+    // Whether the destination is gone after a failure
+    let fail = |error| match io.keep_going {
+        true => {
+            let _ = events.send(CopyEvent::FileFailed { dest, error });
+            Ok(!dest_path.exists())
+        }
+        false => Err(error),
+    };
+    let mut gone = match create_dir(dest_path, Path::new("")) {
+        Ok(()) => false,
+        Err(error) => fail(error)?,
+    };
+    let fat = drive::is_fat(dest_path);
+    let mut current = None;
+    let mut file_bytes = 0;
+    let mut offset = 0;
+    // Transient errors are tried again, up to `--max-retries` times per file
+    let mut retries = 0;
+    let mut cancelled = false;
+    for chunk in chunks {
+        if gone || cancelled {
+            continue;
+        }
+        // What was written of the current file stays for `--resume`
+        if io.cancelled() && !run.cancelled() {
+            current = None;
+            cancelled = true;
+            continue;
+        }
+        let written = match chunk {
+            Chunk::Dir(dir) => create_dir(dest_path, &dir),
+            Chunk::Link(path, link) => {
+                create_link(dest, dest_path, path.to_path_buf(), link, &mut |event| {
+                    let _ = events.send(event);
+                })
+            }
+            Chunk::Open(path, size, kept) => {
+                offset = start.offset(&path, size, dest_path);
+                retries = 0;
+                retrying(io, &mut retries, &path, dest_path, events, || {
+                    io.before_write(&dest_path.join(&*path))?;
+                    open_at(&dest_path.join(&*path), offset)
+                })
+                .map(|writer| {
+                    let _ = events.send(CopyEvent::FileStarted {
+                        dest,
+                        file: path.to_path_buf(),
+                        size,
+                        offset,
+                    });
+                    current = Some((path, size, kept, writer));
+                    file_bytes = 0;
+                })
+            }
+            // Left over from a file that failed
+            Chunk::Data(_) if current.is_none() => Ok(()),
+            Chunk::Data(data) => {
+                let (path, _, _, writer) = current
+                    .as_mut()
+                    .expect("chunk sent before its file was opened");
+                let kept = offset.saturating_sub(file_bytes).min(data.len());
+                let mut first = true;
+                retrying(io, &mut retries, path, dest_path, events, || {
+                    // A failed write may have got partway, so go back to where the chunk starts
+                    if !::std::mem::take(&mut first) {
+                        writer.seek(SeekFrom::Start((file_bytes + kept) as u64))?;
+                    }
+                    io.counters.write();
+                    writer.write_all(&data[kept..])
+                })
+                .map(|()| {
+                    file_bytes += data.len();
+                    let _ = events.send(CopyEvent::Progress { dest, file_bytes });
+                })
+            }
+            Chunk::Close => match current.take() {
+                Some((path, size, kept, mut writer)) => {
+                    retrying(io, &mut retries, &path, dest_path, events, || {
+                        writer.flush()?;
+                        kept.as_ref()
+                            .map_or(Ok(()), |kept| kept.apply(&writer, fat))
+                    })
+                    .map(|()| {
+                        let _ = events.send(CopyEvent::FileDone {
+                            dest,
+                            file: path.to_path_buf(),
+                            size,
+                        });
+                    })
+                }
+                None => Ok(()),
+            },
+            // Part of one version and part of another, of no use to anyone
+            Chunk::Changed => {
+                if let Some((path, _, _, writer)) = current.take() {
+                    drop(writer);
+                    let _ = ::std::fs::remove_file(dest_path.join(&*path));
+                }
+                Ok(())
+            }
+        };
+        if let Err(error) = written {
+            current = None;
+            gone = fail(error)?;
+        }
+    }
+    // A cancelled reader just stops sending, the destination isn't done then
+    run.check_cancelled()
+        .map_err(|e| CopyError::new(dest_path, Some(dest_path), e))?;
+    Ok(!cancelled)
+}
+
+///
+/// Runs `attempt` until it succeeds or fails with an error that isn't tried again, reporting
+/// every retry of `path` on `dest_path`. `retries` counts those of the current file.
+///
+fn retrying<T>(
+    io: IoHooks,
+    retries: &mut u32,
+    path: &Path,
+    dest_path: &Path,
+    events: &Sender<CopyEvent>,
+    mut attempt: impl FnMut() -> ::std::io::Result<T>,
+) -> Result<T, CopyError> {
+    loop {
+        match attempt() {
+            Ok(done) => return Ok(done),
+            Err(e) if io.retry_after(&e, retries) => {
+                let _ = events.send(CopyEvent::Retried {
+                    error: CopyError::new(path, Some(dest_path), e),
+                    attempt: *retries,
+                });
+            }
+            Err(e) => return Err(CopyError::new(path, Some(dest_path), e)),
+        }
+    }
+}
+
+///
+/// Creates `path` for writing, or with an `offset` keeps that much of what is there and writes
+/// on from its end
+///
+fn open_at(path: &Path, offset: usize) -> ::std::io::Result<File> {
+    if offset == 0 {
+        return File::create(path);
+    }
+    let mut file = OpenOptions::new().write(true).open(path)?;
+    file.set_len(offset as u64)?;
+    file.seek(SeekFrom::End(0))?;
+    Ok(file)
+}
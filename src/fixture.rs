@@ -0,0 +1,210 @@
+use std::{
+    collections::HashSet,
+    fs::File,
+    io::{Seek, SeekFrom, Write},
+    path::PathBuf,
+};
+
+use crate::size::ByteSize;
+
+/// Characters file names are made of, plain ones more often than the rest
+const PLAIN_CHARS: &[char] = &[
+    'a', 'b', 'c', 'd', 'e', 'f', 'g', 'h', 'k', 'm', 'n', 'p', 'r', 's', 't', 'x', 'A', 'B', 'Q',
+    'Z', '0', '1', '2', '7', '9', '_', '-',
+];
+/// Accents, CJK, Greek and Cyrillic, and spaces, everything a real payload throws at a filesystem
+/// that is still valid on all of them
+const UNUSUAL_CHARS: &[char] = &[
+    'ä', 'ö', 'ü', 'ß', 'é', 'ñ', '日', '本', '語', 'Ω', 'ж', ' ', '.',
+];
+const EXTENSIONS: &[&str] = &["", ".txt", ".bin", ".dat", ".json", ".so"];
+
+/// Bytes of random data written at a time
+const WRITE_SIZE: u64 = 64 * 1024;
+/// Bytes of data at the start, middle and end of a sparse file, the rest are holes
+const SPARSE_DATA: u64 = 4096;
+
+///
+/// What `dev gen-fixture` builds. The same settings always build the same tree, byte for byte,
+/// so performance numbers can be compared across machines and commits.
+///
+#[derive(clap::Args, Debug, Clone, PartialEq, Eq)]
+pub struct FixtureSpec {
+    /// Where to build the tree, has to be empty or not exist yet
+    pub dir: PathBuf,
+
+    /// Trees built with the same seed and settings are identical
+    #[arg(long, default_value_t = 1)]
+    pub seed: u64,
+
+    /// Number of regular files
+    #[arg(long, default_value_t = 500)]
+    pub files: usize,
+
+    /// How deep directories nest below `dir`
+    #[arg(long, default_value_t = 4)]
+    pub depth: usize,
+
+    /// Size of the largest regular file. Sizes are spread evenly across orders of magnitude, so
+    /// most files are small and a few are large.
+    #[arg(long, value_name = "SIZE", default_value = "8MB")]
+    pub max_size: ByteSize,
+
+    /// Number of sparse files, mostly holes with a little data at the start, middle and end
+    #[arg(long, default_value_t = 1)]
+    pub sparse: usize,
+
+    /// Apparent size of each sparse file
+    #[arg(long, value_name = "SIZE", default_value = "256MB")]
+    pub sparse_size: ByteSize,
+}
+
+///
+/// What `generate` built
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct FixtureSummary {
+    /// Regular and sparse files
+    pub files: usize,
+    /// Directories below the fixture's root
+    pub dirs: usize,
+    /// Apparent size of all files, holes included
+    pub bytes: u64,
+}
+
+///
+/// Builds the tree `spec` describes. Sparse files only take up the space of their data on
+/// filesystems that support holes, elsewhere they are written out in full.
+///
+pub fn generate(spec: &FixtureSpec) -> ::std::io::Result<FixtureSummary> {
+    if ::std::fs::read_dir(&spec.dir).is_ok_and(|mut entries| entries.next().is_some()) {
+        return Err(::std::io::Error::new(
+            ::std::io::ErrorKind::AlreadyExists,
+            "the directory isn't empty, a fixture is only built into an empty one",
+        ));
+    }
+    ::std::fs::create_dir_all(&spec.dir)?;
+
+    let mut rng = Rng(spec.seed);
+    let mut summary = FixtureSummary::default();
+    let mut dirs = vec![PathBuf::new()];
+    let mut taken = HashSet::new();
+
+    for _ in 0..spec.files {
+        let parent = dirs[rng.below(dirs.len() as u64) as usize].clone();
+        // Every so often the file goes into a new directory instead
+        let parent = match parent.components().count() < spec.depth && rng.below(6) == 0 {
+            true => {
+                let dir = unique(&mut taken, parent.join(name(&mut rng, "")));
+                ::std::fs::create_dir(spec.dir.join(&dir))?;
+                dirs.push(dir.clone());
+                summary.dirs += 1;
+                dir
+            }
+            false => parent,
+        };
+
+        let extension = EXTENSIONS[rng.below(EXTENSIONS.len() as u64) as usize];
+        let file = unique(&mut taken, parent.join(name(&mut rng, extension)));
+        let size = file_size(&mut rng, spec.max_size.0);
+        write_random(&mut File::create(spec.dir.join(file))?, &mut rng, size)?;
+        summary.files += 1;
+        summary.bytes += size;
+    }
+
+    for i in 0..spec.sparse {
+        let file = unique(&mut taken, PathBuf::from(format!("sparse-{}.img", i)));
+        let mut sparse = File::create(spec.dir.join(file))?;
+        sparse.set_len(spec.sparse_size.0)?;
+        let data = SPARSE_DATA.min(spec.sparse_size.0);
+        for offset in [
+            0,
+            spec.sparse_size.0 / 2,
+            spec.sparse_size.0.saturating_sub(data),
+        ] {
+            sparse.seek(SeekFrom::Start(offset))?;
+            write_random(&mut sparse, &mut rng, data.min(spec.sparse_size.0 - offset))?;
+        }
+        summary.files += 1;
+        summary.bytes += spec.sparse_size.0;
+    }
+    Ok(summary)
+}
+
+///
+/// A name of 1 to 12 characters plus `extension`, that doesn't end in a space or dot
+///
+fn name(rng: &mut Rng, extension: &str) -> String {
+    let len = 1 + rng.below(12);
+    let mut name = (0..len)
+        .map(|_| match rng.below(4) {
+            0 => UNUSUAL_CHARS[rng.below(UNUSUAL_CHARS.len() as u64) as usize],
+            _ => PLAIN_CHARS[rng.below(PLAIN_CHARS.len() as u64) as usize],
+        })
+        .collect::<String>();
+    name = name.trim_end_matches([' ', '.']).to_string();
+    if name.is_empty() {
+        name.push('x');
+    }
+    name + extension
+}
+
+///
+/// `path`, with a number added when it was already used. Names are compared case-insensitively,
+/// as they are on Windows and macOS.
+///
+fn unique(taken: &mut HashSet<String>, path: PathBuf) -> PathBuf {
+    let mut candidate = path.clone();
+    let mut n = 1;
+    while !taken.insert(candidate.to_string_lossy().to_lowercase()) {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        candidate = path.with_file_name(format!("{}-{}", name, n));
+        n += 1;
+    }
+    candidate
+}
+
+///
+/// A size between 0 and `max`, picked evenly across powers of two so small files dominate
+///
+fn file_size(rng: &mut Rng, max: u64) -> u64 {
+    if max == 0 || rng.below(16) == 0 {
+        return 0;
+    }
+    let bits = 64 - max.leading_zeros() as u64;
+    let upper = (1u64 << rng.below(bits + 1)).min(max);
+    1 + rng.below(upper)
+}
+
+fn write_random(file: &mut File, rng: &mut Rng, size: u64) -> ::std::io::Result<()> {
+    let mut left = size;
+    let mut buffer = Vec::with_capacity(WRITE_SIZE as usize);
+    while left > 0 {
+        let chunk = left.min(WRITE_SIZE);
+        buffer.clear();
+        while (buffer.len() as u64) < chunk {
+            buffer.extend_from_slice(&rng.next().to_le_bytes());
+        }
+        file.write_all(&buffer[..chunk as usize])?;
+        left -= chunk;
+    }
+    Ok(())
+}
+
+/// splitmix64, fast and the same on every platform, which is all a fixture needs
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// A number from 0 up to, not including, `n`
+    fn below(&mut self, n: u64) -> u64 {
+        self.next() % n.max(1)
+    }
+}
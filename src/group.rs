@@ -0,0 +1,63 @@
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+///
+/// A named set of destinations, matching how drives are physically organized on a duplication
+/// station. Parsed from `--group lineA=E:,F:`.
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DestinationGroup {
+    pub name: String,
+    pub destinations: Vec<PathBuf>,
+}
+
+impl FromStr for DestinationGroup {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, destinations) = s
+            .split_once('=')
+            .ok_or_else(|| format!("expected NAME=DEST[,DEST...], got `{}`", s))?;
+        if name.is_empty() {
+            return Err("group name cannot be empty".to_string());
+        }
+
+        let destinations = destinations
+            .split(',')
+            .filter(|dest| !dest.is_empty())
+            .map(PathBuf::from)
+            .collect::<Vec<_>>();
+        if destinations.is_empty() {
+            return Err(format!("group `{}` has no destinations", name));
+        }
+
+        Ok(Self {
+            name: name.to_string(),
+            destinations,
+        })
+    }
+}
+
+impl fmt::Display for DestinationGroup {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let destinations = self
+            .destinations
+            .iter()
+            .map(|dest| dest.display().to_string())
+            .collect::<Vec<_>>();
+        write!(f, "{}={}", self.name, destinations.join(","))
+    }
+}
+
+///
+/// The name of the first group in `groups` containing `dest`
+///
+pub fn group_of<'a>(groups: &'a [DestinationGroup], dest: &Path) -> Option<&'a str> {
+    groups
+        .iter()
+        .find(|group| group.destinations.iter().any(|d| d == dest))
+        .map(|group| group.name.as_str())
+}
@@ -0,0 +1,143 @@
+use sha2::{Digest, Sha256};
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::Read,
+    path::{Path, PathBuf},
+    sync::{
+        mpsc::{channel, sync_channel, Receiver, Sender, SyncSender},
+        Arc, Mutex,
+    },
+    thread::JoinHandle,
+};
+
+use crate::walk::LOOKAHEAD;
+
+/// Size of the buffer every hash reads files with
+pub const READ_BUFFER_SIZE: usize = 1024 * 1024;
+
+///
+/// Hex encoded SHA-256 of everything read from `reader`
+///
+pub fn sha256(reader: impl Read) -> ::std::io::Result<String> {
+    sha256_with_progress(reader, |_| {})
+}
+
+///
+/// Same as `sha256`, calling `onread` with the size of every chunk as it is hashed
+///
+pub fn sha256_with_progress(
+    mut reader: impl Read,
+    mut onread: impl FnMut(usize),
+) -> ::std::io::Result<String> {
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0; READ_BUFFER_SIZE];
+    loop {
+        let read = reader.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+        onread(read);
+    }
+    Ok(finish_hex(hasher))
+}
+
+///
+/// The hex encoded digest of `hasher`
+///
+pub(crate) fn finish_hex(hasher: Sha256) -> String {
+    hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+pub fn sha256_file(path: &Path) -> ::std::io::Result<String> {
+    sha256(File::open(path)?)
+}
+
+type Job = (PathBuf, PathBuf);
+type HashResult = (PathBuf, ::std::io::Result<String>);
+
+///
+/// A small pool of worker threads that hashes files off the copy thread, so computing checksums
+/// never holds up writing to the destinations. Files are queued with `submit` and the results are
+/// collected with `finish` once the copy is done.
+///
+pub struct HashPool {
+    jobs: Option<SyncSender<Job>>,
+    /// For hashes computed outside the pool, see `record`
+    result_tx: Sender<HashResult>,
+    results: Receiver<HashResult>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl HashPool {
+    pub fn new(threads: usize) -> Self {
+        let (jobs, job_rx) = sync_channel::<Job>(LOOKAHEAD);
+        let (result_tx, results) = channel();
+        let job_rx = Arc::new(Mutex::new(job_rx));
+
+        let workers = (0..threads.max(1))
+            .map(|_| {
+                let job_rx = job_rx.clone();
+                let result_tx = result_tx.clone();
+                ::std::thread::spawn(move || loop {
+                    let job = job_rx.lock().unwrap().recv();
+                    let Ok((key, path)) = job else {
+                        break;
+                    };
+                    if result_tx.send((key, sha256_file(&path))).is_err() {
+                        break;
+                    }
+                })
+            })
+            .collect();
+
+        Self {
+            jobs: Some(jobs),
+            result_tx,
+            results,
+            workers,
+        }
+    }
+
+    ///
+    /// The default pool size: one worker per core, capped so hashing doesn't crowd out the
+    /// copy itself
+    ///
+    pub fn default_threads() -> usize {
+        ::std::thread::available_parallelism().map_or(2, |n| n.get().min(4))
+    }
+
+    ///
+    /// Queues `path` for hashing. The result is reported under `key` (usually the path relative to
+    /// the source). Blocks once `LOOKAHEAD` files are waiting, so a slow pool can't pile up paths.
+    ///
+    pub fn submit(&self, key: PathBuf, path: PathBuf) {
+        if let Some(jobs) = &self.jobs {
+            jobs.send((key, path)).expect("hash workers exited early");
+        }
+    }
+
+    ///
+    /// Adds a hash computed elsewhere, e.g. from the chunks `--fan-out` reads anyway, to the
+    /// results under `key`
+    ///
+    pub fn record(&self, key: PathBuf, hash: String) {
+        let _ = self.result_tx.send((key, Ok(hash)));
+    }
+
+    ///
+    /// Waits for every queued file to be hashed and returns the results keyed by `key`
+    ///
+    pub fn finish(mut self) -> BTreeMap<PathBuf, ::std::io::Result<String>> {
+        self.jobs = None;
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+        self.results.try_iter().collect()
+    }
+}
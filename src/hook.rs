@@ -0,0 +1,62 @@
+use std::path::{Path, PathBuf};
+
+use crate::{error::CopyError, verify::Verification};
+
+///
+/// Where the copy of a file to a destination is at, down to the byte
+///
+#[derive(Debug, Clone, Copy)]
+pub struct FileProgress<'a> {
+    pub destination: &'a Path,
+    /// Relative to the source
+    pub file: &'a Path,
+    /// Of the file, on the destination so far
+    pub file_bytes: usize,
+    pub file_size: usize,
+    /// Of the whole payload, on the destination so far
+    pub bytes_copied: usize,
+    /// The size of the payload, once the source has been counted through
+    pub total_bytes: Option<usize>,
+}
+
+///
+/// Integration point for anything that wants to follow a deployment (notifications, metrics,
+/// labeling, ...). Hooks are registered on a `CopyQueue` with `CopyQueue::register_hook` and are
+/// invoked in registration order. Every method has a no-op default so implementors only override
+/// the events they care about.
+///
+pub trait DeploymentHook: Send + Sync {
+    /// Called once before anything is written, with the resolved plan
+    fn on_plan(&self, _source: &Path, _destinations: &[PathBuf], _total_bytes: usize) {}
+
+    /// Called when the first file of this run is about to be written to `destination`
+    fn on_destination_started(&self, _destination: &Path) {}
+
+    /// Called when a file is started on a destination, then every time another buffer of it is
+    /// written (`--copy-buffer`, chunks with `--fan-out`), or less often, see
+    /// `CopyQueue::set_progress_granularity`
+    fn on_file_progress(&self, _progress: &FileProgress) {}
+
+    /// Called after `file` (relative to the source) has been fully written to `destination`
+    fn on_file_copied(&self, _destination: &Path, _file: &Path, _bytes: usize) {}
+
+    /// Called with the hex SHA-256 of each source file when hashing is enabled on the queue
+    fn on_file_hashed(&self, _file: &Path, _sha256: &str) {}
+
+    /// Called once every file has been written to `destination`
+    fn on_destination_done(&self, _destination: &Path) {}
+
+    /// Called with the error that stopped the copy, the verification, `--repair` or `--mirror`,
+    /// and with every file `--keep-going` could not copy
+    fn on_error(&self, _error: &CopyError) {}
+
+    /// Called with a transient error the copy tries again after, for the `attempt`th time
+    fn on_retry(&self, _error: &CopyError, _attempt: u32) {}
+
+    /// Called with the outcome of verifying a destination, again after `--repair` for those
+    /// that failed
+    fn on_verified(&self, _verification: &Verification) {}
+
+    /// Called after the last destination has finished
+    fn on_finish(&self) {}
+}
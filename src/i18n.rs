@@ -0,0 +1,137 @@
+use crate::{error::ErrorClass, locale::Locale};
+
+///
+/// A user-facing message, translated through the catalog below. The UI and the JSON report both
+/// go through here so operators see the same wording in either place.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Message {
+    CopyFailed,
+    Verified,
+    /// Warns of a virus scanner holding up the writes to a destination, see
+    /// `antivirus::suspected`
+    ScanSuspected,
+    Error(ErrorClass),
+}
+
+impl Message {
+    ///
+    /// The message in `locale`'s language. `De` and `Fr` get German and French, everything else
+    /// falls back to English.
+    ///
+    pub fn text(self, locale: Locale) -> &'static str {
+        match locale.resolve() {
+            Locale::De => self.de(),
+            Locale::Fr => self.fr(),
+            _ => self.en(),
+        }
+    }
+
+    fn en(self) -> &'static str {
+        match self {
+            Message::CopyFailed => "Copy failed:",
+            Message::Verified => "verified",
+            Message::ScanSuspected => {
+                "writes are being scanned/blocked - consider excluding the drive in Defender"
+            }
+            Message::Error(class) => match class {
+                ErrorClass::AccessDenied => {
+                    "Access denied - run as administrator or check the drive's write-protect switch"
+                }
+                ErrorClass::WriteProtected => {
+                    "The drive is write-protected - check its lock switch or remount it read-write"
+                }
+                ErrorClass::DiskFull => "The drive is full - free up space or use a larger drive",
+                ErrorClass::DeviceNotReady => "Device not ready - was the drive removed?",
+                ErrorClass::NotFound => {
+                    "A file or folder disappeared - was the source changed during the copy?"
+                }
+                ErrorClass::PathTooLong => {
+                    "The path is too long for the destination - shorten the folder structure"
+                }
+                ErrorClass::InUse => {
+                    "The file is in use by another program - close it or exclude it and retry"
+                }
+                ErrorClass::Other => "An unexpected I/O error occurred",
+            },
+        }
+    }
+
+    fn de(self) -> &'static str {
+        match self {
+            Message::CopyFailed => "Kopieren fehlgeschlagen:",
+            Message::Verified => "überprüft",
+            Message::ScanSuspected => {
+                "Schreibvorgänge werden gescannt/blockiert - das Laufwerk ggf. in Defender \
+                 ausschließen"
+            }
+            Message::Error(class) => match class {
+                ErrorClass::AccessDenied => {
+                    "Zugriff verweigert - als Administrator ausführen oder den Schreibschutzschalter \
+                     des Laufwerks prüfen"
+                }
+                ErrorClass::WriteProtected => {
+                    "Das Laufwerk ist schreibgeschützt - Sperrschalter prüfen oder mit \
+                     Schreibzugriff neu einhängen"
+                }
+                ErrorClass::DiskFull => {
+                    "Das Laufwerk ist voll - Speicher freigeben oder ein größeres Laufwerk verwenden"
+                }
+                ErrorClass::DeviceNotReady => {
+                    "Gerät nicht bereit - wurde das Laufwerk entfernt?"
+                }
+                ErrorClass::NotFound => {
+                    "Eine Datei oder ein Ordner ist verschwunden - wurde die Quelle während des \
+                     Kopierens geändert?"
+                }
+                ErrorClass::PathTooLong => {
+                    "Der Pfad ist für das Ziel zu lang - die Ordnerstruktur verkürzen"
+                }
+                ErrorClass::InUse => {
+                    "Die Datei wird von einem anderen Programm verwendet - das Programm schließen \
+                     oder die Datei ausschließen und erneut versuchen"
+                }
+                ErrorClass::Other => "Ein unerwarteter E/A-Fehler ist aufgetreten",
+            },
+        }
+    }
+
+    fn fr(self) -> &'static str {
+        match self {
+            Message::CopyFailed => "Échec de la copie :",
+            Message::Verified => "vérifié",
+            Message::ScanSuspected => {
+                "les écritures sont analysées/bloquées - envisager d'exclure le lecteur dans \
+                 Defender"
+            }
+            Message::Error(class) => match class {
+                ErrorClass::AccessDenied => {
+                    "Accès refusé - exécuter en tant qu'administrateur ou vérifier le verrou de \
+                     protection en écriture du lecteur"
+                }
+                ErrorClass::WriteProtected => {
+                    "Le lecteur est protégé en écriture - vérifier son verrou ou le remonter en \
+                     lecture-écriture"
+                }
+                ErrorClass::DiskFull => {
+                    "Le lecteur est plein - libérer de l'espace ou utiliser un lecteur plus grand"
+                }
+                ErrorClass::DeviceNotReady => {
+                    "Périphérique non prêt - le lecteur a-t-il été retiré ?"
+                }
+                ErrorClass::NotFound => {
+                    "Un fichier ou dossier a disparu - la source a-t-elle été modifiée pendant la \
+                     copie ?"
+                }
+                ErrorClass::PathTooLong => {
+                    "Le chemin est trop long pour la destination - raccourcir l'arborescence"
+                }
+                ErrorClass::InUse => {
+                    "Le fichier est utilisé par un autre programme - le fermer ou l'exclure et \
+                     réessayer"
+                }
+                ErrorClass::Other => "Une erreur d'E/S inattendue s'est produite",
+            },
+        }
+    }
+}
@@ -0,0 +1,175 @@
+use sha2::{Digest, Sha256};
+use std::{
+    fs::{File, OpenOptions},
+    io::{Read, Seek, Write},
+    path::Path,
+};
+
+use crate::{
+    error::CopyError,
+    fanout::{read_full, CHUNK_SIZE},
+    hash::finish_hex,
+};
+
+/// Writes to raw devices have to come in whole sectors on Windows
+#[cfg(windows)]
+const SECTOR_SIZE: usize = 512;
+
+///
+/// Where writing an image onto a device is at, for the progress shown while `--image` runs
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageProgress {
+    /// Bytes of the image written so far
+    Writing(usize),
+    /// Bytes of the device read back and hashed so far
+    Verifying(usize),
+}
+
+///
+/// Outcome of writing an image onto one device
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImageWrite {
+    /// Hex SHA-256 of the image, taken from the reads done for writing it
+    pub image_hash: String,
+    /// Hex SHA-256 of as much of the device as the image covers, read back after writing
+    pub device_hash: String,
+}
+
+impl ImageWrite {
+    pub fn verified(&self) -> bool {
+        self.image_hash == self.device_hash
+    }
+}
+
+///
+/// The size of the device (or file) at `path`. Block devices report a length of 0 in their
+/// metadata, so they are asked directly.
+///
+pub fn device_size(path: &Path) -> ::std::io::Result<u64> {
+    let mut device = File::open(path)?;
+    if device.metadata()?.is_file() {
+        return device.metadata().map(|metadata| metadata.len());
+    }
+    platform_device_size(&mut device)
+}
+
+#[cfg(not(windows))]
+fn platform_device_size(device: &mut File) -> ::std::io::Result<u64> {
+    device.seek(::std::io::SeekFrom::End(0))
+}
+
+#[cfg(windows)]
+fn platform_device_size(device: &mut File) -> ::std::io::Result<u64> {
+    use ::std::os::windows::io::AsRawHandle;
+    use windows_sys::Win32::System::{
+        Ioctl::{GET_LENGTH_INFORMATION, IOCTL_DISK_GET_LENGTH_INFO},
+        IO::DeviceIoControl,
+    };
+
+    let mut info = GET_LENGTH_INFORMATION { Length: 0 };
+    let mut returned = 0;
+    // SAFETY: the handle is open for the lifetime of `device` and `info` is valid for the length
+    // passed
+    let ok = unsafe {
+        DeviceIoControl(
+            device.as_raw_handle() as _,
+            IOCTL_DISK_GET_LENGTH_INFO,
+            ::std::ptr::null(),
+            0,
+            &mut info as *mut GET_LENGTH_INFORMATION as *mut _,
+            ::std::mem::size_of::<GET_LENGTH_INFORMATION>() as u32,
+            &mut returned,
+            ::std::ptr::null_mut(),
+        )
+    };
+    if ok == 0 {
+        return Err(::std::io::Error::last_os_error());
+    }
+    Ok(info.Length as u64)
+}
+
+///
+/// Writes `image` byte for byte onto `device`, then reads it back and hashes it. The device has
+/// to exist already, so a mistyped device path fails instead of turning into a new file.
+/// `onprogress` gets called after every chunk.
+///
+pub fn write_image(
+    image: &Path,
+    device: &Path,
+    mut onprogress: impl FnMut(ImageProgress),
+) -> Result<ImageWrite, CopyError> {
+    let fail = |e| CopyError::new(image, Some(device), e);
+    let mut reader = File::open(image).map_err(fail)?;
+    let mut writer = OpenOptions::new().write(true).open(device).map_err(fail)?;
+
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0; CHUNK_SIZE];
+    let mut written = 0;
+    loop {
+        let read = read_full(&mut reader, &mut buffer, Err).map_err(fail)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+        #[cfg(windows)]
+        let read = {
+            // Pad the last chunk to a whole sector, only the image's own bytes are verified
+            let padded = read.div_ceil(SECTOR_SIZE) * SECTOR_SIZE;
+            buffer[read..padded].fill(0);
+            padded
+        };
+        writer.write_all(&buffer[..read]).map_err(fail)?;
+        written += read;
+        onprogress(ImageProgress::Writing(written));
+    }
+    writer.sync_all().map_err(fail)?;
+    drop(writer);
+
+    let image_len = reader.stream_position().map_err(fail)?;
+    let device_hash = hash_device(device, image_len, |verified| {
+        onprogress(ImageProgress::Verifying(verified))
+    })
+    .map_err(fail)?;
+    Ok(ImageWrite {
+        image_hash: finish_hex(hasher),
+        device_hash,
+    })
+}
+
+///
+/// Hashes the first `len` bytes of `device`, bypassing what the OS still caches of the write
+/// where it can, so the hash reflects what actually made it onto the media
+///
+fn hash_device(
+    device: &Path,
+    len: u64,
+    mut onprogress: impl FnMut(usize),
+) -> ::std::io::Result<String> {
+    let device = File::open(device)?;
+    #[cfg(target_os = "linux")]
+    {
+        use ::std::os::unix::io::AsRawFd;
+        // SAFETY: the descriptor is open for the lifetime of `device`
+        unsafe { libc::posix_fadvise(device.as_raw_fd(), 0, 0, libc::POSIX_FADV_DONTNEED) };
+    }
+
+    let mut reader = device.take(len);
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0; CHUNK_SIZE];
+    let mut verified = 0;
+    loop {
+        let read = read_full(&mut reader, &mut buffer, Err)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+        verified += read;
+        onprogress(verified);
+    }
+    if (verified as u64) < len {
+        return Err(::std::io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(finish_hex(hasher))
+}
@@ -0,0 +1,100 @@
+use std::sync::{
+    atomic::{AtomicBool, AtomicPtr, Ordering},
+    Arc,
+};
+
+use crate::cancel::CancellationToken;
+
+/// Exit status of a run the operator cut short
+pub const CANCELLED_EXIT_CODE: i32 = 130;
+
+/// The switch Ctrl+C sets while a `CtrlC` is around, null otherwise
+static SWITCH: AtomicPtr<AtomicBool> = AtomicPtr::new(::std::ptr::null_mut());
+
+///
+/// Makes Ctrl+C cancel `token` instead of ending the process, until the returned guard is
+/// dropped. A second Ctrl+C, once it is cancelled, still ends the process right away.
+///
+pub fn on_ctrl_c(token: &CancellationToken) -> ::std::io::Result<CtrlC> {
+    // Leaked, the handler may still be looking at it after the guard is gone
+    SWITCH.store(Arc::into_raw(token.switch()).cast_mut(), Ordering::SeqCst);
+    imp::install()?;
+    Ok(CtrlC)
+}
+
+///
+/// Ctrl+C is back to ending the process once this is dropped, see `on_ctrl_c`
+///
+pub struct CtrlC;
+
+impl Drop for CtrlC {
+    fn drop(&mut self) {
+        SWITCH.store(::std::ptr::null_mut(), Ordering::SeqCst);
+        imp::uninstall();
+    }
+}
+
+///
+/// Sets the switch, returning whether it already was
+///
+fn pressed() -> bool {
+    let switch = SWITCH.load(Ordering::SeqCst);
+    // SAFETY: a switch that was stored is never freed
+    unsafe { switch.as_ref() }.is_none_or(|switch| switch.swap(true, Ordering::SeqCst))
+}
+
+#[cfg(unix)]
+mod imp {
+    extern "C" fn on_sigint(_: libc::c_int) {
+        if super::pressed() {
+            // SAFETY: _exit is async-signal-safe, unlike anything that runs destructors
+            unsafe { libc::_exit(super::CANCELLED_EXIT_CODE) };
+        }
+    }
+
+    pub fn install() -> ::std::io::Result<()> {
+        let handler = on_sigint as extern "C" fn(libc::c_int) as libc::sighandler_t;
+        // SAFETY: the handler only touches atomics and calls _exit
+        if unsafe { libc::signal(libc::SIGINT, handler) } == libc::SIG_ERR {
+            return Err(::std::io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    pub fn uninstall() {
+        // SAFETY: the default disposition needs no handler to stay around
+        unsafe { libc::signal(libc::SIGINT, libc::SIG_DFL) };
+    }
+}
+
+#[cfg(windows)]
+mod imp {
+    use windows_sys::Win32::{
+        Foundation::BOOL,
+        System::Console::{SetConsoleCtrlHandler, CTRL_BREAK_EVENT, CTRL_C_EVENT},
+    };
+
+    unsafe extern "system" fn on_ctrl_c(ctrl_type: u32) -> BOOL {
+        if ctrl_type != CTRL_C_EVENT && ctrl_type != CTRL_BREAK_EVENT {
+            return 0;
+        }
+        // Handlers run on a thread of their own, so exiting from one is fine
+        if super::pressed() {
+            ::std::process::exit(super::CANCELLED_EXIT_CODE);
+        }
+        1
+    }
+
+    pub fn install() -> ::std::io::Result<()> {
+        // SAFETY: the handler is a plain function that lives as long as the process
+        if unsafe { SetConsoleCtrlHandler(Some(on_ctrl_c), 1) } == 0 {
+            return Err(::std::io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    pub fn uninstall() {
+        // SAFETY: as in `install`
+        unsafe { SetConsoleCtrlHandler(Some(on_ctrl_c), 0) };
+    }
+}
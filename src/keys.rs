@@ -0,0 +1,140 @@
+use crossterm::event::{KeyCode, KeyEvent};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+///
+/// A key the full-screen UI can be bound to: a single character, or one of `enter`, `esc`,
+/// `space`, `tab`, `backspace` and `f1` to `f12`. Letters match whether shifted or not.
+///
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(try_from = "String", into = "String")]
+pub struct Key(KeyCode);
+
+impl Key {
+    pub fn matches(&self, key: &KeyEvent) -> bool {
+        match (self.0, key.code) {
+            (KeyCode::Char(bound), KeyCode::Char(pressed)) => bound.eq_ignore_ascii_case(&pressed),
+            (bound, pressed) => bound == pressed,
+        }
+    }
+}
+
+impl TryFrom<String> for Key {
+    type Error = String;
+
+    fn try_from(name: String) -> Result<Self, Self::Error> {
+        let mut chars = name.chars();
+        if let (Some(c), None) = (chars.next(), chars.next()) {
+            return match c.is_control() {
+                true => Err(format!("`{}` can't be typed as a key", c.escape_default())),
+                false => Ok(Key(KeyCode::Char(c.to_ascii_lowercase()))),
+            };
+        }
+        let code = match name.to_ascii_lowercase().as_str() {
+            "enter" => KeyCode::Enter,
+            "esc" => KeyCode::Esc,
+            "space" => KeyCode::Char(' '),
+            "tab" => KeyCode::Tab,
+            "backspace" => KeyCode::Backspace,
+            f => match f.strip_prefix('f').and_then(|n| n.parse().ok()) {
+                Some(n @ 1..=12) => KeyCode::F(n),
+                _ => {
+                    return Err(format!(
+                        "unknown key `{}`, expected a single character, enter, esc, space, tab, \
+                         backspace or f1 to f12",
+                        name
+                    ))
+                }
+            },
+        };
+        Ok(Key(code))
+    }
+}
+
+impl From<Key> for String {
+    fn from(key: Key) -> Self {
+        key.to_string()
+    }
+}
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            KeyCode::Char(' ') => write!(f, "space"),
+            KeyCode::Char(c) => write!(f, "{}", c),
+            KeyCode::Enter => write!(f, "enter"),
+            KeyCode::Esc => write!(f, "esc"),
+            KeyCode::Tab => write!(f, "tab"),
+            KeyCode::Backspace => write!(f, "backspace"),
+            KeyCode::F(n) => write!(f, "f{}", n),
+            code => write!(f, "{:?}", code),
+        }
+    }
+}
+
+///
+/// The keys of the UI's main actions, which the `[keys]` table of the config file remaps, e.g.
+/// for keyboard layouts without a handy `y` or when tmux or another TUI already takes a key.
+/// Enter, Esc and Ctrl+C keep working whatever is bound here.
+///
+/// ```toml
+/// [keys]
+/// confirm = "j"
+/// quit = "f10"
+/// ```
+///
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct KeyBindings {
+    /// Starts the copy from the PreCopy screen, or right away from the Waiting screen
+    pub confirm: Key,
+    /// Backs out of the PreCopy and Waiting screens without copying
+    pub cancel: Key,
+    /// Holds the copy on the Copying screen until pressed again
+    pub pause: Key,
+    /// Leaves the UI from the PreCopy, Waiting, Completed and Failed screens
+    pub quit: Key,
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        Self {
+            confirm: Key(KeyCode::Char('y')),
+            cancel: Key(KeyCode::Char('n')),
+            pause: Key(KeyCode::Char('p')),
+            quit: Key(KeyCode::Char('q')),
+        }
+    }
+}
+
+impl KeyBindings {
+    ///
+    /// Fails when one key is bound to two actions that are on the same screen
+    ///
+    pub fn check(&self) -> Result<(), String> {
+        let bound = [
+            ("confirm", self.confirm),
+            ("cancel", self.cancel),
+            ("quit", self.quit),
+        ];
+        for (i, (action, key)) in bound.iter().enumerate() {
+            if let Some((other, _)) = bound[i + 1..].iter().find(|(_, other)| other == key) {
+                return Err(format!(
+                    "`{}` is bound to both {} and {}",
+                    key, action, other
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    ///
+    /// The bindings as `confirm=y,cancel=n,pause=p,quit=q`, for reports
+    ///
+    pub fn describe(&self) -> String {
+        format!(
+            "confirm={},cancel={},pause={},quit={}",
+            self.confirm, self.cancel, self.pause, self.quit
+        )
+    }
+}
@@ -0,0 +1,1483 @@
+use clap::{
+    builder::BoolishValueParser, parser::ValueSource, ArgAction, ArgMatches, CommandFactory,
+    FromArgMatches, Parser, Subcommand, ValueEnum,
+};
+use std::{collections::BTreeSet, ffi::OsString, path::PathBuf};
+
+use crate::{
+    bench::BenchArgs,
+    budget::{open_file_limit, Schedule, RESERVED_FILES},
+    clean::CleanGlob,
+    config::Config,
+    copy::{ChangingFiles, Incremental, MIN_COPY_BUFFER},
+    download::Checksum,
+    fanout::CHUNK_SIZE,
+    fixture::FixtureSpec,
+    group::DestinationGroup,
+    hash::{HashPool, READ_BUFFER_SIZE},
+    keys::KeyBindings,
+    locale::Locale,
+    offload::Engine,
+    overwrite::Overwrite,
+    preserve::Preserve,
+    size::ByteSize,
+    sound::Sounds,
+    start::{Delay, StartAt},
+    symlink::SymlinkPolicy,
+    tar::StdinFormat,
+    throttle::{LimitRate, LimitSchedule},
+    ui::Theme,
+    verify::MetadataCheck,
+    walk::{Collision, Selection, Source, LOOKAHEAD, LOOKAHEAD_ENTRY_BYTES},
+};
+
+pub mod antivirus;
+pub mod bench;
+pub mod budget;
+pub mod calibration;
+pub mod cancel;
+pub mod capacity;
+pub mod chaos;
+pub mod clean;
+pub mod config;
+pub mod copy;
+pub mod counters;
+pub mod deployed;
+pub mod download;
+pub mod drive;
+pub mod drive_log;
+pub mod dry_run;
+pub mod elevate;
+pub mod error;
+pub mod fanout;
+pub mod fixture;
+pub mod group;
+pub mod hash;
+pub mod hook;
+pub mod i18n;
+pub mod image;
+pub mod interrupt;
+pub mod keys;
+pub mod locale;
+pub mod longpath;
+pub mod manifest;
+pub mod mirror;
+pub mod offload;
+pub mod overwrite;
+pub mod preserve;
+pub mod priority;
+pub mod rawpath;
+pub mod report;
+pub mod rerun;
+pub mod setup;
+pub mod simulate;
+pub mod size;
+pub mod sound;
+pub mod start;
+pub mod state;
+pub mod summary;
+pub mod symlink;
+pub mod tar;
+pub mod throttle;
+pub mod transaction;
+pub mod ui;
+pub mod update;
+pub mod verify;
+pub mod version;
+pub mod walk;
+
+#[derive(Parser, Debug, Clone)]
+#[command(
+    author,
+    version,
+    about,
+    disable_version_flag = true,
+    long_about = None,
+    args_conflicts_with_subcommands = true,
+    after_help = "Every option can also be set through its DEPLOYMENT_COPY_* environment variable \
+                  (lists are comma separated). The command line wins over the environment, which \
+                  wins over the config file."
+)]
+pub struct Args {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// Print version
+    #[arg(short = 'V', long)]
+    pub version: bool,
+
+    /// With `--version`, print the version, cargo features, copy engines and platform
+    /// capabilities as a JSON object
+    #[arg(long, requires = "version")]
+    pub json: bool,
+
+    /// Log more of what goes on, `-vv` down to the copy engine's counters once the copy is
+    /// through: read and write calls, buffer refills, retries and cache hits
+    #[arg(long, short, action = ArgAction::Count)]
+    pub verbose: u8,
+
+    /// The directory to deploy, a single file to put at the top of every destination, or `-` with
+    /// `--stdin-format` to read it from stdin
+    #[arg(env = "DEPLOYMENT_COPY_FROM")]
+    pub copy_from: Option<PathBuf>,
+
+    /// Merge this file or directory into the source too, e.g. a config file next to a build
+    /// folder: a file goes at the top of every destination, the entries of a directory next to
+    /// those of the source. A name two of them share is settled by `--collision`. May be given
+    /// several times, the first stands in for the source, and every path on the command line is
+    /// a destination.
+    #[arg(
+        long,
+        value_name = "PATH",
+        conflicts_with_all = ["stdin_format", "image"],
+        env = "DEPLOYMENT_COPY_ALSO_FROM",
+        value_delimiter = ','
+    )]
+    pub from: Vec<PathBuf>,
+
+    /// What to do when more than one source has an entry of the same name at the top level:
+    /// refuse to start (`error`), copy the one given first (`first-wins`) or last
+    /// (`last-wins`), or copy both, the later one as `name (2).ext` (`suffix`). The collisions
+    /// are listed before the copy starts.
+    #[arg(
+        long,
+        value_enum,
+        value_name = "POLICY",
+        default_value_t,
+        env = "DEPLOYMENT_COPY_COLLISION"
+    )]
+    pub collision: Collision,
+
+    /// Download the payload from this URL and deploy it, e.g. a release artifact. An archive
+    /// (`.tar`, `.tar.gz`, `.tgz`, `.tar.xz`, `.tar.bz2` or `.zip`) is unpacked first, anything
+    /// else is deployed as a single file. The download is checked against `--source-sha256`, or
+    /// the `.sha256` published next to it, and refused when there is neither (see
+    /// `--allow-unchecked-source`). Every path on the command line is a destination.
+    #[arg(
+        long,
+        value_name = "URL",
+        conflicts_with = "stdin_format",
+        env = "DEPLOYMENT_COPY_SOURCE_URL"
+    )]
+    pub source_url: Option<String>,
+
+    /// The SHA-256 the `--source-url` download must have, nothing is deployed otherwise
+    #[arg(long, value_name = "HEX", env = "DEPLOYMENT_COPY_SOURCE_SHA256")]
+    pub source_sha256: Option<Checksum>,
+
+    /// Deploy a `--source-url` download that neither `--source-sha256` nor a published `.sha256`
+    /// checks, which is refused otherwise
+    #[arg(
+        long,
+        env = "DEPLOYMENT_COPY_ALLOW_UNCHECKED_SOURCE",
+        value_parser = BoolishValueParser::new()
+    )]
+    pub allow_unchecked_source: bool,
+
+    #[arg(env = "DEPLOYMENT_COPY_DRIVES", value_delimiter = ',')]
+    pub drives: Vec<PathBuf>,
+
+    #[arg(long, short, env = "DEPLOYMENT_COPY_YES", value_parser = BoolishValueParser::new())]
+    pub yes: bool,
+
+    /// Show what the run would copy, overwrite and delete on every destination, file by file,
+    /// then exit without writing anything
+    #[arg(
+        long,
+        conflicts_with_all = ["stdin_format", "image"],
+        env = "DEPLOYMENT_COPY_DRY_RUN",
+        value_parser = BoolishValueParser::new()
+    )]
+    pub dry_run: bool,
+
+    /// Read a `-` source from stdin in this format and unpack it onto every destination, e.g.
+    /// `docker export app | decopy - E: F: --stdin-format tar`. The stream is read once, so
+    /// there is nothing to resume, repair or clean up from, and `--verify` checks against hashes
+    /// taken from the stream.
+    #[arg(
+        long,
+        value_enum,
+        value_name = "FORMAT",
+        conflicts_with_all = [
+            "resume",
+            "repair",
+            "clean_dest_globs",
+            "skip_too_small",
+            "refuse_too_small",
+            "verify_metadata",
+            "incremental",
+            "skip_identical",
+            "mirror",
+            "include",
+            "respect_gitignore",
+            "force_include",
+            "symlinks",
+            "preserve"
+        ],
+        env = "DEPLOYMENT_COPY_STDIN_FORMAT"
+    )]
+    pub stdin_format: Option<StdinFormat>,
+
+    /// Treat the source as a raw disk image and write it byte for byte onto the destinations,
+    /// which are block devices then (e.g. `decopy --image sd.img /dev/sdb /dev/sdc`). Every
+    /// device is read back and checked against the image's SHA-256 afterwards. Writing asks for
+    /// confirmation three times, even with `--yes`.
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "stdin_format",
+            "fan_out",
+            "resume",
+            "repair",
+            "sha256sums",
+            "skip_too_small",
+            "refuse_too_small",
+            "verify_metadata",
+            "incremental",
+            "skip_identical",
+            "overwrite",
+            "mirror",
+            "drive_log",
+            "clean_dest_globs",
+            "preserve"
+        ],
+        env = "DEPLOYMENT_COPY_IMAGE",
+        value_parser = BoolishValueParser::new()
+    )]
+    pub image: bool,
+
+    /// With `--image`, copy this directory into a partition of every device once the image is
+    /// written and verified, for images that leave a partition to be filled (Linux only)
+    #[arg(
+        long,
+        value_name = "DIR",
+        requires = "image",
+        env = "DEPLOYMENT_COPY_THEN_COPY"
+    )]
+    pub then_copy: Option<PathBuf>,
+
+    /// The partition `--then-copy` copies into, counting from 1 in the image's partition table
+    #[arg(
+        long,
+        value_name = "N",
+        default_value = "1",
+        value_parser = clap::value_parser!(u32).range(1..),
+        env = "DEPLOYMENT_COPY_INTO_PARTITION"
+    )]
+    pub into_partition: u32,
+
+    /// How to group digits in byte counts and file totals
+    #[arg(long, value_enum, default_value_t, env = "DEPLOYMENT_COPY_LOCALE")]
+    pub locale: Locale,
+
+    /// Colors of the full-screen UI
+    #[arg(long, value_enum, default_value_t, env = "DEPLOYMENT_COPY_THEME")]
+    pub theme: Theme,
+
+    /// Keys of the full-screen UI, only set from the `[keys]` table of the config file
+    #[arg(skip)]
+    pub keys: KeyBindings,
+
+    /// What `--beep` plays for each event, only set from the `[sounds]` table of the config file
+    #[arg(skip)]
+    pub sounds: Sounds,
+
+    /// The options given on the command line or in the environment, which the config file
+    /// leaves alone, see `parse_with_sources`
+    #[arg(skip)]
+    given: BTreeSet<String>,
+
+    /// Continue an interrupted run from its last checkpoint instead of starting over
+    #[arg(long, env = "DEPLOYMENT_COPY_RESUME", value_parser = BoolishValueParser::new())]
+    pub resume: bool,
+
+    /// Where progress is checkpointed while copying, for `--resume`
+    #[arg(
+        long,
+        default_value = ".decopy-state.toml",
+        env = "DEPLOYMENT_COPY_STATE_FILE"
+    )]
+    pub state_file: PathBuf,
+
+    /// Run with idle I/O priority so the deployment doesn't slow down interactive work
+    #[arg(long, env = "DEPLOYMENT_COPY_NICE_IO", value_parser = BoolishValueParser::new())]
+    pub nice_io: bool,
+
+    /// Re-read every destination after copying and compare it against the source. A run with
+    /// files that don't match exits with status 3.
+    #[arg(long, env = "DEPLOYMENT_COPY_VERIFY", value_parser = BoolishValueParser::new())]
+    pub verify: bool,
+
+    /// Copy files that fail verification again and re-check them, instead of failing the run.
+    /// Turns on `--verify`.
+    #[arg(long, env = "DEPLOYMENT_COPY_REPAIR", value_parser = BoolishValueParser::new())]
+    pub repair: bool,
+
+    /// What `--verify` compares besides the contents: modification times (`times`), permissions
+    /// (`perms`) and where preserved links point (`links`), or `none` of them. By default what
+    /// `--preserve` and `--symlinks preserve` carry over, so a verified drive has all of it.
+    #[arg(
+        long,
+        value_enum,
+        value_name = "CHECKS",
+        env = "DEPLOYMENT_COPY_VERIFY_METADATA",
+        value_delimiter = ','
+    )]
+    pub verify_metadata: Vec<MetadataCheck>,
+
+    /// Write a `SHA256SUMS` file listing every copied file and its hash into each destination,
+    /// checkable with `sha256sum -c`. The hashes are taken while copying, the source isn't read
+    /// again for them.
+    #[arg(long, env = "DEPLOYMENT_COPY_SHA256SUMS", value_parser = BoolishValueParser::new())]
+    pub sha256sums: bool,
+
+    /// Only copy files that are new or changed since they were last copied, for repeated
+    /// deployments to the same drives. `mtime` (the default) compares size and modification time,
+    /// `--incremental=hash` compares size and SHA-256. Drives written without `--incremental` are
+    /// copied in full once, as their files don't carry the source's modification times yet.
+    #[arg(
+        long,
+        value_enum,
+        value_name = "CHECK",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "mtime",
+        env = "DEPLOYMENT_COPY_INCREMENTAL"
+    )]
+    pub incremental: Option<Incremental>,
+
+    /// Leave files alone that a destination already has with the same size and SHA-256, reading
+    /// both copies. Short for `--incremental=hash`, for re-deploying a mostly unchanged payload
+    /// without rewriting all of it.
+    #[arg(
+        long,
+        conflicts_with = "incremental",
+        env = "DEPLOYMENT_COPY_SKIP_IDENTICAL",
+        value_parser = BoolishValueParser::new()
+    )]
+    pub skip_identical: bool,
+
+    /// What to do with files a destination already has, of those `--incremental` doesn't leave
+    /// alone: write them again (`always`), keep them (`never`), write them again when the
+    /// source's copy was modified later (`if-newer`), or ask about each of them on the Copying
+    /// screen (`prompt`, needs the full-screen UI)
+    #[arg(
+        long,
+        value_enum,
+        value_name = "POLICY",
+        default_value_t,
+        env = "DEPLOYMENT_COPY_OVERWRITE"
+    )]
+    pub overwrite: Overwrite,
+
+    /// Give copied files the source's modification times (`times`) and Unix mode bits
+    /// (`perms`), e.g. `--preserve times,perms` for installers that check timestamps. On FAT
+    /// the times are rounded up to its 2 seconds, and there as on Windows only whether a file is
+    /// read-only is kept of the permissions.
+    #[arg(
+        long,
+        value_enum,
+        value_name = "ATTRS",
+        env = "DEPLOYMENT_COPY_PRESERVE",
+        value_delimiter = ','
+    )]
+    pub preserve: Vec<Preserve>,
+
+    /// Delete files and directories the source doesn't have from every destination before
+    /// copying, so the drives end up exactly like the source. Paths left out with `--exclude`
+    /// are kept. What will be deleted is shown before the copy starts.
+    #[arg(long, env = "DEPLOYMENT_COPY_MIRROR", value_parser = BoolishValueParser::new())]
+    pub mirror: bool,
+
+    /// Copy into a `.decopy-staging` directory on every destination and swap it in only once the
+    /// copy, and with `--verify` the verification, went through for that destination, so a failed
+    /// or cancelled run leaves the drive as it was. Every top level entry of the source replaces
+    /// the destination's entry of the same name as a whole. Needs room for the whole payload
+    /// besides what it replaces.
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "stdin_format",
+            "image",
+            "simulate_speed",
+            "resume",
+            "incremental",
+            "skip_identical",
+            "overwrite",
+            "mirror"
+        ],
+        env = "DEPLOYMENT_COPY_TRANSACTIONAL",
+        value_parser = BoolishValueParser::new()
+    )]
+    pub transactional: bool,
+
+    /// Keep a `deployment.log` in the root of every destination with that drive's own timeline:
+    /// when the copy started, every file written, errors and the verification result. Later
+    /// runs append to it, so a drive returned from the field tells where its contents came from. A
+    /// `deployment.log` in the source is left out of the copy.
+    #[arg(long, env = "DEPLOYMENT_COPY_DRIVE_LOG", value_parser = BoolishValueParser::new())]
+    pub drive_log: bool,
+
+    /// Leave out destinations without enough free space for the payload instead of letting them
+    /// fail partway through the copy
+    #[arg(
+        long,
+        env = "DEPLOYMENT_COPY_SKIP_TOO_SMALL",
+        value_parser = BoolishValueParser::new()
+    )]
+    pub skip_too_small: bool,
+
+    /// Refuse to start while a destination hasn't enough free space for the payload. Otherwise
+    /// such a destination is only warned about, unless `--skip-too-small` leaves it out.
+    #[arg(
+        long,
+        conflicts_with = "skip_too_small",
+        env = "DEPLOYMENT_COPY_REFUSE_TOO_SMALL",
+        value_parser = BoolishValueParser::new()
+    )]
+    pub refuse_too_small: bool,
+
+    /// Eject every destination once the run is done and wait until the OS reports it safe to
+    /// remove
+    #[arg(long, env = "DEPLOYMENT_COPY_EJECT", value_parser = BoolishValueParser::new())]
+    pub eject: bool,
+
+    /// Flush every destination out of the OS write cache before the run is reported done, so a
+    /// drive is safe to pull even without `--eject`. USB drives report writes as done as soon as
+    /// the OS cached them.
+    #[arg(long, env = "DEPLOYMENT_COPY_SYNC", value_parser = BoolishValueParser::new())]
+    pub sync: bool,
+
+    /// Ring the terminal bell as each ejected drive becomes safe to remove. The `[sounds]` table of
+    /// the config file picks other cues, and cues for failed verification and the end of the run.
+    #[arg(long, env = "DEPLOYMENT_COPY_BEEP", value_parser = BoolishValueParser::new())]
+    pub beep: bool,
+
+    /// Delete copied files matching this glob from every destination once the run is done, e.g.
+    /// `*.tmp` for targets that choke on stray build leftovers. May be given several times.
+    #[arg(
+        long = "clean-dest-glob",
+        value_name = "GLOB",
+        env = "DEPLOYMENT_COPY_CLEAN_DEST_GLOBS",
+        value_delimiter = ','
+    )]
+    pub clean_dest_globs: Vec<CleanGlob>,
+
+    /// Leave paths matching this glob, relative to the source, and everything below them out of
+    /// the run, e.g. `*.pdb` or `target/**`. `*` also matches across directories, a plain path
+    /// excludes just that path. May be given several times.
+    #[arg(
+        long,
+        value_name = "GLOB",
+        env = "DEPLOYMENT_COPY_EXCLUDE",
+        value_delimiter = ','
+    )]
+    pub exclude: Vec<PathBuf>,
+
+    /// Only copy files matching this glob, relative to the source, or below a directory that
+    /// does, e.g. `*.bin` or `firmware/**`. Globs work like those of `--exclude`, which still
+    /// leaves out what it matches. May be given several times, without it every file is copied.
+    #[arg(
+        long,
+        value_name = "GLOB",
+        env = "DEPLOYMENT_COPY_INCLUDE",
+        value_delimiter = ','
+    )]
+    pub include: Vec<PathBuf>,
+
+    /// Leave out what the `.gitignore` and `.ignore` files in the source ignore, as git would,
+    /// along with `.git` itself, for deploying straight from a working tree
+    #[arg(
+        long,
+        env = "DEPLOYMENT_COPY_RESPECT_GITIGNORE",
+        value_parser = BoolishValueParser::new()
+    )]
+    pub respect_gitignore: bool,
+
+    /// Copy paths matching this glob, relative to the source, with everything below them even
+    /// when `--exclude`, `--respect-gitignore` or `--include` would leave them out, e.g. for
+    /// vendored or symlinked dependencies the ignore files skip. These go first: a forced path is
+    /// copied, then excluded paths are left out, then ignored ones, then files no `--include`
+    /// matches. May be given several times.
+    #[arg(
+        long,
+        value_name = "GLOB",
+        env = "DEPLOYMENT_COPY_FORCE_INCLUDE",
+        value_delimiter = ','
+    )]
+    pub force_include: Vec<PathBuf>,
+
+    /// What to do with symlinks in the source: copy what they point to, recreate them as links
+    /// on the destinations, or leave them out. Preserved links into the source are made relative
+    /// so they resolve on the destination, those pointing outside it are kept as they are and
+    /// warned about.
+    #[arg(
+        long,
+        value_enum,
+        value_name = "POLICY",
+        default_value_t,
+        env = "DEPLOYMENT_COPY_SYMLINKS"
+    )]
+    pub symlinks: SymlinkPolicy,
+
+    /// Append one row per destination to this CSV file after every run
+    #[arg(long, env = "DEPLOYMENT_COPY_SUMMARY_CSV")]
+    pub summary_csv: Option<PathBuf>,
+
+    /// Write a JSON report of the run to this file, including error codes and localized messages
+    #[arg(long, env = "DEPLOYMENT_COPY_REPORT")]
+    pub report: Option<PathBuf>,
+
+    /// Show a QR code of the run ID and manifest hash once the run is done, for labeling
+    #[arg(long, env = "DEPLOYMENT_COPY_QR", value_parser = BoolishValueParser::new())]
+    pub qr: bool,
+
+    /// Name a set of destinations, e.g. `--group lineA=E:,F:`. Grouped destinations don't need to
+    /// be listed again and get group-level progress and reporting.
+    #[arg(
+        long = "group",
+        value_name = "NAME=DEST,...",
+        env = "DEPLOYMENT_COPY_GROUPS",
+        value_delimiter = ';'
+    )]
+    pub groups: Vec<DestinationGroup>,
+
+    /// Copy to this many destinations at the same time, each of them reading the source on its
+    /// own. Ignored with `--fan-out`, which writes to every destination at once anyway.
+    #[arg(
+        long,
+        value_name = "N",
+        default_value = "1",
+        value_parser = clap::value_parser!(u16).range(1..),
+        env = "DEPLOYMENT_COPY_JOBS"
+    )]
+    pub jobs: u16,
+
+    /// Hash and verify on this many threads: the source is hashed on them while copying, and
+    /// `--verify` checks up to this many destinations at once, each as soon as it is copied
+    /// rather than once all of them are. One per core, at most 4, by default.
+    #[arg(
+        long,
+        value_name = "N",
+        value_parser = clap::value_parser!(u16).range(1..),
+        env = "DEPLOYMENT_COPY_THREADS"
+    )]
+    pub threads: Option<u16>,
+
+    /// Read each source file once and write it to every destination at the same time, with a
+    /// bounded queue per destination so slow drives don't hold back fast ones. The hashes for
+    /// `--verify` are computed from the same reads.
+    #[arg(long, env = "DEPLOYMENT_COPY_FAN_OUT", value_parser = BoolishValueParser::new())]
+    pub fan_out: bool,
+
+    /// How far the reader may get ahead of the slowest destination in `--fan-out` mode. More
+    /// memory lets fast drives run further ahead of slow ones.
+    #[arg(
+        long,
+        value_name = "SIZE",
+        default_value = "16MB",
+        env = "DEPLOYMENT_COPY_PIPELINE_BUFFER"
+    )]
+    pub pipeline_buffer: ByteSize,
+
+    /// How much of a file is read and written at a time when not in `--fan-out` mode. Progress
+    /// is reported after every buffer, so a smaller one moves the progress bars more smoothly
+    /// and a larger one may copy faster. Anything under 4KB counts as 4KB.
+    #[arg(
+        long,
+        value_name = "SIZE",
+        default_value = "1MB",
+        env = "DEPLOYMENT_COPY_COPY_BUFFER"
+    )]
+    pub copy_buffer: ByteSize,
+
+    /// How files are copied: with what the system has for it where it can, falling back to a
+    /// buffer of our own (`auto`), or always through the buffer (`buffered`). On Windows that's
+    /// `CopyFileExW`, which keeps the attributes of the source files. Linux and macOS clone
+    /// files on file systems that can, e.g. Btrfs, XFS and APFS, and Linux otherwise copies them
+    /// inside the kernel. On Windows a file `--resume` continues partway goes through the buffer.
+    #[arg(
+        long,
+        value_enum,
+        value_name = "ENGINE",
+        default_value_t,
+        env = "DEPLOYMENT_COPY_ENGINE"
+    )]
+    pub engine: Engine,
+
+    /// Refuse to start if the copy pipeline could need more than this. The source is walked
+    /// with a bounded look-ahead, so this covers the look-ahead, `--pipeline-buffer` and the
+    /// read buffers, whatever the number of files. The hashes kept for `--verify` and the list of
+    /// mismatched files grow with the source and are not included.
+    #[arg(
+        long,
+        value_name = "SIZE",
+        default_value = "256MB",
+        env = "DEPLOYMENT_COPY_MAX_MEMORY"
+    )]
+    pub max_memory: ByteSize,
+
+    /// Refuse to start if the run could hold more files open at once than this, counting the
+    /// source and destination files being copied, hashed and verified. Fewer destinations are
+    /// copied (`--jobs`) and verified (`--threads`) at a time before that, and the same goes for
+    /// `--max-memory`. By default as many as the system lets the program open, less a few it
+    /// needs for other things.
+    #[arg(
+        long,
+        value_name = "N",
+        value_parser = clap::value_parser!(u32).range(1..),
+        env = "DEPLOYMENT_COPY_MAX_OPEN_FILES"
+    )]
+    pub max_open_files: Option<u32>,
+
+    /// Wait with a countdown and begin copying at this time of day (`HH:MM`), tomorrow if it has
+    /// already passed today
+    #[arg(long, value_name = "HH:MM", env = "DEPLOYMENT_COPY_START_AT")]
+    pub start_at: Option<StartAt>,
+
+    /// Wait with a countdown this long before copying, e.g. `10m` or `1h30m`
+    #[arg(
+        long,
+        value_name = "DURATION",
+        conflicts_with = "start_at",
+        env = "DEPLOYMENT_COPY_DELAY"
+    )]
+    pub delay: Option<Delay>,
+
+    /// Limit the copy speed to this many bytes per second, e.g. `20MB/s` so copying to slow USB 2
+    /// sticks doesn't starve the machine. Covers the whole run, with `--limit-schedule` the lower
+    /// limit applies.
+    #[arg(long, value_name = "RATE", env = "DEPLOYMENT_COPY_LIMIT_RATE")]
+    pub limit_rate: Option<LimitRate>,
+
+    /// Limit the copy speed by time of day, e.g. `09:00-17:00=10MB,else=unlimited` to go easy on
+    /// shared links during working hours. Limits are per second and cover the whole run.
+    #[arg(long, value_name = "SCHEDULE", env = "DEPLOYMENT_COPY_LIMIT_SCHEDULE")]
+    pub limit_schedule: Option<LimitSchedule>,
+
+    /// Try a file again this many times when writing or reading it fails with an error that
+    /// tends to go away, like a drive that isn't ready or a file another program has open,
+    /// before giving up on the destination
+    #[arg(
+        long,
+        value_name = "N",
+        default_value = "3",
+        env = "DEPLOYMENT_COPY_MAX_RETRIES"
+    )]
+    pub max_retries: u32,
+
+    /// Wait this long before the first retry of a file, e.g. `500ms` or `2s`. Every further retry
+    /// of the same file waits twice as long, up to a minute.
+    #[arg(
+        long,
+        value_name = "DURATION",
+        default_value = "1s",
+        env = "DEPLOYMENT_COPY_RETRY_DELAY"
+    )]
+    pub retry_delay: Delay,
+
+    /// Go on with the other files and destinations when a file can't be copied, list what
+    /// failed at the end and exit with status 5. A source that can't be read still stops the run.
+    #[arg(long, env = "DEPLOYMENT_COPY_KEEP_GOING", value_parser = BoolishValueParser::new())]
+    pub keep_going: bool,
+
+    /// What to do with a source file that changes size while it is read, like a log file still
+    /// being written to: read it once more (`retry`) or leave it out right away (`skip`). A file
+    /// left out is listed as a warning at the end rather than copied half old and half new.
+    #[arg(
+        long,
+        value_enum,
+        value_name = "POLICY",
+        default_value_t,
+        env = "DEPLOYMENT_COPY_CHANGING_FILES"
+    )]
+    pub changing_files: ChangingFiles,
+
+    /// When the copy is cancelled with Ctrl+C, delete the file it was writing instead of keeping
+    /// what got written for `--resume` to continue from
+    #[arg(
+        long,
+        env = "DEPLOYMENT_COPY_REMOVE_PARTIAL",
+        value_parser = BoolishValueParser::new()
+    )]
+    pub remove_partial: bool,
+
+    /// Randomly fail, stall or fill up on this fraction of file writes, to exercise and demo the
+    /// failure handling without real broken hardware
+    #[arg(
+        long,
+        hide = true,
+        value_name = "RATE",
+        num_args = 0..=1,
+        default_missing_value = "0.05",
+        env = "DEPLOYMENT_COPY_CHAOS"
+    )]
+    pub chaos: Option<f64>,
+
+    /// Seed for `--chaos`, to replay the same faults
+    #[arg(long, hide = true, env = "DEPLOYMENT_COPY_CHAOS_SEED")]
+    pub chaos_seed: Option<u64>,
+
+    /// Write to simulated drives that take data at this rate, e.g. `5MB/s`, rather than to the
+    /// destinations, which are only used as names. For demos and for testing the UI without
+    /// hardware, nothing is written to disk.
+    #[arg(
+        long,
+        hide = true,
+        value_name = "RATE",
+        conflicts_with_all = [
+            "stdin_format",
+            "image",
+            "dry_run",
+            "fan_out",
+            "resume",
+            "verify",
+            "repair",
+            "mirror",
+            "sha256sums",
+            "drive_log",
+            "eject",
+            "sync",
+            "clean_dest_globs",
+            "skip_too_small",
+            "refuse_too_small",
+        ],
+        env = "DEPLOYMENT_COPY_SIMULATE_SPEED"
+    )]
+    pub simulate_speed: Option<LimitRate>,
+
+    /// Look for a newer release when starting and mention it, giving up after a few seconds when
+    /// offline. Nothing is installed without `self-update`.
+    #[arg(long, env = "DEPLOYMENT_COPY_CHECK_UPDATES", value_parser = BoolishValueParser::new())]
+    pub check_updates: bool,
+
+    /// TOML file providing defaults for the options not given on the command line. Those for one
+    /// run or one machine, such as `--dry-run`, `--delay` or `--locale`, aren't read from it.
+    /// Without it the per-user config file is used when there is one, e.g.
+    /// `~/.config/decopy/config.toml`.
+    #[arg(long, env = "DEPLOYMENT_COPY_CONFIG")]
+    pub config: Option<PathBuf>,
+
+    /// Use the `[profiles.NAME]` section of the config file on top of its other keys. Profiles
+    /// can be saved from the PreCopy screen with `s`.
+    #[arg(long, value_name = "NAME", env = "DEPLOYMENT_COPY_PROFILE")]
+    pub profile: Option<String>,
+}
+
+#[derive(Subcommand, Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    /// Replace this binary with the latest release for this platform, downloaded with `curl`
+    SelfUpdate,
+    /// Measure copy and hashing throughput on a standard workload. With `--baseline`, exits with
+    /// a non-zero status when it dropped further below the baseline than `--threshold` allows.
+    Bench(BenchArgs),
+    /// Tools for working on decopy itself
+    #[command(hide = true)]
+    Dev {
+        #[command(subcommand)]
+        command: DevCommand,
+    },
+}
+
+#[derive(Subcommand, Debug, Clone, PartialEq, Eq)]
+pub enum DevCommand {
+    /// Build a reproducible source tree for tests and benchmarks
+    GenFixture(FixtureSpec),
+}
+
+impl Args {
+    ///
+    /// Parses the command line like `Parser::parse`, noting which options were given there or in
+    /// the environment rather than left at their defaults, for `merge_config`
+    ///
+    pub fn parse_with_sources() -> Self {
+        Self::with_sources(Self::command().get_matches()).unwrap_or_else(|e| e.exit())
+    }
+
+    ///
+    /// `parse_with_sources` for `args` rather than the command line of the process
+    ///
+    pub fn try_parse_with_sources_from(
+        args: impl IntoIterator<Item = impl Into<OsString> + Clone>,
+    ) -> Result<Self, clap::Error> {
+        Self::with_sources(Self::command().try_get_matches_from(args)?)
+    }
+
+    fn with_sources(matches: ArgMatches) -> Result<Self, clap::Error> {
+        let mut args = Self::from_arg_matches(&matches)?;
+        args.given = matches
+            .ids()
+            .filter(|id| {
+                matches!(
+                    matches.value_source(id.as_str()),
+                    Some(ValueSource::CommandLine | ValueSource::EnvVariable)
+                )
+            })
+            .map(|id| id.to_string())
+            .collect();
+        Ok(args)
+    }
+
+    ///
+    /// Whether the option with the field `id` was given on the command line or in the
+    /// environment, which wins over the config file even when it is the default value
+    ///
+    fn given(&self, id: &str) -> bool {
+        self.given.contains(id)
+    }
+
+    ///
+    /// Fills in every option `config` has that wasn't given on the command line or in the
+    /// environment
+    ///
+    pub fn merge_config(&mut self, config: Config) {
+        // The source is taken as a whole from where it is given first
+        if self.copy_from.is_none() && self.from.is_empty() && self.source_url.is_none() {
+            match config.source_url {
+                Some(url) => self.source_url = Some(url),
+                None => self.copy_from = config.copy_from,
+            }
+        }
+        if self.source_sha256.is_none() {
+            self.source_sha256 = config.source_sha256;
+        }
+        if !self.given("allow_unchecked_source") {
+            self.allow_unchecked_source = config
+                .allow_unchecked_source
+                .unwrap_or(self.allow_unchecked_source);
+        }
+        if self.from.is_empty() {
+            self.from = config.from.unwrap_or_default();
+        }
+        if !self.given("collision") {
+            self.collision = config.collision.unwrap_or(self.collision);
+        }
+        if self.drives.is_empty() {
+            self.drives = config.drives.unwrap_or_default();
+        }
+        if !self.given("yes") {
+            self.yes = config.yes.unwrap_or(self.yes);
+        }
+        if !self.given("nice_io") {
+            self.nice_io = config.nice_io.unwrap_or(self.nice_io);
+        }
+        if !self.given("verify") {
+            self.verify = config.verify.unwrap_or(self.verify);
+        }
+        if self.verify_metadata.is_empty() {
+            self.verify_metadata = config.verify_metadata.unwrap_or_default();
+        }
+        if !self.given("repair") {
+            self.repair = config.repair.unwrap_or(self.repair);
+        }
+        if !self.given("sha256sums") {
+            self.sha256sums = config.sha256sums.unwrap_or(self.sha256sums);
+        }
+        // Both say what happens to a destination that is too small, given either the command
+        // line wins
+        if !self.given("skip_too_small") && !self.given("refuse_too_small") {
+            self.skip_too_small = config.skip_too_small.unwrap_or(self.skip_too_small);
+            self.refuse_too_small =
+                !self.skip_too_small && config.refuse_too_small.unwrap_or(self.refuse_too_small);
+        }
+        // Both say how files already there are checked, given either the command line wins
+        if self.incremental.is_none() && !self.given("skip_identical") {
+            self.incremental = config.incremental;
+            self.skip_identical = config.skip_identical.unwrap_or(self.skip_identical);
+        }
+        if !self.given("overwrite") {
+            self.overwrite = config.overwrite.unwrap_or(self.overwrite);
+        }
+        if self.preserve.is_empty() {
+            self.preserve = config.preserve.unwrap_or_default();
+        }
+        if !self.given("mirror") {
+            self.mirror = config.mirror.unwrap_or(self.mirror);
+        }
+        if !self.given("transactional") {
+            self.transactional = config.transactional.unwrap_or(self.transactional);
+        }
+        if !self.given("drive_log") {
+            self.drive_log = config.drive_log.unwrap_or(self.drive_log);
+        }
+        if !self.given("jobs") {
+            self.jobs = config.jobs.unwrap_or(self.jobs).max(1);
+        }
+        if self.threads.is_none() {
+            self.threads = config.threads.filter(|threads| *threads > 0);
+        }
+        if self.max_open_files.is_none() {
+            self.max_open_files = config.max_open_files.filter(|files| *files > 0);
+        }
+        if !self.given("fan_out") {
+            self.fan_out = config.fan_out.unwrap_or(self.fan_out);
+        }
+        if !self.given("eject") {
+            self.eject = config.eject.unwrap_or(self.eject);
+        }
+        if !self.given("sync") {
+            self.sync = config.sync.unwrap_or(self.sync);
+        }
+        if !self.given("beep") {
+            self.beep = config.beep.unwrap_or(self.beep);
+        }
+        if !self.given("check_updates") {
+            self.check_updates = config.check_updates.unwrap_or(self.check_updates);
+        }
+        if !self.given("theme") {
+            self.theme = config.theme.unwrap_or(self.theme);
+        }
+        if let Some(keys) = config.keys {
+            self.keys = keys;
+        }
+        if let Some(sounds) = config.sounds {
+            self.sounds = sounds;
+        }
+        if self.summary_csv.is_none() {
+            self.summary_csv = config.summary_csv;
+        }
+        if self.report.is_none() {
+            self.report = config.report;
+        }
+        if self.limit_rate.is_none() {
+            self.limit_rate = config.limit_rate;
+        }
+        if self.limit_schedule.is_none() {
+            self.limit_schedule = config.limit_schedule;
+        }
+        if !self.given("max_retries") {
+            self.max_retries = config.max_retries.unwrap_or(self.max_retries);
+        }
+        if !self.given("retry_delay") {
+            self.retry_delay = config.retry_delay.unwrap_or(self.retry_delay);
+        }
+        if !self.given("keep_going") {
+            self.keep_going = config.keep_going.unwrap_or(self.keep_going);
+        }
+        if !self.given("changing_files") {
+            self.changing_files = config.changing_files.unwrap_or(self.changing_files);
+        }
+        if !self.given("engine") {
+            self.engine = config.engine.unwrap_or(self.engine);
+        }
+        if !self.given("remove_partial") {
+            self.remove_partial = config.remove_partial.unwrap_or(self.remove_partial);
+        }
+        if self.clean_dest_globs.is_empty() {
+            self.clean_dest_globs = config.clean_dest_globs.unwrap_or_default();
+        }
+        if self.exclude.is_empty() {
+            self.exclude = config.exclude.unwrap_or_default();
+        }
+        if self.include.is_empty() {
+            self.include = config.include.unwrap_or_default();
+        }
+        if !self.given("respect_gitignore") {
+            self.respect_gitignore = config.respect_gitignore.unwrap_or(self.respect_gitignore);
+        }
+        if self.force_include.is_empty() {
+            self.force_include = config.force_include.unwrap_or_default();
+        }
+        if !self.given("symlinks") {
+            self.symlinks = config.symlinks.unwrap_or(self.symlinks);
+        }
+        if self.groups.is_empty() {
+            self.groups = config
+                .groups
+                .unwrap_or_default()
+                .into_iter()
+                .map(|(name, destinations)| DestinationGroup { name, destinations })
+                .collect();
+        }
+    }
+
+    ///
+    /// Every option with the value this run goes by, after the config file, environment and
+    /// command line have been merged, in `--help` order. Logged when a run starts and kept in the
+    /// report, so why a run behaved the way it did can be answered from its output.
+    ///
+    pub fn effective_options(&self) -> Vec<(&'static str, String)> {
+        fn list<T: ToString>(items: &[T], separator: &str) -> String {
+            items
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(separator)
+        }
+        fn opt<T: ToString>(value: &Option<T>) -> String {
+            value.as_ref().map_or(String::new(), ToString::to_string)
+        }
+        let path = |path: &Option<PathBuf>| opt(&path.as_ref().map(|p| p.display()));
+        let drives = self.drives.iter().map(|d| d.display()).collect::<Vec<_>>();
+
+        let mut options = vec![
+            ("copy-from", path(&self.copy_from)),
+            (
+                "from",
+                list(
+                    &self.from.iter().map(|p| p.display()).collect::<Vec<_>>(),
+                    ",",
+                ),
+            ),
+            (
+                "collision",
+                opt(&self
+                    .collision
+                    .to_possible_value()
+                    .map(|v| v.get_name().to_string())),
+            ),
+            ("source-url", opt(&self.source_url)),
+            ("source-sha256", opt(&self.source_sha256)),
+            (
+                "allow-unchecked-source",
+                self.allow_unchecked_source.to_string(),
+            ),
+            ("drives", list(&drives, ",")),
+            ("yes", self.yes.to_string()),
+            ("dry-run", self.dry_run.to_string()),
+            (
+                "stdin-format",
+                opt(&self
+                    .stdin_format
+                    .and_then(|format| format.to_possible_value())
+                    .map(|v| v.get_name().to_string())),
+            ),
+            ("image", self.image.to_string()),
+            ("then-copy", path(&self.then_copy)),
+            ("into-partition", self.into_partition.to_string()),
+            (
+                "locale",
+                opt(&self
+                    .locale
+                    .to_possible_value()
+                    .map(|v| v.get_name().to_string())),
+            ),
+            (
+                "theme",
+                opt(&self
+                    .theme
+                    .to_possible_value()
+                    .map(|v| v.get_name().to_string())),
+            ),
+            ("keys", self.keys.describe()),
+            ("resume", self.resume.to_string()),
+            ("state-file", self.state_file.display().to_string()),
+            ("nice-io", self.nice_io.to_string()),
+            ("verify", self.verify.to_string()),
+            ("repair", self.repair.to_string()),
+            (
+                "verify-metadata",
+                list(
+                    &self
+                        .metadata_checks()
+                        .iter()
+                        .filter_map(|check| check.to_possible_value())
+                        .map(|v| v.get_name().to_string())
+                        .collect::<Vec<_>>(),
+                    ",",
+                ),
+            ),
+            ("sha256sums", self.sha256sums.to_string()),
+            (
+                "incremental",
+                opt(&self
+                    .incremental
+                    .and_then(|check| check.to_possible_value())
+                    .map(|v| v.get_name().to_string())),
+            ),
+            ("skip-identical", self.skip_identical.to_string()),
+            (
+                "overwrite",
+                opt(&self
+                    .overwrite
+                    .to_possible_value()
+                    .map(|v| v.get_name().to_string())),
+            ),
+            (
+                "preserve",
+                list(
+                    &self
+                        .preserve
+                        .iter()
+                        .filter_map(|attr| attr.to_possible_value())
+                        .map(|v| v.get_name().to_string())
+                        .collect::<Vec<_>>(),
+                    ",",
+                ),
+            ),
+            ("mirror", self.mirror.to_string()),
+            ("transactional", self.transactional.to_string()),
+            ("drive-log", self.drive_log.to_string()),
+            ("skip-too-small", self.skip_too_small.to_string()),
+            ("refuse-too-small", self.refuse_too_small.to_string()),
+            ("eject", self.eject.to_string()),
+            ("sync", self.sync.to_string()),
+            ("beep", self.beep.to_string()),
+            ("sounds", self.sounds.describe()),
+            ("clean-dest-glob", list(&self.clean_dest_globs, ",")),
+            (
+                "exclude",
+                list(
+                    &self.exclude.iter().map(|p| p.display()).collect::<Vec<_>>(),
+                    ",",
+                ),
+            ),
+            (
+                "include",
+                list(
+                    &self.include.iter().map(|p| p.display()).collect::<Vec<_>>(),
+                    ",",
+                ),
+            ),
+            ("respect-gitignore", self.respect_gitignore.to_string()),
+            (
+                "force-include",
+                list(
+                    &self
+                        .force_include
+                        .iter()
+                        .map(|p| p.display())
+                        .collect::<Vec<_>>(),
+                    ",",
+                ),
+            ),
+            (
+                "symlinks",
+                opt(&self
+                    .symlinks
+                    .to_possible_value()
+                    .map(|v| v.get_name().to_string())),
+            ),
+            ("summary-csv", path(&self.summary_csv)),
+            ("report", path(&self.report)),
+            ("qr", self.qr.to_string()),
+            ("group", list(&self.groups, ";")),
+            ("jobs", self.jobs.to_string()),
+            ("threads", self.threads().to_string()),
+            ("fan-out", self.fan_out.to_string()),
+            ("pipeline-buffer", self.pipeline_buffer.to_string()),
+            ("copy-buffer", self.copy_buffer.to_string()),
+            (
+                "engine",
+                opt(&self
+                    .engine
+                    .to_possible_value()
+                    .map(|v| v.get_name().to_string())),
+            ),
+            ("max-memory", self.max_memory.to_string()),
+            ("max-open-files", opt(&self.max_open_files())),
+            ("start-at", opt(&self.start_at)),
+            ("delay", opt(&self.delay)),
+            ("limit-rate", opt(&self.limit_rate)),
+            ("limit-schedule", opt(&self.limit_schedule)),
+            ("max-retries", self.max_retries.to_string()),
+            ("retry-delay", self.retry_delay.to_string()),
+            ("keep-going", self.keep_going.to_string()),
+            (
+                "changing-files",
+                opt(&self
+                    .changing_files
+                    .to_possible_value()
+                    .map(|v| v.get_name().to_string())),
+            ),
+            ("remove-partial", self.remove_partial.to_string()),
+        ];
+        // Hidden from `--help`, so only worth mentioning when in use
+        if self.chaos.is_some() {
+            options.push(("chaos", opt(&self.chaos)));
+            options.push(("chaos-seed", opt(&self.chaos_seed)));
+        }
+        if self.simulate_speed.is_some() {
+            options.push(("simulate-speed", opt(&self.simulate_speed)));
+        }
+        options.push(("verbose", self.verbose.to_string()));
+        options.push(("check-updates", self.check_updates.to_string()));
+        options.push(("config", path(&self.config)));
+        options.push(("profile", opt(&self.profile)));
+        options
+    }
+
+    ///
+    /// How many threads hash and verify, see `--threads`
+    ///
+    pub fn threads(&self) -> usize {
+        self.threads
+            .map_or_else(HashPool::default_threads, usize::from)
+    }
+
+    ///
+    /// `--copy-buffer` in bytes, at least `MIN_COPY_BUFFER`
+    ///
+    pub fn copy_buffer(&self) -> usize {
+        (self.copy_buffer.0 as usize).max(MIN_COPY_BUFFER)
+    }
+
+    ///
+    /// Upper bound of what the copy pipeline holds in memory with these options, see
+    /// `--max-memory`
+    ///
+    pub fn memory_needed(&self) -> u64 {
+        self.memory_needed_by(self.schedule())
+    }
+
+    fn memory_needed_by(&self, schedule: Schedule) -> u64 {
+        // Every device is written and read back through a chunk of its own
+        if self.image {
+            return (self.drives.len().max(1) * CHUNK_SIZE) as u64;
+        }
+        let walks = self.walks(schedule);
+        let mut needed = (walks * LOOKAHEAD * LOOKAHEAD_ENTRY_BYTES) as u64;
+        let hashing = self.verify || self.qr || self.sha256sums;
+        if !self.fan_out && self.stdin_format.is_none() {
+            // Every walk copies through a buffer of its own
+            needed += (walks * self.copy_buffer()) as u64;
+        }
+        if self.fan_out || self.stdin_format.is_some() {
+            // Every writer holds one chunk besides the queue, and so do the reader and the
+            // hasher, which hashes the chunks read for copying
+            let holders = self.drives.len() + 1 + hashing as usize;
+            needed += self.pipeline_buffer.0 + (CHUNK_SIZE * holders) as u64;
+        } else if hashing {
+            needed += (schedule.threads * READ_BUFFER_SIZE) as u64;
+        }
+        // Destinations verified while others are still copying read through buffers of their own
+        if self.verify && self.drives.len() > 1 {
+            needed += (schedule.threads.min(self.drives.len()) * READ_BUFFER_SIZE) as u64;
+        }
+        needed
+    }
+
+    ///
+    /// Upper bound of the files the run holds open at once with these options, see
+    /// `--max-open-files`
+    ///
+    pub fn open_files_needed(&self) -> usize {
+        self.open_files_needed_by(self.schedule())
+    }
+
+    fn open_files_needed_by(&self, schedule: Schedule) -> usize {
+        // The source image and every device
+        if self.image {
+            return self.drives.len().max(1) + 1;
+        }
+        // Every walk lists one directory at a time
+        let walks = self.walks(schedule);
+        let hashing = self.verify || self.qr || self.sha256sums;
+        let mut needed = walks;
+        if self.fan_out || self.stdin_format.is_some() {
+            // One source file, written to every destination at once. The chunks read for it
+            // are hashed as well.
+            needed += 1 + self.drives.len();
+        } else {
+            // Every walk copies one source file to one destination file at a time
+            needed += walks * 2;
+            if hashing {
+                needed += schedule.threads;
+            }
+        }
+        // A destination being verified has a source file and its copy open
+        if self.verify && self.drives.len() > 1 {
+            needed += schedule.threads.min(self.drives.len()) * 2;
+        }
+        needed
+    }
+
+    ///
+    /// How many times the source is walked at once: every destination copied on its own walks it
+    /// on its own
+    ///
+    fn walks(&self, schedule: Schedule) -> usize {
+        match self.fan_out || self.stdin_format.is_some() {
+            true => 1,
+            false => schedule.jobs.clamp(1, self.drives.len().max(1)),
+        }
+    }
+
+    ///
+    /// `--max-open-files`, or what the system lets the program open less `RESERVED_FILES`.
+    /// `None` when nothing limits it.
+    ///
+    pub fn max_open_files(&self) -> Option<usize> {
+        match self.max_open_files {
+            Some(files) => Some(files as usize),
+            None => open_file_limit().map(|limit| limit.saturating_sub(RESERVED_FILES).max(1)),
+        }
+    }
+
+    ///
+    /// `--jobs` and `--threads`, each lowered one at a time, the larger first, until the run
+    /// fits within `--max-open-files` and `--max-memory` or both are down to one. The checks
+    /// refuse what doesn't fit even then.
+    ///
+    pub fn schedule(&self) -> Schedule {
+        let fits = |schedule: Schedule| {
+            self.memory_needed_by(schedule) <= self.max_memory.0
+                && self
+                    .max_open_files()
+                    .is_none_or(|max| self.open_files_needed_by(schedule) <= max)
+        };
+        let mut schedule = Schedule {
+            jobs: (self.jobs as usize).max(1),
+            threads: self.threads(),
+        };
+        // The threads only hold files and buffers when they hash or verify
+        let threaded = self.verify || self.qr || self.sha256sums;
+        while !fits(schedule) {
+            if threaded && schedule.threads > 1 && schedule.threads >= schedule.jobs {
+                schedule.threads -= 1;
+            } else if schedule.jobs > 1 {
+                schedule.jobs -= 1;
+            } else {
+                break;
+            }
+        }
+        schedule
+    }
+
+    ///
+    /// Checks `memory_needed` against `--max-memory`
+    ///
+    pub fn check_memory(&self) -> Result<(), String> {
+        let needed = self.memory_needed();
+        if needed <= self.max_memory.0 {
+            return Ok(());
+        }
+        Err(format!(
+            "this run could need {} of memory, more than --max-memory {}; lower --pipeline-buffer or raise --max-memory",
+            ui::get_bytes_string(needed as usize, self.locale),
+            ui::get_bytes_string(self.max_memory.0 as usize, self.locale),
+        ))
+    }
+
+    ///
+    /// Checks `open_files_needed` against `--max-open-files`
+    ///
+    pub fn check_open_files(&self) -> Result<(), String> {
+        let needed = self.open_files_needed();
+        match self.max_open_files() {
+            Some(max) if needed > max => Err(format!(
+                "this run could hold {} files open at once, more than the {} it may; copy to fewer destinations at a time, without --fan-out, or raise --max-open-files and the system's limit (ulimit -n)",
+                needed, max
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    ///
+    /// The metadata `--verify` compares: what `--verify-metadata` lists, or what the run carries
+    /// over when it lists nothing
+    ///
+    pub fn metadata_checks(&self) -> Vec<MetadataCheck> {
+        match self.verify_metadata.is_empty() {
+            true => MetadataCheck::implied(&self.preserve, self.symlinks),
+            false => self
+                .verify_metadata
+                .iter()
+                .copied()
+                .filter(|check| *check != MetadataCheck::None)
+                .filter(|_| !self.verify_metadata.contains(&MetadataCheck::None))
+                .collect(),
+        }
+    }
+
+    ///
+    /// What of the source `--exclude`, `--include`, `--respect-gitignore` and `--force-include`
+    /// leave to the run, and how `--symlinks` goes about links
+    ///
+    pub fn selection(&self) -> Selection {
+        Selection {
+            excluded: self.exclude.clone(),
+            included: self.include.clone(),
+            gitignore: self.respect_gitignore,
+            forced: self.force_include.clone(),
+            symlinks: self.symlinks,
+        }
+    }
+
+    ///
+    /// The tree the run copies: `copy_from` with every `--from` merged into it, see `Source`. A
+    /// `-` source and an `--image` are taken as they are.
+    ///
+    pub fn source(&self) -> ::std::io::Result<Source> {
+        let mut paths = self.copy_from.iter().chain(&self.from);
+        if self.stdin_format.is_some() || self.image {
+            return Ok(paths.next().map(Source::from).unwrap_or_default());
+        }
+        let mut source = Source::default();
+        for path in paths {
+            source.merge(path, self.collision)?;
+        }
+        Ok(source)
+    }
+
+    ///
+    /// With `--from` or `--source-url` the source isn't among the positional arguments, they are
+    /// all destinations. Moves the one clap took for the source over to `drives`.
+    ///
+    pub fn shift_source_to_drives(&mut self) {
+        if self.from.is_empty() && self.source_url.is_none() {
+            return;
+        }
+        if let Some(dest) = self.copy_from.take() {
+            self.drives.insert(0, dest);
+        }
+    }
+
+    ///
+    /// Drops every destination that is the same place as one listed before it, however it is
+    /// spelled, and returns them to be warned about. A destination that is a source directory or
+    /// inside one is an error, the copy would end up copying itself. That includes one reached
+    /// through a bind mount or `subst` drive of a source, which has a path of its own.
+    ///
+    pub fn dedup_destinations(&mut self) -> Result<Vec<PathBuf>, String> {
+        let sources = match self.stdin_format {
+            Some(_) => Vec::new(),
+            None => self
+                .copy_from
+                .iter()
+                .chain(&self.from)
+                .filter(|source| source.is_dir())
+                .map(|source| (source, drive::canonical(source)))
+                .collect(),
+        };
+        let canonical = self
+            .drives
+            .iter()
+            .map(|dest| drive::canonical(dest))
+            .collect::<Vec<_>>();
+        for (dest, canonical) in self.drives.iter().zip(&canonical) {
+            if let Some((source, _)) = sources.iter().find(|(_, dir)| canonical.starts_with(dir)) {
+                return Err(format!(
+                    "destination `{}` is inside the source `{}`, the copy would copy itself",
+                    dest.display(),
+                    source.display()
+                ));
+            }
+            if let Some((source, _)) = sources.iter().find(|(_, dir)| drive::is_within(dest, dir)) {
+                return Err(format!(
+                    "destination `{}` is the source `{}` mounted elsewhere, or inside it, the \
+                     copy would copy itself",
+                    dest.display(),
+                    source.display()
+                ));
+            }
+        }
+        let (kept, duplicates) = ::std::mem::take(&mut self.drives)
+            .into_iter()
+            .enumerate()
+            .partition::<Vec<_>, _>(|(i, _)| !canonical[..*i].contains(&canonical[*i]));
+        self.drives = kept.into_iter().map(|(_, dest)| dest).collect();
+        Ok(duplicates.into_iter().map(|(_, dest)| dest).collect())
+    }
+
+    ///
+    /// Adds every grouped destination that wasn't also listed on its own to `drives`
+    ///
+    pub fn add_group_destinations(&mut self) {
+        for dest in self.groups.iter().flat_map(|group| &group.destinations) {
+            if !self.drives.contains(dest) {
+                self.drives.push(dest.clone());
+            }
+        }
+    }
+}
@@ -0,0 +1,78 @@
+use clap::ValueEnum;
+
+///
+/// Controls how numbers are grouped in the UI and reports. `Auto` picks a locale from the usual
+/// `LC_ALL`/`LC_NUMERIC`/`LANG` environment variables.
+///
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Locale {
+    #[default]
+    Auto,
+    /// 1,234,567
+    En,
+    /// 1.234.567
+    De,
+    /// 1 234 567
+    Fr,
+    /// 1234567
+    None,
+}
+
+impl Locale {
+    ///
+    /// Resolves `Auto` into a concrete locale, falling back to `En` when nothing can be detected
+    ///
+    pub fn resolve(self) -> Self {
+        if self != Locale::Auto {
+            return self;
+        }
+
+        let lang = ["LC_ALL", "LC_NUMERIC", "LANG"]
+            .iter()
+            .filter_map(|var| ::std::env::var(var).ok())
+            .find(|value| !value.is_empty())
+            .unwrap_or_default();
+
+        match lang.get(..2) {
+            Some("de" | "nl" | "it" | "es" | "pt" | "da" | "id" | "tr") => Locale::De,
+            Some("fr" | "ru" | "pl" | "cs" | "sv" | "fi" | "nb" | "uk") => Locale::Fr,
+            _ if lang == "C" || lang == "POSIX" => Locale::None,
+            _ => Locale::En,
+        }
+    }
+
+    pub fn thousands_separator(self) -> Option<char> {
+        match self.resolve() {
+            Locale::De => Some('.'),
+            Locale::Fr => Some(' '),
+            Locale::None => None,
+            _ => Some(','),
+        }
+    }
+
+    pub fn decimal_separator(self) -> char {
+        match self.resolve() {
+            Locale::De | Locale::Fr => ',',
+            _ => '.',
+        }
+    }
+
+    ///
+    /// Formats `n` with this locale's thousands separator, e.g. `1234567` -> `1,234,567`
+    ///
+    pub fn format_number(self, n: u64) -> String {
+        let digits = n.to_string();
+        let Some(separator) = self.thousands_separator() else {
+            return digits;
+        };
+
+        let mut out = String::with_capacity(digits.len() + digits.len() / 3);
+        for (i, c) in digits.chars().enumerate() {
+            if i > 0 && (digits.len() - i).is_multiple_of(3) {
+                out.push(separator);
+            }
+            out.push(c);
+        }
+        out
+    }
+}
@@ -0,0 +1,65 @@
+use std::{
+    ffi::{OsStr, OsString},
+    path::{Component, Path, PathBuf, Prefix},
+};
+
+///
+/// `path` in the `\\?\` form Windows takes paths longer than `MAX_PATH` (260 characters) in, which
+/// deep `node_modules` trees easily get past. That form is taken as it is, so `path` is made
+/// absolute with `.`, `..` and `/` resolved first. Paths in that form already, device paths and
+/// paths that can't be made absolute stay as they are, as does every path on other platforms.
+///
+pub fn extended(path: &Path) -> PathBuf {
+    if !cfg!(windows) {
+        return path.to_path_buf();
+    }
+    let Ok(absolute) = ::std::path::absolute(path) else {
+        return path.to_path_buf();
+    };
+    let mut extended = match absolute.components().next() {
+        Some(Component::Prefix(prefix)) => match prefix.kind() {
+            Prefix::Disk(letter) => PathBuf::from(format!(r"\\?\{}:\", letter as char)),
+            Prefix::UNC(server, share) => unc(r"\\?\UNC\", server, share),
+            _ => return absolute,
+        },
+        _ => return absolute,
+    };
+    extended.extend(normal_components(&absolute));
+    extended
+}
+
+///
+/// `path` without the `\\?\` `extended` put in front of it, for messages
+///
+pub fn shown(path: &Path) -> PathBuf {
+    let mut shown = match path.components().next() {
+        Some(Component::Prefix(prefix)) => match prefix.kind() {
+            Prefix::VerbatimDisk(letter) => PathBuf::from(format!(r"{}:\", letter as char)),
+            Prefix::VerbatimUNC(server, share) => unc(r"\\", server, share),
+            _ => return path.to_path_buf(),
+        },
+        _ => return path.to_path_buf(),
+    };
+    shown.extend(normal_components(path));
+    shown
+}
+
+///
+/// The root of the share `server\share`, after `start`
+///
+fn unc(start: &str, server: &OsStr, share: &OsStr) -> PathBuf {
+    let mut root = OsString::from(start);
+    root.push(server);
+    root.push(r"\");
+    root.push(share);
+    root.push(r"\");
+    PathBuf::from(root)
+}
+
+///
+/// The names of `path` after its prefix and root
+///
+fn normal_components(path: &Path) -> impl Iterator<Item = Component<'_>> {
+    path.components()
+        .filter(|component| matches!(component, Component::Normal(_)))
+}
@@ -1,54 +1,291 @@
-use clap::Parser;
+use chrono::{DateTime, Local};
+use clap::{error::ErrorKind, CommandFactory};
 use crossterm::{
     cursor::{MoveToColumn, MoveUp},
     queue,
     style::{Color, Print, SetForegroundColor, Stylize},
-    terminal::{Clear, ClearType},
+    terminal::{self, Clear, ClearType},
 };
+use qrcode::{render::unicode::Dense1x2, QrCode};
 use std::{
-    io::{stdout, Write},
-    path::PathBuf,
+    cell::RefCell,
+    collections::HashMap,
+    io::{stdin, stdout, IsTerminal, Write},
+    path::{Path, PathBuf},
+    sync::mpsc::channel,
+    time::Duration,
 };
 
-use crate::copy::CopyQueue;
+use deployment_copy::{
+    bench::{self, Baseline, BenchArgs},
+    budget,
+    calibration::{self, Calibration},
+    capacity::{needed_vs_available, Fit},
+    config::{self, Config, DEFAULT_CONFIG},
+    copy::{CopyQueue, DestinationSummary},
+    counters::Counts,
+    deployed::{self, Deployed},
+    download::{self, Checksum},
+    drive, elevate,
+    error::CopyError,
+    fixture::{self, FixtureSpec},
+    group::{group_of, DestinationGroup},
+    i18n::Message,
+    image::{self, ImageProgress},
+    interrupt::{self, CANCELLED_EXIT_CODE},
+    locale::Locale,
+    mirror::{self, Stale},
+    overwrite::Overwrite,
+    priority, rawpath,
+    report::{ErrorReport, Report},
+    rerun::rerun_command,
+    setup::{self, Setup, SetupAction},
+    sound::SoundEvent,
+    start::{countdown, start_time, Delay},
+    summary::{append_summary_csv, run_id, SummaryRow},
+    ui::{
+        self, can_elevate, changed_files_warning, copy_in_background, failed_file_lines,
+        failure_lines, get_bytes_string, retry_in_background, PreviewEntry, Terminal,
+        TerminalEvents, UIState, Ui, UiAction,
+    },
+    update::{self, UpdateOutcome},
+    verify::Verification,
+    version,
+    walk::{prescan_top_level, Exclusions},
+    Args, Command, DevCommand,
+};
 
-pub mod copy;
+/// Exit status of a run that copied everything but failed `--verify`, set apart from the 1 of a
+/// failed copy so scripts can tell a bad drive from a run that never got through
+const VERIFY_FAILED_EXIT_CODE: i32 = 3;
+/// Exit status of a `bench` that ran fine but was slower than its baseline allows
+const BENCH_REGRESSED_EXIT_CODE: i32 = 4;
+/// Exit status of a `--keep-going` run that got through but could not copy some of the files
+const PARTIAL_FAILURE_EXIT_CODE: i32 = 5;
 
-#[derive(Parser, Debug)]
-#[command(author, version, about, long_about = None)]
-pub struct Args {
-    #[arg()]
-    pub copy_from: PathBuf,
+/// Stale files listed by name per destination before `--mirror` deletes them
+const LISTED_STALE: usize = 5;
+/// How many `-v` it takes for the copy engine's counters to be logged
+const VERBOSE_COUNTERS: u8 = 2;
 
-    #[arg()]
-    pub drives: Vec<PathBuf>,
+fn main() {
+    let mut args = Args::parse_with_sources();
+    args.shift_source_to_drives();
+    if args.version {
+        print_version(args.json);
+    }
+    match &args.command {
+        Some(Command::SelfUpdate) => self_update(),
+        Some(Command::Bench(bench)) => run_bench(bench, args.locale.resolve()),
+        Some(Command::Dev {
+            command: DevCommand::GenFixture(spec),
+        }) => gen_fixture(spec, args.locale.resolve()),
+        None => {}
+    }
+    if args.config.is_none() {
+        args.config = config::default_path()
+            .filter(|path| path.exists() || (!args.yes && first_run_setup(path)));
+    }
+    if args.profile.is_some() && args.config.is_none() {
+        Args::command()
+            .error(
+                ErrorKind::MissingRequiredArgument,
+                "--profile needs a config file, give one with --config",
+            )
+            .exit();
+    }
+    if let Some(path) = args.config.clone() {
+        match Config::load_profile(&path, args.profile.as_deref()) {
+            Ok(config) => args.merge_config(config),
+            Err(e) => {
+                eprintln!("error: {}", e);
+                ::std::process::exit(2);
+            }
+        }
+    }
+    if args.check_updates {
+        check_for_update();
+    }
+    if let Some(url) = args.source_url.clone() {
+        args.copy_from = Some(download_source(
+            &url,
+            args.source_sha256.as_ref(),
+            args.allow_unchecked_source,
+            args.locale,
+        ));
+    }
+    // The first `--from` stands in for the source
+    if args.copy_from.is_none() && !args.from.is_empty() {
+        args.copy_from = Some(args.from.remove(0));
+    }
+    let Some(source) = args.copy_from.clone() else {
+        Args::command()
+            .error(
+                ErrorKind::MissingRequiredArgument,
+                "no source given on the command line or in the config file",
+            )
+            .exit();
+    };
 
-    #[arg(long, short)]
-    pub yes: bool,
-}
+    match (source == Path::new("-"), args.stdin_format) {
+        (true, None) => Args::command()
+            .error(
+                ErrorKind::MissingRequiredArgument,
+                "reading the source from stdin needs --stdin-format",
+            )
+            .exit(),
+        (false, Some(_)) => Args::command()
+            .error(
+                ErrorKind::ArgumentConflict,
+                "--stdin-format needs `-` as the source",
+            )
+            .exit(),
+        // A stream can't wait for a confirmation, and there is no listing to show for one
+        (true, Some(_)) => args.yes = true,
+        (false, None) => {}
+    }
 
-fn main() {
-    let args = Args::parse();
+    args.locale = args.locale.resolve();
+    args.verify |= args.repair;
+    // The drives of a simulation are never written, there is nothing on them to check or tidy.
+    // The command line can't ask for that, the config file may have.
+    if args.simulate_speed.is_some() {
+        args.verify = false;
+        args.repair = false;
+        args.mirror = false;
+        args.sha256sums = false;
+        args.drive_log = false;
+        args.eject = false;
+        args.sync = false;
+        args.clean_dest_globs.clear();
+        args.skip_too_small = false;
+        args.refuse_too_small = false;
+    }
+    // A staged copy starts out empty, there is nothing on it to resume, keep or delete
+    if args.transactional {
+        args.resume = false;
+        args.incremental = None;
+        args.skip_identical = false;
+        args.overwrite = Overwrite::Always;
+        args.mirror = false;
+    }
+    args.add_group_destinations();
+    match args.dedup_destinations() {
+        Ok(duplicates) => {
+            for dest in duplicates {
+                log(format!(
+                    "{}\n",
+                    format!(
+                        "`{}` is the same destination as one listed before it, copying to it once",
+                        dest.display()
+                    )
+                    .yellow()
+                ));
+            }
+        }
+        Err(e) => Args::command().error(ErrorKind::ValueValidation, e).exit(),
+    }
+    budget::raise_open_file_limit();
+    if let Err(e) = args.check_memory().and_then(|()| args.check_open_files()) {
+        Args::command().error(ErrorKind::ValueValidation, e).exit();
+    }
+    let schedule = args.schedule();
+    if schedule.jobs < (args.jobs as usize).min(args.drives.len())
+        || schedule.threads < args.threads()
+    {
+        log(format!(
+            "{}\n",
+            format!(
+                "Copying {} and verifying {} destination(s) at a time, to stay within --max-open-files and --max-memory",
+                schedule.jobs, schedule.threads
+            )
+            .yellow()
+        ));
+    }
 
     let mut copy_from = ::std::env::current_dir().expect("Failed to get current directory");
-    copy_from.push(args.copy_from.clone());
-
-    let dir = ::std::fs::read_dir(&copy_from)
-        .unwrap_or_else(|_| panic!("Could not open directory `{}`", copy_from.display()));
+    copy_from.push(source);
+    if args.image {
+        write_image(&args, &copy_from);
+    }
 
-    let dir_list = dir
-        .filter(|d| d.is_ok())
-        .map(|d| match d {
-            Ok(dir) => (dir.path(), dir.file_name().to_string_lossy().to_string()),
-            Err(_) => {
-                unreachable!();
+    let exclusions = Exclusions::new(&args.exclude);
+    let dir_list = match args.stdin_format {
+        Some(_) => Vec::new(),
+        None => {
+            let source = args.source().unwrap_or_else(|e| {
+                Args::command()
+                    .error(ErrorKind::ValueValidation, format!("--from: {}", e))
+                    .exit()
+            });
+            for collision in source.collisions() {
+                log(format!("{}\n", collision.to_string().yellow()));
             }
-        })
-        .collect::<Vec<(PathBuf, String)>>();
-
-    print_pre_copy_status(&dir_list, &args);
+            prescan_top_level(&source, &args.selection()).unwrap_or_else(|e| {
+                panic!(
+                    "Could not open directory `{}`: {}",
+                    source.name().display(),
+                    e
+                )
+            })
+        }
+    }
+    .into_iter()
+    .map(|(path, totals)| PreviewEntry {
+        excluded: exclusions.matches(&path),
+        path,
+        totals,
+    })
+    .collect::<Vec<_>>();
 
-    if !args.yes {
+    // Pipes and CI logs get plain line output, terminals the full-screen UI
+    let interactive = stdout().is_terminal();
+    if args.overwrite == Overwrite::Prompt && !interactive && !args.dry_run {
+        Args::command()
+            .error(
+                ErrorKind::ArgumentConflict,
+                "--overwrite prompt asks on the full-screen UI, which needs a terminal",
+            )
+            .exit();
+    }
+    // Printed ahead of the full-screen UI too, so it stays in the scrollback
+    print_options(&args);
+    if args.dry_run {
+        dry_run(&args);
+    }
+    // The fit margins need the whole payload counted, only worth the wait before a prompt
+    let payload = (!interactive
+        && args.stdin_format.is_none()
+        && (!args.yes || args.skip_too_small || args.refuse_too_small))
+        .then(|| counted_payload(|| ui::payload(&dir_list)));
+    let stale = (args.mirror && args.stdin_format.is_none()).then(|| stale_on_destinations(&args));
+    // Only a source that can be walked is checked against what the destinations already hold
+    let deployed = match args.stdin_format.is_none() && !args.image {
+        true => deployed_on_destinations(&args),
+        false => vec![None; args.drives.len()],
+    };
+    if !interactive {
+        print_pre_copy_status(&dir_list, &args, payload, stale.as_deref());
+        skip_deployed(&mut args, &deployed);
+    }
+    if let Some(payload) = payload.filter(|_| !args.skip_too_small) {
+        let too_small = too_small(&args.drives, payload);
+        if args.refuse_too_small && !too_small.is_empty() {
+            refuse_too_small(&too_small, args.locale);
+        }
+        for (dest, fit) in &too_small {
+            log(format!(
+                "{}\n",
+                format!(
+                    "`{}` is {}, the copy to it will run out of space partway through",
+                    drive::describe(dest),
+                    fit.describe(args.locale)
+                )
+                .yellow()
+            ));
+        }
+    }
+    if !interactive && !args.yes {
         print!(
             "Does everything look correct? (You can disable this prompt with the `-y` flag) (Y/n) "
         );
@@ -77,51 +314,1878 @@ fn main() {
         }
     }
 
+    if let Some(payload) = payload.filter(|_| args.skip_too_small) {
+        let skipped = skip_too_small(&mut args, payload);
+        log_skipped(&skipped, args.locale);
+        if args.drives.is_empty() {
+            no_destination_fits();
+        }
+    }
+
+    if !interactive {
+        if let Some(start) = start_time(args.start_at, args.delay) {
+            wait_for_start(start);
+        }
+    }
+
+    if args.nice_io {
+        if let Err(e) = priority::lower_io_priority() {
+            log(format!("Could not lower I/O priority: {}\n", e));
+        }
+    }
+
     let mut queue = CopyQueue::from(&args);
-    handle_copying(&mut queue);
+    if let Some(simulation) = queue.simulation() {
+        log(format!(
+            "{}\n",
+            format!(
+                "Simulation mode: nothing is written, every destination takes {}/s",
+                get_bytes_string(simulation.rate().0 as usize, args.locale)
+            )
+            .yellow()
+        ));
+    }
+    if let Some(chaos) = queue.chaos() {
+        log(format!(
+            "{}\n",
+            format!(
+                "Chaos mode: injecting faults into {:.0} % of file writes (seed {})",
+                chaos.rate() * 100.,
+                chaos.seed()
+            )
+            .yellow()
+        ));
+    }
+    if let Some(throttle) = queue.throttle() {
+        match throttle.limit_now() {
+            Some(limit) => log(format!(
+                "Copy speed limited to {}/s for now\n",
+                get_bytes_string(limit as usize, args.locale)
+            )),
+            None => log("Copy speed not limited for now by the limit schedule\n"),
+        }
+    }
+    let started_at = Local::now();
+    let (queue, summaries, verifications) = if interactive {
+        run_interactive(queue, &mut args, &dir_list, stale, deployed, started_at)
+    } else {
+        if args.mirror {
+            handle_mirroring(&queue).unwrap_or_else(|e| exit_with_error(&e, &args, started_at));
+        }
+        let summaries = {
+            // Ctrl+C stops the copy cleanly rather than the process, while there is one
+            let _ctrl_c = interrupt::on_ctrl_c(&queue.cancel_token()).ok();
+            handle_copying(&mut queue, args.locale, &args.groups)
+                .unwrap_or_else(|e| exit_with_error(&e, &args, started_at))
+        };
+        log_copy_problems(&summaries, args.locale);
+        if cancelled(&summaries) {
+            log_cancelled(&summaries, &args);
+            return finish(&args, queue, started_at, &summaries, None);
+        }
+        let verifications = args.verify.then(|| {
+            let verifications = handle_verifying(&queue, args.locale)
+                .unwrap_or_else(|e| exit_with_error(&e, &args, started_at));
+            if args.repair && verifications.iter().any(|v| !v.passed()) {
+                handle_repairing(&queue, &verifications, args.locale)
+                    .unwrap_or_else(|e| exit_with_error(&e, &args, started_at))
+            } else {
+                verifications
+            }
+        });
+        if !args.clean_dest_globs.is_empty() {
+            handle_cleaning(&queue).unwrap_or_else(|e| exit_with_error(&e, &args, started_at));
+        }
+        if args.transactional {
+            handle_committing(&queue, &summaries, verifications.as_deref())
+                .unwrap_or_else(|e| exit_with_error(&e, &args, started_at));
+        }
+        if args.sync {
+            handle_syncing(&args).unwrap_or_else(|e| exit_with_error(&e, &args, started_at));
+        }
+        if args.eject {
+            handle_ejecting(&args).unwrap_or_else(|e| exit_with_error(&e, &args, started_at));
+        }
+        let failed = verifications
+            .iter()
+            .flatten()
+            .any(|verification| !verification.passed());
+        play_cue(
+            &args,
+            match failed {
+                true => SoundEvent::VerifyFailed,
+                false => SoundEvent::BatchComplete,
+            },
+        );
+        (queue, summaries, verifications)
+    };
+    finish(
+        &args,
+        queue,
+        started_at,
+        &summaries,
+        verifications.as_deref(),
+    );
+}
+
+///
+/// Wraps up a run that got through the copy, cancelled or not: writes down how it went and
+/// exits with the status for that
+///
+fn finish(
+    args: &Args,
+    queue: CopyQueue,
+    started_at: DateTime<Local>,
+    summaries: &[DestinationSummary],
+    verifications: Option<&[Verification]>,
+) {
+    log_outside_links(&queue);
+    forget_download(args);
+    if args.verbose >= VERBOSE_COUNTERS {
+        log(format!("Copy engine: {}\n", queue.counters()));
+    }
+    remember_speeds(&queue, summaries);
+    let rows = summary_rows(args, started_at, summaries, verifications);
+
+    if let Some(path) = &args.summary_csv {
+        if let Err(e) = append_summary_csv(path, &rows) {
+            log(format!(
+                "Could not write summary to `{}`: {}\n",
+                path.display(),
+                e
+            ));
+        }
+    }
+
+    let failed = rows
+        .iter()
+        .filter(|row| row.result != "ok")
+        .map(|row| row.destination.clone())
+        .collect::<Vec<_>>();
+    let rerun = rerun_command(&::std::env::args_os().collect::<Vec<_>>(), &failed);
+    if let Some(rerun) = &rerun {
+        log(format!(
+            "To run this again for the destinations that didn't get through:\n  {}\n",
+            rerun
+        ));
+    }
+
+    write_report(
+        args,
+        started_at,
+        &rows,
+        verifications,
+        Some(queue.counters()),
+        None,
+        rerun,
+    );
+
+    // The checkpoint stays behind for `--resume`
+    if cancelled(summaries) {
+        ::std::process::exit(CANCELLED_EXIT_CODE);
+    }
+    if args.qr {
+        print_qr(&run_id(started_at), &queue.manifest().digest());
+    }
+    // Files that never made it also fail verification, the partial copy is the cause
+    if summaries.iter().any(|summary| !summary.failed.is_empty()) {
+        ::std::process::exit(PARTIAL_FAILURE_EXIT_CODE);
+    }
+    if verifications.is_some_and(|v| !v.iter().all(Verification::passed)) {
+        ::std::process::exit(VERIFY_FAILED_EXIT_CODE);
+    }
+}
+
+///
+/// Keeps the speed each destination was copied to at for the ETAs of the next run. Throttled
+/// chaos and simulated runs say nothing about the drives, they are left out.
+///
+fn remember_speeds(queue: &CopyQueue, summaries: &[DestinationSummary]) {
+    if queue.throttle().is_some() || queue.chaos().is_some() || queue.simulation().is_some() {
+        return;
+    }
+    let Some(path) = calibration::default_path() else {
+        return;
+    };
+    let mut calibration = Calibration::load(&path);
+    for summary in summaries {
+        calibration.record(&summary.destination, summary.bytes_copied, summary.duration);
+    }
+    // Only the ETAs suffer without it
+    let _ = calibration.save(&path);
+}
+
+///
+/// Runs the deployment behind the full-screen UI, with the copy on a worker thread. Once the
+/// operator leaves the Completed screen the outcome is also logged to the normal screen, so it
+/// stays in the scrollback.
+///
+fn run_interactive(
+    mut queue: CopyQueue,
+    args: &mut Args,
+    dir_list: &[PreviewEntry],
+    stale: Option<Vec<Stale>>,
+    deployed: Vec<Option<Deployed>>,
+    started_at: DateTime<Local>,
+) -> (
+    CopyQueue,
+    Vec<DestinationSummary>,
+    Option<Vec<Verification>>,
+) {
+    let mut ui = Ui::new(
+        args.copy_from.as_deref().unwrap_or(Path::new("")),
+        &args.drives,
+        &args.groups,
+        dir_list.to_vec(),
+        args.locale,
+        terminal::size().unwrap_or((80, 24)),
+    );
+    ui.beep = args.beep;
+    ui.sounds = args.sounds.clone();
+    ui.theme = args.theme;
+    ui.keys = args.keys;
+    ui.paused = Some(queue.pause_switch());
+    ui.cancel = Some(queue.cancel_token());
+    ui.destination_cancels = (0..args.drives.len())
+        .filter_map(|dest| queue.destination_cancel_token(dest))
+        .collect();
+    ui.conflicts = Some(queue.conflicts());
+    ui.throttle = queue.throttle().cloned();
+    if let Some(path) = calibration::default_path() {
+        let calibration = Calibration::load(&path);
+        ui.calibrated = args.drives.iter().map(|d| calibration.speed(d)).collect();
+    }
+    ui.skip_too_small = args.skip_too_small && !queue.streaming();
+    ui.refuse_too_small = args.refuse_too_small && !queue.streaming();
+    ui.stale = stale;
+    ui.deployed = deployed;
+    ui.collisions = queue.collisions().to_vec();
+    let mut terminal = Terminal::enter(stdout(), true).expect("Failed to set up the terminal");
+    let mut events = TerminalEvents;
+
+    if !args.yes {
+        // Saving a profile keeps the PreCopy screen up
+        loop {
+            match ui::run(&mut ui, &mut events, &mut terminal).expect("Failed to draw the UI") {
+                UiAction::Confirm => break,
+                UiAction::SaveProfile => {
+                    let name = ui.take_profile_name().unwrap_or_default();
+                    let profile = Config::from_args(args, excluded_for_run(args, &ui, dir_list));
+                    let path = args
+                        .config
+                        .clone()
+                        .or_else(config::default_path)
+                        .unwrap_or(PathBuf::from(DEFAULT_CONFIG));
+                    let usage = match Some(&path) == config::default_path().as_ref() {
+                        true => format!("--profile {}", name),
+                        false => format!("--config {} --profile {}", path.display(), name),
+                    };
+                    ui.set_status(match Config::save_profile(&path, &name, &profile) {
+                        Ok(()) => format!(
+                            "Saved profile `{}` to `{}`, use it with {}",
+                            name,
+                            path.display(),
+                            usage
+                        ),
+                        Err(e) => format!("Could not save the profile: {}", e).replace('\n', " "),
+                    });
+                }
+                _ => {
+                    drop(terminal);
+                    println!("[decopy] Aborting copy...");
+                    ::std::process::exit(0);
+                }
+            }
+        }
+    }
+    queue.exclude(excluded_for_run(args, &ui, dir_list));
+
+    let deployed_skipped = ui.deployed_skipped();
+    if !deployed_skipped.is_empty() {
+        queue.skip_destinations(&deployed_skipped);
+        ui.skip_destinations(&deployed_skipped);
+        args.drives.retain(|dest| !deployed_skipped.contains(dest));
+        if args.drives.is_empty() {
+            drop(terminal);
+            log_deployed_skipped(&deployed_skipped);
+            nothing_to_deploy();
+        }
+    }
+
+    // Confirmed before the payload was counted, the PreCopy screen couldn't hold it back yet
+    if ui.refuse_too_small {
+        let too_small = too_small(&args.drives, counted_payload(|| ui.payload()));
+        if !too_small.is_empty() {
+            drop(terminal);
+            refuse_too_small(&too_small, args.locale);
+        }
+    }
+
+    let mut skipped = Vec::new();
+    if ui.skip_too_small {
+        skipped = skip_too_small(args, counted_payload(|| ui.payload()));
+        let skipped_paths = skipped
+            .iter()
+            .map(|(dest, _)| dest.clone())
+            .collect::<Vec<_>>();
+        queue.skip_destinations(&skipped_paths);
+        ui.skip_destinations(&skipped_paths);
+        if args.drives.is_empty() {
+            drop(terminal);
+            log_skipped(&skipped, args.locale);
+            no_destination_fits();
+        }
+    }
+
+    if let Some(start) = start_time(args.start_at, args.delay) {
+        ui.state = UIState::Waiting(start);
+        if ui::run(&mut ui, &mut events, &mut terminal).expect("Failed to draw the UI")
+            != UiAction::Confirm
+        {
+            drop(terminal);
+            println!("[decopy] Aborting copy...");
+            ::std::process::exit(0);
+        }
+    }
+
+    let (updates, receiver) = channel();
+    ui.state = UIState::Copying(receiver);
+    let worker_args = args.clone();
+    let mut worker = ::std::thread::spawn(move || {
+        copy_in_background(&mut queue, &worker_args, &updates);
+        queue
+    });
+    let mut action = ui::run(&mut ui, &mut events, &mut terminal).expect("Failed to draw the UI");
+    // The worker is done by the time an error can be retried, it hands the queue back for it
+    while action == UiAction::Retry {
+        let Some(retry) = ui.retry() else {
+            break;
+        };
+        let mut queue = worker.join().expect("copy worker panicked");
+        let (updates, receiver) = channel();
+        ui.retrying(&retry, receiver);
+        let worker_args = args.clone();
+        worker = ::std::thread::spawn(move || {
+            retry_in_background(&mut queue, &worker_args, retry, &updates);
+            queue
+        });
+        action = ui::run(&mut ui, &mut events, &mut terminal).expect("Failed to draw the UI");
+    }
+    drop(terminal);
+    log_deployed_skipped(&deployed_skipped);
+    log_skipped(&skipped, args.locale);
+
+    match ui.state {
+        UIState::Completed {
+            summaries,
+            verifications,
+            ..
+        } => {
+            let queue = worker.join().expect("copy worker panicked");
+            match cancelled(&summaries) {
+                true => log_cancelled(&summaries, args),
+                false => log("Files finished copying\n"),
+            }
+            log_copy_problems(&summaries, args.locale);
+            if let Some(results) = &verifications {
+                print_verifications(results, args.locale);
+            }
+            (queue, summaries, verifications)
+        }
+        UIState::Failed(e) => {
+            if action == UiAction::Elevate {
+                relaunch_elevated();
+            }
+            exit_with_error(&e, args, started_at)
+        }
+        _ => {
+            // Aborted mid-copy, the checkpoint stays behind for `--resume`
+            log("Copy aborted\n");
+            ::std::process::exit(CANCELLED_EXIT_CODE);
+        }
+    }
+}
+
+fn write_report(
+    args: &Args,
+    started_at: DateTime<Local>,
+    rows: &[SummaryRow],
+    verifications: Option<&[Verification]>,
+    counters: Option<Counts>,
+    error: Option<ErrorReport>,
+    rerun: Option<String>,
+) {
+    let Some(path) = &args.report else {
+        return;
+    };
+
+    let report = Report {
+        counters,
+        rerun,
+        ..Report::new(
+            run_id(started_at),
+            args.copy_from.clone().unwrap_or_default(),
+            started_at,
+            rows,
+            verifications,
+            error,
+            &args.effective_options(),
+        )
+    };
+    if let Err(e) = report.write(path) {
+        log(format!(
+            "Could not write report to `{}`: {}\n",
+            path.display(),
+            e
+        ));
+    }
+}
+
+fn print_qr(run_id: &str, manifest_hash: &str) {
+    log(format!("Run {}\n", run_id));
+    log(format!("Manifest sha256 {}\n", manifest_hash));
+
+    let code = QrCode::new(format!("decopy:{}:{}", run_id, manifest_hash))
+        .expect("run ID and hash always fit in a QR code");
+    // Inverted so the code scans on the usual dark terminal background
+    let rendered = code
+        .render::<Dense1x2>()
+        .dark_color(Dense1x2::Light)
+        .light_color(Dense1x2::Dark)
+        .build();
+    println!("{}", rendered);
+}
+
+fn summary_rows(
+    args: &Args,
+    timestamp: DateTime<Local>,
+    summaries: &[DestinationSummary],
+    verifications: Option<&[Verification]>,
+) -> Vec<SummaryRow> {
+    // Without `--profile` the run goes by the name of its config file
+    let profile = args
+        .profile
+        .clone()
+        .or_else(|| {
+            let stem = args.config.as_ref()?.file_stem()?;
+            Some(stem.to_string_lossy().into_owned())
+        })
+        .unwrap_or_default();
+
+    summaries
+        .iter()
+        .enumerate()
+        .map(|(i, summary)| {
+            let verified = verifications.map(|v| v[i].passed());
+            SummaryRow {
+                timestamp,
+                profile: profile.clone(),
+                destination: summary.destination.clone(),
+                label: drive::volume_label(&summary.destination),
+                bytes: summary.bytes_copied,
+                duration: summary.duration,
+                verified,
+                result: match verified {
+                    _ if summary.cancelled => "cancelled",
+                    _ if !summary.failed.is_empty() => "partial",
+                    Some(false) => "verify-failed",
+                    _ => "ok",
+                }
+                .to_string(),
+                group: group_of(&args.groups, &summary.destination).map(str::to_string),
+                warning: summary
+                    .scanned
+                    .then(|| Message::ScanSuspected.text(args.locale).to_string())
+                    .into_iter()
+                    .chain((!summary.changed.is_empty()).then(|| changed_files_warning(summary)))
+                    .reduce(|warnings, warning| format!("{}; {}", warnings, warning)),
+            }
+        })
+        .collect()
+}
+
+///
+/// Offers the guided setup on the first launch in a terminal, returning whether it wrote a config
+/// file to `path`
+///
+fn first_run_setup(path: &Path) -> bool {
+    if !stdout().is_terminal() || !stdin().is_terminal() {
+        return false;
+    }
+
+    let mut setup = Setup::new(terminal::size().unwrap_or((80, 24)));
+    let mut terminal = Terminal::enter(stdout(), true).expect("Failed to set up the terminal");
+    let action =
+        setup::run(&mut setup, &mut TerminalEvents, &mut terminal).expect("Failed to draw the UI");
+    drop(terminal);
+
+    let config = match action {
+        SetupAction::Finish => setup.config(),
+        SetupAction::Skip => Config::default(),
+        _ => {
+            println!("[decopy] Aborting copy...");
+            ::std::process::exit(0);
+        }
+    };
+    match Config::create(path, &config) {
+        Ok(()) => {
+            log(format!("Saved the settings to `{}`\n", path.display()));
+            true
+        }
+        Err(e) => {
+            log(format!("Could not save the settings: {}\n", e));
+            false
+        }
+    }
+}
+
+///
+/// `--exclude` with the top level entries replaced by what the PreCopy screen ended up with
+///
+fn excluded_for_run(args: &Args, ui: &Ui, dir_list: &[PreviewEntry]) -> Vec<PathBuf> {
+    let mut excluded = args
+        .exclude
+        .iter()
+        .filter(|path| !dir_list.iter().any(|entry| entry.path == **path))
+        .cloned()
+        .collect::<Vec<_>>();
+    excluded.extend(ui.excluded());
+    excluded
+}
+
+///
+/// Prints the version, or with `--json` everything `version::info` knows, and exits
+///
+fn print_version(json: bool) -> ! {
+    match json {
+        true => println!(
+            "{}",
+            serde_json::to_string_pretty(&version::info()).expect("version info always serializes")
+        ),
+        false => print!("{}", Args::command().render_version()),
+    }
+    ::std::process::exit(0);
+}
+
+///
+/// Writes the image `image` onto every destination device at once for `--image`, after checking
+/// the devices and asking three times, then exits
+///
+fn write_image(args: &Args, image: &Path) -> ! {
+    let image_len = match ::std::fs::metadata(image) {
+        Ok(metadata) if metadata.is_file() => metadata.len(),
+        _ => Args::command()
+            .error(
+                ErrorKind::ValueValidation,
+                format!(
+                    "--image needs an image file, `{}` isn't one",
+                    image.display()
+                ),
+            )
+            .exit(),
+    };
+    if args.drives.is_empty() {
+        Args::command()
+            .error(
+                ErrorKind::MissingRequiredArgument,
+                "no device given to write the image onto",
+            )
+            .exit();
+    }
+    if let Some(tree) = args.then_copy.as_ref().filter(|tree| !tree.is_dir()) {
+        Args::command()
+            .error(
+                ErrorKind::ValueValidation,
+                format!(
+                    "--then-copy needs a directory, `{}` isn't one",
+                    tree.display()
+                ),
+            )
+            .exit();
+    }
+
+    print_options(args);
+    log(format!(
+        "Writing the image `{}` ({}) onto:\n",
+        image.display(),
+        get_bytes_string(image_len as usize, args.locale)
+    ));
+    let mut refused = false;
+    for device in &args.drives {
+        let mounts = drive::mounts_on_device(device);
+        let size = image::device_size(device);
+        match size {
+            Ok(size) => println!(
+                "  {} {}",
+                drive::describe(device).dark_grey(),
+                format!("({})", get_bytes_string(size as usize, args.locale)).dark_grey()
+            ),
+            Err(_) => println!("  {}", drive::describe(device).dark_grey()),
+        }
+        if !mounts.is_empty() {
+            let mounts = mounts
+                .iter()
+                .map(|mount| format!("`{}`", mount.display()))
+                .collect::<Vec<_>>();
+            println!(
+                "    {}",
+                format!("is mounted on {}, unmount it first", mounts.join(", ")).red()
+            );
+            refused = true;
+        }
+        match size {
+            Ok(size) if size < image_len && !device.is_file() => {
+                println!("    {}", "is smaller than the image".red());
+                refused = true;
+            }
+            Ok(_) => {}
+            Err(e) => {
+                println!("    {}", format!("can't be opened: {}", e).red());
+                refused = true;
+            }
+        }
+    }
+    if refused {
+        log("Nothing was written\n");
+        ::std::process::exit(1);
+    }
+    if let Some(tree) = &args.then_copy {
+        log(format!(
+            "Then copying `{}` into partition {} of each\n",
+            tree.display(),
+            args.into_partition
+        ));
+    }
+
+    // Overwriting whole devices can't be undone, so `--yes` doesn't skip this
+    let confirmed = confirm(
+        &format!(
+            "Write the image onto these {} device(s)? (y/N) ",
+            args.drives.len()
+        ),
+        &["y", "yes"],
+    ) && confirm(
+        "Everything on them will be overwritten and can't be recovered. Are you sure? (y/N) ",
+        &["y", "yes"],
+    ) && confirm("Type ERASE to start writing: ", &["ERASE"]);
+    if !confirmed {
+        println!("[decopy] Aborting copy...");
+        ::std::process::exit(0);
+    }
+
+    if args.nice_io {
+        if let Err(e) = priority::lower_io_priority() {
+            log(format!("Could not lower I/O priority: {}\n", e));
+        }
+    }
+
+    log("Writing image...\n");
+    let devices = &args.drives;
+    for device in devices {
+        queue_progress_bar(device, 0);
+    }
+    stdout().flush().unwrap();
+
+    // Writing and reading back count half each
+    let total = (image_len as usize * 2).max(1);
+    let (updates, progress) = channel();
+    let results = ::std::thread::scope(|scope| {
+        let writers = devices
+            .iter()
+            .enumerate()
+            .map(|(i, device)| {
+                let updates = updates.clone();
+                scope.spawn(move || {
+                    image::write_image(image, device, |progress| {
+                        let done = match progress {
+                            ImageProgress::Writing(written) => written,
+                            ImageProgress::Verifying(verified) => image_len as usize + verified,
+                        };
+                        let _ = updates.send((i, done * 100 / total));
+                    })
+                })
+            })
+            .collect::<Vec<_>>();
+        drop(updates);
+
+        let mut percentages = vec![0; devices.len()];
+        for (i, percent) in progress {
+            if ::std::mem::replace(&mut percentages[i], percent) == percent {
+                continue;
+            }
+            queue!(stdout(), MoveUp(devices.len() as u16)).unwrap();
+            for (device, percent) in devices.iter().zip(&percentages) {
+                queue_progress_bar(device, *percent);
+            }
+            stdout().flush().unwrap();
+        }
+
+        writers
+            .into_iter()
+            .map(|writer| writer.join().expect("image writer panicked"))
+            .collect::<Vec<_>>()
+    });
+
+    let mut exit_code = 0;
+    let mut written_devices = Vec::new();
+    for (device, result) in devices.iter().zip(results) {
+        match result {
+            Ok(written) if written.verified() => {
+                log(format!(
+                    "{} {} (sha256 {})\n",
+                    device.display(),
+                    Message::Verified.text(args.locale).green(),
+                    written.image_hash
+                ));
+                written_devices.push(device.clone());
+            }
+            Ok(written) => {
+                log(format!(
+                    "{} {}\n",
+                    device.display(),
+                    format!(
+                        "does not match the image (sha256 {} read back, {} expected)",
+                        written.device_hash, written.image_hash
+                    )
+                    .red()
+                ));
+                // A device that failed to write at all takes precedence
+                if exit_code == 0 {
+                    exit_code = VERIFY_FAILED_EXIT_CODE;
+                }
+            }
+            Err(e) => {
+                log_device_failure(device, &e, args.locale);
+                exit_code = 1;
+            }
+        }
+    }
+
+    if let (Some(tree), false) = (&args.then_copy, written_devices.is_empty()) {
+        match copy_into_partitions(args, tree, &written_devices) {
+            0 => {}
+            1 => exit_code = 1,
+            code if exit_code == 0 => exit_code = code,
+            _ => {}
+        }
+    }
+    ::std::process::exit(exit_code);
+}
+
+///
+/// Mounts partition `--into-partition` of every device and copies `tree` into it for
+/// `--then-copy`, verifying it with `--verify`. Returns the exit status of this step.
+///
+fn copy_into_partitions(args: &Args, tree: &Path, devices: &[PathBuf]) -> i32 {
+    let mut exit_code = 0;
+    let mut mounts = Vec::new();
+    for device in devices {
+        log(format!(
+            "Mounting partition {} of `{}`...\n",
+            args.into_partition,
+            device.display()
+        ));
+        match drive::mount_partition(device, args.into_partition) {
+            Ok(mount) => mounts.push(mount),
+            Err(e) => {
+                log(format!(
+                    "{} {}\n",
+                    format!(
+                        "Could not mount partition {} of `{}`:",
+                        args.into_partition,
+                        device.display()
+                    )
+                    .red(),
+                    e
+                ));
+                exit_code = 1;
+            }
+        }
+    }
+    if mounts.is_empty() {
+        return exit_code;
+    }
+
+    // The same run options, with the mounted partitions as the destinations
+    let mut tree_args = args.clone();
+    tree_args.image = false;
+    tree_args.copy_from = Some(
+        ::std::env::current_dir()
+            .expect("Failed to get current directory")
+            .join(tree),
+    );
+    tree_args.drives = mounts.clone();
+    tree_args.groups = Vec::new();
+    let mut queue = CopyQueue::from(&tree_args);
+    let copied = handle_copying(&mut queue, args.locale, &[]).and_then(|_| match args.verify {
+        true => handle_verifying(&queue, args.locale).map(Some),
+        false => Ok(None),
+    });
+    match copied {
+        Ok(Some(verifications)) if !verifications.iter().all(Verification::passed) => {
+            exit_code = exit_code.max(VERIFY_FAILED_EXIT_CODE);
+        }
+        Ok(_) => {}
+        Err(e) => {
+            let dest = e.destination.clone().unwrap_or_default();
+            log_device_failure(&dest, &e, args.locale);
+            exit_code = 1;
+        }
+    }
+
+    // Unmounted whatever happened, so the devices can be pulled
+    for mount in &mounts {
+        match drive::unmount(mount) {
+            Ok(()) => log(format!("Unmounted `{}`\n", mount.display())),
+            Err(e) => {
+                log(format!(
+                    "{} {}\n",
+                    format!("Could not unmount `{}`:", mount.display()).red(),
+                    e
+                ));
+                exit_code = 1;
+            }
+        }
+    }
+    exit_code
+}
+
+///
+/// Reports what went wrong on `device` during `--image`, with advice where there is some
+///
+fn log_device_failure(device: &Path, e: &CopyError, locale: Locale) {
+    log(format!(
+        "{} {} {}\n",
+        device.display(),
+        Message::CopyFailed.text(locale).red(),
+        e
+    ));
+    if let Some(suggestion) = e.class().suggestion(locale) {
+        log(format!("{}\n", suggestion.yellow()));
+    }
+}
+
+///
+/// Asks `prompt` on stdin, returning whether the answer is one of `accepted`
+///
+fn confirm(prompt: &str, accepted: &[&str]) -> bool {
+    print!("{}", prompt);
+    stdout().flush().expect("Failed to flush stdout");
+    let mut buffer = String::new();
+    ::std::io::stdin().read_line(&mut buffer).is_ok()
+        && accepted
+            .iter()
+            .any(|answer| buffer.trim() == *answer || buffer.trim().to_lowercase() == *answer)
+}
+
+///
+/// Runs `self-update` and exits
+///
+fn self_update() -> ! {
+    log("Looking for a newer release...\n");
+    match update::self_update() {
+        Ok(UpdateOutcome::UpToDate(tag)) => {
+            log(format!(
+                "Already up to date ({} is the latest release)\n",
+                tag
+            ));
+            ::std::process::exit(0);
+        }
+        Ok(UpdateOutcome::Updated(tag, exe)) => {
+            log(format!("Updated `{}` to {}\n", exe.display(), tag));
+            ::std::process::exit(0);
+        }
+        Err(e) => {
+            log(format!("{} {}\n", "Update failed:".red(), e));
+            ::std::process::exit(1);
+        }
+    }
+}
+
+///
+/// Runs `bench` and exits, with `BENCH_REGRESSED_EXIT_CODE` when a workload got slower than
+/// `--threshold` allows
+///
+fn run_bench(args: &BenchArgs, locale: Locale) -> ! {
+    let fail = |e: String| -> ! {
+        log(format!("{} {}\n", "Benchmark failed:".red(), e));
+        ::std::process::exit(1);
+    };
+    let baseline = match (&args.baseline, args.update) {
+        (Some(path), false) => Some(
+            Baseline::load(path).unwrap_or_else(|e| fail(format!("`{}`: {}", path.display(), e))),
+        ),
+        _ => None,
+    };
+
+    let current = bench::run(args, |workload| log(format!("Measuring {}...\n", workload)))
+        .unwrap_or_else(|e| fail(e));
+    let rate = |bytes_per_second: f64| {
+        format!("{}/s", get_bytes_string(bytes_per_second as usize, locale))
+    };
+
+    let Some(baseline) = baseline else {
+        for (workload, throughput) in &current.throughput {
+            log(format!("{:<12} {:>12}\n", workload, rate(*throughput)));
+        }
+        if let (Some(path), true) = (&args.baseline, args.update) {
+            current
+                .save(path)
+                .unwrap_or_else(|e| fail(format!("`{}`: {}", path.display(), e)));
+            log(format!("Recorded the baseline in `{}`\n", path.display()));
+        }
+        ::std::process::exit(0);
+    };
+
+    let comparisons = bench::compare(&baseline, &current).unwrap_or_else(|e| fail(e));
+    let mut regressed = false;
+    for comparison in &comparisons {
+        let verdict = match comparison.change() {
+            Some(change) if comparison.regressed(args.threshold) => {
+                regressed = true;
+                format!("{:+.1} %, regressed", change).red().to_string()
+            }
+            Some(change) => format!("{:+.1} %", change),
+            None => "not in the baseline".to_string(),
+        };
+        log(format!(
+            "{:<12} {:>12}  baseline {:>12}  {}\n",
+            comparison.workload,
+            rate(comparison.current),
+            comparison.baseline.map(rate).unwrap_or_default(),
+            verdict
+        ));
+    }
+    match regressed {
+        true => {
+            log(format!(
+                "{}\n",
+                format!(
+                    "Throughput dropped more than {} % below the baseline",
+                    args.threshold
+                )
+                .red()
+            ));
+            ::std::process::exit(BENCH_REGRESSED_EXIT_CODE);
+        }
+        false => ::std::process::exit(0),
+    }
+}
+
+///
+/// Runs `dev gen-fixture` and exits
+///
+fn gen_fixture(spec: &FixtureSpec, locale: Locale) -> ! {
+    match fixture::generate(spec) {
+        Ok(summary) => {
+            log(format!(
+                "Built `{}` from seed {}: {} files in {} directories, {}\n",
+                spec.dir.display(),
+                spec.seed,
+                summary.files,
+                summary.dirs,
+                get_bytes_string(summary.bytes as usize, locale)
+            ));
+            ::std::process::exit(0);
+        }
+        Err(e) => {
+            log(format!(
+                "{} `{}`: {}\n",
+                "Could not build the fixture".red(),
+                spec.dir.display(),
+                e
+            ));
+            ::std::process::exit(1);
+        }
+    }
+}
+
+///
+/// Mentions a newer release, for `--check-updates`. Being offline isn't worth more than a note.
+///
+fn check_for_update() {
+    match update::check() {
+        Ok(Some(release)) => log(format!(
+            "{} is available (this is v{}), install it with `decopy self-update`\n",
+            release.tag.as_str().yellow(),
+            env!("CARGO_PKG_VERSION")
+        )),
+        Ok(None) => {}
+        Err(e) => log(format!(
+            "{}\n",
+            format!("Could not check for updates: {}", e).dark_grey()
+        )),
+    }
+}
+
+///
+/// Downloads the payload of `--source-url` with a progress line and returns where it is to be
+/// deployed from. Exits when it can't be downloaded or doesn't match its checksum, or has none
+/// to match without `allow_unchecked` (`--allow-unchecked-source`).
+///
+fn download_source(
+    url: &str,
+    expected: Option<&Checksum>,
+    allow_unchecked: bool,
+    locale: Locale,
+) -> PathBuf {
+    let onprogress = |downloaded: u64, total: Option<u64>| {
+        queue!(stdout(), Clear(ClearType::CurrentLine), MoveToColumn(0)).unwrap();
+        let bytes = get_bytes_string(downloaded as usize, locale);
+        log(match total.filter(|total| *total > 0) {
+            Some(total) => format!(
+                "Downloading `{}`... ({} %) [{} of {}]",
+                url,
+                (downloaded * 100 / total).min(100),
+                bytes,
+                get_bytes_string(total as usize, locale)
+            ),
+            None => format!("Downloading `{}`... [{}]", url, bytes),
+        });
+    };
+    let download = download::download(url, expected, onprogress).unwrap_or_else(|e| {
+        println!();
+        log(format!("{} {}\n", "Download failed:".red(), e));
+        ::std::process::exit(1);
+    });
+    println!();
+    log(format!(
+        "Downloaded `{}` ({}, sha256 {})\n",
+        url,
+        get_bytes_string(download.bytes as usize, locale),
+        download.sha256
+    ));
+    if download.checked.is_none() {
+        if !allow_unchecked {
+            let _ = download::forget(url);
+            log(format!(
+                "{} nothing to check the download against, give its checksum with \
+                 --source-sha256 or deploy it anyway with --allow-unchecked-source\n",
+                "Download refused:".red()
+            ));
+            ::std::process::exit(1);
+        }
+        log(format!(
+            "{}\n",
+            "No checksum to check the download against, deploying it unchecked".yellow()
+        ));
+    }
+    download.source
+}
+
+///
+/// Removes what `--source-url` downloaded, the run is through with it
+///
+fn forget_download(args: &Args) {
+    if let Some(url) = &args.source_url {
+        if let Err(e) = download::forget(url) {
+            log(format!("Could not remove the download: {}\n", e));
+        }
+    }
+}
+
+///
+/// Lists the options the run goes by, to answer "why did it do that?" from the log alone
+///
+fn print_options(args: &Args) {
+    log("Options:\n");
+    for (name, value) in args.effective_options() {
+        println!(
+            "  {}",
+            format!("--{} {}", name, value).trim_end().dark_grey()
+        );
+    }
 }
 
-fn print_pre_copy_status(dir_list: &Vec<(PathBuf, String)>, args: &Args) {
+///
+/// Lists the destinations and the top of the source. With the payload counted each destination
+/// gets its fit margin, otherwise just its free space.
+///
+fn print_pre_copy_status(
+    dir_list: &[PreviewEntry],
+    args: &Args,
+    payload: Option<u64>,
+    stale: Option<&[Stale]>,
+) {
     log("Destinations staged to be copied to:\n");
-    for drive in args.drives.clone() {
-        println!("  {}", drive.display().to_string().dark_grey());
+    let names = args
+        .drives
+        .iter()
+        .map(|dest| (drive::describe(dest), group_of(&args.groups, dest)))
+        .collect::<Vec<_>>();
+    let width = |(described, group): &(String, Option<&str>)| {
+        described.chars().count() + group.map_or(0, |group| group.chars().count() + 3)
+    };
+    // Lined up, so the needed and available sizes read as a column
+    let column = names.iter().map(width).max().unwrap_or_default();
+    let calibration = calibration::default_path()
+        .map(|path| Calibration::load(&path))
+        .unwrap_or_default();
+    for (i, (dest, name)) in args.drives.iter().zip(&names).enumerate() {
+        let group = match name.1 {
+            Some(group) => format!(" {}", format!("({})", group).cyan()),
+            None => String::new(),
+        };
+        let free = drive::free_space(dest);
+        let room = match payload {
+            Some(payload) => {
+                let room = needed_vs_available(payload, free, args.locale);
+                match Fit::new(free, payload) {
+                    fit if fit.too_small() && args.skip_too_small => {
+                        format!("{}, skipped", room).red()
+                    }
+                    fit if fit.too_small() => room.red(),
+                    _ => match calibration.estimate(dest, payload) {
+                        Some(estimate) => {
+                            format!("{}, about {} to copy", room, Delay(estimate)).dark_grey()
+                        }
+                        None => room.dark_grey(),
+                    },
+                }
+            }
+            None => match free {
+                Some(free) => {
+                    format!("{} free", get_bytes_string(free as usize, args.locale)).dark_grey()
+                }
+                None => String::new().dark_grey(),
+            },
+        };
+        println!(
+            "  {}{}{}{}",
+            name.0.as_str().dark_grey(),
+            group,
+            " ".repeat(column - width(name) + 2),
+            room
+        );
+        if let Some(stale) = stale.map(|stale| &stale[i]).filter(|s| !s.files.is_empty()) {
+            print_stale(stale, args.locale);
+        }
     }
-    log(format!("Copying from `{}`...\n", args.copy_from.display()));
+    let size = match payload {
+        Some(payload) => {
+            let files = dir_list
+                .iter()
+                .filter(|entry| !entry.excluded)
+                .map(|entry| entry.totals.files())
+                .sum::<usize>();
+            format!(
+                " ({} file(s), {})",
+                args.locale.format_number(files as u64),
+                get_bytes_string(payload as usize, args.locale)
+            )
+        }
+        None => String::new(),
+    };
+    let sources = args
+        .copy_from
+        .iter()
+        .chain(&args.from)
+        .map(|path| format!("`{}`", path.display()))
+        .collect::<Vec<_>>();
+    log(format!("Copying from {}{}...\n", sources.join(", "), size));
     let (list, is_overflowing) = if dir_list.len() >= 5 {
         (&dir_list[..5], true)
     } else {
-        (&dir_list[..], false)
+        (dir_list, false)
     };
 
-    for (_, display) in list {
-        println!("  {}", display.clone().dark_grey());
+    for entry in list {
+        let name = rawpath::badged_name(entry.path.as_os_str());
+        match entry.excluded {
+            true => println!("  {}", format!("{} (excluded)", name).red()),
+            false => println!("  {}", name.dark_grey()),
+        }
     }
     if is_overflowing {
-        println!("  ... +{} more ...", dir_list.len() - list.len());
+        println!(
+            "  ... +{} more ...",
+            args.locale
+                .format_number((dir_list.len() - list.len()) as u64)
+        );
+    }
+}
+
+///
+/// What `--mirror` will delete from each destination, for the confirmation. A destination that
+/// can't be listed shows nothing here and fails once the run gets to it.
+///
+fn stale_on_destinations(args: &Args) -> Vec<Stale> {
+    let source = args.source().unwrap_or_default();
+    let kept = args.selection().with_excluded(mirror::kept(
+        &args.exclude,
+        args.sha256sums,
+        args.drive_log,
+    ));
+    args.drives
+        .iter()
+        .map(|dest| mirror::stale(&source, &kept, dest).unwrap_or_default())
+        .collect()
+}
+
+///
+/// Which destinations already hold the payload, for the confirmation
+///
+fn deployed_on_destinations(args: &Args) -> Vec<Option<Deployed>> {
+    let source = args.source().unwrap_or_default();
+    deployed::deployed(&source, &args.selection(), &args.drives, args.threads())
+}
+
+///
+/// Offers to leave out each destination that already holds the payload, rather than writing
+/// it all again. Under `-y` there is no one to ask, they are only pointed out.
+///
+fn skip_deployed(args: &mut Args, deployed: &[Option<Deployed>]) {
+    let mut skipped = Vec::new();
+    for (dest, deployed) in args.drives.iter().zip(deployed) {
+        let Some(deployed) = deployed else {
+            continue;
+        };
+        let warning = format!(
+            "`{}` already contains this exact payload (deployed {} ago)",
+            drive::describe(dest),
+            deployed.ago()
+        );
+        match args.yes {
+            true => log(format!("{}\n", warning.yellow())),
+            false => {
+                if confirm(&format!("{}, skip it? (y/N) ", warning), &["y", "yes"]) {
+                    skipped.push(dest.clone());
+                }
+            }
+        }
+    }
+    if skipped.is_empty() {
+        return;
+    }
+    args.drives.retain(|dest| !skipped.contains(dest));
+    log_deployed_skipped(&skipped);
+    if args.drives.is_empty() {
+        nothing_to_deploy();
+    }
+}
+
+fn log_deployed_skipped(skipped: &[PathBuf]) {
+    for dest in skipped {
+        log(format!(
+            "{}\n",
+            format!(
+                "Skipping `{}`: it already contains this exact payload",
+                drive::describe(dest)
+            )
+            .yellow()
+        ));
+    }
+}
+
+fn nothing_to_deploy() -> ! {
+    log("Every destination already contains this exact payload, nothing to copy\n");
+    ::std::process::exit(0);
+}
+
+///
+/// Lists what the run would do to every destination, file by file, and exits (`--dry-run`)
+///
+fn dry_run(args: &Args) -> ! {
+    let plans = match CopyQueue::from(args).dry_run() {
+        Ok(plans) => plans,
+        Err(e) => {
+            log(format!("{} {}\n", "Dry run failed:".red(), e));
+            ::std::process::exit(1);
+        }
+    };
+    let locale = args.locale;
+    let count = |n: usize| locale.format_number(n as u64);
+    log("Dry run, nothing is written\n");
+    for plan in &plans {
+        println!(
+            "  {}: {} to copy, {} to overwrite, {} unchanged, {} to delete, {} to write",
+            drive::describe(&plan.destination),
+            count(plan.copied.len()),
+            count(plan.overwritten.len()),
+            count(plan.unchanged.len()),
+            count(plan.deleted.files.len()),
+            get_bytes_string(plan.bytes(), locale)
+        );
+        let name = |file: &PathBuf| rawpath::badged_name(file.as_os_str());
+        for (file, _) in &plan.deleted.files {
+            println!("    {}", format!("- {}", name(file)).red());
+        }
+        for (file, _) in &plan.copied {
+            println!("    {}", format!("+ {}", name(file)).green());
+        }
+        for (file, _) in &plan.overwritten {
+            println!("    {}", format!("~ {}", name(file)).yellow());
+        }
+    }
+    ::std::process::exit(0);
+}
+
+fn print_stale(stale: &Stale, locale: Locale) {
+    println!(
+        "    {}",
+        format!(
+            "--mirror deletes {} stale file(s), {}:",
+            locale.format_number(stale.files.len() as u64),
+            get_bytes_string(stale.bytes(), locale)
+        )
+        .yellow()
+    );
+    for (file, _) in stale.files.iter().take(LISTED_STALE) {
+        println!(
+            "      {}",
+            rawpath::badged_name(file.as_os_str()).dark_grey()
+        );
+    }
+    if let Some(more) = stale
+        .files
+        .len()
+        .checked_sub(LISTED_STALE)
+        .filter(|n| *n > 0)
+    {
+        println!("      ... +{} more ...", locale.format_number(more as u64));
+    }
+}
+
+///
+/// Waits for the background count of the source to finish, then returns the bytes `payload`
+/// came up with
+///
+fn counted_payload(payload: impl Fn() -> (usize, bool)) -> u64 {
+    loop {
+        match payload() {
+            (bytes, true) => return bytes as u64,
+            _ => ::std::thread::sleep(Duration::from_millis(50)),
+        }
+    }
+}
+
+///
+/// Drops the destinations without room for `payload` from `args` (`--skip-too-small`),
+/// returning them with their fit. Destinations whose free space is unknown are kept.
+///
+fn skip_too_small(args: &mut Args, payload: u64) -> Vec<(PathBuf, Fit)> {
+    let skipped = too_small(&args.drives, payload);
+    args.drives
+        .retain(|dest| !skipped.iter().any(|(skipped, _)| skipped == dest));
+    skipped
+}
+
+///
+/// The destinations of `drives` without room for `payload`, with their fit. Destinations whose
+/// free space is unknown aren't among them.
+///
+fn too_small(drives: &[PathBuf], payload: u64) -> Vec<(PathBuf, Fit)> {
+    drives
+        .iter()
+        .map(|dest| (dest.clone(), Fit::new(drive::free_space(dest), payload)))
+        .filter(|(_, fit)| fit.too_small())
+        .collect()
+}
+
+///
+/// Ends a `--refuse-too-small` run before anything is copied, naming the destinations that are
+/// too small
+///
+fn refuse_too_small(too_small: &[(PathBuf, Fit)], locale: Locale) -> ! {
+    for (dest, fit) in too_small {
+        log(format!(
+            "{}\n",
+            format!("`{}` is {}", drive::describe(dest), fit.describe(locale)).red()
+        ));
+    }
+    log(format!(
+        "{}\n",
+        "Not copying, --refuse-too-small needs room for the payload on every destination".red()
+    ));
+    ::std::process::exit(1);
+}
+
+fn log_skipped(skipped: &[(PathBuf, Fit)], locale: Locale) {
+    for (dest, fit) in skipped {
+        log(format!(
+            "{}\n",
+            format!(
+                "Skipping `{}`: {}",
+                drive::describe(dest),
+                fit.describe(locale)
+            )
+            .yellow()
+        ));
     }
 }
 
-pub fn handle_copying(queue: &mut CopyQueue) {
+///
+/// Warns about the links `--symlinks preserve` recreated that point outside the source, they
+/// only resolve on machines that have the same path
+///
+fn log_outside_links(queue: &CopyQueue) {
+    for (link, target) in queue.outside_links() {
+        log(format!(
+            "{}\n",
+            format!(
+                "`{}` links to `{}` outside the source, it may not resolve on the destination",
+                link.display(),
+                target.display()
+            )
+            .yellow()
+        ));
+    }
+}
+
+fn no_destination_fits() -> ! {
+    log(format!(
+        "{}\n",
+        "No destination has room for the payload, nothing to copy".red()
+    ));
+    ::std::process::exit(1);
+}
+
+pub fn handle_copying(
+    queue: &mut CopyQueue,
+    locale: Locale,
+    groups: &[DestinationGroup],
+) -> Result<Vec<DestinationSummary>, CopyError> {
     // execute!(stdout(), MoveToNextLine(1)).unwrap();
 
-    let onpercentage = move |percent: usize, current_dir: PathBuf, bytes_copied: usize| {
+    // Destinations written at the same time get a progress bar each, as when verifying
+    let destinations = queue.destinations().to_vec();
+    let concurrent = queue.concurrent();
+    let streaming = queue.streaming();
+    if concurrent {
+        log("Copying...\n");
+        for dest in &destinations {
+            queue_progress_bar(dest, 0);
+        }
+        stdout().flush().unwrap();
+    }
+
+    let percentages = RefCell::new(HashMap::<PathBuf, usize>::new());
+    let onpercentage = |percent: usize, current_dir: PathBuf, bytes_copied: usize| {
+        let previous = percentages
+            .borrow_mut()
+            .insert(current_dir.clone(), percent);
+        if concurrent {
+            if previous != Some(percent) {
+                let percentages = percentages.borrow();
+                queue!(stdout(), MoveUp(destinations.len() as u16)).unwrap();
+                for dest in &destinations {
+                    queue_progress_bar(dest, percentages.get(dest).copied().unwrap_or(0));
+                }
+                stdout().flush().unwrap();
+            }
+            return;
+        }
+
         queue!(stdout(), Clear(ClearType::CurrentLine), MoveToColumn(0),).unwrap();
+        // A stream's size isn't known, so only the bytes consumed so far are
+        let percent = match streaming {
+            true => String::new(),
+            false => format!("({} %) ", percent),
+        };
         log_queue(format!(
-            "Copying... ({} %) [{} copied] --> {}",
+            "Copying... {}[{} copied] --> {}",
             percent,
-            get_bytes_string(bytes_copied),
+            get_bytes_string(bytes_copied, locale),
             current_dir.display()
         ));
 
+        // Every destination receives the same payload, so a group's progress is the average of
+        // its members'
+        if let Some(group) = groups
+            .iter()
+            .find(|group| group.destinations.contains(&current_dir))
+        {
+            let percentages = percentages.borrow();
+            let sum = group
+                .destinations
+                .iter()
+                .map(|dest| percentages.get(dest).copied().unwrap_or(0))
+                .sum::<usize>();
+            queue!(
+                stdout(),
+                Print(format!(
+                    " [{} {} %]",
+                    group.name,
+                    sum / group.destinations.len()
+                ))
+            )
+            .unwrap();
+        }
+
         stdout().flush().unwrap();
     };
 
     let oncomplete = move || {
+        if !concurrent {
+            queue!(stdout(), Print("\n")).unwrap();
+        }
+        log("Files finished copying\n");
+    };
+
+    let summaries = queue.start_copy(Box::new(onpercentage), Box::new(oncomplete))?;
+    // Cancelled, the progress line is still open
+    if !concurrent && cancelled(&summaries) {
         queue!(stdout(), Print("\n")).unwrap();
-        log("Files finished copying");
+    }
+    Ok(summaries)
+}
+
+pub fn handle_verifying(queue: &CopyQueue, locale: Locale) -> Result<Vec<Verification>, CopyError> {
+    log("Verifying destinations...\n");
+    let destinations = queue.destinations();
+    let percentages = RefCell::new(vec![0; destinations.len()]);
+    for dest in destinations {
+        queue_progress_bar(dest, 0);
+    }
+    stdout().flush().unwrap();
+
+    let onprogress = |index: usize, percent: usize| {
+        percentages.borrow_mut()[index] = percent;
+        queue!(stdout(), MoveUp(destinations.len() as u16)).unwrap();
+        for (dest, percent) in destinations.iter().zip(percentages.borrow().iter()) {
+            queue_progress_bar(dest, *percent);
+        }
+        stdout().flush().unwrap();
+    };
+
+    let results = queue.start_verify(Box::new(onprogress))?;
+    print_verifications(&results, locale);
+    Ok(results)
+}
+
+///
+/// Deletes what the source doesn't have from every destination (`--mirror`)
+///
+pub fn handle_mirroring(queue: &CopyQueue) -> Result<(), CopyError> {
+    let mirrored = queue.start_mirror()?;
+    for (dest, files) in queue.destinations().iter().zip(mirrored) {
+        log(format!(
+            "Deleted {} stale file(s) from `{}`\n",
+            files.len(),
+            dest.display()
+        ));
+    }
+    Ok(())
+}
+
+///
+/// Deletes whatever matches the `--clean-dest-glob` rules from every destination
+///
+pub fn handle_cleaning(queue: &CopyQueue) -> Result<(), CopyError> {
+    let cleaned = queue.start_clean()?;
+    for (dest, files) in queue.destinations().iter().zip(cleaned) {
+        log(format!(
+            "Cleaned up {} file(s) on `{}`\n",
+            files.len(),
+            dest.display()
+        ));
+    }
+    Ok(())
+}
+
+///
+/// Swaps what `--transactional` staged into the destinations the run got through for, and rolls
+/// back the others
+///
+pub fn handle_committing(
+    queue: &CopyQueue,
+    summaries: &[DestinationSummary],
+    verifications: Option<&[Verification]>,
+) -> Result<(), CopyError> {
+    let committed = queue.start_commit(summaries, verifications)?;
+    for (dest, committed) in queue.destinations().iter().zip(committed) {
+        match committed {
+            true => log(format!("Swapped the new files into `{}`\n", dest.display())),
+            false => log(format!(
+                "{}\n",
+                format!("Rolled back `{}`, it was left as it was", dest.display()).yellow()
+            )),
+        }
+    }
+    Ok(())
+}
+
+///
+/// Counts down to `start` on a single line, for `--start-at` and `--delay` in line mode
+///
+fn wait_for_start(start: DateTime<Local>) {
+    while start > Local::now() {
+        queue!(stdout(), Clear(ClearType::CurrentLine), MoveToColumn(0)).unwrap();
+        log(format!(
+            "Copying starts at {} (in {})",
+            start.format("%H:%M"),
+            countdown(start)
+        ));
+        let left = (start - Local::now()).to_std().unwrap_or_default();
+        ::std::thread::sleep(left.min(Duration::from_secs(1)));
+    }
+    println!();
+}
+
+///
+/// Flushes what the run wrote to every destination out of the OS write cache (`--sync`). Without
+/// `--eject` each drive can be pulled once that is done.
+///
+pub fn handle_syncing(args: &Args) -> Result<(), CopyError> {
+    for dest in &args.drives {
+        log(format!(
+            "Flushing the write cache of `{}`...\n",
+            dest.display()
+        ));
+        drive::sync(dest).map_err(|e| CopyError::new(dest, Some(dest), e))?;
+        if !args.eject {
+            log(format!(
+                "`{}` {}\n",
+                dest.display(),
+                "is safe to remove".green()
+            ));
+            play_cue(args, SoundEvent::DriveDone);
+        }
+    }
+    Ok(())
+}
+
+///
+/// Ejects every destination in turn and waits for the OS to let go of it, so the operator knows
+/// when each drive can be pulled
+///
+pub fn handle_ejecting(args: &Args) -> Result<(), CopyError> {
+    for dest in &args.drives {
+        log(format!("Ejecting `{}`...\n", dest.display()));
+        drive::eject_and_wait(dest, |_| {}).map_err(|e| CopyError::new(dest, Some(dest), e))?;
+        log(format!(
+            "`{}` {}\n",
+            dest.display(),
+            "is safe to remove".green()
+        ));
+        play_cue(args, SoundEvent::DriveDone);
+    }
+    Ok(())
+}
+
+///
+/// Plays what the `[sounds]` table has for `event`, under `--beep`
+///
+fn play_cue(args: &Args, event: SoundEvent) {
+    if args.beep {
+        let _ = args.sounds.cue(event).play(&mut stdout());
+    }
+}
+
+///
+/// Copies whatever failed verification again, with a progress bar for each destination that
+/// needs it
+///
+pub fn handle_repairing(
+    queue: &CopyQueue,
+    verifications: &[Verification],
+    locale: Locale,
+) -> Result<Vec<Verification>, CopyError> {
+    let failed = verifications.iter().map(|v| v.mismatches()).sum::<usize>();
+    log(format!("Repairing {} file(s)...\n", failed));
+    let broken = verifications
+        .iter()
+        .filter(|v| !v.passed())
+        .map(|v| v.destination.clone())
+        .collect::<Vec<_>>();
+    let percentages = RefCell::new(vec![0; broken.len()]);
+    for dest in &broken {
+        queue_progress_bar(dest, 0);
+    }
+    stdout().flush().unwrap();
+
+    let onprogress = |index: usize, percent: usize| {
+        let Some(row) = broken
+            .iter()
+            .position(|dest| *dest == verifications[index].destination)
+        else {
+            return;
+        };
+        percentages.borrow_mut()[row] = percent;
+        queue!(stdout(), MoveUp(broken.len() as u16)).unwrap();
+        for (dest, percent) in broken.iter().zip(percentages.borrow().iter()) {
+            queue_progress_bar(dest, *percent);
+        }
+        stdout().flush().unwrap();
     };
 
-    queue.start_copy(Box::new(onpercentage), Box::new(oncomplete));
+    let results = queue.start_repair(verifications, Box::new(onprogress))?;
+    print_verifications(&results, locale);
+    Ok(results)
+}
+
+///
+/// Whether the copy was cancelled on any destination
+///
+fn cancelled(summaries: &[DestinationSummary]) -> bool {
+    summaries.iter().any(|summary| summary.cancelled)
+}
+
+///
+/// Tells what a cancelled copy got done on every destination
+///
+fn log_cancelled(summaries: &[DestinationSummary], args: &Args) {
+    let locale = args.locale;
+    log(format!("{}\n", "Copy cancelled".yellow()));
+    for summary in summaries {
+        let copied = get_bytes_string(summary.bytes_copied, locale);
+        log(format!(
+            "  {} {}\n",
+            summary.destination.display(),
+            match summary.cancelled {
+                true => format!("cancelled after {} copied", copied).yellow(),
+                false => format!("done, {} copied", copied).green(),
+            }
+        ));
+    }
+    match args.transactional {
+        true => log("Every destination was rolled back and left as it was\n"),
+        false => log("Run it again with --resume to pick up where it stopped\n"),
+    }
+}
+
+///
+/// Lists the files `--keep-going` could not copy and warns of virus scanners holding up the
+/// writes, per destination
+///
+fn log_copy_problems(summaries: &[DestinationSummary], locale: Locale) {
+    for summary in summaries {
+        if !summary.failed.is_empty() {
+            log(format!(
+                "{} {}\n",
+                summary.destination.display(),
+                format!("{} file(s) could not be copied", summary.failed.len()).red()
+            ));
+            for failed in failed_file_lines(summary) {
+                log(format!("  {}\n", failed.red()));
+            }
+        }
+        if !summary.changed.is_empty() {
+            log(format!(
+                "{} {}\n",
+                summary.destination.display(),
+                changed_files_warning(summary).yellow()
+            ));
+            for file in &summary.changed {
+                log(format!("  {}\n", file.display().to_string().yellow()));
+            }
+        }
+        if summary.scanned {
+            log(format!(
+                "{} {}\n",
+                summary.destination.display(),
+                Message::ScanSuspected.text(locale).yellow()
+            ));
+        }
+    }
+}
+
+fn print_verifications(results: &[Verification], locale: Locale) {
+    for result in results {
+        if result.passed() && !result.repaired.is_empty() {
+            log(format!(
+                "{} {} ({} file(s) repaired)\n",
+                result.destination.display(),
+                Message::Verified.text(locale).green(),
+                result.repaired.len()
+            ));
+        } else if result.passed() {
+            log(format!(
+                "{} {}\n",
+                result.destination.display(),
+                Message::Verified.text(locale).green()
+            ));
+        } else {
+            log(format!(
+                "{} {}\n",
+                result.destination.display(),
+                format!("{} file(s) do not match the source", result.mismatches()).red()
+            ));
+            for failure in failure_lines(result) {
+                log(format!("  {}\n", failure.red()));
+            }
+        }
+    }
+}
+
+///
+/// Reports a failed run with advice on what to do about it, then exits
+///
+fn exit_with_error(e: &CopyError, args: &Args, started_at: DateTime<Local>) -> ! {
+    let failed = Message::CopyFailed.text(args.locale);
+    queue!(stdout(), Print("\n")).unwrap();
+    match &e.destination {
+        Some(dest) => log(format!(
+            "{} {} (on `{}`)\n",
+            failed.red(),
+            e,
+            dest.display()
+        )),
+        None => log(format!("{} {}\n", failed.red(), e)),
+    }
+    if let Some(suggestion) = e.class().suggestion(args.locale) {
+        log(format!("{}\n", suggestion.yellow()));
+    }
+    // Only a destination that failed is worth running again for, not a source that did
+    let rerun = e
+        .destination
+        .as_ref()
+        .and_then(|_| rerun_command(&::std::env::args_os().collect::<Vec<_>>(), &args.drives));
+    if let Some(rerun) = &rerun {
+        log(format!("To run this again:\n  {}\n", rerun));
+    }
+    write_report(
+        args,
+        started_at,
+        &[],
+        None,
+        None,
+        Some(ErrorReport::new(e, args.locale)),
+        rerun,
+    );
+    forget_download(args);
+    if !stdout().is_terminal() && ::std::io::stdin().is_terminal() && can_elevate(e) {
+        print!("Retry as administrator? (y/N) ");
+        stdout().flush().expect("Failed to flush stdout");
+        let mut buffer = String::new();
+        if ::std::io::stdin().read_line(&mut buffer).is_ok()
+            && matches!(buffer.trim().to_lowercase().as_str(), "y" | "yes")
+        {
+            relaunch_elevated();
+        }
+    }
+    ::std::process::exit(1);
+}
+
+///
+/// Starts an elevated copy of this run that resumes from its checkpoint
+///
+fn relaunch_elevated() {
+    match elevate::relaunch_elevated() {
+        Ok(()) => log("Continuing as administrator in a new window\n"),
+        Err(e) => log(format!("Could not relaunch as administrator: {}\n", e)),
+    }
+}
+
+fn queue_progress_bar(dest: &Path, percent: usize) {
+    const WIDTH: usize = 30;
+    let filled = percent.min(100) * WIDTH / 100;
+    queue!(
+        stdout(),
+        Clear(ClearType::CurrentLine),
+        MoveToColumn(0),
+        Print(format!("  {} ", dest.display())),
+        SetForegroundColor(Color::Cyan),
+        Print(format!(
+            "[{}{}] {:>3} %",
+            "#".repeat(filled),
+            " ".repeat(WIDTH - filled),
+            percent
+        )),
+        SetForegroundColor(Color::Reset),
+        Print("\n"),
+    )
+    .unwrap();
 }
 
 pub fn log_queue(msg: impl Into<String>) {
@@ -141,21 +2205,3 @@ pub fn log(msg: impl Into<String>) {
     log_queue(msg);
     stdout().flush().unwrap();
 }
-
-pub fn get_bytes_string(bytes: usize) -> String {
-    match bytes {
-        bytes if bytes >= 1024usize.pow(4) => {
-            format!("{}tb", bytes / 1024usize.pow(4))
-        }
-        bytes if bytes >= 1024usize.pow(3) => {
-            format!("{}gb", bytes / 1024usize.pow(3))
-        }
-        bytes if bytes >= 1024usize.pow(2) => {
-            format!("{}mb", bytes / 1024usize.pow(2))
-        }
-        bytes if bytes >= 1024 => {
-            format!("{}kb", bytes / 1024)
-        }
-        n => format!("{}b", n),
-    }
-}
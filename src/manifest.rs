@@ -0,0 +1,56 @@
+use std::{collections::BTreeMap, path::PathBuf};
+
+use crate::{hash::sha256, rawpath::escape_all};
+
+/// Name of the manifest `--sha256sums` writes into the root of every destination
+pub const SHA256SUMS: &str = "SHA256SUMS";
+
+///
+/// The SHA-256 of every file in a deployment, keyed by its path relative to the source
+///
+#[derive(Debug, Default, Clone)]
+pub struct Manifest {
+    pub entries: BTreeMap<PathBuf, String>,
+}
+
+impl Manifest {
+    pub fn new(entries: BTreeMap<PathBuf, String>) -> Self {
+        Self { entries }
+    }
+
+    ///
+    /// Renders the manifest in `sha256sum` format, one `<hash>  <path>` line per file with `/` as
+    /// the path separator on every platform. As with `sha256sum`, the line of a path containing
+    /// `\` or a newline starts with `\` and the path is escaped, which here also covers names that
+    /// aren't valid Unicode (see `rawpath::escape_all`) so no two files share a line.
+    ///
+    pub fn to_sha256sums(&self) -> String {
+        self.entries
+            .iter()
+            .map(|(file, hash)| {
+                let components = file.components().map(|c| c.as_os_str()).collect::<Vec<_>>();
+                let escaped = components.iter().any(|c| {
+                    c.to_str()
+                        .is_none_or(|text| text.contains(['\\', '\n', '\r']))
+                });
+                let path = components
+                    .iter()
+                    .map(|c| match escaped {
+                        true => escape_all(c),
+                        false => c.to_string_lossy().into_owned(),
+                    })
+                    .collect::<Vec<_>>()
+                    .join("/");
+                let marker = if escaped { "\\" } else { "" };
+                format!("{}{}  {}\n", marker, hash, path)
+            })
+            .collect()
+    }
+
+    ///
+    /// A single hash identifying the whole payload: the SHA-256 of the `sha256sum` rendering
+    ///
+    pub fn digest(&self) -> String {
+        sha256(self.to_sha256sums().as_bytes()).expect("hashing from memory cannot fail")
+    }
+}
@@ -0,0 +1,119 @@
+use std::{
+    collections::HashSet,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    drive_log::DRIVE_LOG,
+    error::CopyError,
+    manifest::SHA256SUMS,
+    walk::{Entry, Selection, Source},
+};
+
+///
+/// What `--mirror` deletes from a destination: files the source doesn't have, then the
+/// directories it doesn't have either. Paths are relative to the destination, in `Walk` order.
+///
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Stale {
+    /// Files with their sizes
+    pub files: Vec<(PathBuf, usize)>,
+    pub dirs: Vec<PathBuf>,
+}
+
+impl Stale {
+    pub fn bytes(&self) -> usize {
+        self.files.iter().map(|(_, size)| size).sum()
+    }
+
+    ///
+    /// Without what is below `kept`, for entries excluded on the PreCopy screen after `stale`
+    /// listed the destination
+    ///
+    pub fn keeping(&self, kept: &[PathBuf]) -> Stale {
+        let wanted = |path: &Path| !kept.iter().any(|kept| path.starts_with(kept));
+        Stale {
+            files: self
+                .files
+                .iter()
+                .filter(|(file, _)| wanted(file))
+                .cloned()
+                .collect(),
+            dirs: self
+                .dirs
+                .iter()
+                .filter(|dir| wanted(dir))
+                .cloned()
+                .collect(),
+        }
+    }
+}
+
+///
+/// The paths `stale` leaves alone: those left out of the run, and the `SHA256SUMS` and
+/// `deployment.log` that `--sha256sums` and `--drive-log` put there themselves
+///
+pub fn kept(excluded: &[PathBuf], sha256sums: bool, drive_log: bool) -> Vec<PathBuf> {
+    let mut kept = excluded.to_vec();
+    if sha256sums {
+        kept.push(PathBuf::from(SHA256SUMS));
+    }
+    if drive_log {
+        kept.push(PathBuf::from(DRIVE_LOG));
+    }
+    kept
+}
+
+///
+/// Lists what on `dest` isn't in `source`. What `selection` leaves out is left alone on both
+/// sides, so what an earlier run put there stays. A destination that doesn't exist yet has
+/// nothing stale.
+///
+pub fn stale(source: &Source, selection: &Selection, dest: &Path) -> Result<Stale, CopyError> {
+    if !dest.is_dir() {
+        return Ok(Stale::default());
+    }
+    let mut files = HashSet::new();
+    let mut dirs = HashSet::new();
+    for entry in selection.walk(source) {
+        match entry.map_err(|e| CopyError::new(&source.name(), None, e))? {
+            Entry::Dir(dir) => dirs.insert(dir),
+            Entry::File(file, _) | Entry::Link(file, _) => files.insert(file),
+        };
+    }
+
+    let mut stale = Stale::default();
+    for entry in selection.walk(dest) {
+        match entry.map_err(|e| CopyError::new(dest, Some(dest), e))? {
+            Entry::Dir(dir) if !dirs.contains(&dir) => stale.dirs.push(dir),
+            Entry::File(file, size) if !files.contains(&file) => stale.files.push((file, size)),
+            Entry::Link(link, _) if !files.contains(&link) => stale.files.push((link, 0)),
+            _ => {}
+        }
+    }
+    Ok(stale)
+}
+
+///
+/// Deletes `stale` from `dest`, returning the files removed. Directories go last and deepest
+/// first, any that something was put back into in the meantime stay.
+///
+pub fn remove_stale(dest: &Path, stale: &Stale) -> Result<Vec<PathBuf>, CopyError> {
+    let mut removed = Vec::new();
+    for (file, _) in &stale.files {
+        match ::std::fs::remove_file(dest.join(file)) {
+            Ok(()) => removed.push(file.clone()),
+            Err(e) if e.kind() == ErrorKind::NotFound => {}
+            Err(e) => return Err(CopyError::new(file, Some(dest), e)),
+        }
+    }
+    for dir in stale.dirs.iter().rev() {
+        match ::std::fs::remove_dir(dest.join(dir)) {
+            Ok(()) => {}
+            Err(e) if matches!(e.kind(), ErrorKind::NotFound | ErrorKind::DirectoryNotEmpty) => {}
+            Err(e) => return Err(CopyError::new(dir, Some(dest), e)),
+        }
+    }
+    Ok(removed)
+}
@@ -0,0 +1,281 @@
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::{fs::File, path::Path};
+
+///
+/// How files are copied onto the destinations (`--engine`)
+///
+#[derive(ValueEnum, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Engine {
+    /// With what the system has for copying files where it can (see `NATIVE_ENGINE`), through a
+    /// buffer of ours where it can't
+    #[default]
+    Auto,
+    /// Always through a buffer of ours, `--copy-buffer` at a time
+    Buffered,
+}
+
+///
+/// What the system has for copying files that `Engine::Auto` uses, for `--version --json`
+///
+pub const NATIVE_ENGINE: Option<&str> = if cfg!(windows) {
+    Some("CopyFileExW")
+} else if cfg!(target_os = "linux") {
+    Some("FICLONE, copy_file_range")
+} else if cfg!(target_os = "macos") {
+    Some("clonefile")
+} else {
+    None
+};
+
+///
+/// Makes `dest` a clone of `source` that shares its blocks until either is written to, on file
+/// systems that can (Btrfs and XFS on Linux, APFS on macOS). Whatever is at `dest` is replaced.
+/// Returns the size of the clone, or `None` when the file system can't clone it, for the caller
+/// to copy it another way.
+///
+pub fn clone_file(source: &Path, dest: &Path) -> Option<usize> {
+    imp::clone_file(source, dest).ok()?;
+    ::std::fs::metadata(dest)
+        .ok()
+        .map(|metadata| metadata.len() as usize)
+}
+
+///
+/// Copies up to `len` bytes of `source` from `offset` on to the same offset of `dest` inside the
+/// kernel, without passing them through a buffer of ours (`copy_file_range` on Linux). Returns
+/// how many were copied, 0 at the end of `source`, or `None` when the file systems can't do it
+/// between them, for the caller to copy the rest another way.
+///
+pub fn copy_range(source: &File, dest: &File, offset: usize, len: usize) -> Option<usize> {
+    imp::copy_range(source, dest, offset, len).ok()
+}
+
+///
+/// Copies all of `source` to `dest` the way the system copies files itself, which keeps its
+/// attributes and lets the system pick how to move the bytes (`CopyFileExW` on Windows).
+/// `progress` gets the bytes copied so far, and stops the copy by failing. Returns how many
+/// bytes were copied, or `None` where the system has no such thing, for the caller to copy the
+/// file another way.
+///
+pub fn copy_file(
+    source: &Path,
+    dest: &Path,
+    progress: impl FnMut(usize) -> ::std::io::Result<()>,
+) -> Option<::std::io::Result<usize>> {
+    imp::copy_file(source, dest, progress)
+}
+
+#[cfg(target_os = "linux")]
+mod imp {
+    use std::{
+        fs::{File, OpenOptions},
+        os::unix::io::AsRawFd,
+        path::Path,
+    };
+
+    pub fn clone_file(source: &Path, dest: &Path) -> ::std::io::Result<()> {
+        let source = File::open(source)?;
+        let dest = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(dest)?;
+        // SAFETY: both descriptors stay open for the duration of the call
+        match unsafe { libc::ioctl(dest.as_raw_fd(), libc::FICLONE, source.as_raw_fd()) } {
+            0 => Ok(()),
+            _ => Err(::std::io::Error::last_os_error()),
+        }
+    }
+
+    pub fn copy_range(
+        source: &File,
+        dest: &File,
+        offset: usize,
+        len: usize,
+    ) -> ::std::io::Result<usize> {
+        let mut from = offset as libc::off64_t;
+        let mut to = offset as libc::off64_t;
+        // SAFETY: both descriptors stay open for the duration of the call, and the offsets
+        // outlive it
+        let copied = unsafe {
+            libc::copy_file_range(
+                source.as_raw_fd(),
+                &mut from,
+                dest.as_raw_fd(),
+                &mut to,
+                len,
+                0,
+            )
+        };
+        match copied {
+            -1 => Err(::std::io::Error::last_os_error()),
+            copied => Ok(copied as usize),
+        }
+    }
+
+    pub fn copy_file(
+        _source: &Path,
+        _dest: &Path,
+        _progress: impl FnMut(usize) -> ::std::io::Result<()>,
+    ) -> Option<::std::io::Result<usize>> {
+        None
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod imp {
+    use std::{ffi::CString, fs::File, os::unix::ffi::OsStrExt, path::Path};
+
+    pub fn clone_file(source: &Path, dest: &Path) -> ::std::io::Result<()> {
+        let source = CString::new(source.as_os_str().as_bytes())?;
+        let dest_path = CString::new(dest.as_os_str().as_bytes())?;
+        // `clonefile` only creates new files
+        match ::std::fs::remove_file(dest) {
+            Err(e) if e.kind() != ::std::io::ErrorKind::NotFound => return Err(e),
+            _ => {}
+        }
+        // SAFETY: both paths are NUL-terminated and outlive the call
+        match unsafe { libc::clonefile(source.as_ptr(), dest_path.as_ptr(), 0) } {
+            0 => Ok(()),
+            _ => Err(::std::io::Error::last_os_error()),
+        }
+    }
+
+    pub fn copy_range(
+        _source: &File,
+        _dest: &File,
+        _offset: usize,
+        _len: usize,
+    ) -> ::std::io::Result<usize> {
+        Err(::std::io::ErrorKind::Unsupported.into())
+    }
+
+    pub fn copy_file(
+        _source: &Path,
+        _dest: &Path,
+        _progress: impl FnMut(usize) -> ::std::io::Result<()>,
+    ) -> Option<::std::io::Result<usize>> {
+        None
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+mod imp {
+    use std::{fs::File, path::Path};
+
+    pub fn clone_file(_source: &Path, _dest: &Path) -> ::std::io::Result<()> {
+        Err(::std::io::ErrorKind::Unsupported.into())
+    }
+
+    pub fn copy_range(
+        _source: &File,
+        _dest: &File,
+        _offset: usize,
+        _len: usize,
+    ) -> ::std::io::Result<usize> {
+        Err(::std::io::ErrorKind::Unsupported.into())
+    }
+
+    pub fn copy_file(
+        _source: &Path,
+        _dest: &Path,
+        _progress: impl FnMut(usize) -> ::std::io::Result<()>,
+    ) -> Option<::std::io::Result<usize>> {
+        None
+    }
+}
+
+#[cfg(windows)]
+mod imp {
+    use std::{ffi::c_void, fs::File, os::windows::ffi::OsStrExt, path::Path};
+    use windows_sys::Win32::{
+        Foundation::{BOOL, ERROR_REQUEST_ABORTED, HANDLE},
+        Storage::FileSystem::{CopyFileExW, LPPROGRESS_ROUTINE_CALLBACK_REASON},
+        System::WindowsProgramming::{PROGRESS_CANCEL, PROGRESS_CONTINUE},
+    };
+
+    pub fn clone_file(_source: &Path, _dest: &Path) -> ::std::io::Result<()> {
+        Err(::std::io::ErrorKind::Unsupported.into())
+    }
+
+    pub fn copy_range(
+        _source: &File,
+        _dest: &File,
+        _offset: usize,
+        _len: usize,
+    ) -> ::std::io::Result<usize> {
+        Err(::std::io::ErrorKind::Unsupported.into())
+    }
+
+    /// What `progress` in `copy_file` works with, behind the pointer `CopyFileExW` passes back
+    struct Progress<'a> {
+        progress: &'a mut dyn FnMut(usize) -> ::std::io::Result<()>,
+        copied: usize,
+        /// Why `progress` stopped the copy
+        error: Option<::std::io::Error>,
+    }
+
+    unsafe extern "system" fn on_progress(
+        _total_size: i64,
+        transferred: i64,
+        _stream_size: i64,
+        _stream_transferred: i64,
+        _stream: u32,
+        _reason: LPPROGRESS_ROUTINE_CALLBACK_REASON,
+        _source: HANDLE,
+        _dest: HANDLE,
+        data: *const c_void,
+    ) -> u32 {
+        // SAFETY: `data` is the `Progress` `copy_file` passed, which outlives the copy
+        let state = unsafe { &mut *(data as *mut Progress) };
+        state.copied = transferred as usize;
+        match (state.progress)(state.copied) {
+            Ok(()) => PROGRESS_CONTINUE,
+            Err(e) => {
+                state.error = Some(e);
+                PROGRESS_CANCEL
+            }
+        }
+    }
+
+    pub fn copy_file(
+        source: &Path,
+        dest: &Path,
+        mut progress: impl FnMut(usize) -> ::std::io::Result<()>,
+    ) -> Option<::std::io::Result<usize>> {
+        let wide = |path: &Path| {
+            path.as_os_str()
+                .encode_wide()
+                .chain(Some(0))
+                .collect::<Vec<u16>>()
+        };
+        let (source, dest) = (wide(source), wide(dest));
+        let mut state = Progress {
+            progress: &mut progress,
+            copied: 0,
+            error: None,
+        };
+        let mut cancel: BOOL = 0;
+        // SAFETY: the paths are NUL-terminated, and they, `state` and `cancel` outlive the call
+        let copied = unsafe {
+            CopyFileExW(
+                source.as_ptr(),
+                dest.as_ptr(),
+                Some(on_progress),
+                &mut state as *mut Progress as *const c_void,
+                &mut cancel,
+                0,
+            )
+        };
+        if copied != 0 {
+            return Some(Ok(state.copied));
+        }
+        let error = ::std::io::Error::last_os_error();
+        Some(Err(match state.error {
+            Some(stopped) if error.raw_os_error() == Some(ERROR_REQUEST_ABORTED as i32) => stopped,
+            _ => error,
+        }))
+    }
+}
@@ -0,0 +1,189 @@
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::{
+    path::{Path, PathBuf},
+    sync::{Condvar, Mutex},
+    time::{Duration, SystemTime},
+};
+
+use crate::copy::MTIME_TOLERANCE;
+
+/// How often a copy waiting on an answer checks whether the run was cancelled meanwhile
+const ANSWER_POLL: Duration = Duration::from_millis(50);
+
+///
+/// What a run does with files a destination already has (`--overwrite`)
+///
+#[derive(ValueEnum, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum Overwrite {
+    /// Write them again
+    #[default]
+    Always,
+    /// Leave them as they are
+    Never,
+    /// Write them again when the source's copy was modified later
+    IfNewer,
+    /// Ask the operator about each of them on the Copying screen
+    Prompt,
+}
+
+impl Overwrite {
+    ///
+    /// Whether `existing`, already on a destination, is written over with `source`. The file
+    /// `source` has no path when it comes from stdin, `if-newer` overwrites it then. Under
+    /// `prompt` `ask` is called for the answer, see `Conflicts::ask`.
+    ///
+    pub fn allows(
+        self,
+        source: Option<&Path>,
+        existing: &Path,
+        ask: impl FnOnce() -> bool,
+    ) -> bool {
+        match self {
+            Overwrite::Always => true,
+            Overwrite::Never => false,
+            Overwrite::IfNewer => {
+                let modified = |path: &Path| ::std::fs::metadata(path).and_then(|m| m.modified());
+                match (source.map(modified), modified(existing)) {
+                    (Some(Ok(source)), Ok(existing)) => source
+                        .duration_since(existing)
+                        .is_ok_and(|newer| newer > MTIME_TOLERANCE),
+                    _ => true,
+                }
+            }
+            Overwrite::Prompt => ask(),
+        }
+    }
+}
+
+///
+/// Size and modification time of one side of a `Conflict`
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileVersion {
+    pub size: u64,
+    pub modified: Option<SystemTime>,
+}
+
+impl FileVersion {
+    pub fn of(path: &Path) -> Option<Self> {
+        let metadata = ::std::fs::metadata(path).ok()?;
+        Some(Self {
+            size: metadata.len(),
+            modified: metadata.modified().ok(),
+        })
+    }
+}
+
+///
+/// A file that is already on a destination, waiting for the operator to say whether it is
+/// overwritten
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Conflict {
+    /// Index into the destinations
+    pub dest: usize,
+    /// Relative to the source
+    pub file: PathBuf,
+    /// `None` for a file that comes from stdin
+    pub source: Option<FileVersion>,
+    pub existing: Option<FileVersion>,
+}
+
+///
+/// What the operator answered to a `Conflict`
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Answer {
+    Overwrite,
+    Skip,
+    /// Overwrite this and every later conflict of the run without asking
+    OverwriteAll,
+    /// Skip this and every later conflict of the run without asking
+    SkipAll,
+}
+
+#[derive(Debug, Default)]
+struct Asking {
+    pending: Option<Conflict>,
+    answer: Option<Answer>,
+    /// Set by `OverwriteAll` and `SkipAll`: whether every later conflict is overwritten
+    standing: Option<bool>,
+}
+
+///
+/// The `--overwrite prompt` questions of a run, between the copy threads that ask them and the
+/// UI that shows them. One is asked at a time, the others wait their turn.
+///
+#[derive(Debug, Default)]
+pub struct Conflicts {
+    /// Held by the copy thread whose question is asked
+    turn: Mutex<()>,
+    asking: Mutex<Asking>,
+    answered: Condvar,
+}
+
+impl Conflicts {
+    ///
+    /// Asks whether `conflict` is overwritten and waits for the answer. Gives up on it, leaving
+    /// the file as it is, once `cancelled` says the run was cancelled.
+    ///
+    pub fn ask(&self, conflict: Conflict, cancelled: impl Fn() -> bool) -> bool {
+        let _turn = self.turn.lock().expect("conflicts are never poisoned");
+        let mut asking = self.asking.lock().expect("conflicts are never poisoned");
+        if let Some(standing) = asking.standing {
+            return standing;
+        }
+        asking.pending = Some(conflict);
+        asking.answer = None;
+        let answer = loop {
+            if let Some(answer) = asking.answer.take() {
+                break Some(answer);
+            }
+            if cancelled() {
+                break None;
+            }
+            asking = self
+                .answered
+                .wait_timeout(asking, ANSWER_POLL)
+                .expect("conflicts are never poisoned")
+                .0;
+        };
+        asking.pending = None;
+        match answer {
+            Some(Answer::Overwrite) => true,
+            Some(Answer::OverwriteAll) => {
+                asking.standing = Some(true);
+                true
+            }
+            Some(Answer::SkipAll) => {
+                asking.standing = Some(false);
+                false
+            }
+            Some(Answer::Skip) | None => false,
+        }
+    }
+
+    ///
+    /// The conflict waiting for an answer, if there is one
+    ///
+    pub fn pending(&self) -> Option<Conflict> {
+        self.asking
+            .lock()
+            .expect("conflicts are never poisoned")
+            .pending
+            .clone()
+    }
+
+    ///
+    /// Answers the pending conflict. Does nothing when none is.
+    ///
+    pub fn answer(&self, answer: Answer) {
+        let mut asking = self.asking.lock().expect("conflicts are never poisoned");
+        if asking.pending.is_some() {
+            asking.answer = Some(answer);
+            self.answered.notify_all();
+        }
+    }
+}
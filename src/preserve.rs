@@ -0,0 +1,98 @@
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{File, Permissions},
+    path::Path,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// FAT counts from 1980, a day in so no time zone pushes the local time below it
+const FAT_EARLIEST: u64 = 315_619_200;
+/// And only up to 2107
+const FAT_LATEST: u64 = 4_354_646_400;
+
+///
+/// What of a source file's metadata `--preserve` carries over to its copies
+///
+#[derive(ValueEnum, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Preserve {
+    /// The modification time
+    Times,
+    /// The Unix mode bits, or just whether the file is read-only on Windows and FAT
+    Perms,
+}
+
+///
+/// The metadata a copy of a source file gets once it is written
+///
+#[derive(Debug, Clone, Default)]
+pub struct Kept {
+    pub modified: Option<SystemTime>,
+    pub permissions: Option<Permissions>,
+}
+
+impl Kept {
+    ///
+    /// Gives `file`, a copy on a destination, the kept metadata. On `fat` destinations the
+    /// modification time is rounded up to the next 2 seconds and kept within what FAT can hold,
+    /// so the copy never looks older than the source, and of the permissions only whether the
+    /// file is read-only is kept.
+    ///
+    pub fn apply(&self, file: &File, fat: bool) -> ::std::io::Result<()> {
+        if let Some(modified) = self.modified {
+            file.set_modified(match fat {
+                true => fat_time(modified),
+                false => modified,
+            })?;
+        }
+        if let Some(permissions) = &self.permissions {
+            let permissions = match fat {
+                true => {
+                    let mut current = file.metadata()?.permissions();
+                    current.set_readonly(permissions.readonly());
+                    current
+                }
+                false => permissions.clone(),
+            };
+            file.set_permissions(permissions)?;
+        }
+        Ok(())
+    }
+}
+
+///
+/// Lets `path` be written over if it is a read-only copy, as `--preserve perms` leaves them
+///
+pub fn make_writable(path: &Path) -> ::std::io::Result<()> {
+    let Ok(metadata) = ::std::fs::symlink_metadata(path) else {
+        return Ok(());
+    };
+    let mut permissions = metadata.permissions();
+    if !metadata.is_file() || !permissions.readonly() {
+        return Ok(());
+    }
+    // Only the owner's write bit, the rest comes back with the copy's own permissions
+    #[cfg(unix)]
+    {
+        use ::std::os::unix::fs::PermissionsExt;
+        permissions.set_mode(permissions.mode() | 0o200);
+    }
+    // Elsewhere the read-only flag is all there is, and it is known to be set here
+    #[cfg(not(unix))]
+    permissions.set_readonly(!permissions.readonly());
+    ::std::fs::set_permissions(path, permissions)
+}
+
+///
+/// `time` as FAT can hold it without going back in time
+///
+pub(crate) fn fat_time(time: SystemTime) -> SystemTime {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let mut secs = since_epoch.as_secs();
+    if since_epoch.subsec_nanos() > 0 {
+        secs += 1;
+    }
+    secs += secs % 2;
+    UNIX_EPOCH + Duration::from_secs(secs.clamp(FAT_EARLIEST, FAT_LATEST))
+}
@@ -0,0 +1,51 @@
+///
+/// Drops the I/O priority of the current process so a background deployment yields the disk to
+/// interactive work (`ioprio_set` idle class on Linux, background mode on Windows and a lower
+/// scheduling priority on other Unixes)
+///
+pub fn lower_io_priority() -> ::std::io::Result<()> {
+    imp::lower_io_priority()
+}
+
+#[cfg(target_os = "linux")]
+mod imp {
+    const IOPRIO_WHO_PROCESS: libc::c_long = 1;
+    const IOPRIO_CLASS_IDLE: libc::c_long = 3;
+    const IOPRIO_CLASS_SHIFT: libc::c_long = 13;
+
+    pub fn lower_io_priority() -> ::std::io::Result<()> {
+        let prio = IOPRIO_CLASS_IDLE << IOPRIO_CLASS_SHIFT;
+        // SAFETY: ioprio_set only reads its integer arguments
+        let result = unsafe { libc::syscall(libc::SYS_ioprio_set, IOPRIO_WHO_PROCESS, 0, prio) };
+        if result == -1 {
+            return Err(::std::io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(all(unix, not(target_os = "linux")))]
+mod imp {
+    pub fn lower_io_priority() -> ::std::io::Result<()> {
+        // SAFETY: setpriority only reads its integer arguments
+        if unsafe { libc::setpriority(libc::PRIO_PROCESS, 0, 10) } == -1 {
+            return Err(::std::io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(windows)]
+mod imp {
+    use windows_sys::Win32::System::Threading::{
+        GetCurrentProcess, SetPriorityClass, PROCESS_MODE_BACKGROUND_BEGIN,
+    };
+
+    pub fn lower_io_priority() -> ::std::io::Result<()> {
+        // SAFETY: GetCurrentProcess returns a pseudo handle that is always valid for this process
+        if unsafe { SetPriorityClass(GetCurrentProcess(), PROCESS_MODE_BACKGROUND_BEGIN) } == 0 {
+            return Err(::std::io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,239 @@
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::{
+    borrow::Cow,
+    ffi::OsStr,
+    fmt::Write,
+    path::{Path, PathBuf},
+};
+
+///
+/// Whether `path` can only be shown approximately: Unix names that aren't valid UTF-8 and
+/// Windows names with unpaired UTF-16 surrogates. Such files are still copied as they are.
+///
+pub fn is_lossy(path: &Path) -> bool {
+    path.to_str().is_none()
+}
+
+///
+/// A file name for the PreCopy preview: lossy, with a warning badge when the name isn't valid
+/// Unicode so an odd looking name isn't mistaken for a display bug
+///
+pub fn badged_name(name: &OsStr) -> String {
+    let shown = name.to_string_lossy();
+    if is_lossy(Path::new(name)) {
+        format!("{} [!] not valid Unicode, copied as-is", shown)
+    } else {
+        shown.into_owned()
+    }
+}
+
+///
+/// `path` as text, unchanged when it is valid Unicode and otherwise escaped with `escape_all`, so
+/// two different names never render the same
+///
+pub fn display(path: &Path) -> Cow<'_, str> {
+    match path.to_str() {
+        Some(text) => Cow::Borrowed(text),
+        None => Cow::Owned(escape_all(path.as_os_str())),
+    }
+}
+
+///
+/// Escapes `\` as `\\`, newlines as `\n` and `\r`, and whatever isn't valid Unicode as `\xNN`
+/// (one per byte on Unix) or `\u{NNNN}` (one per unpaired surrogate on Windows). This is the
+/// escaping of `sha256sum`, extended to names it can't represent.
+///
+pub fn escape_all(name: &OsStr) -> String {
+    let mut escaped = String::new();
+
+    #[cfg(unix)]
+    {
+        use ::std::os::unix::ffi::OsStrExt;
+        for chunk in name.as_bytes().utf8_chunks() {
+            chunk
+                .valid()
+                .chars()
+                .for_each(|c| push_escaped(&mut escaped, c));
+            for byte in chunk.invalid() {
+                let _ = write!(escaped, "\\x{:02x}", byte);
+            }
+        }
+    }
+    #[cfg(windows)]
+    {
+        use ::std::os::windows::ffi::OsStrExt;
+        for c in char::decode_utf16(name.encode_wide()) {
+            match c {
+                Ok(c) => push_escaped(&mut escaped, c),
+                Err(e) => {
+                    let _ = write!(escaped, "\\u{{{:04x}}}", e.unpaired_surrogate());
+                }
+            }
+        }
+    }
+    #[cfg(not(any(unix, windows)))]
+    name.to_string_lossy()
+        .chars()
+        .for_each(|c| push_escaped(&mut escaped, c));
+
+    escaped
+}
+
+fn push_escaped(escaped: &mut String, c: char) {
+    match c {
+        '\\' => escaped.push_str("\\\\"),
+        '\n' => escaped.push_str("\\n"),
+        '\r' => escaped.push_str("\\r"),
+        c => escaped.push(c),
+    }
+}
+
+///
+/// Serializes a path as `display` renders it, for `#[serde(serialize_with)]` on reports. Plain
+/// serde refuses paths that aren't valid Unicode, which would lose the whole report.
+///
+pub fn serialize_display<S: Serializer>(path: &Path, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&display(path))
+}
+
+///
+/// `serialize_display` for every path of a list
+///
+pub fn serialize_display_all<S: Serializer>(
+    paths: &[PathBuf],
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_seq(paths.iter().map(|path| display(path)))
+}
+
+///
+/// `serialize_display` for an optional path
+///
+pub fn serialize_display_opt<S: Serializer>(
+    path: &Option<PathBuf>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match path {
+        Some(path) => serialize_display(path, serializer),
+        None => serializer.serialize_none(),
+    }
+}
+
+/// The code units a name is made of, bytes on Unix and UTF-16 on Windows
+#[cfg(unix)]
+type Unit = u8;
+#[cfg(not(unix))]
+type Unit = u16;
+
+///
+/// How a path is stored when it has to come back exactly, e.g. in the `--resume` state: as text
+/// when it is valid Unicode, otherwise as its raw code units
+///
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum Stored {
+    Text(String),
+    Raw { raw: Vec<Unit> },
+}
+
+impl From<&Path> for Stored {
+    fn from(path: &Path) -> Self {
+        if let Some(text) = path.to_str() {
+            return Stored::Text(text.to_string());
+        }
+        #[cfg(unix)]
+        let raw = ::std::os::unix::ffi::OsStrExt::as_bytes(path.as_os_str()).to_vec();
+        #[cfg(windows)]
+        let raw = ::std::os::windows::ffi::OsStrExt::encode_wide(path.as_os_str()).collect();
+        #[cfg(not(any(unix, windows)))]
+        let raw = path.to_string_lossy().encode_utf16().collect();
+        Stored::Raw { raw }
+    }
+}
+
+impl From<Stored> for PathBuf {
+    fn from(stored: Stored) -> Self {
+        let raw = match stored {
+            Stored::Text(text) => return PathBuf::from(text),
+            Stored::Raw { raw } => raw,
+        };
+        #[cfg(unix)]
+        return PathBuf::from(
+            <::std::ffi::OsString as ::std::os::unix::ffi::OsStringExt>::from_vec(raw),
+        );
+        #[cfg(windows)]
+        return PathBuf::from(
+            <::std::ffi::OsString as ::std::os::windows::ffi::OsStringExt>::from_wide(&raw),
+        );
+        #[cfg(not(any(unix, windows)))]
+        return PathBuf::from(String::from_utf16_lossy(&raw));
+    }
+}
+
+///
+/// Lossless (de)serialization of a path, for `#[serde(with = "crate::rawpath::lossless")]`
+///
+pub mod lossless {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(path: &Path, serializer: S) -> Result<S::Ok, S::Error> {
+        Stored::from(path).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<PathBuf, D::Error> {
+        Stored::deserialize(deserializer).map(PathBuf::from)
+    }
+}
+
+///
+/// `lossless` for an optional path
+///
+pub mod lossless_opt {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(
+        path: &Option<PathBuf>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        path.as_deref().map(Stored::from).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<PathBuf>, D::Error> {
+        Option::<Stored>::deserialize(deserializer).map(|stored| stored.map(PathBuf::from))
+    }
+}
+
+///
+/// Lossless (de)serialization of a map keyed by paths, as a list of `path` and `value` pairs:
+/// the keys of a TOML table can only be text
+///
+pub mod lossless_keys {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    #[derive(Serialize, Deserialize)]
+    struct Entry<V> {
+        #[serde(with = "super::lossless")]
+        path: PathBuf,
+        value: V,
+    }
+
+    pub fn serialize<S: Serializer, V: Serialize>(
+        map: &BTreeMap<PathBuf, V>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(map.iter().map(|(path, value)| Entry {
+            path: path.clone(),
+            value,
+        }))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>, V: Deserialize<'de>>(
+        deserializer: D,
+    ) -> Result<BTreeMap<PathBuf, V>, D::Error> {
+        Vec::<Entry<V>>::deserialize(deserializer)
+            .map(|entries| entries.into_iter().map(|e| (e.path, e.value)).collect())
+    }
+}
@@ -0,0 +1,205 @@
+use chrono::{DateTime, Local};
+use serde::Serialize;
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    counters::Counts, drive, error::CopyError, i18n::Message, locale::Locale, rawpath,
+    summary::SummaryRow, verify::Verification,
+};
+
+///
+/// Machine-readable outcome of a run, written with `--report`. Meant to be passed on to operators
+/// and ticketing systems, so errors carry both a stable code and a localized message.
+///
+#[derive(Serialize, Debug, Clone)]
+pub struct Report {
+    pub run_id: String,
+    #[serde(serialize_with = "rawpath::serialize_display")]
+    pub source: PathBuf,
+    pub started_at: String,
+    pub finished_at: String,
+    /// `ok`, `partial` when `--keep-going` went past files that could not be copied,
+    /// `cancelled` when the operator stopped the copy with Ctrl+C, `verify-failed` or `failed`
+    pub result: String,
+    pub destinations: Vec<DestinationReport>,
+    pub error: Option<ErrorReport>,
+    /// Every option the run went by, see `Args::effective_options`
+    pub options: BTreeMap<String, String>,
+    /// What the copy engine did, `None` when the run failed before it was through
+    pub counters: Option<Counts>,
+    /// A command that runs this again for the destinations that didn't get through, see
+    /// `rerun::rerun_command`
+    pub rerun: Option<String>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct DestinationReport {
+    #[serde(serialize_with = "rawpath::serialize_display")]
+    pub destination: PathBuf,
+    pub label: Option<String>,
+    /// The `\\?\Volume{GUID}\` path of the drive, which survives drive letter changes
+    pub volume: Option<String>,
+    /// The hardware serial number of the drive, which survives relabeling and reformatting
+    pub serial: Option<String>,
+    pub group: Option<String>,
+    pub bytes: usize,
+    pub duration_secs: f64,
+    pub verified: Option<bool>,
+    pub result: String,
+    /// What verification found to differ from the source, when it found anything
+    pub diff: Option<DiffReport>,
+    /// Something about the destination worth looking into, such as a virus scanner holding up
+    /// the writes
+    pub warning: Option<String>,
+}
+
+impl From<&SummaryRow> for DestinationReport {
+    fn from(row: &SummaryRow) -> Self {
+        Self {
+            destination: row.destination.clone(),
+            label: row.label.clone(),
+            volume: drive::volume_guid(&row.destination),
+            serial: drive::serial_number(&row.destination),
+            group: row.group.clone(),
+            bytes: row.bytes,
+            duration_secs: row.duration.as_secs_f64(),
+            verified: row.verified,
+            result: row.result.clone(),
+            diff: None,
+            warning: row.warning.clone(),
+        }
+    }
+}
+
+///
+/// Files that differ between the source and a destination, relative to the source, so a re-copy
+/// can target only the broken ones
+///
+#[derive(Serialize, Debug, Clone)]
+pub struct DiffReport {
+    #[serde(serialize_with = "rawpath::serialize_display_all")]
+    pub missing: Vec<PathBuf>,
+    #[serde(serialize_with = "rawpath::serialize_display_all")]
+    pub corrupted: Vec<PathBuf>,
+    #[serde(serialize_with = "rawpath::serialize_display_all")]
+    pub size_mismatch: Vec<PathBuf>,
+    /// Under `--verify-metadata times`
+    #[serde(serialize_with = "rawpath::serialize_display_all")]
+    pub time_mismatch: Vec<PathBuf>,
+    /// Under `--verify-metadata perms`
+    #[serde(serialize_with = "rawpath::serialize_display_all")]
+    pub perms_mismatch: Vec<PathBuf>,
+    /// Preserved links pointing elsewhere, under `--verify-metadata links`
+    #[serde(serialize_with = "rawpath::serialize_display_all")]
+    pub target_mismatch: Vec<PathBuf>,
+    /// On the destination but not in the source, doesn't fail the verification
+    #[serde(serialize_with = "rawpath::serialize_display_all")]
+    pub extra: Vec<PathBuf>,
+    /// Failed at first and were copied again successfully by `--repair`
+    #[serde(serialize_with = "rawpath::serialize_display_all")]
+    pub repaired: Vec<PathBuf>,
+}
+
+impl DiffReport {
+    pub fn new(verification: &Verification) -> Option<Self> {
+        if verification.passed()
+            && verification.extra.is_empty()
+            && verification.repaired.is_empty()
+        {
+            return None;
+        }
+        Some(Self {
+            missing: verification.missing.clone(),
+            corrupted: verification.corrupted.clone(),
+            size_mismatch: verification.size_mismatch.clone(),
+            time_mismatch: verification.time_mismatch.clone(),
+            perms_mismatch: verification.perms_mismatch.clone(),
+            target_mismatch: verification.target_mismatch.clone(),
+            extra: verification.extra.clone(),
+            repaired: verification.repaired.clone(),
+        })
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct ErrorReport {
+    /// Stable identifier, see [`ErrorClass::code`](crate::error::ErrorClass::code)
+    pub code: String,
+    /// What happened and what to do about it, in the run's locale
+    pub message: String,
+    /// The operating system's own description of the error
+    pub detail: String,
+    #[serde(serialize_with = "rawpath::serialize_display")]
+    pub path: PathBuf,
+    #[serde(serialize_with = "rawpath::serialize_display_opt")]
+    pub destination: Option<PathBuf>,
+}
+
+impl ErrorReport {
+    pub fn new(error: &CopyError, locale: Locale) -> Self {
+        let class = error.class();
+        Self {
+            code: class.code().to_string(),
+            message: Message::Error(class).text(locale).to_string(),
+            detail: error.error.to_string(),
+            path: error.path.clone(),
+            destination: error.destination.clone(),
+        }
+    }
+}
+
+impl Report {
+    pub fn new(
+        run_id: String,
+        source: PathBuf,
+        started_at: DateTime<Local>,
+        rows: &[SummaryRow],
+        verifications: Option<&[Verification]>,
+        error: Option<ErrorReport>,
+        options: &[(&str, String)],
+    ) -> Self {
+        let result = if error.is_some() {
+            "failed"
+        } else if rows.iter().any(|row| row.result == "cancelled") {
+            "cancelled"
+        } else if rows.iter().any(|row| row.result == "partial") {
+            "partial"
+        } else if rows.iter().any(|row| row.verified == Some(false)) {
+            "verify-failed"
+        } else {
+            "ok"
+        };
+
+        Self {
+            run_id,
+            source,
+            started_at: started_at.to_rfc3339(),
+            finished_at: Local::now().to_rfc3339(),
+            result: result.to_string(),
+            destinations: rows
+                .iter()
+                .map(|row| DestinationReport {
+                    diff: verifications
+                        .and_then(|v| v.iter().find(|v| v.destination == row.destination))
+                        .and_then(DiffReport::new),
+                    ..DestinationReport::from(row)
+                })
+                .collect(),
+            error,
+            options: options
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.clone()))
+                .collect(),
+            counters: None,
+            rerun: None,
+        }
+    }
+
+    pub fn write(&self, path: &Path) -> ::std::io::Result<()> {
+        let json = serde_json::to_string_pretty(self).expect("reports always serialize");
+        ::std::fs::write(path, json + "\n")
+    }
+}
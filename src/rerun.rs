@@ -0,0 +1,126 @@
+use clap::{CommandFactory, Parser};
+use std::{
+    collections::HashSet,
+    ffi::{OsStr, OsString},
+    path::PathBuf,
+};
+
+use crate::Args;
+
+///
+/// The command that runs `argv` again for just the `failed` destinations, with the same options
+/// and `--resume` to pick up from the checkpoint when the options allow it, quoted for pasting
+/// into a shell. The destinations on the command line and the `--group`s are swapped for
+/// `failed`. `None` when nothing failed or `argv` doesn't parse.
+///
+pub fn rerun_command(argv: &[OsString], failed: &[PathBuf]) -> Option<String> {
+    if failed.is_empty() {
+        return None;
+    }
+    let matches = Args::command().try_get_matches_from(argv).ok()?;
+    let mut positional = matches
+        .indices_of("drives")
+        .into_iter()
+        .flatten()
+        .collect::<HashSet<_>>();
+    // With these the first positional argument is a destination too, see
+    // `Args::shift_source_to_drives`
+    if matches.contains_id("source_url")
+        || matches
+            .get_many::<PathBuf>("from")
+            .is_some_and(|mut from| from.next().is_some())
+    {
+        positional.extend(matches.indices_of("copy_from").into_iter().flatten());
+    }
+
+    let mut kept = Vec::new();
+    let mut args = argv.iter().enumerate().skip(1);
+    while let Some((i, arg)) = args.next() {
+        if positional.contains(&i) || arg.to_string_lossy().starts_with("--group=") {
+            continue;
+        }
+        if arg == "--group" {
+            args.next();
+            continue;
+        }
+        kept.push(arg.clone());
+    }
+
+    let program = argv
+        .first()
+        .cloned()
+        .unwrap_or_else(|| OsString::from("decopy"));
+    let command = |resume: bool| {
+        ::std::iter::once(program.clone())
+            .chain(kept.iter().cloned())
+            .chain(resume.then(|| OsString::from("--resume")))
+            .chain(failed.iter().map(|dest| dest.clone().into_os_string()))
+            .collect::<Vec<_>>()
+    };
+    let resumed = !kept.iter().any(|arg| arg == "--resume");
+    // `--resume` conflicts with a few options, such as `--stdin-format`
+    let rerun = [command(resumed), command(false)]
+        .into_iter()
+        .find(|command| Args::try_parse_from(command).is_ok())?;
+    Some(
+        rerun
+            .iter()
+            .map(|arg| quote(arg))
+            .collect::<Vec<_>>()
+            .join(" "),
+    )
+}
+
+///
+/// `arg` the way the shell of this platform takes it back as one argument
+///
+fn quote(arg: &OsStr) -> String {
+    let arg = arg.to_string_lossy();
+    if cfg!(windows) {
+        let wide = arg.encode_utf16().collect::<Vec<_>>();
+        return String::from_utf16_lossy(&quote_windows(&wide));
+    }
+    let plain = |c: char| c.is_ascii_alphanumeric() || "-_./:=,@%+".contains(c);
+    match !arg.is_empty() && arg.chars().all(plain) {
+        true => arg.into_owned(),
+        false => format!("'{}'", arg.replace('\'', r"'\''")),
+    }
+}
+
+///
+/// `arg`, in UTF-16, quoted the way `CommandLineToArgvW` splits it again: backslashes only need
+/// doubling when they end up in front of a quote. Also what `relaunch_elevated` passes on.
+///
+pub(crate) fn quote_windows(arg: &[u16]) -> Vec<u16> {
+    const QUOTE: u16 = b'"' as u16;
+    const BACKSLASH: u16 = b'\\' as u16;
+
+    let needs_quotes = arg.is_empty()
+        || arg
+            .iter()
+            .any(|&c| c == b' ' as u16 || c == b'\t' as u16 || c == QUOTE);
+    if !needs_quotes {
+        return arg.to_vec();
+    }
+
+    let mut quoted = vec![QUOTE];
+    let mut backslashes = 0;
+    for &c in arg {
+        if c == BACKSLASH {
+            backslashes += 1;
+            continue;
+        }
+        let escaped = if c == QUOTE {
+            backslashes * 2 + 1
+        } else {
+            backslashes
+        };
+        quoted.extend(::std::iter::repeat_n(BACKSLASH, escaped));
+        quoted.push(c);
+        backslashes = 0;
+    }
+    // Trailing backslashes sit in front of the closing quote
+    quoted.extend(::std::iter::repeat_n(BACKSLASH, backslashes * 2));
+    quoted.push(QUOTE);
+    quoted
+}
@@ -0,0 +1,245 @@
+use crossterm::event::{Event, KeyCode, KeyEventKind, KeyModifiers};
+use std::{io::Write, path::PathBuf, time::Duration};
+
+use crate::{
+    config::Config,
+    ui::{too_small, EventSource, Line, Theme},
+};
+
+/// How long the setup waits for input before drawing again
+const TICK: Duration = Duration::from_millis(50);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Step {
+    Welcome,
+    Verify,
+    Notifications,
+    Theme,
+    Excludes,
+}
+
+impl Step {
+    fn question(self) -> &'static str {
+        match self {
+            Step::Welcome => "No config file yet. Set up the usual options for this station?",
+            Step::Verify => "Check the destinations against the source after copying?",
+            Step::Notifications => "Ring the bell when an ejected drive is safe to remove?",
+            Step::Theme => "Colors of the full-screen UI?",
+            Step::Excludes => {
+                "Leave anything out of every run? (paths relative to the source, comma separated)"
+            }
+        }
+    }
+
+    fn choices(self) -> &'static [&'static str] {
+        match self {
+            Step::Welcome => &["Set up now", "Skip and use the defaults"],
+            Step::Verify => &[
+                "Don't verify",
+                "Verify every destination",
+                "Verify and copy files that fail again",
+            ],
+            Step::Notifications => &["No", "Yes"],
+            Step::Theme => &["Default colors", "Plain, in the terminal's own colors"],
+            Step::Excludes => &[],
+        }
+    }
+
+    fn next(self) -> Option<Self> {
+        match self {
+            Step::Welcome => Some(Step::Verify),
+            Step::Verify => Some(Step::Notifications),
+            Step::Notifications => Some(Step::Theme),
+            Step::Theme => Some(Step::Excludes),
+            Step::Excludes => None,
+        }
+    }
+
+    fn previous(self) -> Option<Self> {
+        match self {
+            Step::Welcome => None,
+            Step::Verify => Some(Step::Welcome),
+            Step::Notifications => Some(Step::Verify),
+            Step::Theme => Some(Step::Notifications),
+            Step::Excludes => Some(Step::Theme),
+        }
+    }
+}
+
+///
+/// What the caller of `run` should do next
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SetupAction {
+    None,
+    /// Every question is answered, write `Setup::config`
+    Finish,
+    /// The operator wants the defaults, write an empty config so the setup isn't offered again
+    Skip,
+    /// Leave without writing anything, the setup comes up again next time
+    Quit,
+}
+
+///
+/// The short guided setup offered on the first interactive launch, when there is no config file
+/// yet. Like `Ui` it holds no terminal handles: events come in through `handle_event` and frames
+/// go out through `render`.
+///
+pub struct Setup {
+    pub size: (u16, u16),
+    step: Step,
+    /// The highlighted answer of every question with choices, by step
+    answers: [usize; 5],
+    exclude: String,
+}
+
+impl Setup {
+    pub fn new(size: (u16, u16)) -> Self {
+        Self {
+            size,
+            step: Step::Welcome,
+            answers: [0; 5],
+            exclude: String::new(),
+        }
+    }
+
+    ///
+    /// The config the answers so far make up
+    ///
+    pub fn config(&self) -> Config {
+        let verify = self.answers[Step::Verify as usize];
+        Config {
+            verify: Some(verify >= 1),
+            repair: Some(verify == 2),
+            beep: Some(self.answers[Step::Notifications as usize] == 1),
+            theme: Some(self.theme()),
+            exclude: Some(
+                self.exclude
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|path| !path.is_empty())
+                    .map(PathBuf::from)
+                    .collect(),
+            ),
+            ..Config::default()
+        }
+    }
+
+    fn theme(&self) -> Theme {
+        match self.answers[Step::Theme as usize] {
+            1 => Theme::Plain,
+            _ => Theme::Default,
+        }
+    }
+
+    pub fn handle_event(&mut self, event: Event) -> SetupAction {
+        let key = match event {
+            Event::Resize(width, height) => {
+                self.size = (width, height);
+                return SetupAction::None;
+            }
+            Event::Key(key) if key.kind != KeyEventKind::Release => key,
+            _ => return SetupAction::None,
+        };
+        if key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c') {
+            return SetupAction::Quit;
+        }
+
+        let choices = self.step.choices().len();
+        let answer = &mut self.answers[self.step as usize];
+        match (self.step, key.code) {
+            (Step::Welcome, KeyCode::Esc) => SetupAction::Skip,
+            (Step::Welcome, KeyCode::Enter) if *answer == 1 => SetupAction::Skip,
+            (_, KeyCode::Esc) => {
+                self.step = self.step.previous().unwrap_or(self.step);
+                SetupAction::None
+            }
+            (step, KeyCode::Enter) => match step.next() {
+                Some(next) => {
+                    self.step = next;
+                    SetupAction::None
+                }
+                None => SetupAction::Finish,
+            },
+            (Step::Excludes, KeyCode::Backspace) => {
+                self.exclude.pop();
+                SetupAction::None
+            }
+            (Step::Excludes, KeyCode::Char(c)) => {
+                self.exclude.push(c);
+                SetupAction::None
+            }
+            (_, KeyCode::Up | KeyCode::Char('k')) => {
+                *answer = answer.saturating_sub(1);
+                SetupAction::None
+            }
+            (_, KeyCode::Down | KeyCode::Char('j')) => {
+                *answer = (*answer + 1).min(choices.saturating_sub(1));
+                SetupAction::None
+            }
+            _ => SetupAction::None,
+        }
+    }
+
+    ///
+    /// Draws the current question as one full frame, clipped to `size`, in the theme picked so
+    /// far so the choice can be judged right away
+    ///
+    pub fn render(&self, out: &mut impl Write) -> ::std::io::Result<()> {
+        let (width, height) = (self.size.0 as usize, self.size.1 as usize);
+        if width == 0 || height == 0 {
+            return out.flush();
+        }
+        if too_small(out, self.size, self.theme())? {
+            return Ok(());
+        }
+
+        let mut lines = vec![
+            Line::new(format!("decopy setup - {} of 5", self.step as usize + 1)).magenta(),
+            Line::new("─".repeat(width)).dark_grey(),
+            Line::new(self.step.question()),
+            Line::new(""),
+        ];
+        let answer = self.answers[self.step as usize];
+        for (i, choice) in self.step.choices().iter().enumerate() {
+            lines.push(match i == answer {
+                true => Line::new(format!("> {}", choice)).cyan(),
+                false => Line::new(format!("  {}", choice)).dark_grey(),
+            });
+        }
+        let footer = match self.step {
+            Step::Excludes => {
+                lines.push(Line::new(format!("> {}_", self.exclude)).cyan());
+                "Enter to save the config, Esc to go back"
+            }
+            Step::Welcome => "Arrows to choose, Enter to confirm, Esc to skip",
+            _ => "Arrows to choose, Enter to confirm, Esc to go back",
+        };
+
+        lines.resize_with(height - 1, || Line::new(""));
+        lines.push(Line::new(footer));
+        for (row, line) in lines.iter().take(height).enumerate() {
+            line.queue(out, row as u16, width, self.theme())?;
+        }
+        out.flush()
+    }
+}
+
+///
+/// Runs the setup until the operator finishes, skips or quits it
+///
+pub fn run(
+    setup: &mut Setup,
+    events: &mut impl EventSource,
+    out: &mut impl Write,
+) -> ::std::io::Result<SetupAction> {
+    loop {
+        setup.render(out)?;
+        if let Some(event) = events.poll(TICK)? {
+            match setup.handle_event(event) {
+                SetupAction::None => {}
+                action => return Ok(action),
+            }
+        }
+    }
+}
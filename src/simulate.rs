@@ -0,0 +1,51 @@
+use std::io::Write;
+
+use crate::throttle::{LimitRate, Throttle};
+
+///
+/// Stands in for the destination drives (`--simulate-speed`): the source is read as usual, but
+/// nothing is written, every destination takes its data into memory at no more than `rate`. So
+/// the UI can be demoed and recorded, and the progress math tested, without any hardware.
+///
+#[derive(Debug)]
+pub struct Simulation {
+    rate: LimitRate,
+}
+
+impl Simulation {
+    pub fn new(rate: LimitRate) -> Self {
+        Self { rate }
+    }
+
+    pub fn rate(&self) -> LimitRate {
+        self.rate
+    }
+
+    ///
+    /// A drive for one destination, which goes at the full rate whatever the others do
+    ///
+    pub fn drive(&self) -> SimulatedDrive {
+        SimulatedDrive {
+            pace: Throttle::new(Some(self.rate), None),
+        }
+    }
+}
+
+///
+/// A destination of a `Simulation`, which drops what is written to it once it has been paced
+///
+#[derive(Debug)]
+pub struct SimulatedDrive {
+    pace: Throttle,
+}
+
+impl Write for &SimulatedDrive {
+    fn write(&mut self, buf: &[u8]) -> ::std::io::Result<usize> {
+        self.pace.consume(buf.len());
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> ::std::io::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,48 @@
+use std::{fmt, str::FromStr};
+
+///
+/// A byte count given on the command line, e.g. `64MB`, `512k` or `1GiB`. Units are powers of
+/// 1024, matching how sizes are displayed.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ByteSize(pub u64);
+
+impl FromStr for ByteSize {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let split = s
+            .find(|c: char| !c.is_ascii_digit() && c != '.')
+            .unwrap_or(s.len());
+        let (number, unit) = s.split_at(split);
+
+        let number = number
+            .parse::<f64>()
+            .map_err(|_| format!("invalid size `{}`, expected something like `64MB`", s))?;
+        let multiplier = match unit.trim().to_ascii_lowercase().as_str() {
+            "" | "b" => 1,
+            "k" | "kb" | "kib" => 1024,
+            "m" | "mb" | "mib" => 1024u64.pow(2),
+            "g" | "gb" | "gib" => 1024u64.pow(3),
+            "t" | "tb" | "tib" => 1024u64.pow(4),
+            other => return Err(format!("unknown size unit `{}`", other)),
+        };
+
+        Ok(ByteSize((number * multiplier as f64) as u64))
+    }
+}
+
+impl fmt::Display for ByteSize {
+    ///
+    /// The largest unit that divides the size exactly, so the result parses back unchanged
+    ///
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (multiplier, unit) = [(4, "TB"), (3, "GB"), (2, "MB"), (1, "KB")]
+            .into_iter()
+            .map(|(power, unit)| (1024u64.pow(power), unit))
+            .find(|(multiplier, _)| self.0 != 0 && self.0.is_multiple_of(*multiplier))
+            .unwrap_or((1, "B"));
+        write!(f, "{}{}", self.0 / multiplier, unit)
+    }
+}
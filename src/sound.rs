@@ -0,0 +1,247 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt,
+    io::Write,
+    path::{Path, PathBuf},
+    process::Stdio,
+    time::Duration,
+};
+
+/// Terminals run bells rung back to back into one, these are spaced out so they can be counted
+pub const BELL_GAP: Duration = Duration::from_millis(300);
+/// More bells than this are no clearer, just longer
+const MAX_BELLS: u8 = 9;
+
+///
+/// What is played for an event: nothing (`off`), the terminal bell once (`bell`) or a few times
+/// in a row (`bell*3`), or a sound file given by its path
+///
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(try_from = "String", into = "String")]
+pub enum Cue {
+    Off,
+    Bell(u8),
+    File(PathBuf),
+}
+
+impl Cue {
+    ///
+    /// Starts the cue, returning how often the bell is to ring for it. A sound file plays in the
+    /// background, the bell rings once instead when there is no way to play it.
+    ///
+    pub fn start(&self) -> u8 {
+        match self {
+            Cue::Off => 0,
+            Cue::Bell(times) => *times,
+            Cue::File(path) => match play_file(path) {
+                Ok(()) => 0,
+                Err(_) => 1,
+            },
+        }
+    }
+
+    ///
+    /// Plays the cue, ringing the bell on `out` and waiting `BELL_GAP` between rings
+    ///
+    pub fn play(&self, out: &mut impl Write) -> ::std::io::Result<()> {
+        for ring in 0..self.start() {
+            if ring > 0 {
+                ::std::thread::sleep(BELL_GAP);
+            }
+            out.write_all(b"\x07")?;
+            out.flush()?;
+        }
+        Ok(())
+    }
+}
+
+impl TryFrom<String> for Cue {
+    type Error = String;
+
+    fn try_from(name: String) -> Result<Self, Self::Error> {
+        if name.eq_ignore_ascii_case("off") {
+            return Ok(Cue::Off);
+        }
+        if name.eq_ignore_ascii_case("bell") {
+            return Ok(Cue::Bell(1));
+        }
+        if let Some(times) = name.to_ascii_lowercase().strip_prefix("bell*") {
+            return match times.parse() {
+                Ok(times @ 1..=MAX_BELLS) => Ok(Cue::Bell(times)),
+                _ => Err(format!(
+                    "`{}` rings the bell 1 to {} times, not `{}`",
+                    name, MAX_BELLS, times
+                )),
+            };
+        }
+        match name.is_empty() {
+            true => Err("expected off, bell, bell*N or the path of a sound file".to_string()),
+            false => Ok(Cue::File(PathBuf::from(name))),
+        }
+    }
+}
+
+impl From<Cue> for String {
+    fn from(cue: Cue) -> Self {
+        cue.to_string()
+    }
+}
+
+impl fmt::Display for Cue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Cue::Off => write!(f, "off"),
+            Cue::Bell(1) => write!(f, "bell"),
+            Cue::Bell(times) => write!(f, "bell*{}", times),
+            Cue::File(path) => write!(f, "{}", path.display()),
+        }
+    }
+}
+
+///
+/// Something in a run worth hearing about when the screen is out of sight
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SoundEvent {
+    /// A drive is safe to pull, after `--eject`
+    DriveDone,
+    /// A destination failed verification
+    VerifyFailed,
+    /// The run is through
+    BatchComplete,
+}
+
+///
+/// The cue of every event, which the `[sounds]` table of the config file sets. Nothing is played
+/// without `--beep`.
+///
+/// ```toml
+/// [sounds]
+/// drive_done = "bell"
+/// verify_failed = "bell*3"
+/// batch_complete = "/usr/share/sounds/freedesktop/stereo/complete.oga"
+/// ```
+///
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Sounds {
+    pub drive_done: Cue,
+    pub verify_failed: Cue,
+    pub batch_complete: Cue,
+}
+
+impl Default for Sounds {
+    fn default() -> Self {
+        Self {
+            drive_done: Cue::Bell(1),
+            verify_failed: Cue::Off,
+            batch_complete: Cue::Off,
+        }
+    }
+}
+
+impl Sounds {
+    pub fn cue(&self, event: SoundEvent) -> &Cue {
+        match event {
+            SoundEvent::DriveDone => &self.drive_done,
+            SoundEvent::VerifyFailed => &self.verify_failed,
+            SoundEvent::BatchComplete => &self.batch_complete,
+        }
+    }
+
+    ///
+    /// The cues as `drive_done=bell,verify_failed=off,batch_complete=off`, for reports
+    ///
+    pub fn describe(&self) -> String {
+        format!(
+            "drive_done={},verify_failed={},batch_complete={}",
+            self.drive_done, self.verify_failed, self.batch_complete
+        )
+    }
+}
+
+///
+/// Starts playing the sound file `path` with the platform's player, without waiting for it
+///
+pub fn play_file(path: &Path) -> ::std::io::Result<()> {
+    let mut not_found = ::std::io::Error::from(::std::io::ErrorKind::NotFound);
+    for mut player in imp::players(path) {
+        let spawned = player
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn();
+        match spawned {
+            Ok(mut child) => {
+                // Reaped in the background, so it doesn't linger until the run is over
+                ::std::thread::spawn(move || child.wait());
+                return Ok(());
+            }
+            Err(e) if e.kind() == ::std::io::ErrorKind::NotFound => not_found = e,
+            Err(e) => return Err(e),
+        }
+    }
+    Err(not_found)
+}
+
+#[cfg(target_os = "linux")]
+mod imp {
+    use std::{path::Path, process::Command};
+
+    ///
+    /// The players to try in turn: PulseAudio and PipeWire desktops have paplay, plain ALSA only
+    /// aplay
+    ///
+    pub fn players(path: &Path) -> Vec<Command> {
+        ["paplay", "aplay"]
+            .into_iter()
+            .map(|program| {
+                let mut command = Command::new(program);
+                command.arg(path);
+                command
+            })
+            .collect()
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod imp {
+    use std::{path::Path, process::Command};
+
+    pub fn players(path: &Path) -> Vec<Command> {
+        let mut command = Command::new("afplay");
+        command.arg(path);
+        vec![command]
+    }
+}
+
+#[cfg(windows)]
+mod imp {
+    use std::{path::Path, process::Command};
+
+    pub fn players(path: &Path) -> Vec<Command> {
+        // SoundPlayer only does WAV, which is what Windows ships its own sounds as
+        let mut command = Command::new("powershell");
+        command.args([
+            "-NoProfile",
+            "-NonInteractive",
+            "-Command",
+            &format!(
+                "(New-Object Media.SoundPlayer '{}').PlaySync()",
+                path.display().to_string().replace('\'', "''")
+            ),
+        ]);
+        vec![command]
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+mod imp {
+    use std::{path::Path, process::Command};
+
+    pub fn players(path: &Path) -> Vec<Command> {
+        let mut command = Command::new("play");
+        command.arg(path);
+        vec![command]
+    }
+}
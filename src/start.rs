@@ -0,0 +1,147 @@
+use chrono::{DateTime, Local, NaiveTime, TimeDelta};
+use serde::{Deserialize, Serialize, Serializer};
+use std::{fmt, str::FromStr, time::Duration};
+
+pub const MAX_DELAY: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+///
+/// A wait given on the command line, e.g. `10m`, `1h30m`, `45s` or `500ms`. A bare number is
+/// seconds. Anything over `MAX_DELAY` is refused, that is more likely a typo than a plan.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Delay(pub Duration);
+
+impl FromStr for Delay {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let invalid = || {
+            format!(
+                "invalid delay `{}`, expected something like `10m` or `1h30m`",
+                s
+            )
+        };
+        // In milliseconds
+        let mut total = 0u64;
+        let mut rest = s;
+        if let Ok(secs) = s.parse::<u64>() {
+            total = secs.checked_mul(1000).ok_or_else(invalid)?;
+            rest = "";
+        }
+        while !rest.is_empty() {
+            let split = rest
+                .find(|c: char| !c.is_ascii_digit())
+                .ok_or_else(invalid)?;
+            let (number, unit) = rest.split_at(split);
+            let number = number.parse::<u64>().map_err(|_| invalid())?;
+            let unit_len = unit
+                .find(|c: char| c.is_ascii_digit())
+                .unwrap_or(unit.len());
+            let multiplier = match &unit[..unit_len] {
+                "ms" => 1,
+                "s" => 1000,
+                "m" => 60 * 1000,
+                "h" => 60 * 60 * 1000,
+                "d" => 24 * 60 * 60 * 1000,
+                _ => return Err(invalid()),
+            };
+            total = number
+                .checked_mul(multiplier)
+                .and_then(|secs| total.checked_add(secs))
+                .ok_or_else(invalid)?;
+            rest = &unit[unit_len..];
+        }
+        let delay = Duration::from_millis(total);
+        if delay > MAX_DELAY {
+            return Err(format!("delay `{}` is more than 30 days", s));
+        }
+        Ok(Delay(delay))
+    }
+}
+
+impl fmt::Display for Delay {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut rest = self.0.as_millis();
+        if rest == 0 {
+            return write!(f, "0s");
+        }
+        for (unit, unit_millis) in [
+            ("d", 24 * 60 * 60 * 1000),
+            ("h", 60 * 60 * 1000),
+            ("m", 60 * 1000),
+            ("s", 1000),
+            ("ms", 1),
+        ] {
+            if rest >= unit_millis {
+                write!(f, "{}{}", rest / unit_millis, unit)?;
+                rest %= unit_millis;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Serialize for Delay {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl TryFrom<String> for Delay {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+///
+/// A time of day given on the command line as `HH:MM`
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StartAt(pub NaiveTime);
+
+impl FromStr for StartAt {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        NaiveTime::parse_from_str(s.trim(), "%H:%M")
+            .map(StartAt)
+            .map_err(|_| format!("invalid time `{}`, expected `HH:MM`", s.trim()))
+    }
+}
+
+impl fmt::Display for StartAt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0.format("%H:%M"))
+    }
+}
+
+///
+/// When the copy should begin, `None` to begin right away. A `start_at` that already passed today
+/// means tomorrow.
+///
+pub fn start_time(start_at: Option<StartAt>, delay: Option<Delay>) -> Option<DateTime<Local>> {
+    let now = Local::now();
+    if let Some(Delay(delay)) = delay {
+        return Some(now + TimeDelta::from_std(delay.min(MAX_DELAY)).unwrap_or_default());
+    }
+
+    let StartAt(time) = start_at?;
+    let mut date = now.date_naive();
+    if time <= now.time() {
+        date = date.succ_opt()?;
+    }
+    // A time repeated by a DST change starts the first time round, one skipped starts right away
+    date.and_time(time).and_local_timezone(Local).earliest()
+}
+
+///
+/// What is left until `start`, as `HH:MM:SS`
+///
+pub fn countdown(start: DateTime<Local>) -> String {
+    let secs = (start - Local::now()).num_seconds().max(0);
+    format!("{:02}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+}
@@ -0,0 +1,158 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+/// How often the run state is written to disk while files are being copied
+const CHECKPOINT_INTERVAL: Duration = Duration::from_secs(1);
+
+///
+/// Progress of a deployment, checkpointed to disk so an interrupted run can be picked up with
+/// `--resume` exactly where it stopped
+///
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct RunState {
+    #[serde(with = "crate::rawpath::lossless")]
+    pub source: PathBuf,
+    /// Keyed by the destinations as they are, so one that isn't valid Unicode still finds its own
+    #[serde(default, with = "crate::rawpath::lossless_keys")]
+    pub destinations: BTreeMap<PathBuf, DestinationState>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct DestinationState {
+    /// The last file (relative to the source, in plan order) that was fully written
+    #[serde(default, with = "crate::rawpath::lossless_opt")]
+    pub last_completed: Option<PathBuf>,
+    pub done: bool,
+    /// The file after `last_completed`, if writing it had started
+    #[serde(default)]
+    pub partial: Option<PartialFile>,
+}
+
+///
+/// A file that was cut off while being written, so `--resume` can continue it rather than
+/// write it again from the start
+///
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct PartialFile {
+    #[serde(with = "crate::rawpath::lossless")]
+    pub file: PathBuf,
+    /// Size of the source file, the offset is only trusted while it is unchanged
+    pub size: usize,
+    /// Bytes that were on the destination at the last checkpoint
+    pub bytes: usize,
+}
+
+impl RunState {
+    pub fn load(path: &Path) -> ::std::io::Result<Self> {
+        let contents = ::std::fs::read_to_string(path)?;
+        toml::from_str(&contents)
+            .map_err(|e| ::std::io::Error::new(::std::io::ErrorKind::InvalidData, e))
+    }
+
+    ///
+    /// Writes the state next to `path` and renames it into place, so a crash mid-write never
+    /// leaves a truncated state file behind
+    ///
+    pub fn save(&self, path: &Path) -> ::std::io::Result<()> {
+        let contents = toml::to_string(self)
+            .map_err(|e| ::std::io::Error::new(::std::io::ErrorKind::InvalidData, e))?;
+        let mut tmp = path.as_os_str().to_owned();
+        tmp.push(".tmp");
+        ::std::fs::write(&tmp, contents)?;
+        ::std::fs::rename(&tmp, path)
+    }
+
+    pub fn destination(&self, dest: &Path) -> Option<&DestinationState> {
+        self.destinations.get(dest)
+    }
+
+    pub fn destination_mut(&mut self, dest: &Path) -> &mut DestinationState {
+        self.destinations.entry(dest.to_path_buf()).or_default()
+    }
+}
+
+///
+/// Keeps a `RunState` in sync with the copy loop, writing it out at most once per
+/// `CHECKPOINT_INTERVAL` (and always when a destination finishes)
+///
+pub struct Checkpoint {
+    /// `None` keeps the state in memory only
+    path: Option<PathBuf>,
+    pub state: RunState,
+    last_saved: Instant,
+}
+
+impl Checkpoint {
+    ///
+    /// Starts checkpointing to `path`. When `resume` is set and `path` holds the state of a run
+    /// with the same source, that state is picked up instead of starting fresh. Without a
+    /// `path` nothing is picked up or written.
+    ///
+    pub fn new(path: Option<PathBuf>, source: &Path, resume: bool) -> Self {
+        let state = match path.as_deref().map(RunState::load) {
+            Some(Ok(state)) if resume && state.source == source => state,
+            _ => RunState {
+                source: source.to_path_buf(),
+                ..RunState::default()
+            },
+        };
+
+        Self {
+            path,
+            state,
+            last_saved: Instant::now(),
+        }
+    }
+
+    pub fn file_started(&mut self, dest: &Path, file: &Path, size: usize, offset: usize) {
+        self.state.destination_mut(dest).partial = Some(PartialFile {
+            file: file.to_path_buf(),
+            size,
+            bytes: offset,
+        });
+    }
+
+    pub fn file_progress(&mut self, dest: &Path, bytes: usize) {
+        if let Some(partial) = self.state.destination_mut(dest).partial.as_mut() {
+            partial.bytes = bytes;
+        }
+        if self.last_saved.elapsed() >= CHECKPOINT_INTERVAL {
+            self.save();
+        }
+    }
+
+    pub fn file_completed(&mut self, dest: &Path, file: &Path) {
+        let state = self.state.destination_mut(dest);
+        state.last_completed = Some(file.to_path_buf());
+        state.partial = None;
+        if self.last_saved.elapsed() >= CHECKPOINT_INTERVAL {
+            self.save();
+        }
+    }
+
+    pub fn destination_done(&mut self, dest: &Path) {
+        self.state.destination_mut(dest).done = true;
+        self.save();
+    }
+
+    ///
+    /// The run went through, so there is nothing left to resume
+    ///
+    pub fn finish(self) {
+        if let Some(path) = &self.path {
+            let _ = ::std::fs::remove_file(path);
+        }
+    }
+
+    pub fn save(&mut self) {
+        // Checkpointing is best effort, a failed write shouldn't take the deployment down with it
+        if let Some(path) = &self.path {
+            let _ = self.state.save(path);
+        }
+        self.last_saved = Instant::now();
+    }
+}
@@ -0,0 +1,90 @@
+use chrono::{DateTime, Local};
+use std::{
+    fs::OpenOptions,
+    io::Write,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+const HEADER: &str =
+    "timestamp,profile,destination,label,bytes,duration_secs,verified,result,group";
+
+///
+/// One destination of one run, as appended to the `--summary-csv` spreadsheet
+///
+#[derive(Debug, Clone)]
+pub struct SummaryRow {
+    pub timestamp: DateTime<Local>,
+    pub profile: String,
+    pub destination: PathBuf,
+    pub label: Option<String>,
+    pub bytes: usize,
+    pub duration: Duration,
+    /// `None` when the run wasn't verified
+    pub verified: Option<bool>,
+    pub result: String,
+    pub group: Option<String>,
+    /// Something about the destination worth looking into, which the CSV leaves out
+    pub warning: Option<String>,
+}
+
+impl SummaryRow {
+    fn to_csv(&self) -> String {
+        [
+            self.timestamp.format("%Y-%m-%d %H:%M:%S").to_string(),
+            self.profile.clone(),
+            self.destination.display().to_string(),
+            self.label.clone().unwrap_or_default(),
+            self.bytes.to_string(),
+            format!("{:.2}", self.duration.as_secs_f64()),
+            match self.verified {
+                Some(true) => "yes",
+                Some(false) => "no",
+                None => "",
+            }
+            .to_string(),
+            self.result.clone(),
+            self.group.clone().unwrap_or_default(),
+        ]
+        .iter()
+        .map(|field| escape(field))
+        .collect::<Vec<_>>()
+        .join(",")
+    }
+}
+
+///
+/// Identifies a run in summaries and on drive labels, e.g. `20261015-140312-3f2a`
+///
+pub fn run_id(started: DateTime<Local>) -> String {
+    format!(
+        "{}-{:04x}",
+        started.format("%Y%m%d-%H%M%S"),
+        ::std::process::id() & 0xffff
+    )
+}
+
+///
+/// Appends `rows` to the CSV at `path`, writing the header first if the file is new or empty
+///
+pub fn append_summary_csv(path: &Path, rows: &[SummaryRow]) -> ::std::io::Result<()> {
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    let mut out = String::new();
+    if file.metadata()?.len() == 0 {
+        out.push_str(HEADER);
+        out.push('\n');
+    }
+    for row in rows {
+        out.push_str(&row.to_csv());
+        out.push('\n');
+    }
+    file.write_all(out.as_bytes())
+}
+
+fn escape(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
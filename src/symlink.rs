@@ -0,0 +1,127 @@
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::path::{Component, Path, PathBuf};
+
+///
+/// What a run does with the symlinks of the source (`--symlinks`)
+///
+#[derive(ValueEnum, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum SymlinkPolicy {
+    /// Copy what they point to in their place
+    #[default]
+    Follow,
+    /// Recreate them as links on the destination
+    Preserve,
+    /// Leave them out
+    Skip,
+}
+
+///
+/// A symlink of the source as `--symlinks preserve` recreates it
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Symlink {
+    /// Where it points: relative to the directory it is in when that is inside the source, so
+    /// it resolves the same on the destination, otherwise as it was
+    pub target: PathBuf,
+    /// Whether it points to a directory, Windows creates those differently
+    pub dir: bool,
+    /// It points outside the source, so it may not resolve on the destination
+    pub outside: bool,
+}
+
+impl Symlink {
+    ///
+    /// Reads the link `path` of the tree at `root`, relative to it
+    ///
+    pub fn read(root: &Path, path: &Path) -> ::std::io::Result<Self> {
+        let link = root.join(path);
+        let target = ::std::fs::read_link(&link)?;
+        // A link that points nowhere is recreated as it is
+        let dir = link.metadata().is_ok_and(|metadata| metadata.is_dir());
+
+        let root = normalized(&::std::path::absolute(root)?);
+        let parent = path.parent().unwrap_or(Path::new(""));
+        let resolved = normalized(&root.join(parent).join(&target));
+        match resolved.strip_prefix(&root) {
+            Ok(inside) => Ok(Self {
+                target: relative_to(inside, parent),
+                dir,
+                outside: false,
+            }),
+            Err(_) => Ok(Self {
+                target,
+                dir,
+                outside: true,
+            }),
+        }
+    }
+
+    ///
+    /// Creates the link at `at`, in place of whatever is there
+    ///
+    pub fn create(&self, at: &Path) -> ::std::io::Result<()> {
+        if self.exists_at(at) {
+            return Ok(());
+        }
+        match at.symlink_metadata() {
+            Ok(metadata) if metadata.is_dir() => ::std::fs::remove_dir_all(at)?,
+            Ok(_) => ::std::fs::remove_file(at)?,
+            Err(_) => {}
+        }
+        #[cfg(unix)]
+        return ::std::os::unix::fs::symlink(&self.target, at);
+        #[cfg(windows)]
+        return match self.dir {
+            true => ::std::os::windows::fs::symlink_dir(&self.target, at),
+            false => ::std::os::windows::fs::symlink_file(&self.target, at),
+        };
+    }
+
+    ///
+    /// Whether `at` is this link
+    ///
+    pub fn exists_at(&self, at: &Path) -> bool {
+        ::std::fs::read_link(at).is_ok_and(|target| target == self.target)
+    }
+}
+
+///
+/// `path` with the `.` and `..` in it worked out, without going to the file system
+///
+fn normalized(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+///
+/// `path` as seen from the directory `from`, both relative to the same root
+///
+fn relative_to(path: &Path, from: &Path) -> PathBuf {
+    let common = path
+        .components()
+        .zip(from.components())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let mut relative = PathBuf::new();
+    for _ in from.components().skip(common) {
+        relative.push("..");
+    }
+    for component in path.components().skip(common) {
+        relative.push(component);
+    }
+    match relative.as_os_str().is_empty() {
+        true => PathBuf::from("."),
+        false => relative,
+    }
+}
@@ -0,0 +1,259 @@
+use clap::ValueEnum;
+use std::{
+    io::{ErrorKind, Read},
+    path::{Component, Path, PathBuf},
+};
+
+use crate::{error::CopyError, fanout::Broadcast, walk::Exclusions};
+
+/// Size of a tar header and of the blocks file contents are padded to
+const BLOCK: usize = 512;
+
+/// Largest GNU long name or pax extended header read, their size comes from the stream itself
+const MAX_RECORDS: usize = 1024 * 1024;
+
+///
+/// How a `-` source is read from stdin (`--stdin-format`)
+///
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StdinFormat {
+    /// A tar stream, e.g. from `docker export` or `tar -c`, in ustar, GNU or pax format
+    Tar,
+}
+
+///
+/// Unpacks the tar stream `stream` for `copy_fan_out`. Regular files and directories are
+/// written, anything else (links, devices, fifos) is skipped, as are paths under `excluded`.
+/// Entries with absolute paths or `..` in them are refused rather than written outside the
+/// destination.
+///
+pub(crate) fn read_tar(
+    mut stream: impl Read,
+    excluded: &[PathBuf],
+    broadcast: &Broadcast,
+) -> Result<(), CopyError> {
+    let excluded = Exclusions::new(excluded);
+    let stdin = Path::new("-");
+    let fail = |e: ::std::io::Error| CopyError::new(stdin, None, e);
+    let mut long_name = None;
+    let mut pax_path = None;
+    let mut pax_size = None;
+
+    loop {
+        let mut header = [0; BLOCK];
+        if !read_block(&mut stream, &mut header).map_err(fail)? || header.iter().all(|b| *b == 0) {
+            return Ok(());
+        }
+        if Some(checksum(&header)) != number(&header[148..156]) {
+            return Err(fail(invalid("not a tar stream, or a corrupted one")));
+        }
+        let size = number(&header[124..136])
+            .ok_or_else(|| fail(invalid("a tar entry has an unreadable size")))?
+            as usize;
+        // pax headers give the size of files too big for the header field
+        let size = match header[156] {
+            b'L' | b'x' | b'g' => size,
+            _ => pax_size.take().unwrap_or(size),
+        };
+        let padding = (BLOCK - size % BLOCK) % BLOCK;
+
+        let name = match header[156] {
+            // GNU long name and pax extended header, both apply to the next entry
+            b'L' => {
+                long_name = Some(trim_nul(
+                    &read_data(&mut stream, size, padding).map_err(fail)?,
+                ));
+                continue;
+            }
+            b'x' => {
+                let records = read_data(&mut stream, size, padding).map_err(fail)?;
+                pax_path = pax_value(&records, "path");
+                pax_size = pax_value(&records, "size")
+                    .and_then(|size| String::from_utf8(size).ok()?.parse().ok());
+                continue;
+            }
+            _ => match pax_path.take().or(long_name.take()) {
+                Some(name) => name,
+                None => ustar_name(&header),
+            },
+        };
+
+        let path =
+            relative_path(&name).map_err(|e| CopyError::new(&path_from_bytes(&name), None, e))?;
+        let wanted = !path.as_os_str().is_empty() && !excluded.covers(&path);
+        let carry_on = match header[156] {
+            b'5' if wanted => broadcast.dir(path),
+            b'0' | b'7' | 0 if wanted => {
+                let parent_ok = match path.parent().filter(|p| !p.as_os_str().is_empty()) {
+                    Some(parent) => broadcast.dir(parent.to_path_buf()),
+                    None => true,
+                };
+                let mut contents = (&mut stream).take(size as u64);
+                let reader = &mut contents;
+                let carry_on =
+                    parent_ok && broadcast.file(path.clone(), size, None, move || Ok(reader))?;
+                ::std::io::copy(&mut contents, &mut ::std::io::sink()).map_err(fail)?;
+                if contents.limit() > 0 {
+                    return Err(CopyError::new(
+                        &path,
+                        None,
+                        ::std::io::Error::new(
+                            ErrorKind::UnexpectedEof,
+                            "the tar stream ended in the middle of this file",
+                        ),
+                    ));
+                }
+                skip(&mut stream, padding).map_err(fail)?;
+                carry_on
+            }
+            _ => {
+                skip(&mut stream, size + padding).map_err(fail)?;
+                true
+            }
+        };
+        if !carry_on {
+            return Ok(());
+        }
+    }
+}
+
+///
+/// Reads one header block, returning `false` at the end of the stream
+///
+fn read_block(stream: &mut impl Read, block: &mut [u8; BLOCK]) -> ::std::io::Result<bool> {
+    let mut filled = 0;
+    while filled < BLOCK {
+        match stream.read(&mut block[filled..]) {
+            Ok(0) if filled == 0 => return Ok(false),
+            Ok(0) => return Err(invalid("the tar stream ended in the middle of a header")),
+            Ok(read) => filled += read,
+            Err(e) if e.kind() == ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(true)
+}
+
+fn read_data(stream: &mut impl Read, size: usize, padding: usize) -> ::std::io::Result<Vec<u8>> {
+    if size > MAX_RECORDS {
+        return Err(invalid(
+            "a tar entry has a long name or pax header over 1 MiB",
+        ));
+    }
+    let mut data = vec![0; size];
+    stream.read_exact(&mut data)?;
+    skip(stream, padding)?;
+    Ok(data)
+}
+
+fn skip(stream: &mut impl Read, bytes: usize) -> ::std::io::Result<()> {
+    let skipped = ::std::io::copy(&mut stream.take(bytes as u64), &mut ::std::io::sink())?;
+    match skipped as usize == bytes {
+        true => Ok(()),
+        false => Err(ErrorKind::UnexpectedEof.into()),
+    }
+}
+
+fn invalid(message: &str) -> ::std::io::Error {
+    ::std::io::Error::new(ErrorKind::InvalidData, message)
+}
+
+///
+/// The header's checksum: the sum of its bytes, with the checksum field itself counted as spaces
+///
+fn checksum(header: &[u8; BLOCK]) -> u64 {
+    header
+        .iter()
+        .enumerate()
+        .map(|(i, byte)| match i {
+            148..156 => b' ' as u64,
+            _ => *byte as u64,
+        })
+        .sum()
+}
+
+///
+/// A numeric header field: octal text, or big-endian binary behind a set high bit (GNU, for
+/// files over 8 GB)
+///
+fn number(field: &[u8]) -> Option<u64> {
+    if field.first().is_some_and(|byte| byte & 0x80 != 0) {
+        return field[1..]
+            .iter()
+            .try_fold(u64::from(field[0] & 0x7f), |value, byte| {
+                value.checked_mul(256)?.checked_add(u64::from(*byte))
+            });
+    }
+    let text = ::std::str::from_utf8(field).ok()?;
+    let text = text.trim_matches(|c: char| c == '\0' || c == ' ');
+    match text.is_empty() {
+        true => Some(0),
+        false => u64::from_str_radix(text, 8).ok(),
+    }
+}
+
+///
+/// The name of a plain header, with the ustar prefix in front when there is one
+///
+fn ustar_name(header: &[u8; BLOCK]) -> Vec<u8> {
+    let name = trim_nul(&header[0..100]);
+    let prefix = trim_nul(&header[345..500]);
+    if &header[257..262] != b"ustar" || prefix.is_empty() {
+        return name;
+    }
+    [prefix, b"/".to_vec(), name].concat()
+}
+
+fn trim_nul(field: &[u8]) -> Vec<u8> {
+    let end = field.iter().position(|b| *b == 0).unwrap_or(field.len());
+    field[..end].to_vec()
+}
+
+///
+/// The value of `key` in pax extended header records (`<length> <key>=<value>\n`)
+///
+fn pax_value(records: &[u8], key: &str) -> Option<Vec<u8>> {
+    let mut rest = records;
+    while !rest.is_empty() {
+        let space = rest.iter().position(|b| *b == b' ')?;
+        let length = ::std::str::from_utf8(&rest[..space])
+            .ok()?
+            .parse::<usize>()
+            .ok()?;
+        let record = rest.get(space + 1..length)?.strip_suffix(b"\n")?;
+        if let Some(value) = record
+            .strip_prefix(key.as_bytes())
+            .and_then(|value| value.strip_prefix(b"="))
+        {
+            return Some(value.to_vec());
+        }
+        rest = &rest[length..];
+    }
+    None
+}
+
+///
+/// `name` as a path relative to the destination, refusing anything that would leave it
+///
+fn relative_path(name: &[u8]) -> ::std::io::Result<PathBuf> {
+    let mut path = PathBuf::new();
+    for component in path_from_bytes(name).components() {
+        match component {
+            Component::Normal(part) => path.push(part),
+            Component::CurDir => {}
+            Component::ParentDir | Component::RootDir | Component::Prefix(_) => {
+                return Err(invalid(
+                    "a tar entry points outside the destination, refusing to unpack it",
+                ));
+            }
+        }
+    }
+    Ok(path)
+}
+
+fn path_from_bytes(name: &[u8]) -> PathBuf {
+    #[cfg(unix)]
+    return PathBuf::from(<::std::ffi::OsStr as ::std::os::unix::ffi::OsStrExt>::from_bytes(name));
+    #[cfg(not(unix))]
+    return PathBuf::from(String::from_utf8_lossy(name).into_owned());
+}
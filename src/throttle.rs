@@ -0,0 +1,225 @@
+use chrono::{Local, NaiveTime};
+use serde::{Deserialize, Serialize, Serializer};
+use std::{
+    fmt,
+    str::FromStr,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use crate::size::ByteSize;
+
+///
+/// Copy speed limits by time of day, e.g. `09:00-17:00=10MB,else=unlimited` (`--limit-schedule`).
+/// Limits are per second. The first window containing the current time wins, `else` covers the
+/// rest of the day and defaults to unlimited. Windows may wrap past midnight, e.g. `22:00-06:00`.
+///
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct LimitSchedule {
+    windows: Vec<(NaiveTime, NaiveTime, Option<u64>)>,
+    otherwise: Option<u64>,
+}
+
+impl LimitSchedule {
+    ///
+    /// Bytes per second allowed at `time`, `None` when unlimited
+    ///
+    pub fn limit_at(&self, time: NaiveTime) -> Option<u64> {
+        self.windows
+            .iter()
+            .find(|(start, end, _)| {
+                if start <= end {
+                    *start <= time && time < *end
+                } else {
+                    time >= *start || time < *end
+                }
+            })
+            .map_or(self.otherwise, |(_, _, limit)| *limit)
+    }
+}
+
+impl FromStr for LimitSchedule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut schedule = LimitSchedule {
+            windows: Vec::new(),
+            otherwise: None,
+        };
+        for rule in s.split(',').map(str::trim).filter(|rule| !rule.is_empty()) {
+            let (when, limit) = rule.split_once('=').ok_or_else(|| {
+                format!(
+                    "invalid limit `{}`, expected something like `09:00-17:00=10MB`",
+                    rule
+                )
+            })?;
+            let limit = parse_limit(limit)?;
+            if when.trim().eq_ignore_ascii_case("else") {
+                schedule.otherwise = limit;
+                continue;
+            }
+
+            let (start, end) = when
+                .split_once('-')
+                .ok_or_else(|| format!("invalid time window `{}`, expected `HH:MM-HH:MM`", when))?;
+            schedule
+                .windows
+                .push((parse_time(start)?, parse_time(end)?, limit));
+        }
+        Ok(schedule)
+    }
+}
+
+impl Serialize for LimitSchedule {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl TryFrom<String> for LimitSchedule {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl fmt::Display for LimitSchedule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let limit =
+            |limit: Option<u64>| limit.map_or("unlimited".to_string(), |l| ByteSize(l).to_string());
+        for (start, end, window_limit) in &self.windows {
+            write!(
+                f,
+                "{}-{}={},",
+                start.format("%H:%M"),
+                end.format("%H:%M"),
+                limit(*window_limit)
+            )?;
+        }
+        write!(f, "else={}", limit(self.otherwise))
+    }
+}
+
+///
+/// A fixed copy speed limit, e.g. `20MB/s` (`--limit-rate`)
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct LimitRate(pub u64);
+
+impl FromStr for LimitRate {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_limit(s)?.map(LimitRate).ok_or_else(|| {
+            "a rate of `unlimited` is the default, leave the limit out instead".to_string()
+        })
+    }
+}
+
+impl Serialize for LimitRate {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl TryFrom<String> for LimitRate {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl fmt::Display for LimitRate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/s", ByteSize(self.0))
+    }
+}
+
+fn parse_time(s: &str) -> Result<NaiveTime, String> {
+    NaiveTime::parse_from_str(s.trim(), "%H:%M")
+        .map_err(|_| format!("invalid time `{}`, expected `HH:MM`", s.trim()))
+}
+
+fn parse_limit(s: &str) -> Result<Option<u64>, String> {
+    let s = s.trim();
+    if s.eq_ignore_ascii_case("unlimited") {
+        return Ok(None);
+    }
+    let size = s.strip_suffix("/s").unwrap_or(s).parse::<ByteSize>()?;
+    if size.0 == 0 {
+        return Err(format!("limit `{}` would never copy anything", s));
+    }
+    Ok(Some(size.0))
+}
+
+///
+/// Paces reads from the source to the lower of `--limit-rate` and the limit the schedule sets
+/// for the current time, with a token bucket. Shared by every copy thread, so the limit covers
+/// the whole run rather than each destination.
+///
+#[derive(Debug)]
+pub struct Throttle {
+    rate: Option<LimitRate>,
+    schedule: Option<LimitSchedule>,
+    /// When the bucket was last topped up and the bytes it holds, negative while in debt
+    bucket: Mutex<(Instant, f64)>,
+}
+
+impl Throttle {
+    pub fn new(rate: Option<LimitRate>, schedule: Option<LimitSchedule>) -> Self {
+        Self {
+            rate,
+            schedule,
+            bucket: Mutex::new((Instant::now(), 0.)),
+        }
+    }
+
+    pub fn rate(&self) -> Option<LimitRate> {
+        self.rate
+    }
+
+    pub fn schedule(&self) -> Option<&LimitSchedule> {
+        self.schedule.as_ref()
+    }
+
+    ///
+    /// Bytes per second allowed right now, `None` when unlimited
+    ///
+    pub fn limit_now(&self) -> Option<u64> {
+        let scheduled = self
+            .schedule
+            .as_ref()
+            .and_then(|schedule| schedule.limit_at(Local::now().time()));
+        match (self.rate.map(|rate| rate.0), scheduled) {
+            (Some(rate), Some(scheduled)) => Some(rate.min(scheduled)),
+            (rate, scheduled) => rate.or(scheduled),
+        }
+    }
+
+    ///
+    /// Accounts for `bytes` just read, sleeping as long as needed to stay within the current
+    /// limit
+    ///
+    pub fn consume(&self, bytes: usize) {
+        let mut bucket = self.bucket.lock().unwrap();
+        let Some(limit) = self.limit_now() else {
+            *bucket = (Instant::now(), 0.);
+            return;
+        };
+
+        let limit = limit as f64;
+        let (topped_up, tokens) = &mut *bucket;
+        // At most a second's worth is saved up, so idle time doesn't turn into a long burst
+        *tokens = (*tokens + topped_up.elapsed().as_secs_f64() * limit).min(limit) - bytes as f64;
+        *topped_up = Instant::now();
+        if *tokens < 0. {
+            // Holding the lock while asleep makes every other copy thread wait its turn too
+            ::std::thread::sleep(Duration::from_secs_f64(-*tokens / limit));
+            *bucket = (Instant::now(), 0.);
+        }
+    }
+}
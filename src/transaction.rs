@@ -0,0 +1,139 @@
+use std::{
+    ffi::OsStr,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+/// Where `--transactional` writes a destination's copy until it is swapped in, in its root
+pub const STAGING_DIR: &str = ".decopy-staging";
+/// Where what the staged copy replaces is kept during the swap, in the root of the destination.
+/// Until the swap is done it holds what is needed to undo it: the replaced entries under `old`,
+/// and an empty file under `added` for each entry that is new to the destination.
+const REPLACED_DIR: &str = ".decopy-replaced";
+/// What `REPLACED_DIR` is renamed to once every entry is swapped, to be thrown away
+const DISCARDED_DIR: &str = ".decopy-discarded";
+const OLD: &str = "old";
+const ADDED: &str = "added";
+
+///
+/// The staging directory of `dest`, see `STAGING_DIR`. It is on the same drive, so swapping it
+/// in is a rename rather than a copy.
+///
+pub fn staging(dest: &Path) -> PathBuf {
+    dest.join(STAGING_DIR)
+}
+
+///
+/// Moves the staged copy into `dest`: every top level entry of the staging directory replaces
+/// the entry of the same name on `dest` as a whole, anything else on `dest` stays. Each entry is
+/// swapped with a rename; when one of them fails, those swapped before it are put back, so
+/// `dest` is either updated in full or left as it was. A swap a crash cut off is put back by the
+/// next `roll_back` or `commit`.
+///
+pub fn commit(dest: &Path) -> ::std::io::Result<()> {
+    commit_with(dest, |_| {})
+}
+
+///
+/// `commit`, calling `step` before each of its renames with how many came before, so a test can
+/// cut the swap off anywhere
+///
+pub fn commit_with(dest: &Path, mut step: impl FnMut(usize)) -> ::std::io::Result<()> {
+    let staging = staging(dest);
+    let replaced = dest.join(REPLACED_DIR);
+    recover(dest)?;
+    ::std::fs::create_dir_all(replaced.join(OLD))?;
+    ::std::fs::create_dir(replaced.join(ADDED))?;
+
+    let mut names = ::std::fs::read_dir(&staging)?
+        .map(|entry| entry.map(|entry| entry.file_name()))
+        .collect::<::std::io::Result<Vec<_>>>()?;
+    names.sort();
+    let mut steps = 0..;
+    for name in names {
+        if let Err(e) = swap(dest, &name, &mut || step(steps.next().unwrap_or_default())) {
+            let _ = restore(dest);
+            return Err(e);
+        }
+    }
+    // From here on the swap is done, and what it replaced is thrown away rather than put back
+    step(steps.next().unwrap_or_default());
+    let discarded = dest.join(DISCARDED_DIR);
+    if let Err(e) = ::std::fs::rename(&replaced, &discarded) {
+        let _ = restore(dest);
+        return Err(e);
+    }
+    remove_dir(&discarded)?;
+    remove_dir(&staging)
+}
+
+///
+/// Swaps the staged entry `name` into `dest`, noting in `REPLACED_DIR` first what undoes it
+///
+fn swap(dest: &Path, name: &OsStr, step: &mut impl FnMut()) -> ::std::io::Result<()> {
+    let replaced = dest.join(REPLACED_DIR);
+    step();
+    match ::std::fs::symlink_metadata(dest.join(name)) {
+        Ok(_) => ::std::fs::rename(dest.join(name), replaced.join(OLD).join(name))?,
+        Err(_) => drop(::std::fs::File::create(replaced.join(ADDED).join(name))?),
+    }
+    step();
+    ::std::fs::rename(staging(dest).join(name), dest.join(name))
+}
+
+///
+/// Finishes what a crash left of a swap: one that got through every entry is cleared up, one cut
+/// off halfway is put back with `restore`
+///
+fn recover(dest: &Path) -> ::std::io::Result<()> {
+    remove_dir(&dest.join(DISCARDED_DIR))?;
+    restore(dest)
+}
+
+///
+/// Puts what `commit` swapped in back into the staging directory, and the entries they replaced
+/// back in their place, going by what `REPLACED_DIR` holds
+///
+fn restore(dest: &Path) -> ::std::io::Result<()> {
+    let (staging, replaced) = (staging(dest), dest.join(REPLACED_DIR));
+    if ::std::fs::symlink_metadata(&replaced).is_err() {
+        return Ok(());
+    }
+    ::std::fs::create_dir_all(&staging)?;
+    for (kind, put_back) in [(ADDED, false), (OLD, true)] {
+        let entries = match ::std::fs::read_dir(replaced.join(kind)) {
+            Err(e) if e.kind() == ErrorKind::NotFound => continue,
+            entries => entries?,
+        };
+        for entry in entries {
+            let name = entry?.file_name();
+            // Swapped in already, otherwise it is still staged
+            if ::std::fs::symlink_metadata(dest.join(&name)).is_ok() {
+                ::std::fs::rename(dest.join(&name), staging.join(&name))?;
+            }
+            if put_back {
+                ::std::fs::rename(replaced.join(kind).join(&name), dest.join(&name))?;
+            }
+        }
+    }
+    remove_dir(&replaced)
+}
+
+///
+/// Throws away the staged copy of `dest`, which is left as it was before the run, after putting
+/// back what a swap a crash cut off replaced
+///
+pub fn roll_back(dest: &Path) -> ::std::io::Result<()> {
+    recover(dest)?;
+    remove_dir(&staging(dest))
+}
+
+///
+/// Removes `dir` with everything in it, if it is there
+///
+fn remove_dir(dir: &Path) -> ::std::io::Result<()> {
+    match ::std::fs::remove_dir_all(dir) {
+        Err(e) if e.kind() != ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
@@ -0,0 +1,2343 @@
+use chrono::{DateTime, Local};
+use clap::ValueEnum;
+use crossterm::{
+    cursor::{Hide, MoveTo, Show},
+    event::{Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+    queue,
+    style::{Print, Stylize},
+    terminal::{
+        disable_raw_mode, enable_raw_mode, Clear, ClearType, EnterAlternateScreen,
+        LeaveAlternateScreen,
+    },
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::VecDeque,
+    io::Write,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{Receiver, Sender, TryRecvError},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use crate::{
+    calibration::estimate,
+    cancel::CancellationToken,
+    capacity::{needed_vs_available, Fit},
+    copy::{CopyQueue, DestinationSummary},
+    deployed::Deployed,
+    drive, elevate,
+    error::{CopyError, ErrorClass},
+    group::{group_of, DestinationGroup},
+    hook::{DeploymentHook, FileProgress},
+    i18n::Message,
+    keys::KeyBindings,
+    locale::Locale,
+    mirror::Stale,
+    overwrite::{Answer, Conflict, Conflicts, FileVersion},
+    rawpath::badged_name,
+    sound::{SoundEvent, Sounds, BELL_GAP},
+    start::{countdown, Delay},
+    throttle::Throttle,
+    verify::Verification,
+    walk::{NameCollision, Totals},
+    Args,
+};
+
+/// How long the event loop waits for input before checking on the copy again
+const TICK: Duration = Duration::from_millis(50);
+
+///
+/// Colors of the full-screen UI. `Plain` draws everything in the terminal's own colors, for
+/// screens where the default palette is hard to read.
+///
+#[derive(ValueEnum, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Theme {
+    #[default]
+    Default,
+    Plain,
+}
+
+/// How many entries of the source the PreCopy preview lists at once
+const PREVIEW_ROWS: usize = 5;
+
+/// How many files that failed verification are listed per destination, the rest are counted
+const LISTED_FAILURES: usize = 5;
+
+/// How many errors the browser on the Completed and Failed screens lists at once
+const BROWSED_ERRORS: usize = 5;
+
+/// The smallest terminal the full-screen layouts fit in, as columns and rows
+pub const MIN_SIZE: (u16, u16) = (54, 12);
+
+/// How far back the total speed on the summary strip looks
+const THROUGHPUT_WINDOW: Duration = Duration::from_secs(3);
+
+/// Shown at the right end of the header while the copy is paused
+const PAUSED_BADGE: &str = "[ PAUSED ]";
+
+/// Turns while the source is being counted on the PreCopy screen, a frame per `SPINNER_FRAME`
+const SPINNER: [char; 4] = ['|', '/', '-', '\\'];
+const SPINNER_FRAME: Duration = Duration::from_millis(100);
+
+/// How long the ETAs take to go from the speed a drive had on earlier runs over to the one it
+/// has now
+const CALIBRATION_HANDOVER: Duration = Duration::from_secs(10);
+
+///
+/// A top level entry of the source in the PreCopy preview, which can be excluded from the run
+/// there with `x`
+///
+#[derive(Debug, Clone)]
+pub struct PreviewEntry {
+    /// Relative to the source
+    pub path: PathBuf,
+    /// Filled in by a background count while the preview is up
+    pub totals: Arc<Totals>,
+    pub excluded: bool,
+}
+
+///
+/// The bytes of the entries that aren't excluded as far as they have been counted, and whether
+/// counting is done
+///
+pub fn payload(entries: &[PreviewEntry]) -> (usize, bool) {
+    let bytes = entries
+        .iter()
+        .filter(|entry| !entry.excluded)
+        .map(|entry| entry.totals.bytes())
+        .sum();
+    (bytes, entries.iter().all(|entry| entry.totals.done()))
+}
+
+///
+/// An entry of the error browser on the Completed and Failed screens
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ErrorEntry {
+    /// Index of the destination, `None` when the source failed
+    pub dest: Option<usize>,
+    /// The file that failed verification, relative to the source. `None` for the error that
+    /// ended the run.
+    pub file: Option<PathBuf>,
+    /// Full path of what failed
+    pub path: PathBuf,
+    /// What went wrong, with the OS error where there is one
+    pub error: String,
+    /// What the operator can do about it
+    pub suggestion: Option<&'static str>,
+}
+
+///
+/// What `r` in the error browser asks to be tried again, see `Ui::retry`
+///
+#[derive(Debug, Clone)]
+pub enum Retry {
+    /// The run that failed, from the start
+    Run,
+    /// `file`, which failed verification on destination `dest`. The rest of the outcome of the
+    /// run is carried over to the Completed screen that follows.
+    File {
+        dest: usize,
+        file: PathBuf,
+        summaries: Vec<DestinationSummary>,
+        verifications: Vec<Verification>,
+        manifest_hash: Option<String>,
+    },
+}
+
+///
+/// Progress reported by the copy worker to the UI
+///
+#[derive(Debug)]
+pub enum CopyingState {
+    /// `bytes_copied` of the payload are on `dest` (an index into the destinations)
+    Progress {
+        dest: usize,
+        percent: usize,
+        bytes_copied: usize,
+    },
+    /// `file_bytes` of the `file_size` of `file` are on `dest`
+    FileProgress {
+        dest: usize,
+        file: PathBuf,
+        file_bytes: usize,
+        file_size: usize,
+    },
+    /// `dest` has been re-read up to `percent` of the payload
+    Verifying {
+        dest: usize,
+        percent: usize,
+    },
+    /// The files that failed verification on `dest` are copied again, up to `percent`
+    Repairing {
+        dest: usize,
+        percent: usize,
+    },
+    /// `files` the source doesn't have were deleted from `dest` (`--mirror`)
+    Mirrored {
+        dest: usize,
+        files: usize,
+    },
+    /// `files` matching the cleanup rules were deleted from `dest`
+    Cleaned {
+        dest: usize,
+        files: usize,
+    },
+    /// The staged copy was swapped into `dest`, or thrown away when `committed` isn't set
+    /// (`--transactional`)
+    Committed {
+        dest: usize,
+        committed: bool,
+    },
+    /// What the OS cached for `dest` is being written out to the drive (`--sync`)
+    Syncing {
+        dest: usize,
+    },
+    /// `dest` was ejected, the OS gets `remaining` more to let go of it
+    Ejecting {
+        dest: usize,
+        remaining: Duration,
+    },
+    /// The OS no longer has `dest` mounted, it can be pulled
+    SafeToRemove {
+        dest: usize,
+    },
+    Finished {
+        summaries: Vec<DestinationSummary>,
+        verifications: Option<Vec<Verification>>,
+        /// Digest of the run's manifest, when hashing was enabled
+        manifest_hash: Option<String>,
+    },
+    Failed(CopyError),
+}
+
+///
+/// Which screen the UI is on
+///
+/// ```text
+/// PreCopy --confirm--> [Waiting] --> Copying --Finished--> Completed
+///                                       \------Failed-----> Failed
+/// ```
+///
+#[derive(Debug)]
+pub enum UIState {
+    /// Showing what is about to be copied where, waiting for the operator to confirm
+    PreCopy,
+    /// Counting down to a `--start-at` or `--delay` start
+    Waiting(DateTime<Local>),
+    /// Draining progress from the copy worker until it finishes
+    Copying(Receiver<CopyingState>),
+    Completed {
+        summaries: Vec<DestinationSummary>,
+        verifications: Option<Vec<Verification>>,
+        manifest_hash: Option<String>,
+    },
+    Failed(CopyError),
+}
+
+///
+/// What the caller of `run` should do next
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UiAction {
+    None,
+    /// The operator confirmed the PreCopy screen, or it is time to leave the Waiting screen
+    Confirm,
+    /// The operator wants out, from whatever state the UI is in
+    Quit,
+    /// The operator wants to retry a failed run as administrator
+    Elevate,
+    /// The operator named a profile to save the current options under, see `take_profile_name`
+    SaveProfile,
+    /// The operator wants the error selected in the browser tried again, see `retry`
+    Retry,
+}
+
+///
+/// Progress of a single destination, as shown on the Copying screen
+///
+#[derive(Debug, Clone, Default)]
+struct DestinationProgress {
+    percent: usize,
+    bytes_copied: usize,
+    /// The file being written, with how many of its bytes are on the destination and its size
+    file: Option<(PathBuf, usize, usize)>,
+    verify_percent: Option<usize>,
+    repair_percent: Option<usize>,
+    /// Files deleted by the cleanup rules
+    cleaned: usize,
+    /// Stale files deleted by `--mirror`
+    mirrored: usize,
+    /// Whether the staged copy was swapped in, once `--transactional` decided
+    committed: Option<bool>,
+    removal: Option<Removal>,
+    /// When the first bytes came in and how many there were, which may have been on the
+    /// destination already, for the speed since
+    started: Option<(Instant, usize)>,
+    /// Cancelled on its own from the Copying screen, while the others carry on
+    cancelled: bool,
+}
+
+impl DestinationProgress {
+    ///
+    /// Where the destination is at for the summary strip: verifying and repairing count as
+    /// copying, done is copied in full
+    ///
+    fn phase(&self) -> Phase {
+        let unfinished = |percent: Option<usize>| percent.is_some_and(|percent| percent < 100);
+        if unfinished(self.verify_percent) || unfinished(self.repair_percent) {
+            Phase::Copying
+        } else if self.percent >= 100 {
+            Phase::Done
+        } else if self.percent == 0 && self.bytes_copied == 0 {
+            Phase::Pending
+        } else {
+            Phase::Copying
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Phase {
+    Pending,
+    Copying,
+    Done,
+    Failed,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Removal {
+    /// Writing what the OS cached out to the drive (`--sync`)
+    Syncing,
+    /// Waiting for the OS to dismount, with the time left before giving up
+    Ejecting(Duration),
+    SafeToRemove,
+}
+
+///
+/// The full-screen interface used when running in a terminal. Holds no terminal handles itself:
+/// events come in through `handle_event` and frames go out through `render`, so any sequence of
+/// input can be driven through it without a real terminal.
+///
+pub struct Ui {
+    pub state: UIState,
+    pub size: (u16, u16),
+    source: PathBuf,
+    destinations: Vec<PathBuf>,
+    /// Destinations with their drive letter or volume GUID, looked up once up front
+    described: Vec<String>,
+    /// Free space of each destination, looked up once up front for the fit margins
+    free: Vec<Option<u64>>,
+    groups: Vec<DestinationGroup>,
+    /// Top level entries of the source, for the PreCopy preview
+    entries: Vec<PreviewEntry>,
+    /// The entry of the preview `x` applies to
+    selected: usize,
+    /// The entry of the error browser the detail pane shows
+    selected_error: usize,
+    /// The profile name being typed after `s`, on the PreCopy screen
+    profile_name: Option<String>,
+    locale: Locale,
+    progress: Vec<DestinationProgress>,
+    /// Bytes copied to all destinations together at the ticks of the last `THROUGHPUT_WINDOW`
+    throughput: VecDeque<(Instant, usize)>,
+    pub theme: Theme,
+    /// The keys of the main actions, from the `[keys]` table of the config file
+    pub keys: KeyBindings,
+    /// Set by the pause key on the Copying screen, shared with the copy
+    pub paused: Option<Arc<AtomicBool>>,
+    /// Cancelled by the first Ctrl+C on the Copying screen, which stops the copy cleanly
+    pub cancel: Option<CancellationToken>,
+    /// Each destination's own token, cancelled by `x` on the Copying screen to stop just that one
+    pub destination_cancels: Vec<CancellationToken>,
+    /// The destination of the Copying screen `x` applies to
+    selected_destination: usize,
+    /// Waiting for the copy to stop after Ctrl+C
+    cancelling: bool,
+    /// Where the copy asks about files a destination already has, under `--overwrite prompt`
+    pub conflicts: Option<Arc<Conflicts>>,
+    /// Paces the copy under `--limit-rate` and `--limit-schedule`, for the limit in effect
+    pub throttle: Option<Arc<Throttle>>,
+    /// Bytes per second each destination was copied to at on earlier runs, see `Calibration`
+    pub calibrated: Vec<Option<u64>>,
+    /// Play the cues of `sounds` as things happen
+    pub beep: bool,
+    /// What is played for each event, from the `[sounds]` table of the config file
+    pub sounds: Sounds,
+    /// Destinations too small for the payload will be left out (`--skip-too-small`)
+    pub skip_too_small: bool,
+    /// The run won't start while a destination is too small for the payload
+    /// (`--refuse-too-small`)
+    pub refuse_too_small: bool,
+    /// What `--mirror` deletes from each destination, before anything is excluded here
+    pub stale: Option<Vec<Stale>>,
+    /// The destinations that already hold the payload, before anything is excluded here, see
+    /// `deployed::deployed`
+    pub deployed: Vec<Option<Deployed>>,
+    /// Leave out the destinations that already hold the payload, toggled by `d` on the PreCopy
+    /// screen
+    skip_deployed: bool,
+    /// The top level names more than one source has, see `--collision`
+    pub collisions: Vec<NameCollision>,
+    /// When the bells still to ring are due, `BELL_GAP` apart
+    bells: VecDeque<Instant>,
+    /// When the UI came up, what the spinner turns by
+    opened: Instant,
+    /// One-off feedback shown above the footer, e.g. after copying to the clipboard
+    status: Option<String>,
+    /// Kept open for the rest of the run: on X11 the copied text is only available while the
+    /// clipboard that set it is alive
+    clipboard: Option<arboard::Clipboard>,
+}
+
+impl Ui {
+    pub fn new(
+        source: &Path,
+        destinations: &[PathBuf],
+        groups: &[DestinationGroup],
+        entries: Vec<PreviewEntry>,
+        locale: Locale,
+        size: (u16, u16),
+    ) -> Self {
+        Self {
+            state: UIState::PreCopy,
+            size,
+            source: source.to_path_buf(),
+            destinations: destinations.to_vec(),
+            described: destinations
+                .iter()
+                .map(|dest| drive::describe(dest))
+                .collect(),
+            free: destinations
+                .iter()
+                .map(|dest| drive::free_space(dest))
+                .collect(),
+            groups: groups.to_vec(),
+            entries,
+            selected: 0,
+            selected_error: 0,
+            profile_name: None,
+            locale,
+            progress: vec![DestinationProgress::default(); destinations.len()],
+            throughput: VecDeque::new(),
+            theme: Theme::Default,
+            keys: KeyBindings::default(),
+            paused: None,
+            cancel: None,
+            destination_cancels: Vec::new(),
+            selected_destination: 0,
+            cancelling: false,
+            conflicts: None,
+            throttle: None,
+            calibrated: vec![None; destinations.len()],
+            beep: false,
+            sounds: Sounds::default(),
+            skip_too_small: false,
+            refuse_too_small: false,
+            stale: None,
+            deployed: vec![None; destinations.len()],
+            skip_deployed: false,
+            collisions: Vec::new(),
+            bells: VecDeque::new(),
+            opened: Instant::now(),
+            status: None,
+            clipboard: None,
+        }
+    }
+
+    pub fn handle_event(&mut self, event: Event) -> UiAction {
+        let key = match event {
+            Event::Resize(width, height) => {
+                self.size = (width, height);
+                return UiAction::None;
+            }
+            Event::Key(key) if key.kind != KeyEventKind::Release => key,
+            _ => return UiAction::None,
+        };
+
+        // Raw mode swallows the usual SIGINT, so Ctrl+C has to work from every screen. The first
+        // one on the Copying screen stops the copy cleanly, the next one doesn't wait for that.
+        if key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c') {
+            if let (UIState::Copying(_), Some(cancel)) = (&self.state, &self.cancel) {
+                if !self.cancelling && !self.verifying() {
+                    cancel.cancel();
+                    self.cancelling = true;
+                    return UiAction::None;
+                }
+            }
+            return UiAction::Quit;
+        }
+
+        if let (UIState::PreCopy, Some(name)) = (&self.state, &mut self.profile_name) {
+            return match key.code {
+                KeyCode::Enter if !name.is_empty() => UiAction::SaveProfile,
+                KeyCode::Esc => {
+                    self.profile_name = None;
+                    UiAction::None
+                }
+                KeyCode::Backspace => {
+                    name.pop();
+                    UiAction::None
+                }
+                KeyCode::Char(c) if c.is_ascii_alphanumeric() || c == '-' || c == '_' => {
+                    name.push(c);
+                    UiAction::None
+                }
+                _ => UiAction::None,
+            };
+        }
+
+        // The bound keys come first, so they win over the fixed ones they may shadow
+        let keys = self.keys;
+        match (&self.state, key) {
+            (UIState::PreCopy, KeyEvent { code, .. }) => match code {
+                _ if keys.confirm.matches(&key) || code == KeyCode::Enter => self.confirm_copy(),
+                _ if keys.cancel.matches(&key) || keys.quit.matches(&key) => UiAction::Quit,
+                KeyCode::Esc => UiAction::Quit,
+                KeyCode::Up | KeyCode::Char('k') => {
+                    self.selected = self.selected.saturating_sub(1);
+                    UiAction::None
+                }
+                KeyCode::Down | KeyCode::Char('j') => {
+                    self.selected = (self.selected + 1).min(self.entries.len().saturating_sub(1));
+                    UiAction::None
+                }
+                KeyCode::Char('x') => {
+                    if let Some(entry) = self.entries.get_mut(self.selected) {
+                        entry.excluded = !entry.excluded;
+                    }
+                    UiAction::None
+                }
+                KeyCode::Char('s') => {
+                    self.profile_name = Some(String::new());
+                    UiAction::None
+                }
+                KeyCode::Char('d') => {
+                    self.skip_deployed = !self.skip_deployed;
+                    UiAction::None
+                }
+                _ => UiAction::None,
+            },
+            (UIState::Waiting(_), KeyEvent { code, .. }) => match code {
+                _ if keys.confirm.matches(&key) => UiAction::Confirm,
+                _ if keys.cancel.matches(&key) || keys.quit.matches(&key) => UiAction::Quit,
+                KeyCode::Enter | KeyCode::Char('s') => UiAction::Confirm,
+                KeyCode::Esc => UiAction::Quit,
+                _ => UiAction::None,
+            },
+            (UIState::Copying(_), KeyEvent { code, .. }) if self.conflict().is_some() => {
+                let answer = match code {
+                    KeyCode::Char('o') => Answer::Overwrite,
+                    KeyCode::Char('s') => Answer::Skip,
+                    KeyCode::Char('a') => Answer::OverwriteAll,
+                    KeyCode::Char('n') => Answer::SkipAll,
+                    _ => return UiAction::None,
+                };
+                if let Some(conflicts) = &self.conflicts {
+                    conflicts.answer(answer);
+                }
+                UiAction::None
+            }
+            (UIState::Copying(_), KeyEvent { code, .. }) => {
+                if let Some(paused) = self.paused.as_ref().filter(|_| keys.pause.matches(&key)) {
+                    paused.fetch_xor(true, Ordering::Relaxed);
+                    return UiAction::None;
+                }
+                match code {
+                    KeyCode::Up | KeyCode::Char('k') => {
+                        self.selected_destination = self.selected_destination.saturating_sub(1);
+                    }
+                    KeyCode::Down | KeyCode::Char('j') => {
+                        self.selected_destination = (self.selected_destination + 1)
+                            .min(self.destinations.len().saturating_sub(1));
+                    }
+                    KeyCode::Char('x') if !self.cancelling && !self.verifying() => {
+                        self.cancel_destination(self.selected_destination);
+                    }
+                    _ => {}
+                }
+                UiAction::None
+            }
+            (UIState::Completed { .. } | UIState::Failed(_), KeyEvent { code, .. }) => match code {
+                _ if keys.quit.matches(&key) => UiAction::Quit,
+                KeyCode::Enter | KeyCode::Esc => UiAction::Quit,
+                KeyCode::Up | KeyCode::Char('k') => {
+                    self.selected_error = self.selected_error().saturating_sub(1);
+                    UiAction::None
+                }
+                KeyCode::Down | KeyCode::Char('j') => {
+                    self.selected_error =
+                        (self.selected_error() + 1).min(self.errors().len().saturating_sub(1));
+                    UiAction::None
+                }
+                KeyCode::Char('r') if self.retry().is_some() => UiAction::Retry,
+                KeyCode::Char('c') if matches!(self.state, UIState::Completed { .. }) => {
+                    self.copy_summary();
+                    UiAction::None
+                }
+                KeyCode::Char('a') if matches!(&self.state, UIState::Failed(e) if can_elevate(e)) => {
+                    UiAction::Elevate
+                }
+                _ => UiAction::None,
+            },
+        }
+    }
+
+    ///
+    /// What went wrong in the run, for the error browser: the files that failed verification on
+    /// the Completed screen, the error that ended the run on the Failed screen
+    ///
+    pub fn errors(&self) -> Vec<ErrorEntry> {
+        match &self.state {
+            UIState::Completed {
+                verifications: Some(verifications),
+                ..
+            } => verifications
+                .iter()
+                .enumerate()
+                .flat_map(|(dest, verification)| {
+                    verification
+                        .failures()
+                        .map(move |(file, reason)| ErrorEntry {
+                            dest: Some(dest),
+                            file: Some(file.clone()),
+                            path: verification.destination.join(file),
+                            error: reason.to_string(),
+                            suggestion: verification_suggestion(reason),
+                        })
+                })
+                .collect(),
+            UIState::Failed(e) => vec![ErrorEntry {
+                dest: e
+                    .destination
+                    .as_ref()
+                    .and_then(|dest| self.destinations.iter().position(|d| d == dest)),
+                file: None,
+                // Files are named relative to where they were read or written
+                path: match &e.destination {
+                    _ if e.path.is_absolute() => e.path.clone(),
+                    Some(dest) => dest.join(&e.path),
+                    None => self.source.join(&e.path),
+                },
+                error: format!("{} [{}]", e.error, e.class().code()),
+                suggestion: e.class().suggestion(self.locale),
+            }],
+            _ => Vec::new(),
+        }
+    }
+
+    ///
+    /// The selected entry of the error browser, kept in range as the list changes
+    ///
+    fn selected_error(&self) -> usize {
+        self.selected_error
+            .min(self.errors().len().saturating_sub(1))
+    }
+
+    ///
+    /// What `r` tries again: the whole run after it failed, or the file selected in the error
+    /// browser after it failed verification
+    ///
+    pub fn retry(&self) -> Option<Retry> {
+        match &self.state {
+            UIState::Failed(_) => Some(Retry::Run),
+            UIState::Completed {
+                summaries,
+                verifications: Some(verifications),
+                manifest_hash,
+            } => {
+                let error = self.errors().into_iter().nth(self.selected_error())?;
+                Some(Retry::File {
+                    dest: error.dest?,
+                    file: error.file?,
+                    summaries: summaries.clone(),
+                    verifications: verifications.clone(),
+                    manifest_hash: manifest_hash.clone(),
+                })
+            }
+            _ => None,
+        }
+    }
+
+    ///
+    /// Goes back to the Copying screen for `retry`, with progress from the worker coming in on
+    /// `updates`. A run tried again starts with fresh progress.
+    ///
+    pub fn retrying(&mut self, retry: &Retry, updates: Receiver<CopyingState>) {
+        if let Retry::Run = retry {
+            self.progress = vec![DestinationProgress::default(); self.destinations.len()];
+        }
+        self.throughput.clear();
+        self.selected_error = 0;
+        self.status = None;
+        self.state = UIState::Copying(updates);
+    }
+
+    ///
+    /// The payload as far as it has been counted, and whether counting is done
+    ///
+    pub fn payload(&self) -> (usize, bool) {
+        payload(&self.entries)
+    }
+
+    ///
+    /// How each destination's free space compares with the payload, once it is fully counted
+    ///
+    pub fn fits(&self) -> Option<Vec<Fit>> {
+        let (payload, counted) = self.payload();
+        counted.then(|| {
+            self.free
+                .iter()
+                .map(|free| Fit::new(*free, payload as u64))
+                .collect()
+        })
+    }
+
+    ///
+    /// Starts the copy from the PreCopy screen, unless `--refuse-too-small` holds it back for a
+    /// destination already known to be too small
+    ///
+    fn confirm_copy(&mut self) -> UiAction {
+        if !self.refuse_too_small {
+            return UiAction::Confirm;
+        }
+        let fits = self.fits().unwrap_or_default();
+        let too_small = self
+            .described
+            .iter()
+            .zip(&fits)
+            .filter(|(_, fit)| fit.too_small())
+            .map(|(described, _)| described.as_str())
+            .collect::<Vec<_>>();
+        if too_small.is_empty() {
+            return UiAction::Confirm;
+        }
+        self.set_status(format!(
+            "Not starting, too small for the payload: {}",
+            too_small.join(", ")
+        ));
+        UiAction::None
+    }
+
+    ///
+    /// Drops `skipped` from the destinations shown, for `--skip-too-small`
+    ///
+    pub fn skip_destinations(&mut self, skipped: &[PathBuf]) {
+        let kept = self
+            .destinations
+            .iter()
+            .map(|dest| !skipped.contains(dest))
+            .collect::<Vec<_>>();
+        let mut keep = kept.iter().copied();
+        self.described.retain(|_| keep.next().unwrap_or(true));
+        let mut keep = kept.iter().copied();
+        self.free.retain(|_| keep.next().unwrap_or(true));
+        let mut keep = kept.iter().copied();
+        self.progress.retain(|_| keep.next().unwrap_or(true));
+        let mut keep = kept.iter().copied();
+        self.calibrated.retain(|_| keep.next().unwrap_or(true));
+        let mut keep = kept.iter().copied();
+        self.destination_cancels
+            .retain(|_| keep.next().unwrap_or(true));
+        if let Some(stale) = &mut self.stale {
+            let mut keep = kept.iter().copied();
+            stale.retain(|_| keep.next().unwrap_or(true));
+        }
+        let mut keep = kept.iter().copied();
+        self.deployed.retain(|_| keep.next().unwrap_or(true));
+        self.destinations.retain(|dest| !skipped.contains(dest));
+    }
+
+    ///
+    /// When destination number `dest` got the payload it already holds. Once something is
+    /// excluded the payload is no longer the one it was checked against.
+    ///
+    fn deployed(&self, dest: usize) -> Option<Deployed> {
+        self.deployed
+            .get(dest)
+            .copied()
+            .flatten()
+            .filter(|_| self.entries.iter().all(|entry| !entry.excluded))
+    }
+
+    ///
+    /// The destinations the operator chose to leave out on the PreCopy screen as they already
+    /// hold the payload
+    ///
+    pub fn deployed_skipped(&self) -> Vec<PathBuf> {
+        match self.skip_deployed {
+            true => (0..self.destinations.len())
+                .filter(|dest| self.deployed(*dest).is_some())
+                .map(|dest| self.destinations[dest].clone())
+                .collect(),
+            false => Vec::new(),
+        }
+    }
+
+    ///
+    /// The entries excluded on the PreCopy screen, relative to the source
+    ///
+    pub fn excluded(&self) -> Vec<PathBuf> {
+        self.entries
+            .iter()
+            .filter(|entry| entry.excluded)
+            .map(|entry| entry.path.clone())
+            .collect()
+    }
+
+    ///
+    /// The name typed for the profile once `run` returned `SaveProfile`, leaving the prompt
+    ///
+    pub fn take_profile_name(&mut self) -> Option<String> {
+        self.profile_name.take()
+    }
+
+    ///
+    /// Shows one-off feedback above the footer
+    ///
+    pub fn set_status(&mut self, status: impl Into<String>) {
+        self.status = Some(status.into());
+    }
+
+    ///
+    /// A plain-text summary of a completed run, for pasting into a release ticket
+    ///
+    pub fn summary_text(&self) -> Option<String> {
+        let UIState::Completed {
+            summaries,
+            verifications,
+            manifest_hash,
+        } = &self.state
+        else {
+            return None;
+        };
+
+        let mut text = format!("Deployed `{}`\n", self.source.display());
+        if let Some(hash) = manifest_hash {
+            text.push_str(&format!("Manifest sha256 {}\n", hash));
+        }
+        for (i, summary) in summaries.iter().enumerate() {
+            text.push_str(&format!(
+                "- {}: {} in {:.1}s",
+                summary.destination.display(),
+                get_bytes_string(summary.bytes_copied, self.locale),
+                summary.duration.as_secs_f64()
+            ));
+            match verifications.as_ref().and_then(|v| v.get(i)) {
+                Some(v) if v.passed() => text.push_str(", verified"),
+                Some(v) => text.push_str(&format!(
+                    ", {} file(s) do not match the source",
+                    v.mismatches()
+                )),
+                None => {}
+            }
+            if !summary.failed.is_empty() {
+                text.push_str(&format!(
+                    ", {} file(s) could not be copied",
+                    summary.failed.len()
+                ));
+            }
+            if !summary.changed.is_empty() {
+                text.push_str(&format!(", {}", changed_files_warning(summary)));
+            }
+            if summary.scanned {
+                text.push_str(&format!(", {}", Message::ScanSuspected.text(self.locale)));
+            }
+            text.push('\n');
+        }
+        Some(text)
+    }
+
+    fn copy_summary(&mut self) {
+        let Some(text) = self.summary_text() else {
+            return;
+        };
+
+        let clipboard = match &mut self.clipboard {
+            Some(clipboard) => Ok(clipboard),
+            None => arboard::Clipboard::new().map(|clipboard| self.clipboard.insert(clipboard)),
+        };
+        self.status = Some(
+            match clipboard.and_then(|clipboard| clipboard.set_text(text)) {
+                Ok(()) => "Summary copied to the clipboard".to_string(),
+                Err(e) => format!("Could not copy to the clipboard: {}", e),
+            },
+        );
+    }
+
+    ///
+    /// Applies everything the copy worker has sent since the last tick, moving on to Completed
+    /// or Failed once it is done. A worker that disappears without a word counts as a failure.
+    /// When Waiting, confirms once the start time has come.
+    ///
+    pub fn tick(&mut self) -> UiAction {
+        let updates = match &self.state {
+            UIState::Waiting(start) if *start <= Local::now() => return UiAction::Confirm,
+            UIState::Copying(updates) => updates,
+            _ => return UiAction::None,
+        };
+
+        let mut next = None;
+        let mut drives_done = 0;
+        loop {
+            match updates.try_recv() {
+                Ok(CopyingState::Progress {
+                    dest,
+                    percent,
+                    bytes_copied,
+                }) => {
+                    if let Some(progress) = self.progress.get_mut(dest) {
+                        progress.percent = percent;
+                        progress.bytes_copied = bytes_copied;
+                        progress
+                            .started
+                            .get_or_insert_with(|| (Instant::now(), bytes_copied));
+                    }
+                }
+                Ok(CopyingState::FileProgress {
+                    dest,
+                    file,
+                    file_bytes,
+                    file_size,
+                }) => {
+                    if let Some(progress) = self.progress.get_mut(dest) {
+                        progress.file = Some((file, file_bytes, file_size));
+                    }
+                }
+                Ok(CopyingState::Verifying { dest, percent }) => {
+                    if let Some(progress) = self.progress.get_mut(dest) {
+                        progress.verify_percent = Some(percent);
+                    }
+                }
+                Ok(CopyingState::Repairing { dest, percent }) => {
+                    if let Some(progress) = self.progress.get_mut(dest) {
+                        progress.repair_percent = Some(percent);
+                    }
+                }
+                Ok(CopyingState::Mirrored { dest, files }) => {
+                    if let Some(progress) = self.progress.get_mut(dest) {
+                        progress.mirrored = files;
+                    }
+                }
+                Ok(CopyingState::Cleaned { dest, files }) => {
+                    if let Some(progress) = self.progress.get_mut(dest) {
+                        progress.cleaned = files;
+                    }
+                }
+                Ok(CopyingState::Committed { dest, committed }) => {
+                    if let Some(progress) = self.progress.get_mut(dest) {
+                        progress.committed = Some(committed);
+                    }
+                }
+                Ok(CopyingState::Syncing { dest }) => {
+                    if let Some(progress) = self.progress.get_mut(dest) {
+                        progress.removal = Some(Removal::Syncing);
+                    }
+                }
+                Ok(CopyingState::Ejecting { dest, remaining }) => {
+                    if let Some(progress) = self.progress.get_mut(dest) {
+                        progress.removal = Some(Removal::Ejecting(remaining));
+                    }
+                }
+                Ok(CopyingState::SafeToRemove { dest }) => {
+                    if let Some(progress) = self.progress.get_mut(dest) {
+                        progress.removal = Some(Removal::SafeToRemove);
+                        drives_done += 1;
+                    }
+                }
+                Ok(CopyingState::Finished {
+                    summaries,
+                    verifications,
+                    manifest_hash,
+                }) => {
+                    next = Some(UIState::Completed {
+                        summaries,
+                        verifications,
+                        manifest_hash,
+                    });
+                    break;
+                }
+                Ok(CopyingState::Failed(e)) => {
+                    next = Some(UIState::Failed(e));
+                    break;
+                }
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    next = Some(UIState::Failed(CopyError::new(
+                        &self.source,
+                        None,
+                        ::std::io::Error::other("the copy stopped unexpectedly"),
+                    )));
+                    break;
+                }
+            }
+        }
+        for _ in 0..drives_done {
+            self.play(SoundEvent::DriveDone);
+        }
+        let now = Instant::now();
+        let total = self
+            .progress
+            .iter()
+            .fold(0usize, |total, p| total.saturating_add(p.bytes_copied));
+        self.throughput.push_back((now, total));
+        while self
+            .throughput
+            .front()
+            .is_some_and(|(at, _)| now.duration_since(*at) > THROUGHPUT_WINDOW)
+        {
+            self.throughput.pop_front();
+        }
+        if let Some(next) = next {
+            if let UIState::Completed { verifications, .. } = &next {
+                let failed = verifications
+                    .iter()
+                    .flatten()
+                    .any(|verification| !verification.passed());
+                if !self.cancelling {
+                    self.play(match failed {
+                        true => SoundEvent::VerifyFailed,
+                        false => SoundEvent::BatchComplete,
+                    });
+                }
+            }
+            self.state = next;
+            // Stopped where it was cancelled, or went through before it could be
+            if self.cancelling && matches!(self.state, UIState::Completed { .. }) {
+                return UiAction::Quit;
+            }
+        }
+        // The copy was through by the time it was cancelled, the verification can't be stopped
+        if self.cancelling && self.verifying() {
+            return UiAction::Quit;
+        }
+        UiAction::None
+    }
+
+    ///
+    /// Plays the cue for `event` under `--beep`. Bells are rung by `run`, after the ones already
+    /// due.
+    ///
+    fn play(&mut self, event: SoundEvent) {
+        if !self.beep {
+            return;
+        }
+        for _ in 0..self.sounds.cue(event).start() {
+            let due = match self.bells.back() {
+                Some(last) => (*last + BELL_GAP).max(Instant::now()),
+                None => Instant::now(),
+            };
+            self.bells.push_back(due);
+        }
+    }
+
+    ///
+    /// Stops the copy to `dest` alone, the others carry on. Does nothing without its token.
+    ///
+    pub fn cancel_destination(&mut self, dest: usize) {
+        let (Some(token), Some(progress)) = (
+            self.destination_cancels.get(dest),
+            self.progress.get_mut(dest),
+        ) else {
+            return;
+        };
+        if progress.percent < 100 {
+            token.cancel();
+            progress.cancelled = true;
+        }
+    }
+
+    ///
+    /// Whether the copy is held by the pause key
+    ///
+    fn paused(&self) -> bool {
+        matches!(self.state, UIState::Copying(_))
+            && !self.cancelling
+            && self
+                .paused
+                .as_ref()
+                .is_some_and(|paused| paused.load(Ordering::Relaxed))
+    }
+
+    ///
+    /// The file the copy waits on the operator about, under `--overwrite prompt`
+    ///
+    fn conflict(&self) -> Option<Conflict> {
+        match self.state {
+            UIState::Copying(_) if !self.cancelling => self.conflicts.as_ref()?.pending(),
+            _ => None,
+        }
+    }
+
+    ///
+    /// Whether the copy is through and the destinations are being verified or repaired
+    ///
+    fn verifying(&self) -> bool {
+        self.progress
+            .iter()
+            .any(|progress| progress.verify_percent.is_some() || progress.repair_percent.is_some())
+    }
+
+    ///
+    /// About how long copying to destination number `dest` has left, once the payload is counted.
+    /// Goes by the speed the drive had on earlier runs at first, and more and more by the speed
+    /// it is copied to at since the start over `CALIBRATION_HANDOVER`.
+    ///
+    fn eta(&self, dest: usize) -> Option<Duration> {
+        let progress = self.progress.get(dest)?;
+        let (payload, counted) = self.payload();
+        // Nothing is counted up front for a stream, and a pause has no end to tell
+        if !counted || self.entries.is_empty() || progress.percent >= 100 || self.paused() {
+            return None;
+        }
+        let (elapsed, measured) = match progress.started {
+            Some((at, first)) => {
+                let elapsed = at.elapsed();
+                let copied = progress.bytes_copied.saturating_sub(first);
+                (elapsed, copied as f64 / elapsed.as_secs_f64().max(0.001))
+            }
+            None => (Duration::ZERO, 0.),
+        };
+        let handover = (elapsed.as_secs_f64() / CALIBRATION_HANDOVER.as_secs_f64()).min(1.);
+        let speed = match self.calibrated.get(dest).copied().flatten() {
+            Some(calibrated) => calibrated as f64 * (1. - handover) + measured * handover,
+            // A second in, the speed means something at least
+            None if elapsed >= Duration::from_secs(1) => measured,
+            None => return None,
+        };
+        let remaining = payload.saturating_sub(progress.bytes_copied);
+        (speed >= 1.).then(|| Duration::from_secs_f64(remaining as f64 / speed))
+    }
+
+    ///
+    /// Bytes per second copied to all destinations together lately
+    ///
+    fn total_speed(&self) -> usize {
+        match (self.throughput.front(), self.throughput.back()) {
+            (Some((first_at, first)), Some((last_at, last))) if last_at > first_at => {
+                let elapsed = last_at.duration_since(*first_at).as_secs_f64();
+                (last.saturating_sub(*first) as f64 / elapsed) as usize
+            }
+            _ => 0,
+        }
+    }
+
+    ///
+    /// The one-line overview under the header while copying and after, e.g. `4 drives • 2
+    /// copying • 1 done • 1 pending • 0 failed • 38.0mb/s total`. The destination an error
+    /// ended the run on counts as failed, as do those that failed verification.
+    ///
+    fn summary_strip(&self) -> Option<String> {
+        if matches!(self.state, UIState::PreCopy | UIState::Waiting(_)) {
+            return None;
+        }
+        let failed = |dest: usize, path: &Path| match &self.state {
+            UIState::Completed {
+                summaries,
+                verifications,
+                ..
+            } => {
+                summaries
+                    .iter()
+                    .any(|summary| summary.destination == path && !summary.failed.is_empty())
+                    || verifications
+                        .as_ref()
+                        .and_then(|verifications| verifications.get(dest))
+                        .is_some_and(|verification| !verification.passed())
+            }
+            UIState::Failed(e) => e.destination.as_deref() == Some(path),
+            _ => false,
+        };
+        let phases = self
+            .destinations
+            .iter()
+            .zip(&self.progress)
+            .enumerate()
+            .map(|(dest, (path, progress))| match failed(dest, path) {
+                true => Phase::Failed,
+                false => progress.phase(),
+            })
+            .collect::<Vec<_>>();
+        let count = |phase| phases.iter().filter(|p| **p == phase).count();
+
+        let mut strip = format!(
+            "{} {} • {} copying • {} done • {} pending • {} failed",
+            phases.len(),
+            match phases.len() {
+                1 => "drive",
+                _ => "drives",
+            },
+            count(Phase::Copying),
+            count(Phase::Done),
+            count(Phase::Pending),
+            count(Phase::Failed)
+        );
+        if let UIState::Copying(_) = self.state {
+            strip.push_str(&format!(
+                " • {}/s total",
+                get_bytes_string(self.total_speed(), self.locale)
+            ));
+        }
+        Some(strip)
+    }
+
+    ///
+    /// Draws the current state as one full frame, clipped to `size`
+    ///
+    pub fn render(&self, out: &mut impl Write) -> ::std::io::Result<()> {
+        let (width, height) = (self.size.0 as usize, self.size.1 as usize);
+        if width == 0 || height == 0 {
+            return out.flush();
+        }
+        if too_small(out, self.size, self.theme)? {
+            return Ok(());
+        }
+
+        let title = format!(
+            "decopy - `{}` to {} destination(s)",
+            self.source.display(),
+            self.destinations.len()
+        );
+        // Right-aligned, so it stays in view however long the source path is
+        let title = match self.paused() {
+            true => {
+                let room = width.saturating_sub(PAUSED_BADGE.chars().count());
+                let title = title
+                    .chars()
+                    .take(room.saturating_sub(1))
+                    .collect::<String>();
+                Line::new(format!("{:<room$}{}", title, PAUSED_BADGE)).yellow()
+            }
+            false => Line::new(title).magenta(),
+        };
+        let mut lines = vec![title, Line::new("─".repeat(width)).dark_grey()];
+        if let Some(strip) = self.summary_strip() {
+            lines.push(Line::new(strip).cyan());
+        }
+        let footer = match &self.state {
+            UIState::PreCopy => {
+                self.pre_copy_lines(&mut lines, true);
+                let deployed =
+                    match (0..self.destinations.len()).any(|i| self.deployed(i).is_some()) {
+                        true => ", d to skip the drives that have it already",
+                        false => "",
+                    };
+                format!(
+                    "Does everything look correct? ({}/{}, arrows and x to exclude an entry, s to save a profile{})",
+                    self.keys.confirm.to_string().to_uppercase(),
+                    self.keys.cancel,
+                    deployed
+                )
+            }
+            UIState::Waiting(start) => {
+                lines.push(Line::new(format!(
+                    "Copying starts at {} (in {})",
+                    start.format("%H:%M"),
+                    countdown(*start)
+                )));
+                lines.push(Line::new(""));
+                self.pre_copy_lines(&mut lines, false);
+                format!(
+                    "Waiting... (Enter or {} to start now, {} to cancel)",
+                    self.keys.confirm, self.keys.cancel
+                )
+            }
+            UIState::Copying(_) if self.conflict().is_some() => {
+                if let Some(conflict) = self.conflict() {
+                    self.conflict_lines(&conflict, &mut lines);
+                }
+                "o to overwrite, s to skip, a to overwrite all, n to skip all, Ctrl+C to cancel"
+                    .to_string()
+            }
+            UIState::Copying(_) => {
+                self.copying_lines(&mut lines);
+                // Ctrl+C stops a copy cleanly, but only aborts the verification
+                let ctrl_c = match self.cancel.is_some() && !self.verifying() {
+                    true => "Ctrl+C to cancel",
+                    false => "Ctrl+C to abort",
+                };
+                match &self.paused {
+                    _ if self.cancelling => {
+                        "Cancelling, the copy stops at the next chunk... (Ctrl+C to quit now)"
+                            .to_string()
+                    }
+                    Some(paused) if paused.load(Ordering::Relaxed) => {
+                        format!("Paused ({} to resume, {})", self.keys.pause, ctrl_c)
+                    }
+                    Some(_) if self.destination_cancels.len() > 1 && !self.verifying() => format!(
+                        "Copying... ({} to pause, arrows and x to cancel a destination, {})",
+                        self.keys.pause, ctrl_c
+                    ),
+                    Some(_) => format!("Copying... ({} to pause, {})", self.keys.pause, ctrl_c),
+                    None => format!("Copying... ({})", ctrl_c),
+                }
+            }
+            UIState::Completed {
+                summaries,
+                verifications,
+                ..
+            } => {
+                self.completed_lines(summaries, verifications.as_deref(), &mut lines);
+                match self.errors().is_empty() {
+                    true => format!("Press c to copy the summary, {} to exit", self.keys.quit),
+                    false => format!(
+                        "Arrows to browse the errors, r to retry, c to copy the summary, {} to exit",
+                        self.keys.quit
+                    ),
+                }
+            }
+            UIState::Failed(e) => {
+                self.failed_lines(e, &mut lines);
+                if can_elevate(e) {
+                    format!(
+                        "Press r to retry, a to retry as administrator, {} to exit",
+                        self.keys.quit
+                    )
+                } else {
+                    format!("Press r to retry, {} to exit", self.keys.quit)
+                }
+            }
+        };
+
+        // Lines are overwritten in place rather than clearing the screen, which would flicker
+        lines.resize_with(height - 1, || Line::new(""));
+        if let Some(status) = &self.status {
+            if let Some(last) = lines.last_mut() {
+                *last = Line::new(status.clone()).yellow();
+            }
+        }
+        if let (UIState::PreCopy, Some(name)) = (&self.state, &self.profile_name) {
+            if let Some(last) = lines.last_mut() {
+                *last = Line::new(format!(
+                    "Save as profile: {}_ (Enter to save, Esc to cancel)",
+                    name
+                ))
+                .yellow();
+            }
+        }
+        lines.push(Line::new(footer));
+        for (row, line) in lines.iter().take(height).enumerate() {
+            line.queue(out, row as u16, width, self.theme)?;
+        }
+        out.flush()
+    }
+
+    ///
+    /// The destinations and source of the run, with a marker on the selected entry when
+    /// `selectable`
+    ///
+    fn pre_copy_lines(&self, lines: &mut Vec<Line>, selectable: bool) {
+        lines.push(Line::new("Destinations staged to be copied to:"));
+        let (payload, _) = self.payload();
+        let fits = self.fits();
+        let names = self
+            .destinations
+            .iter()
+            .zip(&self.described)
+            .map(|(dest, described)| match group_of(&self.groups, dest) {
+                Some(group) => format!("{} ({})", described, group),
+                None => described.clone(),
+            })
+            .collect::<Vec<_>>();
+        // Lined up, so the needed and available sizes read as a column
+        let column = names.iter().map(|name| name.chars().count()).max();
+        for (i, name) in names.iter().enumerate() {
+            let mut line = format!("  {:<1$}  ", name, column.unwrap_or_default());
+            // The margin only means something once the payload is counted
+            let too_small = match (fits.as_ref().map(|fits| fits[i]), self.free[i]) {
+                (Some(fit), free) => {
+                    line.push_str(&needed_vs_available(payload as u64, free, self.locale));
+                    // One that is too small doesn't get through to have an estimate
+                    if fit.too_small() && self.skip_too_small {
+                        line.push_str(", skipped");
+                    } else if let Some(speed) = self
+                        .calibrated
+                        .get(i)
+                        .copied()
+                        .flatten()
+                        .filter(|_| !fit.too_small())
+                    {
+                        line.push_str(&format!(
+                            ", about {} to copy",
+                            Delay(estimate(payload as u64, speed))
+                        ));
+                    }
+                    fit.too_small()
+                }
+                // Still counting, what is needed so far
+                (None, Some(free)) => {
+                    line.push_str(&format!(
+                        "{}+ needed / {} free",
+                        get_bytes_string(payload, self.locale),
+                        get_bytes_string(free as usize, self.locale)
+                    ));
+                    false
+                }
+                (None, None) => false,
+            };
+            lines.push(match too_small {
+                true => Line::new(line).red(),
+                false => Line::new(line).dark_grey(),
+            });
+
+            let stale = self
+                .stale
+                .as_ref()
+                .map(|stale| stale[i].keeping(&self.excluded()));
+            if let Some(stale) = stale.filter(|stale| !stale.files.is_empty()) {
+                lines.push(
+                    Line::new(format!(
+                        "    --mirror deletes {} stale file(s), {}",
+                        self.locale.format_number(stale.files.len() as u64),
+                        get_bytes_string(stale.bytes(), self.locale)
+                    ))
+                    .yellow(),
+                );
+            }
+            if let Some(deployed) = self.deployed(i) {
+                let line = format!(
+                    "    {} already contains this exact payload (deployed {} ago)",
+                    self.described[i],
+                    deployed.ago()
+                );
+                lines.push(match self.skip_deployed {
+                    true => Line::new(format!("{}, skipped", line)).dark_grey(),
+                    false => Line::new(format!("{}, skip it?", line)).yellow(),
+                });
+            }
+        }
+
+        let included = self.entries.iter().filter(|entry| !entry.excluded);
+        let (files, bytes) = included.fold((0, 0), |(files, bytes), entry| {
+            (files + entry.totals.files(), bytes + entry.totals.bytes())
+        });
+        let counting = match self.entries.iter().all(|entry| entry.totals.done()) {
+            true => String::new(),
+            false => {
+                let frame = self.opened.elapsed().as_millis() / SPINNER_FRAME.as_millis();
+                format!(", counting {}", SPINNER[frame as usize % SPINNER.len()])
+            }
+        };
+        let excluded = match self.entries.iter().filter(|entry| entry.excluded).count() {
+            0 => String::new(),
+            n => format!(", {} excluded", self.locale.format_number(n as u64)),
+        };
+        lines.push(Line::new(format!(
+            "Copying from `{}`... ({} file(s), {}{}{})",
+            self.source.display(),
+            self.locale.format_number(files as u64),
+            get_bytes_string(bytes, self.locale),
+            excluded,
+            counting
+        )));
+        for collision in self.collisions.iter().take(LISTED_FAILURES) {
+            lines.push(Line::new(format!("  {}", collision)).yellow());
+        }
+        if let Some(more) = self.collisions.len().checked_sub(LISTED_FAILURES) {
+            if more > 0 {
+                lines
+                    .push(Line::new(format!("  ... and {} more name collision(s)", more)).yellow());
+            }
+        }
+
+        // A window of the list that follows the selection
+        let shown = self.entries.len().min(PREVIEW_ROWS);
+        let first = self
+            .selected
+            .saturating_sub(shown.saturating_sub(1))
+            .min(self.entries.len() - shown);
+        for (i, entry) in self.entries.iter().enumerate().skip(first).take(shown) {
+            let marker = if selectable && i == self.selected {
+                ">"
+            } else {
+                " "
+            };
+            let name = badged_name(entry.path.as_os_str());
+            let line = match entry.excluded {
+                true => Line::new(format!("{} {} (excluded)", marker, name)).red(),
+                false => Line::new(format!(
+                    "{} {} ({})",
+                    marker,
+                    name,
+                    get_bytes_string(entry.totals.bytes(), self.locale)
+                ))
+                .dark_grey(),
+            };
+            lines.push(line);
+        }
+        if self.entries.len() > shown {
+            lines.push(Line::new(format!(
+                "  ... +{} more ...",
+                self.locale
+                    .format_number((self.entries.len() - shown) as u64)
+            )));
+        }
+    }
+
+    fn copying_lines(&self, lines: &mut Vec<Line>) {
+        if let Some(limit) = self
+            .throttle
+            .as_ref()
+            .and_then(|throttle| throttle.limit_now())
+        {
+            lines.push(
+                Line::new(format!(
+                    "Copy speed limited to {}/s",
+                    get_bytes_string(limit as usize, self.locale)
+                ))
+                .dark_grey(),
+            );
+        }
+        // Only worth pointing at a destination when there is more than one to cancel
+        let selectable = self.destination_cancels.len() > 1;
+        for (i, (dest, progress)) in self.destinations.iter().zip(&self.progress).enumerate() {
+            let marker = match selectable && i == self.selected_destination {
+                true => '>',
+                false => ' ',
+            };
+            if progress.cancelled {
+                lines.push(
+                    Line::new(format!(
+                        "{} {} cancelled [{} copied]",
+                        marker,
+                        dest.display(),
+                        get_bytes_string(progress.bytes_copied, self.locale)
+                    ))
+                    .yellow(),
+                );
+                continue;
+            }
+            let mut line = format!(
+                "{} {} {} {:>3} % [{} copied]",
+                marker,
+                dest.display(),
+                bar(progress.percent),
+                progress.percent,
+                get_bytes_string(progress.bytes_copied, self.locale)
+            );
+            if let Some(eta) = self.eta(i) {
+                line.push_str(&format!(
+                    " about {} left",
+                    Delay(Duration::from_secs(eta.as_secs().max(1)))
+                ));
+            }
+            lines.push(Line::new(line));
+            if let Some((file, file_bytes, file_size)) =
+                progress.file.as_ref().filter(|_| progress.percent < 100)
+            {
+                lines.push(
+                    Line::new(format!(
+                        "    {} ({} of {})",
+                        file.display(),
+                        get_bytes_string(*file_bytes, self.locale),
+                        get_bytes_string(*file_size, self.locale)
+                    ))
+                    .dark_grey(),
+                );
+            }
+            if let Some(percent) = progress.verify_percent {
+                lines.push(
+                    Line::new(format!("    verifying {} {:>3} %", bar(percent), percent)).cyan(),
+                );
+            }
+            if let Some(percent) = progress.repair_percent {
+                lines.push(
+                    Line::new(format!("    repairing {} {:>3} %", bar(percent), percent)).yellow(),
+                );
+            }
+            match progress.removal {
+                Some(Removal::Syncing) => {
+                    lines.push(Line::new("    flushing the write cache onto the drive").yellow())
+                }
+                Some(Removal::Ejecting(remaining)) => lines.push(
+                    Line::new(format!(
+                        "    ejecting, waiting for the OS to release the drive ({}s)",
+                        remaining.as_secs()
+                    ))
+                    .yellow(),
+                ),
+                Some(Removal::SafeToRemove) => lines.push(Line::new("    safe to remove").green()),
+                None => {}
+            }
+        }
+
+        // Every destination receives the same payload, so a group's progress is the average of
+        // its members'
+        for group in &self.groups {
+            let sum = group
+                .destinations
+                .iter()
+                .filter_map(|dest| self.destinations.iter().position(|d| d == dest))
+                .map(|i| self.progress[i].percent)
+                .sum::<usize>();
+            lines.push(
+                Line::new(format!(
+                    "  [{} {} %]",
+                    group.name,
+                    sum / group.destinations.len().max(1)
+                ))
+                .cyan(),
+            );
+        }
+    }
+
+    ///
+    /// A file the copy found already on a destination, with both copies of it side by side
+    ///
+    fn conflict_lines(&self, conflict: &Conflict, lines: &mut Vec<Line>) {
+        lines.push(Line::new("Already on the destination:").yellow());
+        lines.push(Line::new(format!(
+            "  `{}` on {}",
+            conflict.file.display(),
+            self.described
+                .get(conflict.dest)
+                .map_or("?", |described| described.as_str())
+        )));
+        lines.push(Line::new(""));
+        let version = |version: Option<FileVersion>| match version {
+            Some(FileVersion { size, modified }) => format!(
+                "{}, modified {}",
+                get_bytes_string(size as usize, self.locale),
+                modified.map_or("at an unknown time".to_string(), |modified| {
+                    DateTime::<Local>::from(modified)
+                        .format("%Y-%m-%d %H:%M:%S")
+                        .to_string()
+                })
+            ),
+            None => "read from stdin".to_string(),
+        };
+        lines.push(Line::new(format!(
+            "  source:    {}",
+            version(conflict.source)
+        )));
+        lines.push(Line::new(format!(
+            "  on drive:  {}",
+            match conflict.existing {
+                Some(_) => version(conflict.existing),
+                None => "no longer there".to_string(),
+            }
+        )));
+        lines.push(Line::new(""));
+        lines.push(Line::new("Overwrite it with the source's copy?"));
+    }
+
+    fn completed_lines(
+        &self,
+        summaries: &[DestinationSummary],
+        verifications: Option<&[Verification]>,
+        lines: &mut Vec<Line>,
+    ) {
+        let totals = completed_totals(summaries, self.locale);
+        match summaries
+            .iter()
+            .map(|summary| summary.failed.len())
+            .sum::<usize>()
+        {
+            0 => lines.push(Line::new(format!("Files finished copying, {}", totals)).green()),
+            failed => lines.push(
+                Line::new(format!(
+                    "Files finished copying, {}; {} file(s) could not be copied",
+                    totals, failed
+                ))
+                .yellow(),
+            ),
+        }
+        // Lined up, so the sizes and times read as columns
+        let names = summaries
+            .iter()
+            .map(|summary| summary.destination.display().to_string())
+            .collect::<Vec<_>>();
+        let column = names.iter().map(|name| name.chars().count()).max();
+        let sizes = summaries
+            .iter()
+            .map(|summary| get_bytes_string(summary.bytes_copied, self.locale))
+            .collect::<Vec<_>>();
+        let size_column = sizes.iter().map(|size| size.chars().count()).max();
+        for (i, summary) in summaries.iter().enumerate() {
+            if let Some(Removal::SafeToRemove) = self.progress.get(i).and_then(|p| p.removal) {
+                lines.push(
+                    Line::new(format!(
+                        "  {} safe to remove",
+                        summary.destination.display()
+                    ))
+                    .green(),
+                );
+            }
+            let mut line = format!(
+                "  {:<3$}  {:>4$} in {:5.1}s",
+                names[i],
+                sizes[i],
+                summary.duration.as_secs_f64(),
+                column.unwrap_or_default(),
+                size_column.unwrap_or_default()
+            );
+            match self.progress.get(i).map_or(0, |p| p.mirrored) {
+                0 => {}
+                mirrored => line.push_str(&format!(", {} stale file(s) deleted", mirrored)),
+            }
+            match self.progress.get(i).map_or(0, |p| p.cleaned) {
+                0 => {}
+                cleaned => line.push_str(&format!(", {} file(s) cleaned up", cleaned)),
+            }
+            if summary.cancelled {
+                line.push_str(", cancelled");
+            }
+            match self.progress.get(i).and_then(|p| p.committed) {
+                Some(true) => line.push_str(", swapped in"),
+                Some(false) => line.push_str(", rolled back"),
+                None => {}
+            }
+            match verifications.and_then(|v| v.get(i)) {
+                Some(v) if v.passed() => {
+                    line.push(' ');
+                    line.push_str(Message::Verified.text(self.locale));
+                    if !v.repaired.is_empty() {
+                        line.push_str(&format!(" ({} file(s) repaired)", v.repaired.len()));
+                    }
+                    lines.push(Line::new(line).green());
+                }
+                Some(v) => {
+                    line.push_str(&format!(
+                        " {} file(s) do not match the source",
+                        v.mismatches()
+                    ));
+                    lines.push(Line::new(line).red());
+                }
+                None => lines.push(Line::new(line)),
+            }
+            if !summary.failed.is_empty() {
+                lines.push(
+                    Line::new(format!(
+                        "    {} file(s) could not be copied:",
+                        summary.failed.len()
+                    ))
+                    .red(),
+                );
+                for failed in failed_file_lines(summary) {
+                    lines.push(Line::new(format!("      {}", failed)).red());
+                }
+            }
+            if !summary.changed.is_empty() {
+                lines.push(Line::new(format!("    {}:", changed_files_warning(summary))).yellow());
+                for file in summary.changed.iter().take(LISTED_FAILURES) {
+                    lines.push(Line::new(format!("      {}", file.display())).yellow());
+                }
+            }
+            if summary.scanned {
+                lines.push(
+                    Line::new(format!("    {}", Message::ScanSuspected.text(self.locale))).yellow(),
+                );
+            }
+        }
+        self.error_browser_lines(lines);
+    }
+
+    fn failed_lines(&self, e: &CopyError, lines: &mut Vec<Line>) {
+        let mut line = format!("{} {}", Message::CopyFailed.text(self.locale), e);
+        if let Some(dest) = &e.destination {
+            line.push_str(&format!(" (on `{}`)", dest.display()));
+        }
+        lines.push(Line::new(line).red());
+        self.error_browser_lines(lines);
+    }
+
+    ///
+    /// The error browser: a list of the errors and the details of the selected one
+    ///
+    fn error_browser_lines(&self, lines: &mut Vec<Line>) {
+        let errors = self.errors();
+        if errors.is_empty() {
+            return;
+        }
+        let selected = self.selected_error();
+
+        // The error that ended the run is named above already, it only gets the details
+        if errors.len() > 1 || errors[0].file.is_some() {
+            self.error_list_lines(&errors, selected, lines);
+        }
+
+        let error = &errors[selected];
+        lines.push(Line::new(""));
+        lines.push(Line::new(format!("  Path:  {}", error.path.display())));
+        lines.push(Line::new(format!("  Error: {}", error.error)).red());
+        if let Some(suggestion) = error.suggestion {
+            lines.push(Line::new(format!("  Fix:   {}", suggestion)).yellow());
+        }
+        lines.push(
+            Line::new(match &error.file {
+                Some(_) => "  Press r to copy this file again and re-check it",
+                None => "  Press r to run the copy again",
+            })
+            .dark_grey(),
+        );
+    }
+
+    ///
+    /// A window of the errors that follows the selection
+    ///
+    fn error_list_lines(&self, errors: &[ErrorEntry], selected: usize, lines: &mut Vec<Line>) {
+        lines.push(Line::new(""));
+        lines.push(Line::new(format!(
+            "Errors ({}):",
+            self.locale.format_number(errors.len() as u64)
+        )));
+        let shown = errors.len().min(BROWSED_ERRORS);
+        let first = selected.saturating_sub(shown - 1).min(errors.len() - shown);
+        for (i, error) in errors.iter().enumerate().skip(first).take(shown) {
+            let marker = if i == selected { ">" } else { " " };
+            let what = match &error.file {
+                Some(file) => file.display().to_string(),
+                None => error.path.display().to_string(),
+            };
+            let on = match error.dest.and_then(|dest| self.described.get(dest)) {
+                Some(described) => format!("{}: ", described),
+                None => String::new(),
+            };
+            lines.push(Line::new(format!("{} {}{} ({})", marker, on, what, error.error)).red());
+        }
+        if errors.len() > shown {
+            lines.push(Line::new(format!(
+                "  ... {} of {} ...",
+                selected + 1,
+                self.locale.format_number(errors.len() as u64)
+            )));
+        }
+    }
+}
+
+///
+/// What the operator can do about a file that failed verification for `reason`, see
+/// `Verification::failures`
+///
+fn verification_suggestion(reason: &str) -> Option<&'static str> {
+    match reason {
+        "missing" => Some("The file never made it onto the drive - copy it again"),
+        "size differs" => {
+            Some("The copy was cut short - check the drive has room and stays plugged in")
+        }
+        "contents differ" => {
+            Some("The drive gave back something else - copy it again, replace the drive if it keeps failing")
+        }
+        _ => None,
+    }
+}
+
+///
+/// The first few files that failed verification with why, and how many more there are
+///
+pub fn failure_lines(verification: &Verification) -> Vec<String> {
+    let mut lines = verification
+        .failures()
+        .take(LISTED_FAILURES)
+        .map(|(file, reason)| format!("{} ({})", file.display(), reason))
+        .collect::<Vec<_>>();
+    if let Some(more) = verification.mismatches().checked_sub(LISTED_FAILURES) {
+        if more > 0 {
+            lines.push(format!("... and {} more", more));
+        }
+    }
+    lines
+}
+
+///
+/// The first few files `--keep-going` could not copy to the destination of `summary`, with why,
+/// and how many more there are
+///
+pub fn failed_file_lines(summary: &DestinationSummary) -> Vec<String> {
+    let mut lines = summary
+        .failed
+        .iter()
+        .take(LISTED_FAILURES)
+        .map(ToString::to_string)
+        .collect::<Vec<_>>();
+    if let Some(more) = summary.failed.len().checked_sub(LISTED_FAILURES) {
+        if more > 0 {
+            lines.push(format!("... and {} more", more));
+        }
+    }
+    lines
+}
+
+///
+/// The warning about the files left out of the destination of `summary` for changing while they
+/// were read, see `--changing-files`
+///
+pub fn changed_files_warning(summary: &DestinationSummary) -> String {
+    format!(
+        "{} file(s) changed while they were read and were left out",
+        summary.changed.len()
+    )
+}
+
+///
+/// Whether `e` is worth retrying elevated, see `elevate::relaunch_elevated`
+///
+pub fn can_elevate(e: &CopyError) -> bool {
+    elevate::SUPPORTED && e.class() == ErrorClass::AccessDenied
+}
+
+///
+/// Forwards where the copy of each file is at to the UI, as `CopyingState::FileProgress`
+///
+struct FileUpdates {
+    destinations: Vec<PathBuf>,
+    updates: Sender<CopyingState>,
+}
+
+impl DeploymentHook for FileUpdates {
+    fn on_file_progress(&self, progress: &FileProgress) {
+        if let Some(dest) = self
+            .destinations
+            .iter()
+            .position(|d| d == progress.destination)
+        {
+            let _ = self.updates.send(CopyingState::FileProgress {
+                dest,
+                file: progress.file.to_path_buf(),
+                file_bytes: progress.file_bytes,
+                file_size: progress.file_size,
+            });
+        }
+    }
+}
+
+///
+/// Copies (and verifies) on the worker thread, forwarding progress to the UI. The UI may already
+/// be gone, so failed sends are ignored.
+///
+pub fn copy_in_background(queue: &mut CopyQueue, args: &Args, updates: &Sender<CopyingState>) {
+    let destinations = queue.destinations().to_vec();
+    queue.register_hook(Box::new(FileUpdates {
+        destinations: destinations.clone(),
+        updates: updates.clone(),
+    }));
+    let onpercentage = |percent: usize, dest: PathBuf, bytes_copied: usize| {
+        if let Some(dest) = destinations.iter().position(|d| *d == dest) {
+            let _ = updates.send(CopyingState::Progress {
+                dest,
+                percent,
+                bytes_copied,
+            });
+        }
+    };
+    match queue.start_mirror() {
+        Ok(mirrored) => {
+            for (dest, files) in mirrored.iter().enumerate() {
+                let _ = updates.send(CopyingState::Mirrored {
+                    dest,
+                    files: files.len(),
+                });
+            }
+        }
+        Err(e) => {
+            let _ = updates.send(CopyingState::Failed(e));
+            return;
+        }
+    }
+
+    let summaries = match queue.start_copy(Box::new(onpercentage), Box::new(|| {})) {
+        Ok(summaries) => summaries,
+        Err(e) => {
+            let _ = updates.send(CopyingState::Failed(e));
+            return;
+        }
+    };
+    // Nothing more happens to a copy that was cancelled
+    if summaries.iter().any(|summary| summary.cancelled) {
+        let _ = updates.send(CopyingState::Finished {
+            summaries,
+            verifications: None,
+            manifest_hash: None,
+        });
+        return;
+    }
+
+    let verifications = if args.verify {
+        let onprogress = |dest: usize, percent: usize| {
+            let _ = updates.send(CopyingState::Verifying { dest, percent });
+        };
+        let verified = queue
+            .start_verify(Box::new(onprogress))
+            .and_then(|verifications| {
+                if !args.repair || verifications.iter().all(Verification::passed) {
+                    return Ok(verifications);
+                }
+                let onprogress = |dest: usize, percent: usize| {
+                    let _ = updates.send(CopyingState::Repairing { dest, percent });
+                };
+                queue.start_repair(&verifications, Box::new(onprogress))
+            });
+        match verified {
+            Ok(verifications) => Some(verifications),
+            Err(e) => {
+                let _ = updates.send(CopyingState::Failed(e));
+                return;
+            }
+        }
+    } else {
+        None
+    };
+
+    match queue.start_clean() {
+        Ok(cleaned) => {
+            for (dest, files) in cleaned.iter().enumerate() {
+                let _ = updates.send(CopyingState::Cleaned {
+                    dest,
+                    files: files.len(),
+                });
+            }
+        }
+        Err(e) => {
+            let _ = updates.send(CopyingState::Failed(e));
+            return;
+        }
+    }
+
+    match queue.start_commit(&summaries, verifications.as_deref()) {
+        Ok(committed) if queue.transactional() => {
+            for (dest, committed) in committed.into_iter().enumerate() {
+                let _ = updates.send(CopyingState::Committed { dest, committed });
+            }
+        }
+        Ok(_) => {}
+        Err(e) => {
+            let _ = updates.send(CopyingState::Failed(e));
+            return;
+        }
+    }
+
+    if args.sync {
+        for (dest, path) in destinations.iter().enumerate() {
+            let _ = updates.send(CopyingState::Syncing { dest });
+            if let Err(e) = drive::sync(path) {
+                let _ = updates.send(CopyingState::Failed(CopyError::new(path, Some(path), e)));
+                return;
+            }
+            // Ejecting says so once the OS let go of the drive
+            if !args.eject {
+                let _ = updates.send(CopyingState::SafeToRemove { dest });
+            }
+        }
+    }
+
+    if args.eject {
+        for (dest, path) in destinations.iter().enumerate() {
+            let onwait = |remaining| {
+                let _ = updates.send(CopyingState::Ejecting { dest, remaining });
+            };
+            if let Err(e) = drive::eject_and_wait(path, onwait) {
+                let _ = updates.send(CopyingState::Failed(CopyError::new(path, Some(path), e)));
+                return;
+            }
+            let _ = updates.send(CopyingState::SafeToRemove { dest });
+        }
+    }
+
+    let _ = updates.send(CopyingState::Finished {
+        summaries,
+        verifications,
+        manifest_hash: (args.verify || args.qr).then(|| queue.manifest().digest()),
+    });
+}
+
+///
+/// Tries `retry` again on the worker thread: the whole run, or one file that failed
+/// verification, which is copied again and re-checked before the Completed screen comes back
+///
+pub fn retry_in_background(
+    queue: &mut CopyQueue,
+    args: &Args,
+    retry: Retry,
+    updates: &Sender<CopyingState>,
+) {
+    let Retry::File {
+        dest,
+        file,
+        summaries,
+        mut verifications,
+        manifest_hash,
+    } = retry
+    else {
+        return copy_in_background(queue, args, updates);
+    };
+    // What a transactional run staged is gone by now, it can only be tried again as a whole
+    if queue.transactional() {
+        return copy_in_background(queue, args, updates);
+    }
+
+    let onprogress = |percent: usize| {
+        let _ = updates.send(CopyingState::Repairing { dest, percent });
+    };
+    match queue.repair_file(&verifications[dest], &file, onprogress) {
+        Ok(verification) => verifications[dest] = verification,
+        Err(e) => {
+            let _ = updates.send(CopyingState::Failed(e));
+            return;
+        }
+    }
+    let _ = updates.send(CopyingState::Finished {
+        summaries,
+        verifications: Some(verifications),
+        manifest_hash,
+    });
+}
+
+///
+/// Source of terminal events, so the event loop can be driven by something other than a real
+/// terminal
+///
+pub trait EventSource {
+    /// Waits up to `timeout` for the next event
+    fn poll(&mut self, timeout: Duration) -> ::std::io::Result<Option<Event>>;
+}
+
+///
+/// Events from the terminal the process is attached to
+///
+pub struct TerminalEvents;
+
+impl EventSource for TerminalEvents {
+    fn poll(&mut self, timeout: Duration) -> ::std::io::Result<Option<Event>> {
+        if crossterm::event::poll(timeout)? {
+            crossterm::event::read().map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+///
+/// Runs the UI until the operator confirms the PreCopy screen, the Waiting screen's countdown
+/// runs out, or the operator quits. Progress from the copy worker is picked up between events,
+/// so a busy worker never waits on the UI.
+///
+pub fn run(
+    ui: &mut Ui,
+    events: &mut impl EventSource,
+    out: &mut impl Write,
+) -> ::std::io::Result<UiAction> {
+    loop {
+        match ui.tick() {
+            UiAction::None => {}
+            action => return Ok(action),
+        }
+        ui.render(out)?;
+        if ui.bells.front().is_some_and(|due| *due <= Instant::now()) {
+            ui.bells.pop_front();
+            queue!(out, Print('\x07'))?;
+            out.flush()?;
+        }
+        if let Some(event) = events.poll(TICK)? {
+            match ui.handle_event(event) {
+                UiAction::None => {}
+                action => return Ok(action),
+            }
+        }
+    }
+}
+
+///
+/// Switches the terminal to the alternate screen (and raw mode when `raw` is set) for as long as
+/// it lives. Everything is put back on drop, including when unwinding from a panic.
+///
+pub struct Terminal<W: Write> {
+    out: W,
+    raw: bool,
+}
+
+impl<W: Write> Terminal<W> {
+    pub fn enter(out: W, raw: bool) -> ::std::io::Result<Self> {
+        if raw {
+            enable_raw_mode()?;
+        }
+        // Built before writing anything so a failed write still restores raw mode on drop
+        let mut terminal = Self { out, raw };
+        queue!(terminal.out, EnterAlternateScreen, Hide)?;
+        terminal.out.flush()?;
+        Ok(terminal)
+    }
+}
+
+impl<W: Write> Write for Terminal<W> {
+    fn write(&mut self, buf: &[u8]) -> ::std::io::Result<usize> {
+        self.out.write(buf)
+    }
+
+    fn flush(&mut self) -> ::std::io::Result<()> {
+        self.out.flush()
+    }
+}
+
+impl<W: Write> Drop for Terminal<W> {
+    fn drop(&mut self) {
+        let _ = queue!(self.out, Show, LeaveAlternateScreen);
+        let _ = self.out.flush();
+        if self.raw {
+            let _ = disable_raw_mode();
+        }
+    }
+}
+
+///
+/// A line of the frame with the color it is drawn in
+///
+pub(crate) struct Line {
+    text: String,
+    style: fn(String) -> crossterm::style::StyledContent<String>,
+}
+
+impl Line {
+    pub(crate) fn new(text: impl Into<String>) -> Self {
+        Self {
+            text: text.into(),
+            style: Stylize::reset,
+        }
+    }
+
+    pub(crate) fn magenta(self) -> Self {
+        Self {
+            style: Stylize::magenta,
+            ..self
+        }
+    }
+
+    pub(crate) fn dark_grey(self) -> Self {
+        Self {
+            style: Stylize::dark_grey,
+            ..self
+        }
+    }
+
+    pub(crate) fn cyan(self) -> Self {
+        Self {
+            style: Stylize::cyan,
+            ..self
+        }
+    }
+
+    pub(crate) fn green(self) -> Self {
+        Self {
+            style: Stylize::green,
+            ..self
+        }
+    }
+
+    pub(crate) fn red(self) -> Self {
+        Self {
+            style: Stylize::red,
+            ..self
+        }
+    }
+
+    pub(crate) fn yellow(self) -> Self {
+        Self {
+            style: Stylize::yellow,
+            ..self
+        }
+    }
+
+    pub(crate) fn queue(
+        &self,
+        out: &mut impl Write,
+        row: u16,
+        width: usize,
+        theme: Theme,
+    ) -> ::std::io::Result<()> {
+        let text = self.text.chars().take(width).collect::<String>();
+        queue!(out, MoveTo(0, row))?;
+        match theme {
+            Theme::Default => queue!(out, Print((self.style)(text)))?,
+            Theme::Plain => queue!(out, Print(text))?,
+        }
+        queue!(out, Clear(ClearType::UntilNewLine))
+    }
+}
+
+///
+/// Draws a note asking for a larger terminal, centered, in place of a layout that doesn't fit
+/// `size`, and returns whether it had to. The layout is back with the first frame after a resize
+/// that makes room for it.
+///
+pub(crate) fn too_small(
+    out: &mut impl Write,
+    size: (u16, u16),
+    theme: Theme,
+) -> ::std::io::Result<bool> {
+    if size.0 >= MIN_SIZE.0 && size.1 >= MIN_SIZE.1 {
+        return Ok(false);
+    }
+
+    let (width, height) = (size.0 as usize, size.1 as usize);
+    let need = format!("(need {}×{})", MIN_SIZE.0, MIN_SIZE.1);
+    let message = match width > "terminal too small ".len() + need.chars().count() {
+        true => vec![format!("terminal too small {}", need)],
+        false => vec!["terminal too small".to_string(), need],
+    };
+    let top = height.saturating_sub(message.len()) / 2;
+    for row in 0..height {
+        let text = match row.checked_sub(top).and_then(|i| message.get(i)) {
+            Some(text) => format!(
+                "{}{}",
+                " ".repeat(width.saturating_sub(text.chars().count()) / 2),
+                text
+            ),
+            None => String::new(),
+        };
+        Line::new(text)
+            .yellow()
+            .queue(out, row as u16, width, theme)?;
+    }
+    out.flush()?;
+    Ok(true)
+}
+
+///
+/// What a finished run copied over all, e.g. "30mb to 3 destination(s) in 12.5s"
+///
+pub fn completed_totals(summaries: &[DestinationSummary], locale: Locale) -> String {
+    // The destinations were copied side by side, so the run took as long as the slowest
+    let duration = summaries
+        .iter()
+        .map(|summary| summary.duration)
+        .max()
+        .unwrap_or_default();
+    format!(
+        "{} to {} destination(s) in {:.1}s",
+        get_bytes_string(
+            summaries.iter().map(|summary| summary.bytes_copied).sum(),
+            locale
+        ),
+        summaries.len(),
+        duration.as_secs_f64()
+    )
+}
+
+fn bar(percent: usize) -> String {
+    const WIDTH: usize = 30;
+    let filled = percent.min(100) * WIDTH / 100;
+    format!("[{}{}]", "#".repeat(filled), " ".repeat(WIDTH - filled))
+}
+
+pub fn get_bytes_string(bytes: usize, locale: Locale) -> String {
+    let (unit, suffix) = match bytes {
+        bytes if bytes >= 1024usize.pow(4) => (1024usize.pow(4), "tb"),
+        bytes if bytes >= 1024usize.pow(3) => (1024usize.pow(3), "gb"),
+        bytes if bytes >= 1024usize.pow(2) => (1024usize.pow(2), "mb"),
+        bytes if bytes >= 1024 => (1024, "kb"),
+        n => return format!("{}b", locale.format_number(n as u64)),
+    };
+
+    let tenths = (bytes as u128 * 10 / unit as u128) as u64;
+    format!(
+        "{}{}{}{}",
+        locale.format_number(tenths / 10),
+        locale.decimal_separator(),
+        tenths % 10,
+        suffix
+    )
+}
@@ -0,0 +1,200 @@
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::{
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+/// Where the latest release is looked up, see https://docs.github.com/en/rest/releases
+const LATEST_RELEASE_URL: &str =
+    "https://api.github.com/repos/jeremyirvine/deployment-copy/releases/latest";
+
+/// How long a download may take before giving up, in seconds
+const DOWNLOAD_TIMEOUT: &str = "300";
+
+/// How long the check at startup (`--check-updates`) may hold up a run, in seconds
+const CHECK_TIMEOUT: &str = "5";
+
+///
+/// A published release, as far as updating is concerned
+///
+#[derive(Debug, Clone, Deserialize)]
+pub struct Release {
+    /// The release's git tag, e.g. `v0.2.0`
+    #[serde(rename = "tag_name")]
+    pub tag: String,
+    assets: Vec<Asset>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct Asset {
+    name: String,
+    browser_download_url: String,
+}
+
+impl Release {
+    ///
+    /// Whether this release is newer than the running binary
+    ///
+    pub fn is_newer(&self) -> bool {
+        match (
+            parse_version(&self.tag),
+            parse_version(env!("CARGO_PKG_VERSION")),
+        ) {
+            (Some(latest), Some(current)) => latest > current,
+            _ => false,
+        }
+    }
+
+    fn asset(&self, name: &str) -> Option<&Asset> {
+        self.assets.iter().find(|asset| asset.name == name)
+    }
+}
+
+///
+/// What `self_update` did
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UpdateOutcome {
+    /// The running binary is already the latest release
+    UpToDate(String),
+    /// The binary at the path was replaced with this release, it takes effect on the next start
+    Updated(String, PathBuf),
+}
+
+///
+/// The name of the release asset for this platform, e.g. `decopy-x86_64-windows.exe`. A
+/// `.sha256` asset next to it, holding the hex SHA-256 of the binary, has to be there too.
+///
+pub fn asset_name() -> String {
+    format!(
+        "decopy-{}-{}{}",
+        ::std::env::consts::ARCH,
+        ::std::env::consts::OS,
+        ::std::env::consts::EXE_SUFFIX
+    )
+}
+
+///
+/// Looks up the latest release, giving up after `timeout` seconds. Downloads go through the
+/// `curl` command, which ships with Windows 10 and later and most Linux distributions, so no
+/// HTTP or TLS stack has to be built in.
+///
+fn latest_release(timeout: &str) -> Result<Release, String> {
+    let body = fetch(LATEST_RELEASE_URL, timeout)?;
+    serde_json::from_slice(&body).map_err(|e| format!("unexpected answer from GitHub: {}", e))
+}
+
+///
+/// The latest release if it is newer than the running binary, for the opt-in check at startup
+///
+pub fn check() -> Result<Option<Release>, String> {
+    latest_release(CHECK_TIMEOUT).map(|release| release.is_newer().then_some(release))
+}
+
+///
+/// Replaces the running binary with the latest release for this platform (`self-update`)
+///
+pub fn self_update() -> Result<UpdateOutcome, String> {
+    let release = latest_release(CHECK_TIMEOUT)?;
+    if !release.is_newer() {
+        return Ok(UpdateOutcome::UpToDate(release.tag));
+    }
+
+    let name = asset_name();
+    let asset = release.asset(&name).ok_or_else(|| {
+        format!(
+            "release {} has no build for this platform ({})",
+            release.tag, name
+        )
+    })?;
+    // An unchecked binary never replaces the running one
+    let checksum = release.asset(&format!("{}.sha256", name)).ok_or_else(|| {
+        format!(
+            "release {} has no checksum for {}, nothing was replaced",
+            release.tag, name
+        )
+    })?;
+    let binary = fetch(&asset.browser_download_url, DOWNLOAD_TIMEOUT)?;
+    let expected = String::from_utf8_lossy(&fetch(&checksum.browser_download_url, CHECK_TIMEOUT)?)
+        .split_whitespace()
+        .next()
+        .unwrap_or_default()
+        .to_lowercase();
+    let actual = format!("{:x}", Sha256::digest(&binary));
+    if actual != expected {
+        return Err(format!(
+            "the download of {} doesn't match its checksum, nothing was replaced",
+            name
+        ));
+    }
+
+    let exe = ::std::env::current_exe()
+        .map_err(|e| format!("could not locate the running binary: {}", e))?;
+    replace_binary(&exe, &binary)
+        .map_err(|e| format!("could not replace `{}`: {}", exe.display(), e))?;
+    Ok(UpdateOutcome::Updated(release.tag, exe))
+}
+
+///
+/// Writes `binary` next to `exe` and moves it into place. The running binary can't be
+/// overwritten on Windows but can be renamed, so it is set aside as `.old` first and removed by
+/// the next update.
+///
+fn replace_binary(exe: &Path, binary: &[u8]) -> ::std::io::Result<()> {
+    let new = exe.with_extension("new");
+    let old = exe.with_extension("old");
+    let _ = ::std::fs::remove_file(&old);
+
+    ::std::fs::write(&new, binary)?;
+    #[cfg(unix)]
+    {
+        use ::std::os::unix::fs::PermissionsExt;
+        ::std::fs::set_permissions(&new, ::std::fs::Permissions::from_mode(0o755))?;
+    }
+
+    ::std::fs::rename(exe, &old)?;
+    if let Err(e) = ::std::fs::rename(&new, exe) {
+        // Put the old binary back rather than leave nothing behind
+        let _ = ::std::fs::rename(&old, exe);
+        return Err(e);
+    }
+    #[cfg(not(windows))]
+    let _ = ::std::fs::remove_file(&old);
+    Ok(())
+}
+
+pub(crate) fn fetch(url: &str, timeout: &str) -> Result<Vec<u8>, String> {
+    let output = Command::new("curl")
+        .args(["--fail", "--silent", "--show-error", "--location"])
+        .args(["--max-time", timeout])
+        .args([
+            "--user-agent",
+            concat!("decopy/", env!("CARGO_PKG_VERSION")),
+        ])
+        .arg(url)
+        .output()
+        .map_err(|e| format!("could not run curl: {}", e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "could not download `{}`: {}",
+            url,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(output.stdout)
+}
+
+///
+/// `v1.2.3` or `1.2.3` as numbers that compare in version order, ignoring anything after a `-`
+///
+fn parse_version(version: &str) -> Option<(u64, u64, u64)> {
+    let version = version.trim_start_matches('v');
+    let mut parts = version.split('-').next()?.split('.').map(str::parse);
+    let version = (
+        parts.next()?.ok()?,
+        parts.next().unwrap_or(Ok(0)).ok()?,
+        parts.next().unwrap_or(Ok(0)).ok()?,
+    );
+    Some(version)
+}
@@ -0,0 +1,446 @@
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::{
+    cell::Cell,
+    collections::BTreeMap,
+    fs::{File, Metadata},
+    path::{Path, PathBuf},
+    sync::{
+        mpsc::{channel, Receiver, Sender},
+        Arc, Mutex,
+    },
+    thread::JoinHandle,
+};
+
+use crate::{
+    copy::MTIME_TOLERANCE,
+    drive,
+    error::CopyError,
+    hash::{sha256_file, sha256_with_progress},
+    preserve::{fat_time, Preserve},
+    symlink::SymlinkPolicy,
+    walk::{only_in, walk_ahead, Entry, Selection, Source, Walk},
+};
+
+///
+/// What `--verify` compares besides the contents (`--verify-metadata`)
+///
+#[derive(ValueEnum, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum MetadataCheck {
+    /// The modification time, as close as the destination keeps it
+    Times,
+    /// The Unix mode bits, or just whether the file is read-only on Windows and FAT
+    Perms,
+    /// Where links recreated by `--symlinks preserve` point, otherwise they only have to be there
+    Links,
+    /// None of them, just the contents
+    None,
+}
+
+impl MetadataCheck {
+    ///
+    /// The checks that match what a run carries over to its copies: the `--preserve`d times and
+    /// permissions, and the links of `--symlinks preserve`
+    ///
+    pub fn implied(preserve: &[Preserve], symlinks: SymlinkPolicy) -> Vec<Self> {
+        let mut checks = Vec::new();
+        if preserve.contains(&Preserve::Times) {
+            checks.push(MetadataCheck::Times);
+        }
+        if preserve.contains(&Preserve::Perms) {
+            checks.push(MetadataCheck::Perms);
+        }
+        if symlinks == SymlinkPolicy::Preserve {
+            checks.push(MetadataCheck::Links);
+        }
+        checks
+    }
+}
+
+///
+/// Outcome of re-reading one destination after the copy. Paths are relative to the source, so a
+/// re-copy can target just the broken files.
+///
+#[derive(Debug, Clone, Default)]
+pub struct Verification {
+    pub destination: PathBuf,
+    /// Files of the source that aren't on the destination at all
+    pub missing: Vec<PathBuf>,
+    /// Files whose size differs from the source, typically cut short by a full or pulled drive
+    pub size_mismatch: Vec<PathBuf>,
+    /// Files of the right size whose contents differ from the source
+    pub corrupted: Vec<PathBuf>,
+    /// Files whose modification time differs from the source's, under `--verify-metadata times`
+    pub time_mismatch: Vec<PathBuf>,
+    /// Files whose permissions differ from the source's, under `--verify-metadata perms`
+    pub perms_mismatch: Vec<PathBuf>,
+    /// Preserved links that point elsewhere than in the source, under `--verify-metadata links`
+    pub target_mismatch: Vec<PathBuf>,
+    /// Files on the destination that aren't in the source. Reported, but they don't fail the
+    /// verification since drives may well carry other things.
+    pub extra: Vec<PathBuf>,
+    /// Files that failed at first and matched after `--repair` copied them again
+    pub repaired: Vec<PathBuf>,
+}
+
+impl Verification {
+    pub fn passed(&self) -> bool {
+        self.mismatches() == 0
+    }
+
+    ///
+    /// How many files don't match the source, whatever the reason
+    ///
+    pub fn mismatches(&self) -> usize {
+        self.missing.len()
+            + self.size_mismatch.len()
+            + self.corrupted.len()
+            + self.time_mismatch.len()
+            + self.perms_mismatch.len()
+            + self.target_mismatch.len()
+    }
+
+    ///
+    /// Every file that doesn't match the source, in no particular order
+    ///
+    pub fn failed_files(&self) -> impl Iterator<Item = &PathBuf> {
+        self.missing
+            .iter()
+            .chain(&self.size_mismatch)
+            .chain(&self.corrupted)
+            .chain(&self.time_mismatch)
+            .chain(&self.perms_mismatch)
+            .chain(&self.target_mismatch)
+    }
+
+    ///
+    /// Every file that doesn't match the source with why, in the order `failed_files` gives
+    ///
+    pub fn failures(&self) -> impl Iterator<Item = (&PathBuf, &'static str)> {
+        let missing = self.missing.iter().map(|file| (file, "missing"));
+        let size = self.size_mismatch.iter().map(|file| (file, "size differs"));
+        let corrupted = self.corrupted.iter().map(|file| (file, "contents differ"));
+        let time = self
+            .time_mismatch
+            .iter()
+            .map(|file| (file, "modification time differs"));
+        let perms = self
+            .perms_mismatch
+            .iter()
+            .map(|file| (file, "permissions differ"));
+        let target = self
+            .target_mismatch
+            .iter()
+            .map(|link| (link, "link points elsewhere"));
+        missing
+            .chain(size)
+            .chain(corrupted)
+            .chain(time)
+            .chain(perms)
+            .chain(target)
+    }
+}
+
+///
+/// Re-reads every file of the source on `dest` and compares its size and SHA-256, and the
+/// metadata of `checks`, then lists what else is on `dest`. Source hashes missing from
+/// `source_hashes` are computed on the fly. What `selection` leaves out of the source is
+/// skipped, so what it left on `dest` counts as extra.
+///
+/// Callbacks:
+/// * `onprogress` - `|bytes_verified: usize| -> ()`
+///
+pub fn verify_destination(
+    source: &Source,
+    selection: &Selection,
+    dest: &Path,
+    source_hashes: &BTreeMap<PathBuf, String>,
+    checks: &[MetadataCheck],
+    onprogress: impl FnMut(usize),
+) -> Result<Verification, CopyError> {
+    let source_walk = || selection.walk(source);
+    let mut verification = compare_files(
+        source,
+        dest,
+        walk_ahead(source_walk()),
+        source_hashes,
+        checks,
+        onprogress,
+    )
+    .map_err(|e| CopyError::new(&source.name(), None, e))?;
+
+    // Best effort, a destination that can't be listed completely just reports no extras
+    let broken = Cell::new(false);
+    let files = |walk: Walk| {
+        walk.map_while(|entry| entry.map_err(|_| broken.set(true)).ok())
+            .filter_map(|entry| match entry {
+                Entry::File(file, _) | Entry::Link(file, _) => Some(file),
+                Entry::Dir(_) => None,
+            })
+    };
+    let dest_walk = Walk::new(dest).with_symlinks(selection.symlinks);
+    let extra = only_in(files(source_walk()), files(dest_walk));
+    if !broken.get() {
+        verification.extra = extra;
+    }
+    Ok(verification)
+}
+
+///
+/// The file by file part of `verify_destination`, for the files in `entries` and without
+/// looking for extra files
+///
+pub(crate) fn compare_files(
+    source: &Source,
+    dest: &Path,
+    entries: impl IntoIterator<Item = ::std::io::Result<Entry>>,
+    source_hashes: &BTreeMap<PathBuf, String>,
+    checks: &[MetadataCheck],
+    mut onprogress: impl FnMut(usize),
+) -> ::std::io::Result<Verification> {
+    let mut verification = Verification {
+        destination: dest.to_path_buf(),
+        ..Verification::default()
+    };
+    // FAT holds less of the metadata, it is compared the way `--preserve` put it there
+    let fat = !checks.is_empty() && drive::is_fat(dest);
+    let mut verified_bytes = 0;
+    for entry in entries {
+        let (file, size) = match entry? {
+            Entry::File(file, size) => (file, size),
+            Entry::Link(link, symlink) => {
+                match ::std::fs::read_link(dest.join(&link)) {
+                    Err(_) => verification.missing.push(link),
+                    Ok(target)
+                        if target != symlink.target && checks.contains(&MetadataCheck::Links) =>
+                    {
+                        verification.target_mismatch.push(link)
+                    }
+                    Ok(_) => {}
+                }
+                continue;
+            }
+            Entry::Dir(_) => continue,
+        };
+        let base = verified_bytes;
+        verified_bytes = base + size;
+        let metadata = match ::std::fs::metadata(dest.join(&file)) {
+            Err(_) => {
+                verification.missing.push(file);
+                onprogress(verified_bytes);
+                continue;
+            }
+            Ok(metadata) if metadata.len() as usize != size => {
+                verification.size_mismatch.push(file);
+                onprogress(verified_bytes);
+                continue;
+            }
+            Ok(metadata) => metadata,
+        };
+
+        let mut hashed_bytes = base;
+        let expected = match source_hashes.get(&file) {
+            Some(hash) => Some(hash.clone()),
+            None => sha256_file(&source.path(&file)).ok(),
+        };
+
+        let actual = File::open(dest.join(&file)).and_then(|f| {
+            sha256_with_progress(f, |read| {
+                hashed_bytes += read;
+                onprogress(hashed_bytes);
+            })
+        });
+
+        if expected.is_none() || actual.ok() != expected {
+            verification.corrupted.push(file);
+        } else if let Ok(original) = ::std::fs::metadata(source.path(&file)) {
+            match metadata_differs(&original, &metadata, checks, fat) {
+                Some(MetadataCheck::Times) => verification.time_mismatch.push(file),
+                Some(MetadataCheck::Perms) => verification.perms_mismatch.push(file),
+                Some(MetadataCheck::Links | MetadataCheck::None) | None => {}
+            }
+        }
+        onprogress(verified_bytes);
+    }
+    Ok(verification)
+}
+
+type VerifyJob = (usize, PathBuf, Arc<BTreeMap<PathBuf, String>>);
+
+///
+/// What the workers of a `VerifyPool` report, tagged with the index the destination was
+/// submitted under
+///
+enum VerifyEvent {
+    Progress(usize, usize),
+    Done(usize, Box<Result<Verification, CopyError>>),
+}
+
+///
+/// A pool of worker threads that verifies several destinations side by side, so a run isn't
+/// held up by checking its drives one after the other. Destinations are queued with `submit`,
+/// also while others are still being copied, and the results are collected with `finish`.
+///
+pub struct VerifyPool {
+    jobs: Option<Sender<VerifyJob>>,
+    events: Receiver<VerifyEvent>,
+    workers: Vec<JoinHandle<()>>,
+    submitted: Vec<usize>,
+}
+
+impl VerifyPool {
+    ///
+    /// Starts `threads` workers comparing destinations against `source`, see
+    /// `verify_destination`. With `streamed` they compare just those files instead, for a source
+    /// read from stdin that can't be walked.
+    ///
+    pub fn new(
+        threads: usize,
+        source: Source,
+        selection: Selection,
+        checks: Vec<MetadataCheck>,
+        streamed: Option<Vec<Entry>>,
+    ) -> Self {
+        let (jobs, job_rx) = channel::<VerifyJob>();
+        let (event_tx, events) = channel();
+        let job_rx = Arc::new(Mutex::new(job_rx));
+        let shared = Arc::new((source, selection, checks, streamed));
+
+        let workers = (0..threads.max(1))
+            .map(|_| {
+                let job_rx = job_rx.clone();
+                let event_tx = event_tx.clone();
+                let shared = shared.clone();
+                ::std::thread::spawn(move || loop {
+                    let job = job_rx.lock().unwrap().recv();
+                    let Ok((index, dest, source_hashes)) = job else {
+                        break;
+                    };
+                    let (source, selection, checks, streamed) = &*shared;
+                    let onprogress = |bytes| {
+                        let _ = event_tx.send(VerifyEvent::Progress(index, bytes));
+                    };
+                    let verification = match streamed {
+                        None => verify_destination(
+                            source,
+                            selection,
+                            &dest,
+                            &source_hashes,
+                            checks,
+                            onprogress,
+                        ),
+                        // No listing of extra files, there is no source to list
+                        Some(streamed) => compare_files(
+                            source,
+                            &dest,
+                            streamed.iter().cloned().map(Ok),
+                            &source_hashes,
+                            checks,
+                            onprogress,
+                        )
+                        .map_err(|e| CopyError::new(&source.name(), None, e)),
+                    };
+                    if event_tx
+                        .send(VerifyEvent::Done(index, Box::new(verification)))
+                        .is_err()
+                    {
+                        break;
+                    }
+                })
+            })
+            .collect();
+
+        Self {
+            jobs: Some(jobs),
+            events,
+            workers,
+            submitted: Vec::new(),
+        }
+    }
+
+    ///
+    /// Queues `dest` for verification, reported under `index`. Source files missing from
+    /// `source_hashes` are hashed again by the worker.
+    ///
+    pub fn submit(
+        &mut self,
+        index: usize,
+        dest: PathBuf,
+        source_hashes: Arc<BTreeMap<PathBuf, String>>,
+    ) {
+        if let Some(jobs) = &self.jobs {
+            jobs.send((index, dest, source_hashes))
+                .expect("verify workers exited early");
+            self.submitted.push(index);
+        }
+    }
+
+    ///
+    /// Whether `index` was submitted already
+    ///
+    pub fn submitted(&self, index: usize) -> bool {
+        self.submitted.contains(&index)
+    }
+
+    ///
+    /// Waits for every submitted destination to be verified and returns the results by index
+    ///
+    /// Callbacks:
+    /// * `onprogress` - `|index: usize, bytes_verified: usize| -> ()`, including what was
+    ///   verified before `finish` was called
+    ///
+    pub fn finish(
+        mut self,
+        mut onprogress: impl FnMut(usize, usize),
+    ) -> BTreeMap<usize, Result<Verification, CopyError>> {
+        self.jobs = None;
+        let mut results = BTreeMap::new();
+        // Every worker holds a sender until it exits, which it does once the jobs run out
+        while let Ok(event) = self.events.recv() {
+            match event {
+                VerifyEvent::Progress(index, bytes) => onprogress(index, bytes),
+                VerifyEvent::Done(index, verification) => {
+                    results.insert(index, *verification);
+                }
+            }
+        }
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+        results
+    }
+}
+
+///
+/// The first of `checks` that `copy`, on a destination, fails against `original`, the source
+/// file it was copied from
+///
+fn metadata_differs(
+    original: &Metadata,
+    copy: &Metadata,
+    checks: &[MetadataCheck],
+    fat: bool,
+) -> Option<MetadataCheck> {
+    checks.iter().copied().find(|check| match check {
+        MetadataCheck::Times => match (original.modified(), copy.modified()) {
+            (Ok(original), Ok(copy)) => {
+                let original = match fat {
+                    true => fat_time(original),
+                    false => original,
+                };
+                let drift = match original > copy {
+                    true => original.duration_since(copy),
+                    false => copy.duration_since(original),
+                };
+                drift.map_or(true, |drift| drift > MTIME_TOLERANCE)
+            }
+            _ => false,
+        },
+        MetadataCheck::Perms => match fat {
+            true => original.permissions().readonly() != copy.permissions().readonly(),
+            false => original.permissions() != copy.permissions(),
+        },
+        MetadataCheck::Links | MetadataCheck::None => false,
+    })
+}
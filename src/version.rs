@@ -0,0 +1,101 @@
+use serde::Serialize;
+
+use crate::{elevate, offload, update};
+
+///
+/// What this binary is and can do, for `--version --json`. Orchestration scripts check this
+/// rather than guessing from the version number. Keys are only ever added, never renamed.
+///
+#[derive(Serialize, Debug, Clone)]
+pub struct VersionInfo {
+    pub name: &'static str,
+    pub version: &'static str,
+    pub target: Target,
+    /// Cargo features this binary was built with, e.g. `gui`
+    pub features: Vec<&'static str>,
+    /// The ways of copying that can be picked: `sequential`, `parallel` (`--jobs`), `fan-out` and
+    /// `image` (`--image`)
+    pub copy_engines: Vec<&'static str>,
+    pub capabilities: Capabilities,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct Target {
+    pub os: &'static str,
+    pub arch: &'static str,
+    pub family: &'static str,
+}
+
+///
+/// Platform dependent parts of the feature set. Anything `false` is accepted on the command line
+/// but has no effect, or fails at run time, on this platform.
+///
+#[derive(Serialize, Debug, Clone)]
+pub struct Capabilities {
+    /// `--eject` can unmount and power off drives
+    pub eject: bool,
+    /// How drives are ejected: `udisks` (falling back to `umount`), `ioctl` or `diskutil`
+    pub eject_backend: Option<&'static str>,
+    /// Drive hotplug is followed through udev. Destinations are given up front instead.
+    pub udev: bool,
+    /// The source is copied from a Volume Shadow Copy snapshot. Open files are read as they are.
+    pub vss: bool,
+    /// Destinations can be given as `\\?\Volume{GUID}\` paths
+    pub volume_guid_paths: bool,
+    /// A run that failed on permissions can be continued as administrator
+    pub elevate: bool,
+    /// `--nice-io` lowers the I/O priority rather than just the CPU priority
+    pub io_priority: bool,
+    /// `self-update` can replace this binary, its release asset name is `self_update_asset`
+    pub self_update: bool,
+    pub self_update_asset: String,
+    /// `--then-copy` can mount a partition of a freshly written image to copy into
+    pub partition_mount: bool,
+    /// What `--engine auto` copies files with besides its own buffer, e.g. `CopyFileExW`
+    pub native_engine: Option<&'static str>,
+}
+
+///
+/// The version and capabilities of the running binary
+///
+pub fn info() -> VersionInfo {
+    let mut features = Vec::new();
+    if cfg!(feature = "gui") {
+        features.push("gui");
+    }
+
+    let eject_backend = if cfg!(target_os = "linux") {
+        Some("udisks")
+    } else if cfg!(windows) {
+        Some("ioctl")
+    } else if cfg!(target_os = "macos") {
+        Some("diskutil")
+    } else {
+        None
+    };
+
+    VersionInfo {
+        name: "decopy",
+        version: env!("CARGO_PKG_VERSION"),
+        target: Target {
+            os: ::std::env::consts::OS,
+            arch: ::std::env::consts::ARCH,
+            family: ::std::env::consts::FAMILY,
+        },
+        features,
+        copy_engines: vec!["sequential", "parallel", "fan-out", "image"],
+        capabilities: Capabilities {
+            eject: eject_backend.is_some(),
+            eject_backend,
+            udev: false,
+            vss: false,
+            volume_guid_paths: cfg!(windows),
+            elevate: elevate::SUPPORTED,
+            io_priority: cfg!(any(target_os = "linux", windows)),
+            self_update: true,
+            self_update_asset: update::asset_name(),
+            partition_mount: cfg!(target_os = "linux"),
+            native_engine: offload::NATIVE_ENGINE,
+        },
+    }
+}
@@ -0,0 +1,1023 @@
+use clap::ValueEnum;
+use glob::Pattern;
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use serde::{Deserialize, Serialize};
+use std::{
+    cmp::Ordering,
+    collections::{BTreeMap, VecDeque},
+    ffi::{OsStr, OsString},
+    fmt,
+    path::{Component, Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering as AtomicOrdering},
+        mpsc::{sync_channel, Receiver},
+        Arc,
+    },
+    thread::JoinHandle,
+};
+
+use crate::{
+    longpath,
+    symlink::{Symlink, SymlinkPolicy},
+};
+
+///
+/// How many entries the walker may read ahead of the copy. Together with `--pipeline-buffer`
+/// this is what bounds the memory a run needs, however many files the source holds.
+///
+pub const LOOKAHEAD: usize = 4096;
+
+///
+/// Rough cost of one queued entry, for checking the look-ahead against `--max-memory`
+///
+pub const LOOKAHEAD_ENTRY_BYTES: usize = 512;
+
+///
+/// A directory or file of the source, relative to it
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Entry {
+    Dir(PathBuf),
+    File(PathBuf, usize),
+    /// A symlink kept as a link, with `--symlinks preserve`
+    Link(PathBuf, Symlink),
+}
+
+impl Entry {
+    pub fn path(&self) -> &Path {
+        match self {
+            Entry::Dir(path) | Entry::File(path, _) | Entry::Link(path, _) => path,
+        }
+    }
+}
+
+///
+/// What `--exclude` leaves out. Every entry is a path relative to the root or a glob matched
+/// against such paths, with `/` between directories and `*` also matching across them, like
+/// `--clean-dest-glob`. A directory that matches is left out with everything below it, and
+/// `dir/**` leaves out `dir` itself too rather than copying it empty.
+///
+#[derive(Debug, Clone, Default)]
+pub struct Exclusions(Vec<(PathBuf, Option<Pattern>)>);
+
+impl Exclusions {
+    pub fn new(excluded: &[PathBuf]) -> Self {
+        Self(
+            excluded
+                .iter()
+                .map(|path| {
+                    let mut glob = path.to_string_lossy().into_owned();
+                    if cfg!(windows) {
+                        glob = glob.replace('\\', "/");
+                    }
+                    let glob = glob.strip_suffix("/**").unwrap_or(&glob);
+                    // Anything that isn't a valid glob is still excluded as a plain path
+                    (path.clone(), Pattern::new(glob).ok())
+                })
+                .collect(),
+        )
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    ///
+    /// Whether `path` itself is excluded, not looking at the directories above it
+    ///
+    pub fn matches(&self, path: &Path) -> bool {
+        if self.is_empty() {
+            return false;
+        }
+        let slashed = slashed(path);
+        self.0.iter().any(|(excluded, pattern)| {
+            excluded == path || pattern.as_ref().is_some_and(|p| p.matches(&slashed))
+        })
+    }
+
+    ///
+    /// Whether something below the directory `dir` might match, judging by the part of every
+    /// glob before its first wildcard
+    ///
+    pub fn may_match_below(&self, dir: &Path) -> bool {
+        let below = slashed(dir) + "/";
+        self.0.iter().any(|(path, pattern)| match pattern {
+            Some(pattern) => {
+                let glob = pattern.as_str();
+                let literal = &glob[..glob.find(['*', '?', '[']).unwrap_or(glob.len())];
+                below.starts_with(literal) || literal.starts_with(&below)
+            }
+            None => path.starts_with(dir) && path != dir,
+        })
+    }
+
+    ///
+    /// Whether `path` or a directory above it is excluded, for sources that aren't walked
+    ///
+    pub fn covers(&self, path: &Path) -> bool {
+        path.ancestors()
+            .filter(|ancestor| !ancestor.as_os_str().is_empty())
+            .any(|ancestor| self.matches(ancestor))
+    }
+}
+
+///
+/// What `--include` lets through, globs and plain paths like those of `Exclusions`. A file is
+/// included when it or a directory above it matches, so `*.bin` and `firmware/**` both work.
+/// Without any, everything is.
+///
+#[derive(Debug, Clone, Default)]
+pub struct Inclusions(Exclusions);
+
+impl Inclusions {
+    pub fn new(included: &[PathBuf]) -> Self {
+        Self(Exclusions::new(included))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    ///
+    /// Whether the file `path` is copied
+    ///
+    pub fn matches(&self, path: &Path) -> bool {
+        self.is_empty() || self.0.covers(path)
+    }
+}
+
+///
+/// What happens when two sources both have an entry of the same name at their top level
+/// (`--collision`)
+///
+#[derive(ValueEnum, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum Collision {
+    /// Refuse to start, one of the two would be lost
+    #[default]
+    Error,
+    /// Copy the entry of the source given first, leave out the later one
+    FirstWins,
+    /// Copy the entry of the source given last in place of the earlier one
+    LastWins,
+    /// Copy both, the later one as `name (2).ext`
+    Suffix,
+}
+
+///
+/// A top level name more than one source has, and how `--collision` settled it
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NameCollision {
+    /// Relative to the tree
+    pub name: PathBuf,
+    /// Where the entry given first and the one given later really are
+    pub first: PathBuf,
+    pub later: PathBuf,
+    pub collision: Collision,
+    /// What the later entry is copied as, with `--collision suffix`
+    pub renamed: Option<PathBuf>,
+}
+
+impl fmt::Display for NameCollision {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "`{}` is in more than one source, ", self.name.display())?;
+        match (self.collision, &self.renamed) {
+            (Collision::LastWins, _) => write!(
+                f,
+                "`{}` is copied in place of `{}`",
+                self.later.display(),
+                self.first.display()
+            ),
+            (Collision::Suffix, Some(renamed)) => write!(
+                f,
+                "`{}` is copied as `{}`",
+                self.later.display(),
+                renamed.display()
+            ),
+            _ => write!(
+                f,
+                "`{}` is copied and `{}` left out",
+                self.first.display(),
+                self.later.display()
+            ),
+        }
+    }
+}
+
+///
+/// What a run copies from: a directory, with files and directories from elsewhere merged into
+/// its top level (`--from`). Paths of the source are relative to this merged tree, wherever
+/// their top level entry really is.
+///
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Source {
+    /// `None` when everything is merged in, as for a single file
+    dir: Option<PathBuf>,
+    /// The top level entries merged in, by name, and the directory each of them is in
+    merged: BTreeMap<OsString, PathBuf>,
+    /// The names merged entries are really found under, for those `--collision suffix` renamed
+    renamed: BTreeMap<OsString, OsString>,
+    collisions: Vec<NameCollision>,
+}
+
+impl Source {
+    ///
+    /// Merges `path` in: the entries of a directory, or a file under its own name. The first
+    /// directory is the one the tree is rooted in. A name the tree already has is settled by
+    /// `collision`.
+    ///
+    pub fn merge(&mut self, path: &Path, collision: Collision) -> ::std::io::Result<()> {
+        if !path.is_dir() {
+            let name = path.file_name().ok_or_else(|| {
+                ::std::io::Error::new(
+                    ::std::io::ErrorKind::InvalidInput,
+                    format!("`{}` has no file name", path.display()),
+                )
+            })?;
+            let dir = path
+                .parent()
+                .filter(|dir| !dir.as_os_str().is_empty())
+                .unwrap_or(Path::new("."));
+            return self.add(name.to_os_string(), dir, collision);
+        }
+        if self.dir.is_none() && self.merged.is_empty() {
+            self.dir = Some(path.to_path_buf());
+            return Ok(());
+        }
+        let mut names = ::std::fs::read_dir(path)?
+            .map(|entry| entry.map(|entry| entry.file_name()))
+            .collect::<::std::io::Result<Vec<_>>>()?;
+        // Suffixes are handed out in the same order every run
+        names.sort();
+        for name in names {
+            self.add(name, path, collision)?;
+        }
+        Ok(())
+    }
+
+    ///
+    /// Adds the entry `name` of `dir` to the top level
+    ///
+    fn add(&mut self, name: OsString, dir: &Path, collision: Collision) -> ::std::io::Result<()> {
+        if !self.taken(&name) {
+            self.merged.insert(name, dir.to_path_buf());
+            return Ok(());
+        }
+        if collision == Collision::Error {
+            return Err(::std::io::Error::new(
+                ::std::io::ErrorKind::AlreadyExists,
+                format!(
+                    "`{}` is in more than one source, see --collision",
+                    Path::new(&name).display()
+                ),
+            ));
+        }
+        let mut collided = NameCollision {
+            name: PathBuf::from(&name),
+            first: self.path(&name),
+            later: dir.join(&name),
+            collision,
+            renamed: None,
+        };
+        match collision {
+            Collision::Error | Collision::FirstWins => {}
+            Collision::LastWins => {
+                self.renamed.remove(&name);
+                self.merged.insert(name, dir.to_path_buf());
+            }
+            Collision::Suffix => {
+                let renamed = (2..)
+                    .map(|n| suffixed(&name, n))
+                    .find(|renamed| !self.taken(renamed))
+                    .expect("some suffix is free");
+                collided.renamed = Some(PathBuf::from(&renamed));
+                self.merged.insert(renamed.clone(), dir.to_path_buf());
+                self.renamed.insert(renamed, name);
+            }
+        }
+        self.collisions.push(collided);
+        Ok(())
+    }
+
+    ///
+    /// Whether the top level has an entry called `name` already
+    ///
+    fn taken(&self, name: &OsString) -> bool {
+        self.merged.contains_key(name)
+            || self
+                .dir
+                .as_ref()
+                .is_some_and(|root| root.join(name).symlink_metadata().is_ok())
+    }
+
+    ///
+    /// The names more than one source has, in the order they came up
+    ///
+    pub fn collisions(&self) -> &[NameCollision] {
+        &self.collisions
+    }
+
+    ///
+    /// The directory `relative` is found in, the one its top level entry is in
+    ///
+    pub fn root_of(&self, relative: &Path) -> &Path {
+        relative
+            .iter()
+            .next()
+            .and_then(|top| self.merged.get(top))
+            .or(self.dir.as_ref())
+            .map_or(Path::new(""), PathBuf::as_path)
+    }
+
+    ///
+    /// `relative` as it is found under `root_of`, which only differs for a top level entry
+    /// `--collision suffix` renamed
+    ///
+    pub fn real(&self, relative: &Path) -> PathBuf {
+        let mut components = relative.iter();
+        match components.next().and_then(|top| self.renamed.get(top)) {
+            // Joining an empty path would add a trailing separator
+            Some(original) if components.as_path().as_os_str().is_empty() => {
+                PathBuf::from(original)
+            }
+            Some(original) => Path::new(original).join(components.as_path()),
+            None => relative.to_path_buf(),
+        }
+    }
+
+    ///
+    /// Where `relative` really is
+    ///
+    pub fn path(&self, relative: impl AsRef<Path>) -> PathBuf {
+        let relative = relative.as_ref();
+        self.root_of(relative).join(self.real(relative))
+    }
+
+    ///
+    /// The directory, or the first thing merged in, for naming the source in messages and the
+    /// state file
+    ///
+    pub fn name(&self) -> PathBuf {
+        match (&self.dir, self.merged.keys().next()) {
+            (Some(dir), _) => longpath::shown(dir),
+            (None, Some(name)) => longpath::shown(&self.path(name)),
+            (None, None) => PathBuf::new(),
+        }
+    }
+
+    ///
+    /// The same tree with every directory in `longpath::extended` form, for the copy engine
+    ///
+    pub fn extended(&self) -> Source {
+        Source {
+            dir: self.dir.as_deref().map(longpath::extended),
+            merged: self
+                .merged
+                .iter()
+                .map(|(name, dir)| (name.clone(), longpath::extended(dir)))
+                .collect(),
+            ..self.clone()
+        }
+    }
+
+    ///
+    /// The top level entries merged in, as paths relative to the tree
+    ///
+    fn merged(&self) -> impl Iterator<Item = PathBuf> + '_ {
+        self.merged.keys().map(PathBuf::from)
+    }
+
+    ///
+    /// Whether the entry `name` of the directory the tree is rooted in was merged over by
+    /// `--collision last-wins`
+    ///
+    fn merged_over(&self, name: &OsStr) -> bool {
+        self.merged.contains_key(name)
+    }
+}
+
+///
+/// `name` with ` (n)` before its extension
+///
+fn suffixed(name: &OsStr, n: usize) -> OsString {
+    let path = Path::new(name);
+    let mut suffixed = path.file_stem().unwrap_or(name).to_os_string();
+    suffixed.push(format!(" ({})", n));
+    if let Some(extension) = path.extension() {
+        suffixed.push(".");
+        suffixed.push(extension);
+    }
+    suffixed
+}
+
+impl From<&Path> for Source {
+    fn from(dir: &Path) -> Self {
+        Source {
+            dir: Some(dir.to_path_buf()),
+            ..Source::default()
+        }
+    }
+}
+
+impl From<&PathBuf> for Source {
+    fn from(dir: &PathBuf) -> Self {
+        Source::from(dir.as_path())
+    }
+}
+
+impl From<&Source> for Source {
+    fn from(source: &Source) -> Self {
+        source.clone()
+    }
+}
+
+///
+/// What of a tree a run goes through: everything but what `--exclude` leaves out, only the files
+/// `--include` matches when there are any, and without what `--respect-gitignore` ignores, but
+/// with whatever `--force-include` brings back. See `Walk` for the order. Symlinks go as
+/// `--symlinks` says.
+///
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Selection {
+    /// Relative to the root, see `Exclusions`
+    pub excluded: Vec<PathBuf>,
+    /// Relative to the root, see `Inclusions`
+    pub included: Vec<PathBuf>,
+    pub gitignore: bool,
+    /// Relative to the root, globs like those of `Exclusions`
+    pub forced: Vec<PathBuf>,
+    pub symlinks: SymlinkPolicy,
+}
+
+impl Selection {
+    pub fn walk(&self, root: impl Into<Source>) -> Walk {
+        Walk::new(root)
+            .excluding(&self.excluded)
+            .including(&self.included)
+            .respecting_gitignore(self.gitignore)
+            .force_including(&self.forced)
+            .with_symlinks(self.symlinks)
+    }
+
+    ///
+    /// The same selection with `excluded` in place of its own exclusions
+    ///
+    pub fn with_excluded(&self, excluded: Vec<PathBuf>) -> Selection {
+        Selection {
+            excluded,
+            ..self.clone()
+        }
+    }
+}
+
+///
+/// `path` with `/` between its components whatever the platform, for matching globs
+///
+fn slashed(path: &Path) -> String {
+    path.components()
+        .filter_map(|component| match component {
+            Component::Normal(part) => Some(part.to_string_lossy()),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+///
+/// Streams a directory tree depth first, with the entries of every directory sorted by name.
+///
+/// Only the listings of the directories on the way to the current entry are held in memory, not
+/// the whole tree. The order is stable between runs and is the same as comparing the relative
+/// paths, which `--resume` and verification rely on.
+///
+/// What is left out is decided in this order, the first rule that applies wins:
+/// 1. a path `force_including` matches, or one below such a directory, is walked
+/// 2. a path `excluding` matches is left out, with everything below it
+/// 3. a path the ignore files ignore with `respecting_gitignore` is left out, with everything
+///    below it
+/// 4. a file `including` doesn't match is left out, if there are any inclusions
+/// 5. anything else is walked
+///
+/// With inclusions, directories only show up once a file in them does, so none are left empty.
+/// The same goes for directories that are only gone through for what `force_including` brings
+/// back.
+///
+/// Symlinks are followed and walked as what they point to unless `with_symlinks` says otherwise.
+/// A link to a directory above it is an error rather than a walk that never ends.
+///
+pub struct Walk {
+    root: Source,
+    /// Paths relative to the root that are skipped along with everything below them
+    excluded: Exclusions,
+    /// Files that are walked, all unless there are some
+    included: Inclusions,
+    /// Paths that are walked with everything below them, whatever else leaves them out
+    forced: Exclusions,
+    /// Skip what the `.gitignore` and `.ignore` files in the tree ignore, and `.git` itself
+    gitignore: bool,
+    symlinks: SymlinkPolicy,
+    /// The rules of the ignore files of every directory on `stack`, innermost last
+    ignores: Vec<Option<Gitignore>>,
+    /// Listings still to go through, innermost last. `None` until the root has been read.
+    stack: Option<Vec<::std::vec::IntoIter<Listed>>>,
+    /// The directories of the listings on `stack` below the root, and whether each was returned
+    entered: Vec<(PathBuf, bool)>,
+    /// Directories held back until an included file in them came up, and that file
+    ready: VecDeque<Entry>,
+}
+
+impl Walk {
+    pub fn new(root: impl Into<Source>) -> Self {
+        Self {
+            root: root.into(),
+            excluded: Exclusions::default(),
+            included: Inclusions::default(),
+            forced: Exclusions::default(),
+            gitignore: false,
+            symlinks: SymlinkPolicy::Follow,
+            ignores: Vec::new(),
+            stack: None,
+            entered: Vec::new(),
+            ready: VecDeque::new(),
+        }
+    }
+
+    ///
+    /// Leaves out `excluded`, given relative to the root, and everything below them
+    ///
+    pub fn excluding(mut self, excluded: &[PathBuf]) -> Self {
+        self.excluded = Exclusions::new(excluded);
+        self
+    }
+
+    ///
+    /// Only walks the files `included` matches, given relative to the root, if there are any
+    ///
+    pub fn including(mut self, included: &[PathBuf]) -> Self {
+        self.included = Inclusions::new(included);
+        self
+    }
+
+    ///
+    /// Walks what `forced` matches, given relative to the root, and everything below it, however
+    /// it would be left out otherwise
+    ///
+    pub fn force_including(mut self, forced: &[PathBuf]) -> Self {
+        self.forced = Exclusions::new(forced);
+        self
+    }
+
+    ///
+    /// Skips what the `.gitignore` and `.ignore` files of every directory ignore, the way git
+    /// does, and the `.git` directory itself
+    ///
+    pub fn respecting_gitignore(mut self, gitignore: bool) -> Self {
+        self.gitignore = gitignore;
+        self
+    }
+
+    ///
+    /// Follows symlinks, returns them as `Entry::Link` or leaves them out. Links are kept or left
+    /// out like files.
+    ///
+    pub fn with_symlinks(mut self, symlinks: SymlinkPolicy) -> Self {
+        self.symlinks = symlinks;
+        self
+    }
+
+    ///
+    /// The sorted entries of the directory `relative` that aren't left out, and the ignore rules
+    /// it brings along. `parent` is how the directory itself was kept.
+    ///
+    fn read_dir(&self, relative: &Path, parent: Kept) -> ::std::io::Result<Listing> {
+        let dir = self.root.path(relative);
+        // A link to a directory above it would be walked forever
+        if !relative.as_os_str().is_empty() && dir.symlink_metadata()?.is_symlink() {
+            let target = dir.canonicalize()?;
+            if let Some(above) = dir.parent() {
+                if above.canonicalize()?.starts_with(target) {
+                    return Err(::std::io::Error::new(
+                        ::std::io::ErrorKind::InvalidInput,
+                        format!(
+                            "`{}` links back to a directory above it",
+                            relative.display()
+                        ),
+                    ));
+                }
+            }
+        }
+        let top = relative.as_os_str().is_empty();
+        // Everything is merged in when there is no directory, there is nothing to list
+        let listed = match top && self.root.dir.is_none() {
+            true => Vec::new(),
+            false => ::std::fs::read_dir(&dir)?.collect::<::std::io::Result<Vec<_>>>()?,
+        };
+        let ignore = match listed.is_empty() {
+            true => None,
+            false => self.gitignore.then(|| ignore_rules(&dir)).flatten(),
+        };
+        let mut entries = listed
+            .into_iter()
+            // What another source has in its place is listed with those merged in
+            .filter(|entry| !(top && self.root.merged_over(&entry.file_name())))
+            .map(|entry| {
+                let path = relative.join(entry.file_name());
+                self.listed(
+                    path,
+                    entry.file_type()?.is_symlink(),
+                    parent,
+                    ignore.as_ref(),
+                )
+            })
+            .chain(
+                top.then(|| self.root.merged())
+                    .into_iter()
+                    .flatten()
+                    .map(|path| {
+                        let is_symlink = self.root.path(&path).symlink_metadata()?.is_symlink();
+                        self.listed(path, is_symlink, parent, ignore.as_ref())
+                    }),
+            )
+            .filter_map(Result::transpose)
+            .collect::<::std::io::Result<Vec<_>>>()?;
+        entries.sort_by(|(a, _), (b, _)| a.path().cmp(b.path()));
+        Ok((entries, ignore))
+    }
+
+    ///
+    /// The entry at `path`, in a directory kept as `parent`, or `None` when it is left out.
+    /// Symlinks that are followed are listed as what they point to.
+    ///
+    fn listed(
+        &self,
+        path: PathBuf,
+        is_symlink: bool,
+        parent: Kept,
+        ignore: Option<&Gitignore>,
+    ) -> ::std::io::Result<Option<Listed>> {
+        if is_symlink && self.symlinks != SymlinkPolicy::Follow {
+            let Some(kept) = self.kept(&path, false, parent, ignore) else {
+                return Ok(None);
+            };
+            return match self.symlinks {
+                SymlinkPolicy::Skip => Ok(None),
+                _ => Ok(Some((
+                    Entry::Link(
+                        path.clone(),
+                        Symlink::read(self.root.root_of(&path), &self.root.real(&path))?,
+                    ),
+                    kept,
+                ))),
+            };
+        }
+        let metadata = ::std::fs::metadata(self.root.path(&path))?;
+        Ok(self
+            .kept(&path, metadata.is_dir(), parent, ignore)
+            .map(|kept| match metadata.is_dir() {
+                true => (Entry::Dir(path), kept),
+                false => (Entry::File(path, metadata.len() as usize), kept),
+            }))
+    }
+
+    ///
+    /// How `path` is kept, in a directory kept as `parent`, or `None` when it is left out. See
+    /// `Walk` for the order the rules go in.
+    ///
+    fn kept(
+        &self,
+        path: &Path,
+        is_dir: bool,
+        parent: Kept,
+        local: Option<&Gitignore>,
+    ) -> Option<Kept> {
+        if parent == Kept::Forced || self.forced.matches(path) {
+            return Some(Kept::Forced);
+        }
+        let left_out = parent == Kept::Searched
+            || self.excluded.matches(path)
+            || self.ignored(path, is_dir, local)
+            || (!is_dir && !self.included.matches(path));
+        match left_out {
+            false => Some(Kept::Selected),
+            true if is_dir && self.forced.may_match_below(path) => Some(Kept::Searched),
+            true => None,
+        }
+    }
+
+    ///
+    /// Queues the directories on the way to the current entry that were held back
+    ///
+    fn reveal_entered(&mut self) {
+        for (dir, returned) in self.entered.iter_mut().filter(|(_, returned)| !returned) {
+            *returned = true;
+            self.ready.push_back(Entry::Dir(dir.clone()));
+        }
+    }
+
+    ///
+    /// Whether the ignore rules skip `path`, those of its own directory in `local`. The rules
+    /// closest to it win, as in git.
+    ///
+    fn ignored(&self, path: &Path, is_dir: bool, local: Option<&Gitignore>) -> bool {
+        if !self.gitignore {
+            return false;
+        }
+        if is_dir && path.file_name().is_some_and(|name| name == ".git") {
+            return true;
+        }
+        let path = self.root.path(path);
+        local
+            .into_iter()
+            .chain(self.ignores.iter().rev().flatten())
+            .map(|rules| rules.matched(&path, is_dir))
+            .find(|matched| !matched.is_none())
+            .is_some_and(|matched| matched.is_ignore())
+    }
+}
+
+///
+/// Why an entry is walked
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kept {
+    /// Nothing leaves it out
+    Selected,
+    /// `force_including` brings it back
+    Forced,
+    /// A directory that is left out but might have something `force_including` brings back
+    Searched,
+}
+
+/// An entry of a directory and why it's kept
+type Listed = (Entry, Kept);
+
+/// A directory's entries, and the ignore rules found in it
+type Listing = (Vec<Listed>, Option<Gitignore>);
+
+///
+/// The rules of the `.gitignore` and `.ignore` files in `dir`, `None` when it has neither.
+/// Lines that don't parse are skipped, like git does.
+///
+fn ignore_rules(dir: &Path) -> Option<Gitignore> {
+    let mut builder = GitignoreBuilder::new(dir);
+    let mut found = false;
+    for name in [".gitignore", ".ignore"] {
+        let file = dir.join(name);
+        if file.is_file() {
+            found = true;
+            builder.add(file);
+        }
+    }
+    found.then(|| builder.build().ok()).flatten()
+}
+
+impl Iterator for Walk {
+    type Item = ::std::io::Result<Entry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.stack.is_none() {
+            match self.read_dir(Path::new(""), Kept::Selected) {
+                Ok((entries, ignore)) => {
+                    self.stack = Some(vec![entries.into_iter()]);
+                    self.ignores.push(ignore);
+                }
+                Err(e) => {
+                    self.stack = Some(Vec::new());
+                    return Some(Err(e));
+                }
+            }
+        }
+
+        loop {
+            if let Some(entry) = self.ready.pop_front() {
+                return Some(Ok(entry));
+            }
+            let stack = self.stack.as_mut()?;
+            let Some((entry, kept)) = stack.last_mut()?.next() else {
+                stack.pop();
+                self.ignores.pop();
+                self.entered.pop();
+                continue;
+            };
+            let Entry::Dir(path) = entry else {
+                self.reveal_entered();
+                if self.ready.is_empty() {
+                    return Some(Ok(entry));
+                }
+                self.ready.push_back(entry);
+                continue;
+            };
+            match self.read_dir(&path, kept) {
+                Ok((entries, ignore)) => {
+                    self.stack.as_mut()?.push(entries.into_iter());
+                    self.ignores.push(ignore);
+                    let returned = match kept {
+                        Kept::Forced => true,
+                        Kept::Selected => self.included.is_empty(),
+                        Kept::Searched => false,
+                    };
+                    if returned {
+                        self.reveal_entered();
+                    }
+                    self.entered.push((path.clone(), returned));
+                    if returned {
+                        if self.ready.is_empty() {
+                            return Some(Ok(Entry::Dir(path)));
+                        }
+                        self.ready.push_back(Entry::Dir(path));
+                    }
+                }
+                Err(e) => {
+                    // The walk can't go on in a consistent order after a failed listing
+                    self.stack = Some(Vec::new());
+                    return Some(Err(e));
+                }
+            }
+        }
+    }
+}
+
+///
+/// Runs `walk` on a background thread, at most `LOOKAHEAD` entries ahead of the receiver. The
+/// walk stops early once the receiver is dropped.
+///
+pub fn walk_ahead(walk: Walk) -> Receiver<::std::io::Result<Entry>> {
+    let (entries, receiver) = sync_channel(LOOKAHEAD);
+    ::std::thread::spawn(move || {
+        for entry in walk {
+            if entries.send(entry).is_err() {
+                break;
+            }
+        }
+    });
+    receiver
+}
+
+///
+/// The paths of `theirs` that aren't in `ours`, found by merging the two instead of collecting
+/// either. Both have to be in `Walk` order.
+///
+pub fn only_in(
+    ours: impl Iterator<Item = PathBuf>,
+    theirs: impl Iterator<Item = PathBuf>,
+) -> Vec<PathBuf> {
+    let mut ours = ours.peekable();
+    let mut extra = Vec::new();
+    for file in theirs {
+        while ours.next_if(|ours| *ours < file).is_some() {}
+        match ours.peek().map(|ours| ours.cmp(&file)) {
+            Some(Ordering::Equal) => {
+                ours.next();
+            }
+            _ => extra.push(file),
+        }
+    }
+    extra
+}
+
+///
+/// Size of the source as totalled by `prescan`, filled in while the copy is already running
+///
+#[derive(Debug, Default)]
+pub struct Totals {
+    files: AtomicUsize,
+    bytes: AtomicUsize,
+    done: AtomicBool,
+}
+
+impl Totals {
+    pub fn files(&self) -> usize {
+        self.files.load(AtomicOrdering::Relaxed)
+    }
+
+    pub fn bytes(&self) -> usize {
+        self.bytes.load(AtomicOrdering::Relaxed)
+    }
+
+    ///
+    /// Whether the whole source has been counted, so `files` and `bytes` are final
+    ///
+    pub fn done(&self) -> bool {
+        self.done.load(AtomicOrdering::Acquire)
+    }
+}
+
+///
+/// A running `prescan`
+///
+pub struct Prescan {
+    totals: Arc<Totals>,
+    thread: JoinHandle<()>,
+}
+
+impl Prescan {
+    ///
+    /// The totals so far
+    ///
+    pub fn totals(&self) -> &Totals {
+        &self.totals
+    }
+
+    ///
+    /// Waits for the pre-scan to get through the whole source
+    ///
+    pub fn finish(self) -> Arc<Totals> {
+        let _ = self.thread.join();
+        self.totals
+    }
+}
+
+///
+/// Totals what `walk` goes through on a background thread without keeping the file list around,
+/// so progress can be shown against the full size while copying starts right away. A walk that
+/// fails counts what it got to, the copy itself reports the error.
+///
+pub fn prescan(walk: Walk) -> Prescan {
+    let totals = Arc::new(Totals::default());
+    let counting = totals.clone();
+    let thread = ::std::thread::spawn(move || {
+        for entry in walk {
+            match entry {
+                Ok(Entry::File(_, size)) => {
+                    counting.files.fetch_add(1, AtomicOrdering::Relaxed);
+                    counting.bytes.fetch_add(size, AtomicOrdering::Relaxed);
+                }
+                Ok(Entry::Dir(_) | Entry::Link(..)) => {}
+                Err(_) => break,
+            }
+        }
+        counting.done.store(true, AtomicOrdering::Release);
+    });
+    Prescan { totals, thread }
+}
+
+///
+/// Totals `walk` on the calling thread, for when nothing else can happen before the total is known
+///
+pub fn total_bytes(walk: Walk) -> ::std::io::Result<usize> {
+    let mut total = 0;
+    for entry in walk {
+        if let Entry::File(_, size) = entry? {
+            total += size;
+        }
+    }
+    Ok(total)
+}
+
+///
+/// Lists the top level entries of `root`, in `Walk` order, and totals each of them on a single
+/// background thread, for the PreCopy preview. What `selection` leaves out isn't counted, except
+/// for top level entries excluded by their exact path, as the preview can switch those back on.
+///
+pub fn prescan_top_level(
+    root: &Source,
+    selection: &Selection,
+) -> ::std::io::Result<Vec<(PathBuf, Arc<Totals>)>> {
+    // Files `--include` leaves out are left out of the preview too, directories may have some
+    // that it doesn't below them
+    let included = Inclusions::new(&selection.included);
+    let entries = Walk::new(root)
+        .with_symlinks(selection.symlinks)
+        .read_dir(Path::new(""), Kept::Selected)?
+        .0
+        .into_iter()
+        .filter(|(entry, _)| {
+            !matches!(entry, Entry::File(..))
+                || included.is_empty()
+                || included.matches(entry.path())
+        })
+        .map(|(entry, _)| (entry.path().to_path_buf(), Arc::new(Totals::default())))
+        .collect::<Vec<_>>();
+    let counted = selection
+        .excluded
+        .iter()
+        .filter(|path| !entries.iter().any(|(entry, _)| entry == *path))
+        .cloned()
+        .collect::<Vec<_>>();
+    let walk = selection.with_excluded(counted).walk(root);
+
+    let counting = entries.clone();
+    ::std::thread::spawn(move || {
+        for entry in walk {
+            match entry {
+                Ok(Entry::File(path, size)) => {
+                    let top = path.iter().next().map(Path::new).unwrap_or(&path);
+                    if let Ok(index) =
+                        counting.binary_search_by(|(entry, _)| entry.as_path().cmp(top))
+                    {
+                        let totals = &counting[index].1;
+                        totals.files.fetch_add(1, AtomicOrdering::Relaxed);
+                        totals.bytes.fetch_add(size, AtomicOrdering::Relaxed);
+                    }
+                }
+                Ok(Entry::Dir(_) | Entry::Link(..)) => {}
+                Err(_) => break,
+            }
+        }
+        for (_, totals) in counting {
+            totals.done.store(true, AtomicOrdering::Release);
+        }
+    });
+    Ok(entries)
+}